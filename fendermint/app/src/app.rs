@@ -1,43 +1,62 @@
 // Copyright 2022-2023 Protocol Labs
 // SPDX-License-Identifier: Apache-2.0, MIT
+use std::collections::VecDeque;
 use std::future::Future;
 use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 
 use anyhow::{anyhow, Context, Result};
 use async_trait::async_trait;
+use bytes::Bytes;
 use cid::Cid;
 use fendermint_abci::{AbciResult, Application};
 use fendermint_storage::{
     Codec, Encode, KVCollection, KVRead, KVReadable, KVStore, KVWritable, KVWrite,
 };
 use fendermint_vm_core::Timestamp;
+use fendermint_vm_genesis::Validator;
 use fendermint_vm_interpreter::bytes::{
     BytesMessageApplyRet, BytesMessageCheckRet, BytesMessageQuery, BytesMessageQueryRet,
 };
-use fendermint_vm_interpreter::chain::{ChainMessageApplyRet, IllegalMessage};
+use fendermint_vm_interpreter::chain::{ChainMessageApplyRet, IllegalExecution, IllegalMessage};
 use fendermint_vm_interpreter::fvm::state::{
-    empty_state_tree, FvmCheckState, FvmExecState, FvmGenesisState, FvmQueryState, FvmStateParams,
+    empty_state_tree, next_base_fee, CheckPolicy, FvmCheckState, FvmExecState, FvmGenesisState,
+    FvmQueryState, FvmStateParams,
 };
-use fendermint_vm_interpreter::fvm::{FvmApplyRet, FvmGenesisOutput};
-use fendermint_vm_interpreter::signed::InvalidSignature;
+use fendermint_vm_interpreter::fvm::upgrades::UpgradeScheduler;
+use fendermint_vm_interpreter::fvm::{
+    to_trace_result, FvmApplyRet, FvmGenesisOutput, FvmQuery, FvmQueryRet,
+};
+use fendermint_vm_interpreter::signed::{InvalidSignature, SignedApplyRet};
 use fendermint_vm_interpreter::{
-    CheckInterpreter, ExecInterpreter, GenesisInterpreter, QueryInterpreter,
+    CheckInterpreter, ExecInterpreter, GenesisInterpreter, ProposalInterpreter, QueryInterpreter,
+};
+use fendermint_vm_snapshot::{
+    SnapshotClient, SnapshotManifest, SnapshotRestore, SnapshotRestoreError, SNAPSHOT_FORMAT,
 };
 use fvm::engine::MultiEngine;
 use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_encoding::RawBytes;
 use fvm_shared::chainid::ChainID;
+use fvm_shared::clock::ChainEpoch;
 use fvm_shared::econ::TokenAmount;
 use fvm_shared::version::NetworkVersion;
 use num_traits::Zero;
 use serde::{Deserialize, Serialize};
 use tendermint::abci::request::CheckTxKind;
+use tendermint::abci::response::ApplySnapshotChunkResult;
 use tendermint::abci::{request, response};
 use tendermint::block::Height;
 
+use crate::gc::GcClient;
+use crate::query::{QueryMetrics, QueryPool};
 use crate::{tmconv::*, VERSION};
 use crate::{BlockHeight, APP_VERSION};
 
+/// Number of states recreated by replaying blocks in archive mode to keep around,
+/// so that repeated queries at the same heights don't have to replay them again.
+const REPLAY_CACHE_SIZE: usize = 16;
+
 #[derive(Serialize)]
 #[repr(u8)]
 pub enum AppStoreKey {
@@ -55,6 +74,8 @@ pub enum AppError {
     IllegalMessage = 53,
     /// The genesis block hasn't been initialized yet.
     NotInitialized = 54,
+    /// The state at the requested height has been pruned, or doesn't exist yet.
+    StateNotAvailable = 55,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -67,6 +88,17 @@ pub struct AppState {
     state_params: FvmStateParams,
 }
 
+/// What is needed to replay a block in archive mode, besides the state at the previous height.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ArchivedBlock {
+    /// Address of the validator who proposed the block.
+    proposer: RawBytes,
+    /// Hash of the block, if known.
+    block_hash: Option<RawBytes>,
+    /// Transactions in the order they were delivered.
+    txs: Vec<RawBytes>,
+}
+
 impl AppState {
     pub fn state_root(&self) -> Cid {
         self.state_params.state_root
@@ -75,11 +107,37 @@ impl AppState {
         ChainID::from(self.state_params.chain_id)
     }
     pub fn app_hash(&self) -> tendermint::hash::AppHash {
-        tendermint::hash::AppHash::try_from(self.state_root().to_bytes())
-            .expect("hash can be wrapped")
+        to_app_hash(&self.state_params)
     }
 }
 
+pub struct AppConfig<S: KVStore> {
+    /// Namespace to store the current app state.
+    pub app_namespace: S::Namespace,
+    /// Namespace to store the app state history.
+    pub state_hist_namespace: S::Namespace,
+    /// Size of state history to keep; 0 means unlimited.
+    pub state_hist_size: u64,
+    /// Namespace to store the transactions of blocks in archive mode.
+    pub archive_namespace: S::Namespace,
+    /// Keep the state history of every height, but only retain the state of every
+    /// height divisible by this number beyond the state history size, recreating
+    /// the rest by replaying blocks; 0 means archive mode is disabled.
+    pub archive_interval: u64,
+    /// Path to the Wasm bundle.
+    ///
+    /// Only loaded once during genesis; later comes from the [`StateTree`].
+    pub builtin_actors_bundle: PathBuf,
+    /// Rules about which transactions are admitted into the mempool.
+    pub check_policy: CheckPolicy,
+    /// Number of Wasm engine instances dedicated to queries.
+    pub query_engines: u32,
+    /// Maximum number of queries running at the same time.
+    pub query_max_concurrent: usize,
+    /// Number of idle query states to keep for reuse by later queries at the same height.
+    pub query_cache_size: usize,
+}
+
 /// Handle ABCI requests.
 #[derive(Clone)]
 pub struct App<DB, SS, S, I>
@@ -97,6 +155,8 @@ where
     state_store: Arc<SS>,
     /// Wasm engine cache.
     multi_engine: Arc<MultiEngine>,
+    /// Engines, limits and cached states for queries, separate from block execution.
+    query_pool: QueryPool<SS>,
     /// Path to the Wasm bundle.
     ///
     /// Only loaded once during genesis; later comes from the [`StateTree`].
@@ -114,8 +174,20 @@ where
     /// so that we can retrospectively execute FVM messages at past block heights
     /// in read-only mode.
     state_hist: KVCollection<S, BlockHeight, FvmStateParams>,
+    /// Transactions of past blocks, stored in archive mode to be able to replay them.
+    archive: KVCollection<S, BlockHeight, ArchivedBlock>,
+    /// Heights at which the state is retained in archive mode; 0 means archive mode is disabled.
+    archive_interval: u64,
+    /// Block being executed, collected for the archive.
+    archived_block: Arc<Mutex<Option<ArchivedBlock>>>,
+    /// Held while replaying blocks, so concurrent queries don't recreate the same states.
+    replay_lock: Arc<tokio::sync::Mutex<()>>,
+    /// States recently recreated by replaying blocks, oldest first, kept from garbage collection.
+    replayed: Arc<Mutex<VecDeque<(BlockHeight, Cid)>>>,
     /// Interpreter for block lifecycle events.
     interpreter: Arc<I>,
+    /// Interpreter for replaying past blocks, which only affects the state it's given.
+    replay_interpreter: Arc<I>,
     /// State accumulating changes during block execution.
     exec_state: Arc<Mutex<Option<FvmExecState<SS>>>>,
     /// Projected partial state accumulating during transaction checks.
@@ -124,6 +196,16 @@ where
     ///
     /// Zero means unlimited.
     state_hist_size: u64,
+    /// Rules about which transactions are admitted into the mempool.
+    check_policy: CheckPolicy,
+    /// Interface to the snapshot manager, if snapshots are enabled.
+    snapshots: Option<SnapshotClient>,
+    /// Snapshot being restored during state sync, if any.
+    snapshot_restore: Arc<tokio::sync::Mutex<Option<SnapshotRestore>>>,
+    /// Interface to the garbage collector of the state store, if enabled.
+    gc: Option<GcClient>,
+    /// Network upgrades scheduled at certain block heights.
+    upgrades: UpgradeScheduler<SS>,
 }
 
 impl<DB, SS, S, I> App<DB, SS, S, I>
@@ -132,34 +214,58 @@ where
         + Codec<AppState>
         + Encode<AppStoreKey>
         + Encode<BlockHeight>
-        + Codec<FvmStateParams>,
+        + Codec<FvmStateParams>
+        + Codec<ArchivedBlock>,
     DB: KVWritable<S> + KVReadable<S> + Clone + 'static,
     SS: Blockstore + Clone + 'static,
 {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
+        config: AppConfig<S>,
         db: DB,
         state_store: SS,
-        actor_bundle_path: PathBuf,
-        app_namespace: S::Namespace,
-        state_hist_namespace: S::Namespace,
-        state_hist_size: u64,
         interpreter: I,
+        replay_interpreter: I,
+        snapshots: Option<SnapshotClient>,
+        gc: Option<GcClient>,
+        upgrades: UpgradeScheduler<SS>,
     ) -> Result<Self> {
         let app = Self {
+            archive: KVCollection::new(config.archive_namespace),
+            archive_interval: config.archive_interval,
+            archived_block: Arc::new(Mutex::new(None)),
+            replay_lock: Arc::new(tokio::sync::Mutex::new(())),
+            replayed: Arc::new(Mutex::new(VecDeque::new())),
             db: Arc::new(db),
             state_store: Arc::new(state_store),
             multi_engine: Arc::new(MultiEngine::new(1)),
-            actor_bundle_path,
-            namespace: app_namespace,
-            state_hist: KVCollection::new(state_hist_namespace),
-            state_hist_size,
+            query_pool: QueryPool::new(
+                config.query_engines,
+                config.query_max_concurrent,
+                config.query_cache_size,
+            ),
+            actor_bundle_path: config.builtin_actors_bundle,
+            namespace: config.app_namespace,
+            state_hist: KVCollection::new(config.state_hist_namespace),
+            state_hist_size: config.state_hist_size,
+            check_policy: config.check_policy,
             interpreter: Arc::new(interpreter),
+            replay_interpreter: Arc::new(replay_interpreter),
             exec_state: Arc::new(Mutex::new(None)),
             check_state: Arc::new(tokio::sync::Mutex::new(None)),
+            snapshots,
+            snapshot_restore: Arc::new(tokio::sync::Mutex::new(None)),
+            gc,
+            upgrades,
         };
         app.init_committed_state()?;
         Ok(app)
     }
+
+    /// Metrics of the queries, to be exported.
+    pub fn query_metrics(&self) -> Arc<QueryMetrics> {
+        self.query_pool.metrics()
+    }
 }
 
 impl<DB, SS, S, I> App<DB, SS, S, I>
@@ -168,7 +274,8 @@ where
         + Codec<AppState>
         + Encode<AppStoreKey>
         + Encode<BlockHeight>
-        + Codec<FvmStateParams>,
+        + Codec<FvmStateParams>
+        + Codec<ArchivedBlock>,
     DB: KVWritable<S> + KVReadable<S> + 'static + Clone,
     SS: Blockstore + 'static + Clone,
 {
@@ -192,11 +299,13 @@ where
                     state_root,
                     network_version: NetworkVersion::MAX,
                     base_fee: TokenAmount::zero(),
+                    base_fee_params: None,
+                    block_reward: TokenAmount::zero(),
                     circ_supply: TokenAmount::zero(),
                     chain_id: 0,
                 },
             };
-            self.set_committed_state(state)?;
+            self.set_committed_state(state, None)?;
         }
         Ok(())
     }
@@ -216,16 +325,28 @@ where
         }
     }
 
-    /// Set the last committed state.
-    fn set_committed_state(&self, mut state: AppState) -> Result<()> {
+    /// Set the last committed state, along with the block that led to it in archive mode.
+    fn set_committed_state(
+        &self,
+        mut state: AppState,
+        archived_block: Option<&ArchivedBlock>,
+    ) -> Result<()> {
         self.db
             .with_write(|tx| {
+                // Archive the block in the same transaction, so the history can't have a gap in it.
+                if let Some(block) = archived_block {
+                    self.archive.put(tx, &state.block_height, block)?;
+                }
+
                 // Insert latest state history point.
                 self.state_hist
                     .put(tx, &state.block_height, &state.state_params)?;
 
-                // Prune state history.
-                if self.state_hist_size > 0 && state.block_height >= self.state_hist_size {
+                // Prune state history, unless we keep all of it in archive mode.
+                if self.archive_interval == 0
+                    && self.state_hist_size > 0
+                    && state.block_height >= self.state_hist_size
+                {
                     let prune_height = state.block_height.saturating_sub(self.state_hist_size);
                     while state.oldest_state_height <= prune_height {
                         self.state_hist.delete(tx, &state.oldest_state_height)?;
@@ -273,22 +394,438 @@ where
     /// Under height 0 we saved the empty state, which we must not query,
     /// because it doesn't contain any initialized state for the actors.
     ///
-    /// Returns the state params and the height of the block which committed it.
-    fn state_params_at_height(&self, height: Height) -> Result<(FvmStateParams, BlockHeight)> {
+    /// Returns the state params and the height of the block which committed it,
+    /// or `None` if the height is before the oldest state we have, or after the latest.
+    fn state_params_at_height(
+        &self,
+        height: Height,
+    ) -> Result<Option<(FvmStateParams, BlockHeight)>> {
         let h = height.value();
-        if h > 0 {
-            let tx = self.db.read();
-            let sh = self
+        let state = self.committed_state()?;
+
+        if h == 0 || h == state.block_height {
+            return Ok(Some((state.state_params, state.block_height)));
+        }
+        if h > state.block_height {
+            return Ok(None);
+        }
+
+        let tx = self.db.read();
+        let sh = self
+            .state_hist
+            .get(&tx, &h)
+            .context("error looking up history")?;
+
+        Ok(sh.map(|p| (p, h)))
+    }
+
+    /// The height from which CometBFT has to keep the blocks, or 0 to keep all of them.
+    fn retain_height(&self, state: &AppState) -> Result<BlockHeight> {
+        let oldest_snapshot_height = match self.snapshots {
+            Some(ref snapshots) => snapshots
+                .list_snapshots()?
+                .first()
+                .map(|item| item.manifest.block_height),
+            None => None,
+        };
+        Ok(retain_height(
+            self.state_hist_size,
+            state.oldest_state_height,
+            oldest_snapshot_height,
+        ))
+    }
+
+    /// Collect the state roots which are still in the history window.
+    ///
+    /// In archive mode the window only covers the last heights configured by the state history size,
+    /// but the states at the oldest height and at every archive interval before that are kept as well,
+    /// along with the states recently recreated by replaying blocks, and the ones still being
+    /// exported as snapshots, which might have dropped out of the history in the meantime.
+    fn retained_state_roots(&self, state: &AppState) -> Result<Vec<Cid>> {
+        let tx = self.db.read();
+        let mut roots = Vec::new();
+
+        let mut window_start = state.oldest_state_height;
+
+        if self.archive_interval > 0 && self.state_hist_size > 0 {
+            window_start = std::cmp::max(
+                window_start,
+                state.block_height.saturating_sub(self.state_hist_size) + 1,
+            );
+            let checkpoints = (state.oldest_state_height..window_start)
+                .filter(|h| *h == state.oldest_state_height || *h % self.archive_interval == 0);
+
+            for h in checkpoints {
+                if let Some(p) = self
+                    .state_hist
+                    .get(&tx, &h)
+                    .context("error looking up history")?
+                {
+                    roots.push(p.state_root);
+                }
+            }
+        }
+
+        for h in window_start..=state.block_height {
+            if let Some(p) = self
                 .state_hist
                 .get(&tx, &h)
-                .context("error looking up history")?;
+                .context("error looking up history")?
+            {
+                roots.push(p.state_root);
+            }
+        }
+
+        let replayed = self.replayed.lock().expect("mutex poisoned");
+        roots.extend(replayed.iter().map(|(_, root)| *root));
+
+        if let Some(ref snapshots) = self.snapshots {
+            roots.extend(snapshots.exporting_state_roots());
+        }
+
+        Ok(roots)
+    }
+
+    /// Remember a state recreated by replaying blocks, forgetting the oldest one if there are too many.
+    fn remember_replayed(&self, block_height: BlockHeight, state_root: Cid) {
+        let mut replayed = self.replayed.lock().expect("mutex poisoned");
+        replayed.retain(|(h, _)| *h != block_height);
+        replayed.push_back((block_height, state_root));
+        while replayed.len() > REPLAY_CACHE_SIZE {
+            replayed.pop_front();
+        }
+    }
+}
 
-            if let Some(p) = sh {
-                return Ok((p, h));
+impl<DB, SS, S, I> App<DB, SS, S, I>
+where
+    S: KVStore
+        + Codec<AppState>
+        + Encode<AppStoreKey>
+        + Encode<BlockHeight>
+        + Codec<FvmStateParams>
+        + Codec<ArchivedBlock>
+        + 'static,
+    S::Namespace: Sync + Send,
+    DB: KVWritable<S> + KVReadable<S> + Clone + Send + Sync + 'static,
+    SS: Blockstore + Clone + Send + Sync + 'static,
+    I: ExecInterpreter<
+        State = FvmExecState<SS>,
+        Message = Vec<u8>,
+        BeginOutput = FvmApplyRet,
+        DeliverOutput = BytesMessageApplyRet,
+        EndOutput = Vec<Validator>,
+    >,
+{
+    /// Create the state to execute a block in, on top of the state committed by the previous block,
+    /// switching to the network version and actor bundle scheduled for its height, if any.
+    ///
+    /// Tracing records the execution trace of every message, which is only meant for debugging.
+    async fn block_exec_state(
+        &self,
+        multi_engine: &MultiEngine,
+        height: ChainEpoch,
+        mut state_params: FvmStateParams,
+        proposer: tendermint::account::Id,
+        block_hash: Option<Vec<u8>>,
+        tracing: bool,
+    ) -> Result<FvmExecState<SS>> {
+        let db = self.state_store_clone();
+        let upgrade = self.upgrades.get(height);
+
+        if let Some(upgrade) = upgrade {
+            // The upgrade writes straight to the store, so same as during commit,
+            // the garbage collector must know about the new root.
+            let _guard = match self.gc {
+                Some(ref gc) => Some(gc.lock().await),
+                None => None,
+            };
+
+            state_params = upgrade.apply(&db, state_params).await?;
+
+            if let Some(ref gc) = self.gc {
+                gc.committed(state_params.state_root);
             }
         }
-        let state = self.committed_state()?;
-        Ok((state.state_params, state.block_height))
+
+        let mut state =
+            FvmExecState::new_with_tracing(db, multi_engine, height, state_params, tracing)
+                .context("error creating new state")?
+                .with_block_producer(proposer.into());
+
+        if let Some(hash) = block_hash {
+            state = state.with_block_hash(hash);
+        }
+
+        // Rewards are paid to the account of the validator who proposed the block.
+        let validators = state.validators().context("failed to get validators")?;
+
+        match to_validator_address(&validators, &proposer)? {
+            Some(addr) => state = state.with_block_producer_addr(addr),
+            None => tracing::warn!(
+                proposer = proposer.to_string(),
+                "block proposer not in the validator set"
+            ),
+        }
+
+        if let Some(upgrade) = upgrade {
+            upgrade.migrate(&mut state)?;
+        }
+
+        Ok(state)
+    }
+
+    /// Recreate the state committed at a height which is no longer retained, by replaying
+    /// the archived blocks on top of the nearest state retained or recently recreated before it.
+    ///
+    /// Returns `false` if the state can't be recreated because the blocks or the starting
+    /// state aren't available, e.g. because the node was bootstrapped from a snapshot.
+    ///
+    /// The last few recreated states are kept from garbage collection, so that repeated
+    /// queries at the same heights don't have to replay the blocks again.
+    async fn replay_state(&self, block_height: BlockHeight) -> Result<bool> {
+        if self.archive_interval == 0 {
+            return Ok(false);
+        }
+
+        // Another query might be recreating the same state; wait for it to finish.
+        let _guard = self.replay_lock.lock().await;
+
+        let oldest_state_height = self.committed_state()?.oldest_state_height;
+
+        if block_height <= oldest_state_height {
+            return Ok(false);
+        }
+
+        let get_params = |height: BlockHeight| -> Result<FvmStateParams> {
+            let tx = self.db.read();
+            self.state_hist
+                .get(&tx, &height)
+                .context("error looking up history")?
+                .ok_or_else(|| anyhow!("no state history at height {height}"))
+        };
+
+        let target_root = get_params(block_height)?.state_root;
+
+        if self.state_store.has(&target_root)? {
+            return Ok(true);
+        }
+
+        let checkpoint_height = std::cmp::max(
+            block_height - block_height % self.archive_interval,
+            oldest_state_height,
+        );
+
+        // Carry on from a recently recreated state if it's closer than the checkpoint.
+        let replayed_height = self
+            .replayed
+            .lock()
+            .expect("mutex poisoned")
+            .iter()
+            .map(|(h, _)| *h)
+            .filter(|h| *h > checkpoint_height && *h < block_height)
+            .max();
+
+        let start_height = match replayed_height {
+            Some(h) if self.state_store.has(&get_params(h)?.state_root)? => h,
+            _ => checkpoint_height,
+        };
+
+        let mut state_params = get_params(start_height)?;
+
+        if !self.state_store.has(&state_params.state_root)? {
+            return Ok(false);
+        }
+
+        tracing::info!(
+            block_height,
+            start_height,
+            "replaying blocks to recreate state"
+        );
+
+        for height in start_height + 1..=block_height {
+            let block = {
+                let tx = self.db.read();
+                self.archive
+                    .get(&tx, &height)
+                    .context("error looking up archived block")?
+            };
+
+            let block = match block {
+                Some(block) => block,
+                None => return Ok(false),
+            };
+
+            let committed = get_params(height)?;
+            state_params.timestamp = committed.timestamp;
+
+            let proposer = tendermint::account::Id::try_from(Vec::from(block.proposer))
+                .context("invalid archived proposer address")?;
+
+            let state = self
+                .block_exec_state(
+                    self.query_pool.multi_engine().as_ref(),
+                    height.try_into()?,
+                    state_params,
+                    proposer,
+                    block.block_hash.map(Vec::from),
+                    false,
+                )
+                .await?;
+
+            let (mut state, _) = self.replay_interpreter.begin(state).await?;
+            for msg in block.txs {
+                (state, _) = self.replay_interpreter.deliver(state, msg.into()).await?;
+            }
+            let (state, _) = self.replay_interpreter.end(state).await?;
+
+            let state_root = {
+                // Same as during commit, the garbage collector must know about the new root.
+                let _guard = match self.gc {
+                    Some(ref gc) => Some(gc.lock().await),
+                    None => None,
+                };
+
+                let state_root = state.commit().context("failed to commit replayed state")?;
+
+                if let Some(ref gc) = self.gc {
+                    gc.committed(state_root);
+                }
+
+                state_root
+            };
+
+            if state_root != committed.state_root {
+                return Err(anyhow!(
+                    "replaying block {height} resulted in state root {state_root} instead of {}",
+                    committed.state_root
+                ));
+            }
+
+            state_params = committed;
+        }
+
+        self.remember_replayed(block_height, target_root);
+
+        Ok(true)
+    }
+
+    /// Re-execute a transaction of an archived block with execution tracing enabled, after
+    /// replaying everything before it in the block on top of the state of the previous block.
+    ///
+    /// Nothing is committed; the effects of the replay are discarded along with the state.
+    async fn trace_tx(&self, block_height: BlockHeight, index: u64) -> Result<response::Query> {
+        let not_available = |info: String| Ok(invalid_query(AppError::StateNotAvailable, info));
+
+        let block = {
+            let tx = self.db.read();
+            self.archive
+                .get(&tx, &block_height)
+                .context("error looking up archived block")?
+        };
+
+        let block = match block {
+            Some(block) if block_height > 0 => block,
+            _ => return not_available(format!("Block {block_height} is not archived.")),
+        };
+
+        let index = usize::try_from(index)?;
+
+        if index >= block.txs.len() {
+            return not_available(format!(
+                "Transaction {index} not found in block {block_height}."
+            ));
+        }
+
+        let get_params = |height: BlockHeight| -> Result<Option<FvmStateParams>> {
+            let tx = self.db.read();
+            self.state_hist
+                .get(&tx, &height)
+                .context("error looking up history")
+        };
+
+        let parent_height = block_height - 1;
+
+        let (mut state_params, committed) =
+            match (get_params(parent_height)?, get_params(block_height)?) {
+                (Some(parent), Some(committed)) => (parent, committed),
+                _ => {
+                    return not_available(format!(
+                        "The state at height {parent_height} is not available."
+                    ))
+                }
+            };
+
+        if !self.state_store.has(&state_params.state_root)?
+            && !self.replay_state(parent_height).await?
+        {
+            return not_available(format!(
+                "The state at height {parent_height} is not available."
+            ));
+        }
+
+        state_params.timestamp = committed.timestamp;
+
+        let proposer = tendermint::account::Id::try_from(Vec::from(block.proposer))
+            .context("invalid archived proposer address")?;
+
+        // Tracing is a query like any other, so it waits for its turn.
+        let _permit = self.query_pool.acquire().await?;
+
+        let state = self
+            .block_exec_state(
+                self.query_pool.multi_engine().as_ref(),
+                block_height.try_into()?,
+                state_params,
+                proposer,
+                block.block_hash.map(Vec::from),
+                true,
+            )
+            .await?;
+
+        let mut txs = block.txs.into_iter();
+
+        let (mut state, _) = self.replay_interpreter.begin(state).await?;
+        for msg in txs.by_ref().take(index) {
+            (state, _) = self.replay_interpreter.deliver(state, msg.into()).await?;
+        }
+
+        let msg = txs.next().expect("index checked");
+        let (_, ret) = self.replay_interpreter.deliver(state, msg.into()).await?;
+
+        let apply_ret = match ret {
+            Ok(ChainMessageApplyRet::Signed(Ok(SignedApplyRet { ret, .. })))
+            | Ok(ChainMessageApplyRet::ForExecution(Ok(ret))) => ret.apply_ret,
+            _ => {
+                return Ok(invalid_query(
+                    AppError::IllegalMessage,
+                    format!(
+                        "Transaction {index} in block {block_height} did not execute a message."
+                    ),
+                ))
+            }
+        };
+
+        to_query(
+            FvmQueryRet::Trace(Box::new(to_trace_result(apply_ret))),
+            block_height,
+        )
+    }
+}
+
+/// Blocks before the oldest retained state can't be queried any more, so CometBFT can prune them too,
+/// except the ones after the oldest snapshot we offer, which peers restoring from it need to catch up.
+fn retain_height(
+    state_hist_size: u64,
+    oldest_state_height: BlockHeight,
+    oldest_snapshot_height: Option<BlockHeight>,
+) -> BlockHeight {
+    if state_hist_size == 0 {
+        return 0;
+    }
+    match oldest_snapshot_height {
+        Some(h) => h.min(oldest_state_height),
+        None => oldest_state_height,
     }
 }
 
@@ -304,7 +841,9 @@ where
         + Codec<AppState>
         + Encode<AppStoreKey>
         + Encode<BlockHeight>
-        + Codec<FvmStateParams>,
+        + Codec<FvmStateParams>
+        + Codec<ArchivedBlock>
+        + 'static,
     S::Namespace: Sync + Send,
     DB: KVWritable<S> + KVReadable<S> + Clone + Send + Sync + 'static,
     SS: Blockstore + Clone + Send + Sync + 'static,
@@ -313,7 +852,7 @@ where
         Message = Vec<u8>,
         BeginOutput = FvmApplyRet,
         DeliverOutput = BytesMessageApplyRet,
-        EndOutput = (),
+        EndOutput = Vec<Validator>,
     >,
     I: CheckInterpreter<
         State = FvmCheckState<SS>,
@@ -325,11 +864,13 @@ where
         Query = BytesMessageQuery,
         Output = BytesMessageQueryRet,
     >,
+    I: ProposalInterpreter<State = ChainID, Message = Vec<u8>>,
     I: GenesisInterpreter<
         State = FvmGenesisState<SS>,
         Genesis = Vec<u8>,
         Output = FvmGenesisOutput,
     >,
+    I: 'static,
 {
     /// Provide information about the ABCI application.
     async fn info(&self, _request: request::Info) -> AbciResult<response::Info> {
@@ -381,6 +922,8 @@ where
                 timestamp: out.timestamp,
                 network_version: out.network_version,
                 base_fee: out.base_fee,
+                base_fee_params: out.base_fee_params,
+                block_reward: out.block_reward,
                 circ_supply: out.circ_supply,
                 chain_id: out.chain_id.into(),
             },
@@ -398,15 +941,34 @@ where
             "init chain"
         );
 
-        self.set_committed_state(app_state)?;
+        self.set_committed_state(app_state, None)?;
 
         Ok(response)
     }
 
     /// Query the application for data at the current or past height.
     async fn query(&self, request: request::Query) -> AbciResult<response::Query> {
-        let db = self.state_store_clone();
-        let (state_params, block_height) = self.state_params_at_height(request.height)?;
+        // Tracing a transaction needs the block it's in, which the query interpreter doesn't have.
+        if let Ok(FvmQuery::TraceTx(index)) = fvm_ipld_encoding::from_slice(&request.data) {
+            let block_height = match request.height.value() {
+                0 => self.committed_state()?.block_height,
+                h => h,
+            };
+            return Ok(self.trace_tx(block_height, index).await?);
+        }
+
+        let (state_params, block_height) = match self.state_params_at_height(request.height)? {
+            Some(params) => params,
+            None => {
+                return Ok(invalid_query(
+                    AppError::StateNotAvailable,
+                    format!(
+                        "The state at height {} is not available.",
+                        request.height.value()
+                    ),
+                ))
+            }
+        };
 
         tracing::info!(
             query_height = request.height.value(),
@@ -423,22 +985,51 @@ where
             ));
         }
 
-        let state = FvmQueryState::new(
-            db,
-            self.multi_engine.clone(),
-            block_height.try_into()?,
-            state_params,
-        )
-        .context("error creating query state")?;
+        // In archive mode the states between the retained ones have to be recreated first.
+        // Replays are done one at a time, without a permit, so they don't hold up other queries.
+        if !self.state_store.has(&state_params.state_root)?
+            && !self.replay_state(block_height).await?
+        {
+            return Ok(invalid_query(
+                AppError::StateNotAvailable,
+                format!("The state at height {block_height} is not available."),
+            ));
+        }
+
+        // Wait for our turn, so that queries can't starve block execution.
+        let _permit = self.query_pool.acquire().await?;
+
+        tracing::debug!(
+            queue_depth = self.query_pool.metrics().queue_depth(),
+            running = self.query_pool.metrics().running(),
+            "query permit acquired"
+        );
+
+        let state = match self
+            .query_pool
+            .take_state(block_height, &state_params.state_root)
+        {
+            Some(state) => state,
+            None => FvmQueryState::new(
+                self.state_store_clone(),
+                self.query_pool.multi_engine(),
+                block_height.try_into()?,
+                state_params,
+            )
+            .context("error creating query state")?,
+        };
 
         let qry = (request.path, request.data.to_vec());
 
-        let (_, result) = self
-            .interpreter
-            .query(state, qry)
+        // Run the query on a task of its own, so that concurrent queries can use multiple threads.
+        let interpreter = self.interpreter.clone();
+        let (state, result) = tokio::spawn(async move { interpreter.query(state, qry).await })
             .await
+            .context("query task failed")?
             .context("error running query")?;
 
+        self.query_pool.put_state(block_height, state);
+
         let response = match result {
             Err(e) => invalid_query(AppError::InvalidEncoding, e.description),
             Ok(result) => to_query(result, block_height)?,
@@ -456,8 +1047,14 @@ where
             None => {
                 let db = self.state_store_clone();
                 let state = self.committed_state()?;
-                FvmCheckState::new(db, state.state_root(), state.chain_id())
-                    .context("error creating check state")?
+                FvmCheckState::new(
+                    db,
+                    state.state_root(),
+                    state.chain_id(),
+                    state.state_params.base_fee,
+                    self.check_policy.clone(),
+                )
+                .context("error creating check state")?
             }
         };
 
@@ -488,9 +1085,55 @@ where
         Ok(response)
     }
 
+    /// Select the transactions to propose from the mempool, dropping the ones that would fail.
+    async fn prepare_proposal(
+        &self,
+        request: request::PrepareProposal,
+    ) -> AbciResult<response::PrepareProposal> {
+        let chain_id = self.committed_state()?.chain_id();
+        let txs = request.txs.into_iter().map(|tx| tx.to_vec()).collect();
+
+        let txs = self
+            .interpreter
+            .prepare(chain_id, txs)
+            .await
+            .context("failed to prepare proposal")?;
+
+        let txs = take_until_max_size(txs, request.max_tx_bytes.try_into().unwrap());
+
+        Ok(response::PrepareProposal {
+            txs: txs.into_iter().map(Bytes::from).collect(),
+        })
+    }
+
+    /// Only vote for proposals where every transaction can be delivered.
+    async fn process_proposal(
+        &self,
+        request: request::ProcessProposal,
+    ) -> AbciResult<response::ProcessProposal> {
+        let chain_id = self.committed_state()?.chain_id();
+        let txs = request.txs.into_iter().map(|tx| tx.to_vec()).collect();
+
+        let accept = self
+            .interpreter
+            .process(chain_id, txs)
+            .await
+            .context("failed to process proposal")?;
+
+        if accept {
+            Ok(response::ProcessProposal::Accept)
+        } else {
+            tracing::warn!(
+                height = request.height.value(),
+                proposer = request.proposer_address.to_string(),
+                "rejected proposal"
+            );
+            Ok(response::ProcessProposal::Reject)
+        }
+    }
+
     /// Signals the beginning of a new block, prior to any `DeliverTx` calls.
     async fn begin_block(&self, request: request::BeginBlock) -> AbciResult<response::BeginBlock> {
-        let db = self.state_store_clone();
         let height = request.header.height.into();
         let state = self.committed_state()?;
         let mut state_params = state.state_params.clone();
@@ -498,8 +1141,32 @@ where
 
         tracing::debug!(height, "begin block");
 
-        let state = FvmExecState::new(db, self.multi_engine.as_ref(), height, state_params)
-            .context("error creating new state")?;
+        let proposer = request.header.proposer_address;
+
+        let block_hash = match request.hash {
+            tendermint::Hash::Sha256(hash) => Some(hash.to_vec()),
+            tendermint::Hash::None => None,
+        };
+
+        if self.archive_interval > 0 {
+            let block = ArchivedBlock {
+                proposer: RawBytes::new(proposer.as_bytes().to_vec()),
+                block_hash: block_hash.clone().map(RawBytes::new),
+                txs: Vec::new(),
+            };
+            *self.archived_block.lock().expect("mutex poisoned") = Some(block);
+        }
+
+        let state = self
+            .block_exec_state(
+                self.multi_engine.as_ref(),
+                height,
+                state_params,
+                proposer,
+                block_hash,
+                false,
+            )
+            .await?;
 
         tracing::debug!("initialized exec state");
 
@@ -516,6 +1183,11 @@ where
     /// Apply a transaction to the application's state.
     async fn deliver_tx(&self, request: request::DeliverTx) -> AbciResult<response::DeliverTx> {
         let msg = request.tx.to_vec();
+
+        if let Some(block) = self.archived_block.lock().expect("mutex poisoned").as_mut() {
+            block.txs.push(RawBytes::new(msg.clone()));
+        }
+
         let result = self
             .modify_exec_state(|s| self.interpreter.deliver(s, msg))
             .await
@@ -527,13 +1199,36 @@ where
                 ChainMessageApplyRet::Signed(Err(InvalidSignature(d))) => {
                     invalid_deliver_tx(AppError::InvalidSignature, d)
                 }
-                ChainMessageApplyRet::Signed(Ok(ret)) => {
+                ChainMessageApplyRet::Signed(Ok(SignedApplyRet { ret, eth_hash })) => {
                     tracing::info!(
                         from = ret.from.to_string(),
                         to = ret.to.to_string(),
                         method_num = ret.method_num,
                         "tx delivered"
                     );
+                    let mut response = to_deliver_tx(ret);
+
+                    // Messages created from Ethereum transactions are indexed by the hash of the transaction,
+                    // so that the Ethereum API can look them up by the hash the client knows them by.
+                    if let Some(hash) = eth_hash {
+                        response.events.push(to_eth_hash_event(&hash));
+                    }
+                    response
+                }
+                ChainMessageApplyRet::ForResolution(cid) => {
+                    tracing::info!(cid = cid.to_string(), "CID proposed for resolution");
+                    Default::default()
+                }
+                ChainMessageApplyRet::ForExecution(Err(IllegalExecution(d))) => {
+                    invalid_deliver_tx(AppError::IllegalMessage, d)
+                }
+                ChainMessageApplyRet::ForExecution(Ok(ret)) => {
+                    tracing::info!(
+                        from = ret.from.to_string(),
+                        to = ret.to.to_string(),
+                        method_num = ret.method_num,
+                        "resolved message executed"
+                    );
                     to_deliver_tx(ret)
                 }
             },
@@ -552,7 +1247,9 @@ where
             .await
             .context("end failed")?;
 
-        Ok(to_end_block(ret))
+        let response = to_end_block(ret).context("failed to convert validator updates")?;
+
+        Ok(response)
     }
 
     /// Commit the current state at the current height.
@@ -562,29 +1259,555 @@ where
         let mut state = self.committed_state()?;
         state.block_height = exec_state.block_height().try_into()?;
         state.state_params.timestamp = exec_state.timestamp();
-        state.state_params.state_root = exec_state.commit().context("failed to commit FVM")?;
+        state.state_params.network_version = exec_state.network_version();
+        state.state_params.circ_supply += exec_state.minted();
+
+        // The base fee of the next block depends on how congested this one was.
+        if let Some(ref params) = state.state_params.base_fee_params {
+            state.state_params.base_fee = next_base_fee(
+                &state.state_params.base_fee,
+                exec_state.block_gas_used(),
+                params,
+            );
+        }
 
-        let state_root = state.state_root();
+        let block_height = state.block_height;
 
-        tracing::debug!(
-            state_root = state_root.to_string(),
-            timestamp = state.state_params.timestamp.0,
-            "commit state"
-        );
+        let archived_block = self.archived_block.lock().expect("mutex poisoned").take();
+
+        let (state_root, state_params) = {
+            // The garbage collector must not sweep while blocks are flushed but the new root isn't recorded yet.
+            let _guard = match self.gc {
+                Some(ref gc) => Some(gc.lock().await),
+                None => None,
+            };
+
+            state.state_params.state_root = exec_state.commit().context("failed to commit FVM")?;
+
+            let state_root = state.state_root();
+            let state_params = state.state_params.clone();
+
+            tracing::debug!(
+                state_root = state_root.to_string(),
+                timestamp = state.state_params.timestamp.0,
+                base_fee = state.state_params.base_fee.to_string(),
+                "commit state"
+            );
+
+            self.set_committed_state(state, archived_block.as_ref())?;
+
+            if let Some(ref gc) = self.gc {
+                gc.committed(state_root);
+            }
+
+            (state_root, state_params)
+        };
 
-        self.set_committed_state(state)?;
+        // Pruning might have moved the oldest retained height.
+        let state = self.committed_state()?;
+
+        // Let the snapshot manager decide whether this state should be exported;
+        // if so, the GC has to keep it until the export is finished.
+        if let Some(ref snapshots) = self.snapshots {
+            snapshots.notify(block_height, state_params);
+        }
 
-        // Reset check state.
+        if let Some(ref gc) = self.gc {
+            if gc.is_due(block_height) {
+                gc.collect(self.retained_state_roots(&state)?);
+            }
+        }
+
+        // Move the check state on top of the committed state, keeping the transactions
+        // still in the mempool, so they can be rechecked against what has changed.
         let mut guard = self.check_state.lock().await;
-        *guard = None;
+        if let Some(check_state) = guard.as_mut() {
+            if let Err(e) =
+                check_state.rebase(state.state_root(), state.state_params.base_fee.clone())
+            {
+                tracing::warn!(error = e.to_string(), "failed to rebase check state");
+                *guard = None;
+            }
+        }
 
         tracing::debug!("committed state");
 
+        let retain_height = self.retain_height(&state)?.try_into()?;
+
         let response = response::Commit {
             data: state_root.to_bytes().into(),
-            // We have to retain blocks until we can support Snapshots.
-            retain_height: Default::default(),
+            retain_height,
         };
         Ok(response)
     }
+
+    /// Used during state sync to discover available snapshots on peers.
+    async fn list_snapshots(&self) -> AbciResult<response::ListSnapshots> {
+        let snapshots = match self.snapshots {
+            Some(ref snapshots) => snapshots.list_snapshots()?,
+            None => Vec::new(),
+        };
+        let snapshots = snapshots
+            .into_iter()
+            .map(to_snapshot)
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(response::ListSnapshots { snapshots })
+    }
+
+    /// Used during state sync to retrieve chunks of snapshots from peers.
+    async fn load_snapshot_chunk(
+        &self,
+        request: request::LoadSnapshotChunk,
+    ) -> AbciResult<response::LoadSnapshotChunk> {
+        if let Some(ref snapshots) = self.snapshots {
+            if let Some(item) = snapshots.access_snapshot(request.height.value(), request.format)? {
+                if let Some(chunk) = item.load_chunk(request.chunk)? {
+                    return Ok(response::LoadSnapshotChunk {
+                        chunk: chunk.into(),
+                    });
+                }
+            }
+        }
+        Ok(Default::default())
+    }
+
+    /// Called when bootstrapping the node using state sync.
+    async fn offer_snapshot(
+        &self,
+        request: request::OfferSnapshot,
+    ) -> AbciResult<response::OfferSnapshot> {
+        let snapshots = match self.snapshots {
+            Some(ref snapshots) => snapshots,
+            None => {
+                tracing::warn!("snapshots are disabled; cannot restore from a snapshot");
+                return Ok(response::OfferSnapshot::Abort);
+            }
+        };
+
+        // State sync is only meant to be used on a node which hasn't got any state yet.
+        if self.committed_state()?.block_height > 0 {
+            tracing::warn!("the application already has state; cannot restore from a snapshot");
+            return Ok(response::OfferSnapshot::Abort);
+        }
+
+        let snapshot = request.snapshot;
+
+        if snapshot.format != SNAPSHOT_FORMAT {
+            return Ok(response::OfferSnapshot::RejectFormat);
+        }
+
+        let manifest = match fvm_ipld_encoding::from_slice::<SnapshotManifest>(&snapshot.metadata) {
+            Ok(manifest) => manifest,
+            Err(e) => {
+                tracing::warn!(error = e.to_string(), "failed to decode snapshot manifest");
+                return Ok(response::OfferSnapshot::Reject);
+            }
+        };
+
+        // Check that the manifest agrees with the snapshot, and that the state in it
+        // is the one the light client verified against the headers of the chain.
+        if manifest.block_height != snapshot.height.value()
+            || manifest.chunks != snapshot.chunks
+            || manifest.checksum.as_slice() != snapshot.hash.as_ref()
+            || to_app_hash(&manifest.state_params) != request.app_hash
+        {
+            tracing::warn!(
+                block_height = snapshot.height.value(),
+                "snapshot manifest doesn't match the offer"
+            );
+            return Ok(response::OfferSnapshot::Reject);
+        }
+
+        tracing::info!(
+            block_height = manifest.block_height,
+            state_root = manifest.state_params.state_root.to_string(),
+            chunks = manifest.chunks,
+            "accepted snapshot"
+        );
+
+        let restore = SnapshotRestore::new(&snapshots.download_dir(), manifest)?;
+
+        let mut guard = self.snapshot_restore.lock().await;
+        *guard = Some(restore);
+
+        Ok(response::OfferSnapshot::Accept)
+    }
+
+    /// Called when bootstrapping the node using state sync.
+    async fn apply_snapshot_chunk(
+        &self,
+        request: request::ApplySnapshotChunk,
+    ) -> AbciResult<response::ApplySnapshotChunk> {
+        let mut guard = self.snapshot_restore.lock().await;
+
+        let restore = match guard.as_mut() {
+            Some(restore) => restore,
+            None => return Ok(to_apply_snapshot_chunk(ApplySnapshotChunkResult::Abort)),
+        };
+
+        let result = match restore.append_chunk(request.index, &request.chunk) {
+            Ok(false) => ApplySnapshotChunkResult::Accept,
+            Ok(true) => {
+                let restore = guard.take().expect("restore is in progress");
+                drop(guard);
+
+                // Importing and verifying the whole state takes a while, so don't block the runtime.
+                let state_store = self.state_store.clone();
+                let res = tokio::task::spawn_blocking(move || restore.finish(state_store.as_ref()))
+                    .await
+                    .context("snapshot restore panicked")?;
+
+                match res {
+                    Ok(manifest) => {
+                        let block_height = manifest.block_height;
+                        let state_root = manifest.state_params.state_root;
+
+                        self.set_committed_state(
+                            AppState {
+                                block_height,
+                                oldest_state_height: block_height,
+                                state_params: manifest.state_params,
+                            },
+                            None,
+                        )?;
+
+                        tracing::info!(
+                            block_height,
+                            state_root = state_root.to_string(),
+                            "restored snapshot"
+                        );
+
+                        ApplySnapshotChunkResult::Accept
+                    }
+                    Err(
+                        e @ (SnapshotRestoreError::ChecksumMismatch
+                        | SnapshotRestoreError::StateRootMismatch(_, _)
+                        | SnapshotRestoreError::Incomplete(_)),
+                    ) => {
+                        tracing::warn!(error = e.to_string(), "rejecting snapshot");
+                        ApplySnapshotChunkResult::RejectSnapshot
+                    }
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            Err(SnapshotRestoreError::UnexpectedChunk(index, expected)) => {
+                tracing::warn!(index, expected, "unexpected snapshot chunk");
+                ApplySnapshotChunkResult::RetrySnapshot
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(to_apply_snapshot_chunk(result))
+    }
+}
+
+/// Take transactions as long as their total size fits into the limit.
+fn take_until_max_size(txs: Vec<Vec<u8>>, max_tx_bytes: usize) -> Vec<Vec<u8>> {
+    let mut size: usize = 0;
+    let mut selected = Vec::new();
+    for tx in txs {
+        if size.saturating_add(tx.len()) > max_tx_bytes {
+            break;
+        }
+        size += tx.len();
+        selected.push(tx);
+    }
+    selected
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::{Arc, RwLock};
+    use std::time::Duration;
+
+    use cid::Cid;
+    use fendermint_storage::im::InMemoryBackend;
+    use fendermint_storage::KVReadable;
+    use fendermint_vm_core::Timestamp;
+    use fendermint_vm_genesis::Genesis;
+    use fendermint_vm_interpreter::bytes::BytesMessageInterpreter;
+    use fendermint_vm_interpreter::chain::ChainMessageInterpreter;
+    use fendermint_vm_interpreter::fvm::bundle::{bundle_path, genesis_state};
+    use fendermint_vm_interpreter::fvm::state::CheckPolicy;
+    use fendermint_vm_interpreter::fvm::upgrades::UpgradeScheduler;
+    use fendermint_vm_interpreter::fvm::FvmMessageInterpreter;
+    use fendermint_vm_interpreter::signed::SignedMessageInterpreter;
+    use fendermint_vm_interpreter::ExecInterpreter;
+    use fendermint_vm_resolver::{LocalDirResolver, ResolvePool};
+    use fvm_ipld_blockstore::Blockstore;
+    use fvm_ipld_encoding::RawBytes;
+    use fvm_shared::econ::TokenAmount;
+    use quickcheck::Arbitrary;
+    use tendermint::block::Height;
+
+    use crate::AppStore;
+
+    use super::{
+        retain_height, App, AppConfig, AppError, AppState, ArchivedBlock, REPLAY_CACHE_SIZE,
+    };
+
+    /// Blockstore which can be shared between threads, like the database.
+    #[derive(Default, Clone)]
+    struct MemoryBlockstore {
+        blocks: Arc<RwLock<HashMap<Cid, Vec<u8>>>>,
+    }
+
+    impl Blockstore for MemoryBlockstore {
+        fn has(&self, k: &Cid) -> anyhow::Result<bool> {
+            Ok(self.blocks.read().unwrap().contains_key(k))
+        }
+
+        fn get(&self, k: &Cid) -> anyhow::Result<Option<Vec<u8>>> {
+            Ok(self.blocks.read().unwrap().get(k).cloned())
+        }
+
+        fn put_keyed(&self, k: &Cid, block: &[u8]) -> anyhow::Result<()> {
+            self.blocks.write().unwrap().insert(*k, block.into());
+            Ok(())
+        }
+    }
+
+    type TestInterpreter = BytesMessageInterpreter<
+        ChainMessageInterpreter<
+            SignedMessageInterpreter<FvmMessageInterpreter<MemoryBlockstore>>,
+            LocalDirResolver,
+        >,
+    >;
+
+    type TestApp<I> = App<InMemoryBackend<AppStore>, MemoryBlockstore, AppStore, I>;
+
+    /// Create an interpreter to execute blocks with, and one to replay them with.
+    fn interpreters() -> (TestInterpreter, TestInterpreter) {
+        let interpreter = FvmMessageInterpreter::new();
+        let interpreter = SignedMessageInterpreter::new(interpreter);
+        let resolve_pool = ResolvePool::new(
+            LocalDirResolver::new(std::env::temp_dir()),
+            Duration::from_secs(1),
+        );
+        let interpreter = ChainMessageInterpreter::new(interpreter, resolve_pool);
+        let replay_interpreter = interpreter.for_replay();
+        (
+            BytesMessageInterpreter::new(interpreter),
+            BytesMessageInterpreter::new(replay_interpreter),
+        )
+    }
+
+    fn new_app<I>(
+        db: InMemoryBackend<AppStore>,
+        store: MemoryBlockstore,
+        (interpreter, replay_interpreter): (I, I),
+        state_hist_size: u64,
+        archive_interval: u64,
+    ) -> TestApp<I> {
+        let config = AppConfig {
+            app_namespace: "app".to_owned(),
+            state_hist_namespace: "state_hist".to_owned(),
+            state_hist_size,
+            archive_namespace: "archive".to_owned(),
+            archive_interval,
+            builtin_actors_bundle: bundle_path(),
+            check_policy: CheckPolicy {
+                min_gas_fee_cap: TokenAmount::from_atto(0),
+                max_pending_per_sender: 0,
+                max_nonce_gap: 0,
+                max_pending_blocks: 0,
+            },
+            query_engines: 1,
+            query_max_concurrent: 1,
+            query_cache_size: 0,
+        };
+        App::new(
+            config,
+            db,
+            store,
+            interpreter,
+            replay_interpreter,
+            None,
+            None,
+            UpgradeScheduler::new(),
+        )
+        .expect("failed to create app")
+    }
+
+    /// Execute and commit an empty block, archiving it along the way.
+    async fn execute_block(app: &TestApp<TestInterpreter>, block_height: u64) {
+        let proposer = tendermint::account::Id::new([1u8; 20]);
+        let mut state = app.committed_state().unwrap();
+        state.state_params.timestamp = Timestamp(block_height);
+
+        let exec_state = app
+            .block_exec_state(
+                app.multi_engine.as_ref(),
+                block_height.try_into().unwrap(),
+                state.state_params.clone(),
+                proposer,
+                None,
+                false,
+            )
+            .await
+            .expect("failed to create exec state");
+
+        let (exec_state, _) = app.interpreter.begin(exec_state).await.unwrap();
+        let (exec_state, _) = app.interpreter.end(exec_state).await.unwrap();
+
+        state.block_height = block_height;
+        state.state_params.state_root = exec_state.commit().expect("failed to commit");
+
+        let block = ArchivedBlock {
+            proposer: RawBytes::new(proposer.as_bytes().to_vec()),
+            block_hash: None,
+            txs: Vec::new(),
+        };
+
+        app.set_committed_state(state, Some(&block)).unwrap();
+    }
+
+    fn height(h: u64) -> Height {
+        Height::try_from(h).unwrap()
+    }
+
+    #[test]
+    fn state_not_available_outside_history() {
+        let db = InMemoryBackend::<AppStore>::default();
+        let app = new_app(db, MemoryBlockstore::default(), ((), ()), 2, 0);
+
+        for h in 1..=5 {
+            let mut state = app.committed_state().unwrap();
+            state.block_height = h;
+            state.state_params.timestamp = Timestamp(h);
+            app.set_committed_state(state, None).unwrap();
+        }
+
+        let at = |h| {
+            app.state_params_at_height(height(h))
+                .unwrap()
+                .map(|(p, h)| (p.timestamp.0, h))
+        };
+
+        // Zero means the latest state.
+        assert_eq!(at(0), Some((5, 5)));
+        assert_eq!(at(5), Some((5, 5)));
+        assert_eq!(at(4), Some((4, 4)));
+        // Pruned.
+        assert_eq!(at(3), None);
+        assert_eq!(at(2), None);
+        assert_eq!(at(1), None);
+        // Not committed yet.
+        assert_eq!(at(6), None);
+    }
+
+    #[test]
+    fn retain_height_within_history() {
+        let db = InMemoryBackend::<AppStore>::default();
+        let app = new_app(db.clone(), MemoryBlockstore::default(), ((), ()), 3, 0);
+
+        for h in 1..=5 {
+            let mut state = app.committed_state().unwrap();
+            state.block_height = h;
+            app.set_committed_state(state, None).unwrap();
+        }
+
+        let state = app.committed_state().unwrap();
+        assert_eq!(app.retain_height(&state).unwrap(), 3);
+
+        // Without a history window everything is kept.
+        let app = new_app(db, MemoryBlockstore::default(), ((), ()), 0, 0);
+        let state = app.committed_state().unwrap();
+        assert_eq!(app.retain_height(&state).unwrap(), 0);
+    }
+
+    #[test]
+    fn retain_height_keeps_blocks_after_snapshots() {
+        // Nothing is pruned without a history window, regardless of snapshots.
+        assert_eq!(retain_height(0, 10, None), 0);
+        assert_eq!(retain_height(0, 10, Some(5)), 0);
+        // Otherwise the oldest retained state decides.
+        assert_eq!(retain_height(5, 10, None), 10);
+        assert_eq!(retain_height(5, 10, Some(12)), 10);
+        // Unless a snapshot is older than that.
+        assert_eq!(retain_height(5, 10, Some(7)), 7);
+    }
+
+    #[tokio::test]
+    async fn replay_recreates_missing_states() {
+        let mut g = quickcheck::Gen::new(5);
+        let genesis = Genesis::arbitrary(&mut g);
+        let db = InMemoryBackend::<AppStore>::default();
+
+        // Execute blocks on a node which keeps every state.
+        let store = MemoryBlockstore::default();
+        let app = new_app(db.clone(), store.clone(), interpreters(), 0, 100);
+
+        let genesis_params = genesis_state(store.clone(), genesis.clone()).await;
+        app.set_committed_state(
+            AppState {
+                block_height: 1,
+                oldest_state_height: 1,
+                state_params: genesis_params.clone(),
+            },
+            None,
+        )
+        .unwrap();
+
+        for h in 2..=8 {
+            execute_block(&app, h).await;
+        }
+
+        {
+            let tx = db.read();
+            for h in 2..=8 {
+                assert!(app.archive.get(&tx, &h).unwrap().is_some());
+            }
+        }
+
+        let root_at = |h| {
+            app.state_params_at_height(height(h))
+                .unwrap()
+                .unwrap()
+                .0
+                .state_root
+        };
+
+        // Another node sharing the history, but only having the genesis state.
+        let replay_store = MemoryBlockstore::default();
+        let replay_params = genesis_state(replay_store.clone(), genesis.clone()).await;
+        assert_eq!(replay_params.state_root, genesis_params.state_root);
+
+        let replay_app = new_app(db, replay_store.clone(), interpreters(), 0, 100);
+
+        assert!(!replay_store.has(&root_at(5)).unwrap());
+        assert!(replay_app.replay_state(5).await.unwrap());
+        assert!(replay_store.has(&root_at(5)).unwrap());
+
+        // Carries on from the state replayed before.
+        assert!(replay_app.replay_state(7).await.unwrap());
+        assert!(replay_store.has(&root_at(7)).unwrap());
+
+        // Recreated states are kept from garbage collection.
+        let state = replay_app.committed_state().unwrap();
+        let retained = replay_app.retained_state_roots(&state).unwrap();
+        assert!(retained.contains(&root_at(5)));
+        assert!(retained.contains(&root_at(7)));
+
+        // Only a limited number of them.
+        for h in 0..REPLAY_CACHE_SIZE as u64 {
+            replay_app.remember_replayed(100 + h, Cid::default());
+        }
+        let retained = replay_app.retained_state_roots(&state).unwrap();
+        assert!(!retained.contains(&root_at(5)));
+
+        // The genesis state is the oldest, there's nothing to replay on top of.
+        assert!(!replay_app.replay_state(1).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn trace_tx_needs_archived_block() {
+        let db = InMemoryBackend::<AppStore>::default();
+        let app = new_app(db, MemoryBlockstore::default(), interpreters(), 0, 100);
+
+        let res = app.trace_tx(3, 0).await.unwrap();
+        assert_eq!(res.code.value(), AppError::StateNotAvailable as u32);
+    }
 }
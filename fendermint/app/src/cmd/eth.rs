@@ -1,6 +1,9 @@
 // Copyright 2022-2023 Protocol Labs
 // SPDX-License-Identifier: Apache-2.0, MIT
 
+use std::time::Duration;
+
+use fendermint_eth_api::{AccessConfig, ApiConfig, CorsOrigins, TokenAccess};
 use fendermint_rpc::client::http_client;
 use tendermint_rpc::HttpClient;
 
@@ -23,5 +26,27 @@ cmd! {
 
 /// Run the Ethereum
 async fn run(settings: EthSettings, client: HttpClient) -> anyhow::Result<()> {
-    fendermint_eth_api::listen(settings.http.addr(), client).await
+    let config = ApiConfig {
+        filter_timeout: Duration::from_secs(settings.filter_timeout_secs),
+        cors_origins: CorsOrigins(settings.cors_allowed_origins),
+        max_body_size: settings.max_body_size_bytes,
+        max_batch_len: settings.max_batch_len,
+        max_subscriptions: settings.max_subscriptions,
+        access: AccessConfig {
+            tokens: settings
+                .tokens
+                .into_iter()
+                .map(|t| TokenAccess {
+                    token: t.token,
+                    methods: t.methods,
+                })
+                .collect(),
+            jwt_secret: settings.jwt_secret.map(String::into_bytes),
+            rate_limit_window: Duration::from_secs(settings.rate_limit.window_secs),
+            max_requests_per_ip: settings.rate_limit.max_requests_per_ip,
+            max_requests_per_token: settings.rate_limit.max_requests_per_token,
+        },
+    };
+
+    fendermint_eth_api::listen(settings.http.addr(), settings.ws.addr(), client, config).await
 }
@@ -8,7 +8,8 @@ use std::path::PathBuf;
 
 use fendermint_vm_core::Timestamp;
 use fendermint_vm_genesis::{
-    Account, Actor, ActorMeta, Genesis, Multisig, Power, SignerAddr, Validator, ValidatorKey,
+    Account, Actor, ActorMeta, BaseFeeParams, Genesis, Multisig, Power, SignerAddr, Validator,
+    ValidatorKey,
 };
 
 use crate::cmd;
@@ -39,8 +40,14 @@ cmd! {
       chain_name: self.chain_name.clone(),
       network_version: self.network_version,
       base_fee: self.base_fee.clone(),
+      base_fee_params: self.gas_target.map(|gas_target| BaseFeeParams {
+        gas_target,
+        max_change_denominator: self.base_fee_max_change_denominator,
+      }),
+      block_reward: self.block_reward.clone(),
       validators: Vec::new(),
-      accounts: Vec::new()
+      accounts: Vec::new(),
+      validator_admin: self.validator_admin.map(SignerAddr),
     };
 
     let json = serde_json::to_string_pretty(&genesis)?;
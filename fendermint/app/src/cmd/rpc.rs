@@ -8,7 +8,7 @@ use std::pin::Pin;
 use anyhow::Context;
 use async_trait::async_trait;
 use bytes::Bytes;
-use fendermint_rpc::client::BoundFendermintClient;
+use fendermint_rpc::client::{BoundFendermintClient, TendermintClient};
 use fendermint_rpc::tx::{
     AsyncResponse, BoundClient, CallClient, CommitResponse, SyncResponse, TxAsync, TxClient,
     TxCommit, TxSync,
@@ -23,7 +23,7 @@ use serde::Serialize;
 use serde_json::json;
 use tendermint::abci::response::DeliverTx;
 use tendermint::block::Height;
-use tendermint_rpc::HttpClient;
+use tendermint_rpc::{Client, HttpClient};
 
 use fendermint_rpc::message::{GasParams, MessageFactory};
 use fendermint_rpc::{client::FendermintClient, query::QueryClient};
@@ -102,6 +102,17 @@ async fn query(
             let json = json!({ "response": res });
             print_json(&json)?;
         }
+        RpcQueryCommands::Validators => {
+            let res = client.validators(Some(height)).await?;
+            let json = json!({ "response": res });
+            print_json(&json)?;
+        }
+        RpcQueryCommands::Trace { tx_hash } => {
+            let tx = client.underlying().tx(tx_hash, false).await?;
+            let res = client.trace_tx(tx.height, tx.index as usize).await?;
+            let json = json!({ "response": res });
+            print_json(&json)?;
+        }
     };
     Ok(())
 }
@@ -1,14 +1,26 @@
 // Copyright 2022-2023 Protocol Labs
 // SPDX-License-Identifier: Apache-2.0, MIT
 
+use std::sync::Arc;
+use std::time::Duration;
+
 use anyhow::{anyhow, Context};
+use cid::Cid;
 use fendermint_abci::ApplicationService;
-use fendermint_app::{App, AppStore};
+use fendermint_app::{serve_metrics, App, AppConfig, AppStore, GcManager};
 use fendermint_rocksdb::{blockstore::NamespaceBlockstore, namespaces, RocksDb, RocksDbConfig};
 use fendermint_vm_interpreter::{
-    bytes::BytesMessageInterpreter, chain::ChainMessageInterpreter, fvm::FvmMessageInterpreter,
+    bytes::BytesMessageInterpreter,
+    chain::ChainMessageInterpreter,
+    fvm::state::CheckPolicy,
+    fvm::upgrades::{self, Upgrade, UpgradeScheduler},
+    fvm::FvmMessageInterpreter,
     signed::SignedMessageInterpreter,
 };
+use fendermint_vm_resolver::{LocalDirResolver, ResolvePool};
+use fendermint_vm_snapshot::SnapshotManager;
+use fvm_ipld_car::CarReader;
+use fvm_shared::{econ::TokenAmount, version::NetworkVersion};
 use tracing::info;
 
 use crate::{cmd, options::run::RunArgs, settings::Settings};
@@ -22,7 +34,12 @@ cmd! {
 async fn run(settings: Settings) -> anyhow::Result<()> {
     let interpreter = FvmMessageInterpreter::<NamespaceBlockstore>::new();
     let interpreter = SignedMessageInterpreter::new(interpreter);
-    let interpreter = ChainMessageInterpreter::new(interpreter);
+    let resolve_pool = ResolvePool::new(
+        LocalDirResolver::new(settings.resolver_dir()),
+        Duration::from_secs(settings.resolver.retry_delay_secs),
+    );
+    let interpreter = ChainMessageInterpreter::new(interpreter, resolve_pool);
+    let replay_interpreter = BytesMessageInterpreter::new(interpreter.for_replay());
     let interpreter = BytesMessageInterpreter::new(interpreter);
 
     let ns = Namespaces::default();
@@ -31,16 +48,105 @@ async fn run(settings: Settings) -> anyhow::Result<()> {
     let state_store =
         NamespaceBlockstore::new(db.clone(), ns.state_store).context("error creating state DB")?;
 
+    let snapshots = if settings.snapshots.enabled {
+        let (manager, client) = SnapshotManager::new(
+            state_store.clone(),
+            settings.snapshots_dir(),
+            settings.snapshots.block_interval,
+            settings.snapshots.hist_size,
+            settings.snapshots.chunk_size_bytes,
+        )
+        .context("failed to create snapshot manager")?;
+
+        tokio::spawn(manager.run());
+
+        Some(client)
+    } else {
+        info!("snapshots disabled");
+        None
+    };
+
+    let gc = if settings.db.state_hist_size > 0 && settings.db.gc_interval > 0 {
+        let (manager, client) = GcManager::new(
+            state_store.clone(),
+            settings.db.gc_interval,
+            bundle_roots(&settings).await,
+        )
+        .context("failed to create garbage collector")?;
+
+        tokio::spawn(manager.run());
+
+        Some(client)
+    } else {
+        info!("garbage collection disabled");
+        None
+    };
+
+    // Read the bundles up front, so a node with a broken schedule doesn't start at all,
+    // rather than halting at the upgrade height.
+    let migrations = upgrades::migrations();
+    let mut upgrade_scheduler = UpgradeScheduler::new();
+    for u in settings.upgrades() {
+        let network_version = NetworkVersion::new(u.network_version);
+        let bundle = match u.builtin_actors_bundle {
+            Some(path) => Some(Arc::new(upgrades::read_bundle(&path).await?)),
+            None => None,
+        };
+        upgrade_scheduler
+            .add(Upgrade {
+                block_height: u.block_height.try_into()?,
+                network_version,
+                bundle,
+                migration: migrations.get(network_version),
+            })
+            .context("invalid upgrade schedule")?;
+    }
+
     let app: App<_, _, AppStore, _> = App::new(
+        AppConfig {
+            app_namespace: ns.app,
+            state_hist_namespace: ns.state_hist,
+            state_hist_size: settings.db.state_hist_size,
+            archive_namespace: ns.archive,
+            archive_interval: settings.db.archive_interval,
+            builtin_actors_bundle: settings.builtin_actors_bundle(),
+            check_policy: CheckPolicy {
+                min_gas_fee_cap: TokenAmount::from_atto(settings.mempool.min_gas_fee_cap),
+                max_pending_per_sender: settings.mempool.max_pending_per_sender,
+                max_nonce_gap: settings.mempool.max_nonce_gap,
+                max_pending_blocks: settings.mempool.max_pending_blocks,
+            },
+            query_engines: settings.query.engines,
+            query_max_concurrent: settings.query.max_concurrent,
+            query_cache_size: settings.query.cache_size,
+        },
         db,
         state_store,
-        settings.builtin_actors_bundle(),
-        ns.app,
-        ns.state_hist,
-        settings.db.state_hist_size,
         interpreter,
+        replay_interpreter,
+        snapshots,
+        gc,
+        upgrade_scheduler,
     )?;
 
+    if settings.metrics.enabled {
+        let addr = settings
+            .metrics
+            .listen
+            .addr()
+            .parse()
+            .context("invalid metrics listen address")?;
+        let query_metrics = app.query_metrics();
+
+        tokio::spawn(async move {
+            if let Err(e) = serve_metrics(addr, query_metrics).await {
+                tracing::error!(error = e.to_string(), "metrics server stopped");
+            }
+        });
+    } else {
+        info!("metrics disabled");
+    }
+
     let service = ApplicationService(app);
 
     // Split it into components.
@@ -69,10 +175,33 @@ namespaces! {
     Namespaces {
         app,
         state_hist,
+        archive,
         state_store
     }
 }
 
+/// Roots of the actor bundle, which the garbage collector should keep.
+///
+/// The bundle is only needed for genesis, so it might not exist on a node which joined later.
+async fn bundle_roots(settings: &Settings) -> Vec<Cid> {
+    let path = settings.builtin_actors_bundle();
+    let roots = match std::fs::read(&path) {
+        Ok(bundle) => CarReader::new(bundle.as_slice())
+            .await
+            .map(|reader| reader.header.roots)
+            .map_err(|e| e.to_string()),
+        Err(e) => Err(e.to_string()),
+    };
+    roots.unwrap_or_else(|e| {
+        tracing::warn!(
+            path = path.to_string_lossy().into_owned(),
+            error = e,
+            "failed to read actor bundle roots"
+        );
+        Vec::new()
+    })
+}
+
 /// Open database with all
 fn open_db(settings: &Settings, ns: &Namespaces) -> anyhow::Result<RocksDb> {
     let path = settings.data_dir().join("rocksdb");
@@ -0,0 +1,273 @@
+// Copyright 2022-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+//! Mark-and-sweep garbage collection of the state store.
+//!
+//! Blocks are only ever added to the state store, but once the state history
+//! is pruned, anything which isn't reachable from the retained state roots
+//! can be removed.
+
+use std::sync::Arc;
+
+use anyhow::Context;
+use cid::Cid;
+use fendermint_rocksdb::blockstore::NamespaceBlockstore;
+use fendermint_vm_snapshot::DagWalk;
+use fvm_ipld_blockstore::Blockstore;
+use tokio::sync::{mpsc, Mutex, MutexGuard};
+
+/// Number of keys to delete while holding the lock.
+const SWEEP_BATCH_SIZE: usize = 1000;
+
+/// Blockstore operations needed by the garbage collector, beyond what the FVM uses.
+pub trait SweepBlockstore: Blockstore {
+    /// Visit every key in the store, as they were when the iteration started.
+    fn for_each_key(&self, f: &mut dyn FnMut(Cid) -> anyhow::Result<()>) -> anyhow::Result<()>;
+
+    /// Remove blocks from the store.
+    fn delete_many(&self, keys: &[Cid]) -> anyhow::Result<()>;
+}
+
+impl SweepBlockstore for NamespaceBlockstore {
+    fn for_each_key(&self, f: &mut dyn FnMut(Cid) -> anyhow::Result<()>) -> anyhow::Result<()> {
+        NamespaceBlockstore::for_each_key(self, f)
+    }
+
+    fn delete_many(&self, keys: &[Cid]) -> anyhow::Result<()> {
+        NamespaceBlockstore::delete_many(self, keys)
+    }
+}
+
+enum GcEvent {
+    /// A new state root has been committed.
+    Committed(Cid),
+    /// Collect everything not reachable from the given state roots.
+    Collect(Vec<Cid>),
+}
+
+/// Interface for the application to the background [GcManager].
+#[derive(Clone)]
+pub struct GcClient {
+    events: mpsc::UnboundedSender<GcEvent>,
+    /// Held while blocks are flushed to the store and while the collector is deleting them,
+    /// so that blocks which become reachable again are not swept from under the new state.
+    lock: Arc<Mutex<()>>,
+    /// Run the garbage collection at every block height divisible by this number.
+    block_interval: u64,
+}
+
+impl GcClient {
+    /// Hold the lock while writing blocks to the store and notifying about the new root.
+    ///
+    /// The collector only holds it while deleting a batch of blocks, so waiting for it is short,
+    /// but it's an async lock so the runtime isn't blocked in the meantime.
+    pub async fn lock(&self) -> MutexGuard<()> {
+        self.lock.lock().await
+    }
+
+    /// Notify the collector about a new state root, which it has to consider reachable.
+    pub fn committed(&self, state_root: Cid) {
+        self.send(GcEvent::Committed(state_root))
+    }
+
+    /// Check whether a garbage collection should be started at this height.
+    pub fn is_due(&self, block_height: u64) -> bool {
+        block_height > 0 && block_height % self.block_interval == 0
+    }
+
+    /// Start a garbage collection, keeping everything reachable from the state roots.
+    pub fn collect(&self, state_roots: Vec<Cid>) {
+        self.send(GcEvent::Collect(state_roots))
+    }
+
+    fn send(&self, event: GcEvent) {
+        if self.events.send(event).is_err() {
+            tracing::warn!("garbage collector is no longer running");
+        }
+    }
+}
+
+/// Collects garbage from the state store in the background.
+pub struct GcManager<BS> {
+    store: BS,
+    events: mpsc::UnboundedReceiver<GcEvent>,
+    lock: Arc<Mutex<()>>,
+    /// Roots to keep regardless of the state, e.g. the actor bundle manifest.
+    extra_roots: Vec<Cid>,
+}
+
+impl<BS> GcManager<BS>
+where
+    BS: SweepBlockstore + Send + 'static,
+{
+    /// Create a new collector, and a client the application can use to notify it
+    /// about committed state roots and to trigger collections.
+    pub fn new(
+        store: BS,
+        block_interval: u64,
+        extra_roots: Vec<Cid>,
+    ) -> anyhow::Result<(Self, GcClient)> {
+        if block_interval == 0 {
+            return Err(anyhow::anyhow!("the GC block interval must be positive"));
+        }
+
+        let (tx, rx) = mpsc::unbounded_channel();
+        let lock = Arc::new(Mutex::new(()));
+
+        let manager = Self {
+            store,
+            events: rx,
+            lock: lock.clone(),
+            extra_roots,
+        };
+
+        let client = GcClient {
+            events: tx,
+            lock,
+            block_interval,
+        };
+
+        Ok((manager, client))
+    }
+
+    /// Run collections when requested, until all clients are dropped.
+    pub async fn run(mut self) {
+        while let Some(event) = self.events.recv().await {
+            let state_roots = match event {
+                GcEvent::Committed(_) => continue,
+                GcEvent::Collect(state_roots) => state_roots,
+            };
+
+            // The sweep needs to see the roots committed while it's running,
+            // so the whole manager moves to the blocking thread and comes back.
+            let res = tokio::task::spawn_blocking(move || {
+                let res = self.collect(state_roots);
+                (self, res)
+            })
+            .await;
+
+            self = match res {
+                Ok((this, Ok(deleted))) => {
+                    tracing::info!(deleted, "garbage collection finished");
+                    this
+                }
+                Ok((this, Err(e))) => {
+                    tracing::warn!(error = e.to_string(), "garbage collection failed");
+                    this
+                }
+                Err(e) => {
+                    tracing::error!(error = e.to_string(), "garbage collection panicked");
+                    return;
+                }
+            };
+        }
+    }
+
+    /// Mark everything reachable from the roots, then sweep the rest.
+    ///
+    /// Returns the number of blocks deleted.
+    fn collect(&mut self, state_roots: Vec<Cid>) -> anyhow::Result<usize> {
+        let roots = state_roots
+            .into_iter()
+            .chain(self.extra_roots.iter().cloned());
+        let mut walk = DagWalk::new(&self.store, roots);
+
+        for res in walk.by_ref() {
+            res.context("failed to mark reachable blocks")?;
+        }
+
+        let events = &mut self.events;
+        let lock = &self.lock;
+        let store = &self.store;
+
+        let mut batch = Vec::with_capacity(SWEEP_BATCH_SIZE);
+        let mut deleted = 0;
+
+        store.for_each_key(&mut |cid| {
+            if !walk.is_visited(&cid) {
+                batch.push(cid);
+            }
+            if batch.len() >= SWEEP_BATCH_SIZE {
+                deleted += sweep_batch(store, lock, events, &mut walk, &mut batch)?;
+            }
+            Ok(())
+        })?;
+
+        deleted += sweep_batch(store, lock, events, &mut walk, &mut batch)?;
+
+        Ok(deleted)
+    }
+}
+
+/// Delete a batch of unreachable blocks, after marking anything reachable
+/// from the roots which have been committed since the collection started.
+fn sweep_batch<BS: SweepBlockstore>(
+    store: &BS,
+    lock: &Mutex<()>,
+    events: &mut mpsc::UnboundedReceiver<GcEvent>,
+    walk: &mut DagWalk<BS>,
+    batch: &mut Vec<Cid>,
+) -> anyhow::Result<usize> {
+    // This runs on a blocking thread, outside the async runtime.
+    let _guard = lock.blocking_lock();
+
+    while let Ok(event) = events.try_recv() {
+        if let GcEvent::Committed(state_root) = event {
+            walk.push(state_root);
+        }
+    }
+    for res in walk.by_ref() {
+        res.context("failed to mark newly committed blocks")?;
+    }
+
+    batch.retain(|cid| !walk.is_visited(cid));
+    store.delete_many(batch)?;
+
+    let deleted = batch.len();
+    batch.clear();
+
+    Ok(deleted)
+}
+
+#[cfg(test)]
+mod tests {
+    use cid::multihash::Code;
+    use cid::Cid;
+    use fendermint_rocksdb::{blockstore::NamespaceBlockstore, RocksDb, RocksDbConfig};
+    use fvm_ipld_blockstore::Blockstore;
+    use fvm_ipld_encoding::CborStore;
+
+    use super::GcManager;
+
+    #[test]
+    fn collect_unreachable_blocks() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = RocksDb::open_cf(dir.path(), &RocksDbConfig::default(), ["state"].iter()).unwrap();
+        let store = NamespaceBlockstore::new(db, "state".to_owned()).unwrap();
+
+        let put = |value: &(u64, Vec<Cid>)| store.put_cbor(value, Code::Blake2b256).unwrap();
+
+        let shared = put(&(0, vec![]));
+        let extra = put(&(1, vec![]));
+        let old_root = put(&(2, vec![shared]));
+        let kept_root = put(&(3, vec![shared]));
+        let new_root = put(&(4, vec![old_root]));
+
+        let (mut manager, client) = GcManager::new(store.clone(), 10, vec![extra]).unwrap();
+
+        // Committed while the collection was already running.
+        client.committed(new_root);
+
+        let deleted = manager.collect(vec![kept_root]).unwrap();
+
+        assert_eq!(deleted, 0);
+
+        let deleted = manager.collect(vec![kept_root]).unwrap();
+
+        assert_eq!(deleted, 2);
+        assert!(store.has(&kept_root).unwrap());
+        assert!(store.has(&shared).unwrap());
+        assert!(store.has(&extra).unwrap());
+        assert!(!store.has(&old_root).unwrap());
+        assert!(!store.has(&new_root).unwrap());
+    }
+}
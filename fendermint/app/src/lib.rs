@@ -1,10 +1,16 @@
 // Copyright 2022-2023 Protocol Labs
 // SPDX-License-Identifier: Apache-2.0, MIT
 mod app;
+mod gc;
+mod metrics;
+mod query;
 mod store;
 mod tmconv;
 
-pub use app::App;
+pub use app::{App, AppConfig};
+pub use gc::{GcClient, GcManager, SweepBlockstore};
+pub use metrics::serve_metrics;
+pub use query::QueryMetrics;
 pub use store::AppStore;
 
 // Different type from `ChainEpoch` just because we might use epoch in a more traditional sense for checkpointing.
@@ -0,0 +1,62 @@
+// Copyright 2022-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+//! Metrics of the application in the Prometheus text format, served over HTTP for scraping.
+
+use std::fmt::{Display, Write};
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use anyhow::Context;
+use axum::extract::State;
+use axum::routing::get;
+use axum::Router;
+
+use crate::query::QueryMetrics;
+
+/// Serve the metrics at `/metrics` until the server fails.
+pub async fn serve_metrics(addr: SocketAddr, query: Arc<QueryMetrics>) -> anyhow::Result<()> {
+    let router = Router::new()
+        .route("/metrics", get(render))
+        .with_state(query);
+
+    let server = axum::Server::try_bind(&addr)?.serve(router.into_make_service());
+    tracing::info!(?addr, "bound metrics");
+
+    server.await.context("metrics server failed")
+}
+
+async fn render(State(query): State<Arc<QueryMetrics>>) -> String {
+    let mut out = String::new();
+    query.render(&mut out);
+    out
+}
+
+/// Append a single metric with its description.
+pub(crate) fn write_metric(
+    out: &mut String,
+    name: &str,
+    kind: &str,
+    help: &str,
+    value: impl Display,
+) {
+    // Writing to a `String` can't fail.
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+    let _ = writeln!(out, "{name} {value}");
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::query::QueryMetrics;
+
+    #[test]
+    fn render_text_format() {
+        let mut out = String::new();
+        QueryMetrics::default().render(&mut out);
+
+        assert!(out.contains(
+            "# TYPE fendermint_query_queue_depth gauge\nfendermint_query_queue_depth 0\n"
+        ));
+        assert!(out.contains("# TYPE fendermint_query_cache_hits_total counter\n"));
+    }
+}
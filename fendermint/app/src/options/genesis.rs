@@ -5,8 +5,8 @@ use std::path::PathBuf;
 
 use clap::{Args, Subcommand};
 
-use super::parse::{parse_network_version, parse_token_amount};
-use fvm_shared::{econ::TokenAmount, version::NetworkVersion};
+use super::parse::{parse_address, parse_network_version, parse_token_amount};
+use fvm_shared::{address::Address, econ::TokenAmount, version::NetworkVersion};
 
 #[derive(Subcommand, Debug)]
 pub enum GenesisCommands {
@@ -46,6 +46,18 @@ pub struct GenesisNewArgs {
     /// Base fee for running transactions in atto.
     #[arg(long, short = 'f', value_parser = parse_token_amount)]
     pub base_fee: TokenAmount,
+    /// Gas used per block that the base fee adjustment aims for; the base fee stays fixed if not set.
+    #[arg(long)]
+    pub gas_target: Option<u64>,
+    /// The base fee changes by at most 1/denominator of itself from one block to the next.
+    #[arg(long, default_value = "8")]
+    pub base_fee_max_change_denominator: u64,
+    /// Amount in atto minted in every block for the validator who proposed it, on top of the gas premiums.
+    #[arg(long, default_value = "0", value_parser = parse_token_amount)]
+    pub block_reward: TokenAmount,
+    /// Address of the account allowed to change the power of validators with signed messages.
+    #[arg(long, value_parser = parse_address)]
+    pub validator_admin: Option<Address>,
 }
 
 #[derive(Args, Debug)]
@@ -50,3 +50,10 @@ pub fn parse_bytes(s: &str) -> Result<Bytes, String> {
         Err(e) => Err(format!("error parsing raw bytes as hex: {e}")),
     }
 }
+
+pub fn parse_tx_hash(s: &str) -> Result<tendermint::Hash, String> {
+    let bz = hex::decode(s.trim_start_matches("0x"))
+        .map_err(|e| format!("error parsing transaction hash as hex: {e}"))?;
+    tendermint::Hash::from_bytes(tendermint::hash::Algorithm::Sha256, &bz)
+        .map_err(|e| format!("error parsing transaction hash: {e}"))
+}
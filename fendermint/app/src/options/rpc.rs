@@ -10,7 +10,9 @@ use fvm_ipld_encoding::RawBytes;
 use fvm_shared::{address::Address, econ::TokenAmount, MethodNum};
 use tendermint_rpc::Url;
 
-use crate::options::parse::{parse_address, parse_bytes, parse_cid, parse_token_amount};
+use crate::options::parse::{
+    parse_address, parse_bytes, parse_cid, parse_token_amount, parse_tx_hash,
+};
 
 #[derive(Args, Debug)]
 pub struct RpcArgs {
@@ -89,6 +91,16 @@ pub enum RpcQueryCommands {
     },
     /// Get the slowly changing state parameters.
     StateParams,
+    /// Get the current validator set with their power.
+    Validators,
+    /// Re-execute a transaction included in a block with execution tracing enabled; print the call tree as JSON.
+    ///
+    /// The transactions preceding it in its block are replayed first, so the query height is ignored.
+    Trace {
+        /// Hash of the transaction as Tendermint knows it, in hexadecimal format.
+        #[arg(long, short = 't', value_parser = parse_tx_hash)]
+        tx_hash: tendermint::Hash,
+    },
 }
 
 #[derive(Subcommand, Debug, Clone)]
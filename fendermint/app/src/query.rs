@@ -0,0 +1,252 @@
+// Copyright 2022-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+//! Resources for running read-only queries against committed states.
+//!
+//! Queries get their own Wasm engines and a limit on how many of them can run
+//! at the same time, so that read traffic doesn't compete with block execution.
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use anyhow::Context;
+use cid::Cid;
+use fendermint_vm_interpreter::fvm::state::FvmQueryState;
+use fvm::engine::MultiEngine;
+use fvm_ipld_blockstore::Blockstore;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::metrics::write_metric;
+use crate::BlockHeight;
+
+/// Query states are cached for the state root they were created for, at a given height.
+type CacheKey = (BlockHeight, Cid);
+
+/// Idle query states, least recently used first.
+type StateCache<DB> = VecDeque<(CacheKey, FvmQueryState<DB>)>;
+
+/// Counters and gauges of the query pool, exported as Prometheus metrics.
+#[derive(Default)]
+pub struct QueryMetrics {
+    /// Number of queries waiting for a permit.
+    queued: AtomicU64,
+    /// Number of queries holding a permit.
+    running: AtomicU64,
+    /// Number of queries which got a permit.
+    started: AtomicU64,
+    /// Time spent by queries waiting for a permit, in microseconds.
+    wait_micros: AtomicU64,
+    /// Number of idle query states in the cache.
+    cached: AtomicU64,
+    /// Number of queries which found a state to reuse in the cache.
+    cache_hits: AtomicU64,
+    /// Number of queries which had to create a new state.
+    cache_misses: AtomicU64,
+}
+
+impl QueryMetrics {
+    /// Number of queries waiting for their turn to run.
+    pub fn queue_depth(&self) -> u64 {
+        self.queued.load(Ordering::Relaxed)
+    }
+
+    /// Number of queries running at the moment.
+    pub fn running(&self) -> u64 {
+        self.running.load(Ordering::Relaxed)
+    }
+
+    /// Append the metrics in the Prometheus text format.
+    pub fn render(&self, out: &mut String) {
+        let get = |v: &AtomicU64| v.load(Ordering::Relaxed);
+
+        write_metric(
+            out,
+            "fendermint_query_queue_depth",
+            "gauge",
+            "Number of queries waiting for a permit.",
+            get(&self.queued),
+        );
+        write_metric(
+            out,
+            "fendermint_query_running",
+            "gauge",
+            "Number of queries running.",
+            get(&self.running),
+        );
+        write_metric(
+            out,
+            "fendermint_query_started_total",
+            "counter",
+            "Number of queries which got a permit to run.",
+            get(&self.started),
+        );
+        write_metric(
+            out,
+            "fendermint_query_wait_seconds_total",
+            "counter",
+            "Time spent by queries waiting for a permit.",
+            get(&self.wait_micros) as f64 / 1e6,
+        );
+        write_metric(
+            out,
+            "fendermint_query_cached_states",
+            "gauge",
+            "Number of idle query states kept for reuse.",
+            get(&self.cached),
+        );
+        write_metric(
+            out,
+            "fendermint_query_cache_hits_total",
+            "counter",
+            "Number of queries which reused a cached state.",
+            get(&self.cache_hits),
+        );
+        write_metric(
+            out,
+            "fendermint_query_cache_misses_total",
+            "counter",
+            "Number of queries which had to create a new state.",
+            get(&self.cache_misses),
+        );
+    }
+}
+
+/// Shared pool of engines, permits and cached states for running queries.
+#[derive(Clone)]
+pub struct QueryPool<DB>
+where
+    DB: Blockstore + 'static,
+{
+    /// Wasm engines used by queries only; the number of instances limits how many
+    /// messages can be executed in parallel.
+    multi_engine: Arc<MultiEngine>,
+    /// Permits to run a query, limiting the number of concurrent queries.
+    permits: Arc<Semaphore>,
+    /// Queue depth, cache hit rate and the like.
+    metrics: Arc<QueryMetrics>,
+    /// Idle query states, least recently used first.
+    cache: Arc<Mutex<StateCache<DB>>>,
+    /// Maximum number of idle query states to keep; 0 means no caching.
+    cache_size: usize,
+}
+
+/// Held while a query is running, releasing its permit when dropped.
+pub struct QueryPermit {
+    _permit: OwnedSemaphorePermit,
+    metrics: Arc<QueryMetrics>,
+}
+
+impl Drop for QueryPermit {
+    fn drop(&mut self) {
+        self.metrics.running.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl<DB> QueryPool<DB>
+where
+    DB: Blockstore + Clone + 'static,
+{
+    /// Create a pool with the given number of engine instances, allowing at most
+    /// `max_concurrent` queries to run at the same time, caching `cache_size` idle states.
+    pub fn new(engines: u32, max_concurrent: usize, cache_size: usize) -> Self {
+        Self {
+            multi_engine: Arc::new(MultiEngine::new(engines.max(1))),
+            permits: Arc::new(Semaphore::new(max_concurrent.max(1))),
+            metrics: Arc::new(QueryMetrics::default()),
+            cache: Arc::new(Mutex::new(VecDeque::new())),
+            cache_size,
+        }
+    }
+
+    /// Wasm engines to create query states with.
+    pub fn multi_engine(&self) -> Arc<MultiEngine> {
+        self.multi_engine.clone()
+    }
+
+    /// Metrics to export about the queries.
+    pub fn metrics(&self) -> Arc<QueryMetrics> {
+        self.metrics.clone()
+    }
+
+    /// Wait until the query can run.
+    pub async fn acquire(&self) -> anyhow::Result<QueryPermit> {
+        let metrics = &self.metrics;
+        let queued = metrics.queued.fetch_add(1, Ordering::Relaxed) + 1;
+
+        tracing::debug!(
+            queue_depth = queued,
+            running = metrics.running(),
+            "waiting for query permit"
+        );
+
+        let wait_start = Instant::now();
+        let permit = self.permits.clone().acquire_owned().await;
+
+        metrics.queued.fetch_sub(1, Ordering::Relaxed);
+
+        let permit = permit.context("query pool closed")?;
+
+        let wait_micros = wait_start
+            .elapsed()
+            .as_micros()
+            .try_into()
+            .unwrap_or(u64::MAX);
+        metrics
+            .wait_micros
+            .fetch_add(wait_micros, Ordering::Relaxed);
+        metrics.started.fetch_add(1, Ordering::Relaxed);
+        metrics.running.fetch_add(1, Ordering::Relaxed);
+
+        Ok(QueryPermit {
+            _permit: permit,
+            metrics: metrics.clone(),
+        })
+    }
+
+    /// Take an idle state created for the same height and state root, if there is any.
+    pub fn take_state(
+        &self,
+        block_height: BlockHeight,
+        state_root: &Cid,
+    ) -> Option<FvmQueryState<DB>> {
+        let mut cache = self.cache.lock().expect("mutex poisoned");
+        let state = cache
+            .iter()
+            .position(|((h, r), _)| *h == block_height && r == state_root)
+            .and_then(|idx| cache.remove(idx))
+            .map(|(_, state)| state);
+
+        let counter = if state.is_some() {
+            &self.metrics.cache_hits
+        } else {
+            &self.metrics.cache_misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        self.metrics
+            .cached
+            .store(cache.len() as u64, Ordering::Relaxed);
+
+        state
+    }
+
+    /// Return a state after a query, so the next one at the same height can reuse it.
+    ///
+    /// The effects of any messages executed by the query are discarded.
+    pub fn put_state(&self, block_height: BlockHeight, state: FvmQueryState<DB>) {
+        if self.cache_size == 0 {
+            return;
+        }
+        state.clear_exec_state();
+
+        let key = (block_height, state.state_params().state_root);
+        let mut cache = self.cache.lock().expect("mutex poisoned");
+        cache.push_back((key, state));
+        while cache.len() > self.cache_size {
+            cache.pop_front();
+        }
+        self.metrics
+            .cached
+            .store(cache.len() as u64, Ordering::Relaxed);
+    }
+}
@@ -25,6 +25,73 @@ pub struct DbSettings {
     ///
     /// This affects how long we can go back in state queries.
     pub state_hist_size: u64,
+    /// How often to run garbage collection on the state store, in terms of block height; 0 means never.
+    ///
+    /// Only takes effect if the state history is limited, otherwise every block stays reachable.
+    pub gc_interval: u64,
+    /// Run as an archive node, which keeps the state history and transactions of every block,
+    /// but beyond the state history size only retains the state at every height divisible
+    /// by this number, replaying blocks to recreate the states in between; 0 means disabled.
+    ///
+    /// Has to be enabled from genesis, otherwise older heights can't be recreated.
+    pub archive_interval: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SnapshotSettings {
+    /// Enable the export and import of snapshots.
+    pub enabled: bool,
+    /// How often to attempt to export snapshots in terms of block height.
+    pub block_interval: u64,
+    /// Number of snapshots to keep before purging old ones; 0 means unlimited.
+    pub hist_size: usize,
+    /// Target chunk size, in bytes.
+    pub chunk_size_bytes: usize,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MempoolSettings {
+    /// Minimum gas fee cap of transactions admitted into the mempool, in atto;
+    /// they also have to cover the current base fee.
+    pub min_gas_fee_cap: u64,
+    /// Maximum number of transactions a sender can have in the mempool; 0 means unlimited.
+    pub max_pending_per_sender: u64,
+    /// How far ahead of the next expected nonce of a sender a transaction can be and still
+    /// be admitted, waiting for the missing nonces to arrive; 0 means no gaps are allowed.
+    pub max_nonce_gap: u64,
+    /// Number of blocks after which a transaction admitted into the mempool but not yet
+    /// included in a block stops counting towards the sender's nonce and balance; 0 means never.
+    pub max_pending_blocks: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct QuerySettings {
+    /// Number of Wasm engine instances dedicated to queries, which is how many
+    /// read-only messages can be executed in parallel.
+    pub engines: u32,
+    /// Maximum number of queries running at the same time; the rest wait in a queue.
+    pub max_concurrent: usize,
+    /// Number of idle query states to keep, so queries at the same height don't have to load the state again.
+    pub cache_size: usize,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResolverSettings {
+    /// Number of seconds to wait before trying to resolve a CID again.
+    pub retry_delay_secs: u64,
+}
+
+/// A network upgrade scheduled at a block height; all validators need to have the same schedule.
+#[derive(Debug, Deserialize, Clone)]
+pub struct UpgradeSettings {
+    /// Height of the first block executed with the new network version.
+    pub block_height: u64,
+    /// Network version to switch to; has to increase with every upgrade.
+    pub network_version: u32,
+    /// New builtin actor bundle path relative to the `--home-dir`, unless given as an absolute path.
+    ///
+    /// If missing, the builtin actors stay the same.
+    pub builtin_actors_bundle: Option<PathBuf>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -39,6 +106,14 @@ impl Address {
     }
 }
 
+#[derive(Debug, Deserialize)]
+pub struct MetricsSettings {
+    /// Serve Prometheus metrics over HTTP.
+    pub enabled: bool,
+    /// Listen address for scraping the metrics at `/metrics`.
+    pub listen: Address,
+}
+
 /// Ethereum API facade settings.
 #[derive(Debug, Deserialize)]
 pub struct EthSettings {
@@ -46,6 +121,43 @@ pub struct EthSettings {
     pub http: Address,
     /// Listen address for WebSockets
     pub ws: Address,
+    /// Number of seconds after which filters which haven't been polled are uninstalled.
+    pub filter_timeout_secs: u64,
+    /// Origins allowed to make cross-origin requests, e.g. `https://app.example.com`; `*` allows any.
+    pub cors_allowed_origins: Vec<String>,
+    /// Maximum size of a request body, in bytes.
+    pub max_body_size_bytes: usize,
+    /// Maximum number of requests in a batch; 0 means unlimited.
+    pub max_batch_len: usize,
+    /// Maximum number of subscriptions a WebSocket connection can have; 0 means unlimited.
+    pub max_subscriptions: usize,
+    pub rate_limit: EthRateLimitSettings,
+    /// Bearer tokens; if there are any, every request has to be authenticated with one of them.
+    #[serde(default)]
+    pub tokens: Vec<EthTokenSettings>,
+    /// Secret to verify HS256 JWT bearer tokens with, which list the methods they can call
+    /// in a `methods` claim; if set, every request has to be authenticated with a token.
+    #[serde(default)]
+    pub jwt_secret: Option<String>,
+}
+
+/// Request rate limits of the Ethereum API facade.
+#[derive(Debug, Deserialize)]
+pub struct EthRateLimitSettings {
+    /// Length of the time windows in which requests are counted, in seconds.
+    pub window_secs: u64,
+    /// Maximum number of requests from an IP address per window; 0 means unlimited.
+    pub max_requests_per_ip: u32,
+    /// Maximum number of requests with a token per window; 0 means unlimited.
+    pub max_requests_per_token: u32,
+}
+
+/// A bearer token which can be used to call the Ethereum API facade.
+#[derive(Debug, Deserialize, Clone)]
+pub struct EthTokenSettings {
+    pub token: String,
+    /// Methods the token can call, or prefixes ending with `*`, e.g. `eth_get*`; `*` allows all.
+    pub methods: Vec<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -53,10 +165,19 @@ pub struct Settings {
     /// Home directory configured on the CLI, to which all paths in settings can be set relative.
     home_dir: PathBuf,
     data_dir: PathBuf,
+    snapshots_dir: PathBuf,
+    resolver_dir: PathBuf,
     builtin_actors_bundle: PathBuf,
     pub abci: AbciSettings,
     pub db: DbSettings,
+    pub snapshots: SnapshotSettings,
+    pub mempool: MempoolSettings,
+    pub query: QuerySettings,
+    pub resolver: ResolverSettings,
     pub eth: EthSettings,
+    pub metrics: MetricsSettings,
+    #[serde(default)]
+    upgrades: Vec<UpgradeSettings>,
 }
 
 impl Settings {
@@ -103,9 +224,31 @@ impl Settings {
         self.expand_path(&self.data_dir)
     }
 
+    pub fn snapshots_dir(&self) -> PathBuf {
+        self.expand_path(&self.snapshots_dir)
+    }
+
+    pub fn resolver_dir(&self) -> PathBuf {
+        self.expand_path(&self.resolver_dir)
+    }
+
     pub fn builtin_actors_bundle(&self) -> PathBuf {
         self.expand_path(&self.builtin_actors_bundle)
     }
+
+    /// The network upgrades, with the bundle paths expanded.
+    pub fn upgrades(&self) -> Vec<UpgradeSettings> {
+        self.upgrades
+            .iter()
+            .map(|u| UpgradeSettings {
+                builtin_actors_bundle: u
+                    .builtin_actors_bundle
+                    .as_ref()
+                    .map(|p| self.expand_path(p)),
+                ..u.clone()
+            })
+            .collect()
+    }
 }
 
 /// Expand paths that begin with "~" to `$HOME`.
@@ -4,11 +4,14 @@
 use anyhow::{anyhow, Context};
 use fendermint_vm_core::Timestamp;
 use fendermint_vm_genesis::Validator;
+use fendermint_vm_interpreter::fvm::state::FvmStateParams;
 use fendermint_vm_interpreter::fvm::{FvmApplyRet, FvmCheckRet, FvmQueryRet};
-use fvm_shared::{error::ExitCode, event::StampedEvent};
+use fendermint_vm_snapshot::SnapshotItem;
+use fvm_shared::{address::Address, error::ExitCode, event::StampedEvent};
 use prost::Message;
 use std::num::NonZeroU32;
-use tendermint::abci::{response, Code, Event, EventAttribute};
+use tendermint::abci::response::ApplySnapshotChunkResult;
+use tendermint::abci::{response, types::Snapshot, Code, Event, EventAttribute};
 
 use crate::{app::AppError, BlockHeight};
 
@@ -81,9 +84,12 @@ pub fn to_deliver_tx(ret: FvmApplyRet) -> response::DeliverTx {
 pub fn to_check_tx(ret: FvmCheckRet) -> response::CheckTx {
     response::CheckTx {
         code: to_code(ret.exit_code),
-        info: to_error_msg(ret.exit_code).to_owned(),
+        info: ret
+            .info
+            .unwrap_or_else(|| to_error_msg(ret.exit_code).to_owned()),
         gas_wanted: ret.gas_limit.try_into().unwrap_or(i64::MAX),
         sender: ret.sender.to_string(),
+        priority: ret.priority,
         ..Default::default()
     }
 }
@@ -91,12 +97,15 @@ pub fn to_check_tx(ret: FvmCheckRet) -> response::CheckTx {
 /// Map the return values from epoch boundary operations to validator updates.
 ///
 /// (Currently just a placeholder).
-pub fn to_end_block(_ret: ()) -> response::EndBlock {
-    response::EndBlock {
-        validator_updates: Vec::new(),
+/// Map the changes in the validator set to Tendermint.
+pub fn to_end_block(validators: Vec<Validator>) -> anyhow::Result<response::EndBlock> {
+    let validator_updates = to_validator_updates(validators)?;
+
+    Ok(response::EndBlock {
+        validator_updates,
         consensus_param_updates: None,
         events: Vec::new(),
-    }
+    })
 }
 
 /// Map the return values from cron operations.
@@ -106,6 +115,20 @@ pub fn to_begin_block(ret: FvmApplyRet) -> response::BeginBlock {
     response::BeginBlock { events }
 }
 
+/// Event carrying the hash of the Ethereum transaction a message was created from.
+///
+/// Tendermint indexes it, so transactions can be searched for with the `eth.hash='<hex>'` query.
+pub fn to_eth_hash_event(hash: &[u8]) -> Event {
+    Event::new(
+        "eth".to_string(),
+        vec![EventAttribute {
+            key: "hash".to_string(),
+            value: hex::encode(hash),
+            index: true,
+        }],
+    )
+}
+
 /// Convert events to key-value pairs.
 pub fn to_events(kind: &str, stamped_events: Vec<StampedEvent>) -> Vec<Event> {
     stamped_events
@@ -140,7 +163,9 @@ pub fn to_query(ret: FvmQueryRet, block_height: BlockHeight) -> anyhow::Result<r
         // For calls and estimates, the caller needs to look into the `value` field to see the real exit code;
         // the query itself is successful, even if the value represents a failure.
         FvmQueryRet::Call(_) | FvmQueryRet::EstimateGas(_) => ExitCode::OK,
-        FvmQueryRet::StateParams(_) => ExitCode::OK,
+        FvmQueryRet::StateParams(_) | FvmQueryRet::Validators(_) => ExitCode::OK,
+        // Same as calls, the trace contains the exit code of the message.
+        FvmQueryRet::Trace(_) => ExitCode::OK,
     };
 
     // The return value has a `key` field which is supposed to be set to the data matched.
@@ -177,6 +202,14 @@ pub fn to_query(ret: FvmQueryRet, block_height: BlockHeight) -> anyhow::Result<r
             let v = ipld_encode!(sp);
             (Vec::new(), v)
         }
+        FvmQueryRet::Validators(vs) => {
+            let v = ipld_encode!(vs);
+            (Vec::new(), v)
+        }
+        FvmQueryRet::Trace(tr) => {
+            let v = ipld_encode!(tr);
+            (Vec::new(), v)
+        }
     };
 
     // The height here is the height of the block that was committed, not in which the app hash appeared.
@@ -194,7 +227,7 @@ pub fn to_query(ret: FvmQueryRet, block_height: BlockHeight) -> anyhow::Result<r
     Ok(res)
 }
 
-/// Project Genesis validators to Tendermint.
+/// Project Genesis validators to Tendermint; zero power means removal.
 pub fn to_validator_updates(
     validators: Vec<Validator>,
 ) -> anyhow::Result<Vec<tendermint::validator::Update>> {
@@ -213,6 +246,55 @@ pub fn to_validator_updates(
     Ok(updates)
 }
 
+/// Find the FVM account of the validator with the given CometBFT address.
+///
+/// CometBFT identifies the block proposer by the hash of their public key,
+/// while the FVM account is derived from the public key itself.
+pub fn to_validator_address(
+    validators: &[Validator],
+    proposer: &tendermint::account::Id,
+) -> anyhow::Result<Option<Address>> {
+    for v in validators {
+        let bz = v.public_key.0.serialize();
+
+        let key = tendermint::crypto::default::ecdsa_secp256k1::VerifyingKey::from_sec1_bytes(&bz)
+            .map_err(|e| anyhow!("failed to convert public key: {e}"))?;
+
+        if tendermint::account::Id::from(key) == *proposer {
+            return Ok(Some(Address::new_secp256k1(&bz)?));
+        }
+    }
+    Ok(None)
+}
+
+/// The app hash is the CID of the state root, which commits to the whole state tree.
+pub fn to_app_hash(state_params: &FvmStateParams) -> tendermint::hash::AppHash {
+    tendermint::hash::AppHash::try_from(state_params.state_root.to_bytes())
+        .expect("hash can be wrapped")
+}
+
+/// Advertise a snapshot to peers, sending the manifest along as metadata.
+pub fn to_snapshot(item: SnapshotItem) -> anyhow::Result<Snapshot> {
+    let manifest = item.manifest;
+    let metadata = ipld_encode!(manifest);
+
+    Ok(Snapshot {
+        height: manifest.block_height.try_into()?,
+        format: manifest.version,
+        chunks: manifest.chunks,
+        hash: manifest.checksum.into(),
+        metadata: metadata.into(),
+    })
+}
+
+pub fn to_apply_snapshot_chunk(result: ApplySnapshotChunkResult) -> response::ApplySnapshotChunk {
+    response::ApplySnapshotChunk {
+        result,
+        refetch_chunks: Vec::new(),
+        reject_senders: Vec::new(),
+    }
+}
+
 pub fn to_timestamp(time: tendermint::time::Time) -> Timestamp {
     Timestamp(
         time.unix_timestamp()
@@ -258,9 +340,11 @@ pub fn to_error_msg(exit_code: ExitCode) -> &'static str {
 
 #[cfg(test)]
 mod tests {
-    use fvm_shared::error::ExitCode;
+    use fendermint_vm_genesis::{Power, Validator};
+    use fvm_shared::{address::Address, error::ExitCode};
+    use quickcheck_macros::quickcheck;
 
-    use crate::tmconv::to_error_msg;
+    use crate::tmconv::{to_end_block, to_error_msg, to_validator_address};
 
     #[test]
     fn code_error_message() {
@@ -270,4 +354,42 @@ mod tests {
             "The message sender doesn't exist."
         );
     }
+
+    #[quickcheck]
+    fn prop_validator_address(validators: Vec<Validator>) -> bool {
+        validators.iter().all(|v| {
+            let bz = v.public_key.0.serialize();
+            let key =
+                tendermint::crypto::default::ecdsa_secp256k1::VerifyingKey::from_sec1_bytes(&bz)
+                    .unwrap();
+            let proposer = tendermint::account::Id::from(key);
+            let addr = Address::new_secp256k1(&bz).unwrap();
+            to_validator_address(&validators, &proposer).unwrap() == Some(addr)
+        })
+    }
+
+    #[quickcheck]
+    fn prop_end_block_validator_updates(validators: Vec<Validator>) -> bool {
+        // Tendermint power is signed; zero means the validator is removed.
+        let validators = validators
+            .into_iter()
+            .enumerate()
+            .map(|(i, v)| Validator {
+                public_key: v.public_key,
+                power: Power(v.power.0 % 100 * (i as u64 % 2)),
+            })
+            .collect::<Vec<_>>();
+
+        let response = to_end_block(validators.clone()).unwrap();
+
+        response.validator_updates.len() == validators.len()
+            && response
+                .validator_updates
+                .iter()
+                .zip(validators.iter())
+                .all(|(u, v)| {
+                    u.power.value() == v.power.0
+                        && u.pub_key.to_bytes() == v.public_key.0.serialize_compressed().to_vec()
+                })
+    }
 }
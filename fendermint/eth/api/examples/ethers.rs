@@ -26,7 +26,8 @@ use std::fmt::Debug;
 
 use anyhow::anyhow;
 use clap::Parser;
-use ethers::providers::{Http, Middleware, Provider, ProviderError};
+use ethers::providers::{FilterKind, Http, Middleware, Provider, ProviderError};
+use ethers::types::{Address, BlockNumber, Filter, SyncingStatus, H256};
 use tracing::Level;
 
 #[derive(Parser, Debug)]
@@ -79,26 +80,33 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 // OK:
 // - eth_accounts
 // - eth_blockNumber
+// - eth_chainId
+// - eth_feeHistory
+// - eth_gasPrice
+// - eth_getBalance
+// - eth_getBlockByHash
+// - eth_getBlockByNumber
+// - eth_getCode
+// - eth_getFilterChanges
+// - eth_getLogs
+// - eth_getStorageAt
+// - eth_getTransactionByHash
+// - eth_getTransactionCount
+// - eth_getTransactionReceipt
+// - eth_newBlockFilter
+// - eth_syncing
+// - eth_uninstallFilter
+// - net_version
+// - web3_clientVersion
 //
 // TODO:
-// - eth_newBlockFilter
-// - eth_newBlockFilter
 // - eth_newPendingTransactionFilter
-// - eth_getBlockByHash
-// - eth_getBlockByNumber
 // - eth_call
 // - eth_getUncleCountByBlockHash
 // - eth_getUncleCountByBlockNumber
 // - eth_getUncleByBlockHashAndIndex
 // - eth_getUncleByBlockNumberAndIndex
-// - eth_getTransactionByHash
-// - eth_getTransactionReceipt
 // - eth_getBlockReceipts
-// - eth_gasPrice
-// - eth_getTransactionCount
-// - eth_getBalance
-// - eth_chainId
-// - eth_syncing
 // - eth_call
 // - eth_estimateGas
 // - eth_createAccessList
@@ -106,23 +114,13 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 // - eth_sendRawTransaction
 // - eth_sign
 // - eth_sign
-// - eth_getLogs
-// - eth_newBlockFilter
 // - eth_newPendingTransactionFilter
 // - eth_newFilter
-// - eth_uninstallFilter
-// - eth_getFilterChanges
-// - eth_getstorageat
-// - eth_getStorageAt
-// - eth_getCode
 // - eth_getProof
 // - eth_mining
 // - eth_subscribe
 // - eth_unsubscribe
-// - eth_feeHistory
-// - eth_feeHistory
 // - eth_blockNumber
-// - eth_getChainId
 // - eth_estimateGas
 // - geth_admin_nodeinfo
 // - spawn_geth_and_create_provider
@@ -162,10 +160,103 @@ where
 }
 
 async fn run(provider: Provider<Http>) -> anyhow::Result<()> {
-    request("eth_blockNumber", provider.get_block_number().await, |bn| {
+    let bn = request("eth_blockNumber", provider.get_block_number().await, |bn| {
         bn.as_u64() > 0
     })?;
 
+    let b = request("eth_getBlockByNumber", provider.get_block(bn).await, |b| {
+        b.as_ref().and_then(|b| b.number) == Some(bn)
+    })?;
+
+    let bh = b.unwrap().hash.expect("hash should be set");
+
+    request(
+        "eth_getBlockByHash",
+        provider.get_block_with_txs(bh).await,
+        |b| b.as_ref().and_then(|b| b.hash) == Some(bh),
+    )?;
+
+    request(
+        "eth_getLogs",
+        provider.get_logs(&Filter::new().select(bn)).await,
+        |logs| logs.iter().all(|l| l.block_number == Some(bn)),
+    )?;
+
+    let filter_id = request(
+        "eth_newBlockFilter",
+        provider.new_filter(FilterKind::NewBlocks).await,
+        |_| true,
+    )?;
+
+    request(
+        "eth_getFilterChanges",
+        provider.get_filter_changes::<_, H256>(filter_id).await,
+        |_| true,
+    )?;
+
+    request(
+        "eth_uninstallFilter",
+        provider.uninstall_filter(filter_id).await,
+        |ok| *ok,
+    )?;
+
+    // An address nobody owns, for which the defaults are expected.
+    let addr = Address::repeat_byte(0x11);
+
+    request(
+        "eth_getBalance",
+        provider.get_balance(addr, Some(bn.into())).await,
+        |b| b.is_zero(),
+    )?;
+
+    request(
+        "eth_getTransactionCount",
+        provider.get_transaction_count(addr, Some(bn.into())).await,
+        |n| n.is_zero(),
+    )?;
+
+    request(
+        "eth_getCode",
+        provider.get_code(addr, Some(bn.into())).await,
+        |c| c.is_empty(),
+    )?;
+
+    request(
+        "eth_getStorageAt",
+        provider
+            .get_storage_at(addr, H256::zero(), Some(bn.into()))
+            .await,
+        |s| s.is_zero(),
+    )?;
+
+    request("eth_chainId", provider.get_chainid().await, |id| {
+        !id.is_zero()
+    })?;
+
+    request("net_version", provider.get_net_version().await, |v| {
+        !v.is_empty()
+    })?;
+
+    request("web3_clientVersion", provider.client_version().await, |v| {
+        v.starts_with("fendermint/")
+    })?;
+
+    request("eth_syncing", provider.syncing().await, |s| {
+        *s == SyncingStatus::IsFalse
+    })?;
+
+    request("eth_gasPrice", provider.get_gas_price().await, |p| {
+        !p.is_zero()
+    })?;
+
+    request(
+        "eth_feeHistory",
+        provider
+            .fee_history(4, BlockNumber::Number(bn), &[25.0, 75.0])
+            .await,
+        |h| h.base_fee_per_gas.len() == h.gas_used_ratio.len() + 1,
+    )?;
+
     request("eth_accounts", provider.get_accounts().await, |acnts| {
         acnts.is_empty()
     })?;
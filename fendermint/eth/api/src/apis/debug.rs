@@ -0,0 +1,90 @@
+// Copyright 2022-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+// See https://geth.ethereum.org/docs/interacting-with-geth/rpc/ns-debug
+
+use anyhow::anyhow;
+use ethers_core::types as ethtypes;
+use fendermint_rpc::query::QueryClient;
+use fendermint_vm_message::conv::from_eth::to_fvm_call_message;
+use jsonrpc_v2::Params;
+use serde::Deserialize;
+use tendermint_rpc::Client;
+
+use crate::state::to_query_height;
+use crate::trace::{to_transaction_trace, TransactionTrace};
+use crate::{JsonRpcData, JsonRpcResult};
+
+/// Parameters of `debug_traceTransaction`.
+///
+/// The tracing options are accepted for compatibility, but ignored,
+/// because there is only one kind of trace the FVM can produce.
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum TraceTransactionParams {
+    WithOptions(ethtypes::H256, serde_json::Value),
+    Hash((ethtypes::H256,)),
+}
+
+/// Parameters of `debug_traceCall`, where the block is optional and defaults to the latest.
+///
+/// The tracing options are ignored, the same way as with `debug_traceTransaction`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum TraceCallParams {
+    WithOptions(
+        ethtypes::TransactionRequest,
+        ethtypes::BlockNumber,
+        serde_json::Value,
+    ),
+    AtBlock(ethtypes::TransactionRequest, ethtypes::BlockNumber),
+    Latest((ethtypes::TransactionRequest,)),
+}
+
+/// Re-executes a transaction included in a block and returns the tree of calls it made between actors.
+///
+/// The transactions preceding it in the block are executed first, to recreate the state it was executed in.
+pub async fn trace_transaction<C>(
+    data: JsonRpcData<C>,
+    Params(params): Params<TraceTransactionParams>,
+) -> JsonRpcResult<TransactionTrace>
+where
+    C: Client + Sync + Send,
+{
+    let tx_hash = match params {
+        TraceTransactionParams::WithOptions(tx_hash, _) => tx_hash,
+        TraceTransactionParams::Hash((tx_hash,)) => tx_hash,
+    };
+
+    let res = data
+        .find_tx(tx_hash)
+        .await?
+        .ok_or_else(|| anyhow!("transaction not found: {tx_hash:?}"))?;
+
+    let res = data.client.trace_tx(res.height, res.index as usize).await?;
+
+    Ok(to_transaction_trace(res.value)?)
+}
+
+/// Executes a new message call immediately, without creating a transaction on the block chain,
+/// and returns the tree of calls it made between actors.
+pub async fn trace_call<C>(
+    data: JsonRpcData<C>,
+    Params(params): Params<TraceCallParams>,
+) -> JsonRpcResult<TransactionTrace>
+where
+    C: Client + Sync + Send,
+{
+    let (tx, block_number) = match params {
+        TraceCallParams::WithOptions(tx, block_number, _) => (tx, block_number),
+        TraceCallParams::AtBlock(tx, block_number) => (tx, block_number),
+        TraceCallParams::Latest((tx,)) => (tx, ethtypes::BlockNumber::Latest),
+    };
+
+    let msg = to_fvm_call_message(&tx)?;
+    let height = to_query_height(block_number)?;
+
+    let res = data.client.trace(vec![msg], height).await?;
+
+    Ok(to_transaction_trace(res.value)?)
+}
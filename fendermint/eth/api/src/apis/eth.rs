@@ -5,11 +5,35 @@
 // * https://github.com/evmos/ethermint/blob/ebbe0ffd0d474abd745254dc01e60273ea758dae/rpc/namespaces/ethereum/eth/api.go#L44
 // * https://github.com/filecoin-project/lotus/blob/v1.23.1-rc2/api/api_full.go#L783
 
+use anyhow::{anyhow, Context};
+use ethers_core::abi::{self, ParamType};
 use ethers_core::types as ethtypes;
+use ethers_core::types::transaction::eip2718::TypedTransaction;
+use ethers_core::utils::rlp;
+use fendermint_rpc::query::QueryClient;
+use fendermint_rpc::response::{decode_data, decode_fevm_create, decode_fevm_invoke};
+use fendermint_vm_actor_interface::evm;
+use fendermint_vm_message::conv::from_eth::to_fvm_call_message;
+use fendermint_vm_message::conv::from_fvm::to_u256;
+use fendermint_vm_message::conv::to_fvm_address;
+use fendermint_vm_message::{chain::ChainMessage, signed::SignedMessage};
+use fvm_ipld_encoding::RawBytes;
+use fvm_shared::{econ::TokenAmount, error::ExitCode, BLOCK_GAS_LIMIT};
 use jsonrpc_v2::Params;
+use serde::Deserialize;
+use tendermint::abci::response::DeliverTx;
 use tendermint::block::Height;
-use tendermint_rpc::{endpoint::block, Client};
+use tendermint::Time;
+use tendermint_rpc::{
+    endpoint::{block, block_results, broadcast::tx_sync, header, status},
+    Client,
+};
 
+use crate::conv::from_tm;
+use crate::filters::{FilterChanges, FilterId, FilterKind};
+use crate::gas::{check_percentiles, reward_percentiles};
+use crate::jsonrpc::SERVER_ERROR;
+use crate::state::{decode_signed, resolve_height, to_query_height, MAX_BLOCK_RANGE};
 use crate::{JsonRpcData, JsonRpcResult};
 
 /// Returns a list of addresses owned by client.
@@ -22,41 +46,877 @@ pub async fn accounts<C>(_data: JsonRpcData<C>) -> JsonRpcResult<Vec<ethtypes::A
 /// Returns the number of most recent block.
 pub async fn block_number<C>(data: JsonRpcData<C>) -> JsonRpcResult<ethtypes::U64>
 where
-    C: Client + Sync,
+    C: Client + Sync + Send,
 {
-    let res: block::Response = data.client.latest_block().await?;
+    let res: block::Response = data.tm().latest_block().await?;
     let height = res.block.header.height;
     Ok(ethtypes::U64::from(height.value()))
 }
 
+/// Returns the chain ID used for signing replay-protected transactions.
+pub async fn chain_id<C>(data: JsonRpcData<C>) -> JsonRpcResult<ethtypes::U64>
+where
+    C: Client + Sync + Send,
+{
+    let res = data.client.state_params(None).await?;
+    Ok(ethtypes::U64::from(res.value.chain_id))
+}
+
+/// Returns an object with data about the sync status or `false`.
+pub async fn syncing<C>(data: JsonRpcData<C>) -> JsonRpcResult<serde_json::Value>
+where
+    C: Client + Sync + Send,
+{
+    let status: status::Response = data.tm().status().await?;
+    let info = status.sync_info;
+    let current = info.latest_block_height.value();
+
+    let starting = match data.sync_start(info.catching_up, current) {
+        Some(height) => height,
+        None => return Ok(serde_json::Value::Bool(false)),
+    };
+
+    // CometBFT doesn't tell how high the chain is, but we know how long ago our latest block was
+    // produced, and how often the blocks before it were.
+    let earlier = current.saturating_sub(SYNC_RATE_BLOCKS).max(1);
+    let header: header::Response = data.tm().header(Height::try_from(earlier)?).await?;
+
+    let highest = estimate_highest_block(
+        (earlier, header.header.time),
+        (current, info.latest_block_time),
+        Time::now(),
+    );
+
+    let progress = serde_json::json!({
+        "startingBlock": ethtypes::U64::from(starting),
+        "currentBlock": ethtypes::U64::from(current),
+        "highestBlock": ethtypes::U64::from(highest),
+    });
+
+    Ok(progress)
+}
+
+/// Number of blocks to measure the block rate over, when estimating the height of the chain during a sync.
+const SYNC_RATE_BLOCKS: u64 = 100;
+
+/// Extrapolate the height of the chain from the time passed since the latest block we have,
+/// assuming blocks kept being produced at the same rate as between the earlier block and the latest.
+fn estimate_highest_block(earlier: (u64, Time), latest: (u64, Time), now: Time) -> u64 {
+    let (earlier_height, earlier_time) = earlier;
+    let (latest_height, latest_time) = latest;
+
+    let (Ok(span), Ok(behind)) = (
+        latest_time.duration_since(earlier_time),
+        now.duration_since(latest_time),
+    ) else {
+        return latest_height;
+    };
+
+    if latest_height <= earlier_height || span.is_zero() {
+        return latest_height;
+    }
+
+    let block_time = span.as_secs_f64() / (latest_height - earlier_height) as f64;
+
+    latest_height + (behind.as_secs_f64() / block_time) as u64
+}
+
+/// The minimum premium to suggest, even if the recent blocks paid less.
+const MIN_GAS_PREMIUM: u64 = 100_000;
+
+/// Number of recent blocks to look at when suggesting a premium.
+const GAS_PREMIUM_LOOKBACK: u64 = 20;
+
+/// Returns the current price per gas in wei, which is the base fee plus the suggested premium.
+pub async fn gas_price<C>(data: JsonRpcData<C>) -> JsonRpcResult<ethtypes::U256>
+where
+    C: Client + Sync + Send,
+{
+    let res = data.client.state_params(None).await?;
+    let premium = suggest_premium(&data).await?;
+    let price = res.value.base_fee + premium;
+    Ok(to_u256(&price)?)
+}
+
+/// Returns a fee per gas that is an estimate of how much you can pay as a priority fee,
+/// or "tip", to get a transaction included in the current block.
+pub async fn max_priority_fee_per_gas<C>(data: JsonRpcData<C>) -> JsonRpcResult<ethtypes::U256>
+where
+    C: Client + Sync + Send,
+{
+    let premium = suggest_premium(&data).await?;
+    Ok(to_u256(&premium)?)
+}
+
+/// The median of the effective premiums paid in recent blocks, weighted by gas used.
+async fn suggest_premium<C>(data: &JsonRpcData<C>) -> JsonRpcResult<TokenAmount>
+where
+    C: Client + Sync + Send,
+{
+    let latest = data.latest_height().await?;
+    let oldest = latest.saturating_sub(GAS_PREMIUM_LOOKBACK - 1).max(1);
+
+    let mut rewards = Vec::new();
+    for height in oldest..=latest {
+        let block_gas = data.block_gas(Height::try_from(height)?).await?;
+        rewards.extend(block_gas.rewards);
+    }
+
+    let premium = if rewards.is_empty() {
+        TokenAmount::default()
+    } else {
+        reward_percentiles(rewards, &[50.0]).remove(0)
+    };
+
+    Ok(std::cmp::max(
+        premium,
+        TokenAmount::from_atto(MIN_GAS_PREMIUM),
+    ))
+}
+
+/// Maximum number of blocks `eth_feeHistory` returns.
+const MAX_FEE_HISTORY: u64 = 128;
+
+/// Returns the base fee per gas and the effective priority fees at the requested percentiles
+/// of a range of blocks, ending at the newest block requested.
+///
+/// The base fees include the one of the block after the newest.
+pub async fn fee_history<C>(
+    data: JsonRpcData<C>,
+    Params((block_count, newest_block, reward_percentiles_requested)): Params<(
+        ethtypes::U256,
+        ethtypes::BlockNumber,
+        Vec<f64>,
+    )>,
+) -> JsonRpcResult<ethtypes::FeeHistory>
+where
+    C: Client + Sync + Send,
+{
+    check_percentiles(&reward_percentiles_requested)?;
+
+    let block_count = std::cmp::min(block_count, ethtypes::U256::from(MAX_FEE_HISTORY)).as_u64();
+    let latest = data.latest_height().await?;
+    let newest = resolve_height(Some(newest_block), latest);
+
+    let mut history = ethtypes::FeeHistory {
+        base_fee_per_gas: Vec::new(),
+        gas_used_ratio: Vec::new(),
+        oldest_block: ethtypes::U256::from(newest),
+        reward: Vec::new(),
+    };
+
+    if block_count == 0 {
+        return Ok(history);
+    }
+
+    let oldest = newest.saturating_sub(block_count - 1).max(1);
+    history.oldest_block = ethtypes::U256::from(oldest);
+
+    for height in oldest..=newest {
+        let block_gas = data.block_gas(Height::try_from(height)?).await?;
+
+        history.base_fee_per_gas.push(to_u256(&block_gas.base_fee)?);
+        history
+            .gas_used_ratio
+            .push(block_gas.gas_used as f64 / BLOCK_GAS_LIMIT as f64);
+
+        if !reward_percentiles_requested.is_empty() {
+            let rewards = reward_percentiles(block_gas.rewards, &reward_percentiles_requested)
+                .iter()
+                .map(to_u256)
+                .collect::<Result<Vec<_>, _>>()?;
+
+            history.reward.push(rewards);
+        }
+    }
+
+    // The state committed by the newest block has the base fee of the next one.
+    let next = data
+        .client
+        .state_params(Some(Height::try_from(newest)?))
+        .await?;
+
+    history
+        .base_fee_per_gas
+        .push(to_u256(&next.value.base_fee)?);
+
+    Ok(history)
+}
+
+/// Returns the balance of the account of given address.
+///
+/// The address can be the Ethereum form of a delegated address, or a masked ID address.
+pub async fn get_balance<C>(
+    data: JsonRpcData<C>,
+    Params((addr, block_number)): Params<(ethtypes::Address, ethtypes::BlockNumber)>,
+) -> JsonRpcResult<ethtypes::U256>
+where
+    C: Client + Sync + Send,
+{
+    let height = to_query_height(block_number)?;
+    let res = data
+        .client
+        .actor_state(&to_fvm_address(addr), height)
+        .await?;
+    match res.value {
+        Some((_, state)) => Ok(to_u256(&state.balance)?),
+        None => Ok(ethtypes::U256::zero()),
+    }
+}
+
+/// Returns the number of transactions sent from an address, ie. the sequence of the actor.
+pub async fn get_transaction_count<C>(
+    data: JsonRpcData<C>,
+    Params((addr, block_number)): Params<(ethtypes::Address, ethtypes::BlockNumber)>,
+) -> JsonRpcResult<ethtypes::U256>
+where
+    C: Client + Sync + Send,
+{
+    let height = to_query_height(block_number)?;
+    let res = data
+        .client
+        .actor_state(&to_fvm_address(addr), height)
+        .await?;
+    match res.value {
+        Some((_, state)) => Ok(ethtypes::U256::from(state.sequence)),
+        None => Ok(ethtypes::U256::zero()),
+    }
+}
+
+/// Returns code at a given address.
+///
+/// Anything other than an EVM contract has no code.
+pub async fn get_code<C>(
+    data: JsonRpcData<C>,
+    Params((addr, block_number)): Params<(ethtypes::Address, ethtypes::BlockNumber)>,
+) -> JsonRpcResult<ethtypes::Bytes>
+where
+    C: Client + Sync + Send,
+{
+    let height = to_query_height(block_number)?;
+    let deliver_tx = match read_evm_actor(
+        &data,
+        addr,
+        evm::Method::GetBytecode,
+        RawBytes::default(),
+        height,
+    )
+    .await?
+    {
+        Some(deliver_tx) => deliver_tx,
+        None => return Ok(ethtypes::Bytes::default()),
+    };
+
+    let ret = decode_data(&deliver_tx.data)?;
+    let ret: evm::BytecodeReturn =
+        fvm_ipld_encoding::from_slice(&ret).context("failed to decode BytecodeReturn")?;
+
+    match ret.code {
+        Some(cid) => {
+            let code = data.client.ipld(&cid).await?.unwrap_or_default();
+            Ok(ethtypes::Bytes::from(code))
+        }
+        None => Ok(ethtypes::Bytes::default()),
+    }
+}
+
+/// Returns the value from a storage position at a given address.
+///
+/// Anything other than an EVM contract has nothing in its storage.
+pub async fn get_storage_at<C>(
+    data: JsonRpcData<C>,
+    Params((addr, position, block_number)): Params<(
+        ethtypes::Address,
+        ethtypes::U256,
+        ethtypes::BlockNumber,
+    )>,
+) -> JsonRpcResult<ethtypes::H256>
+where
+    C: Client + Sync + Send,
+{
+    let height = to_query_height(block_number)?;
+
+    let mut storage_key = [0u8; 32];
+    position.to_big_endian(&mut storage_key);
+    let params = RawBytes::serialize(evm::GetStorageAtParams { storage_key })
+        .context("failed to serialize GetStorageAtParams")?;
+
+    let deliver_tx =
+        match read_evm_actor(&data, addr, evm::Method::GetStorageAt, params, height).await? {
+            Some(deliver_tx) => deliver_tx,
+            None => return Ok(ethtypes::H256::zero()),
+        };
+
+    let value = decode_fevm_invoke(&deliver_tx)?;
+    if value.len() > 32 {
+        return Err(anyhow!("storage value is longer than 32 bytes").into());
+    }
+
+    // The value might have its leading zeros stripped.
+    let mut bz = [0u8; 32];
+    bz[32 - value.len()..].copy_from_slice(&value);
+
+    Ok(ethtypes::H256::from(bz))
+}
+
 /// Returns the number of transactions in a block matching the given block number.
 ///
 /// QUANTITY|TAG - integer of a block number, or the string "earliest", "latest" or "pending", as in the default block parameter.
-pub async fn get_block_transaction_count_by_number<C: Client>(
+pub async fn get_block_transaction_count_by_number<C>(
     data: JsonRpcData<C>,
     Params(params): Params<ethtypes::BlockNumber>,
 ) -> JsonRpcResult<ethtypes::U64>
 where
-    C: Client + Sync,
+    C: Client + Sync + Send,
 {
-    let block = match params {
-        ethtypes::BlockNumber::Number(height) => {
-            let height = Height::try_from(height.as_u64())?;
-            let res: block::Response = data.client.block(height).await?;
-            res.block
+    let block = data.block_by_height(params).await?;
+
+    Ok(ethtypes::U64::from(block.data.len()))
+}
+
+/// Returns information about a block by block number.
+///
+/// Returns `null` if the block doesn't exist yet. If `full` is `false` the transactions
+/// are only returned as hashes, otherwise as full transaction objects.
+pub async fn get_block_by_number<C>(
+    data: JsonRpcData<C>,
+    Params((block_number, full_tx)): Params<(ethtypes::BlockNumber, bool)>,
+) -> JsonRpcResult<Option<ethtypes::Block<serde_json::Value>>>
+where
+    C: Client + Sync + Send,
+{
+    if let ethtypes::BlockNumber::Number(height) = block_number {
+        let latest: block::Response = data.tm().latest_block().await?;
+        if height.as_u64() > latest.block.header.height.value() {
+            return Ok(None);
         }
-        ethtypes::BlockNumber::Finalized
-        | ethtypes::BlockNumber::Latest
-        | ethtypes::BlockNumber::Safe
-        | ethtypes::BlockNumber::Pending => {
-            let res: block::Response = data.client.latest_block().await?;
-            res.block
+    }
+    let block = data.block_by_height(block_number).await?;
+    let block = data.enrich_block(&block, full_tx).await?;
+    Ok(Some(block))
+}
+
+/// Returns information about a block by hash.
+///
+/// The hash of a block is the same as its Tendermint hash.
+pub async fn get_block_by_hash<C>(
+    data: JsonRpcData<C>,
+    Params((block_hash, full_tx)): Params<(ethtypes::H256, bool)>,
+) -> JsonRpcResult<Option<ethtypes::Block<serde_json::Value>>>
+where
+    C: Client + Sync + Send,
+{
+    let hash = tendermint::Hash::Sha256(block_hash.0);
+    let res = data.tm().block_by_hash(hash).await?;
+    match res.block {
+        Some(block) => {
+            let block = data.enrich_block(&block, full_tx).await?;
+            Ok(Some(block))
         }
-        ethtypes::BlockNumber::Earliest => {
-            let res: block::Response = data.client.block(Height::from(1u32)).await?;
-            res.block
+        None => Ok(None),
+    }
+}
+
+/// Returns the information about a transaction requested by transaction hash.
+///
+/// Returns `null` if the transaction is not known or hasn't been included in a block yet.
+pub async fn get_transaction_by_hash<C>(
+    data: JsonRpcData<C>,
+    Params((tx_hash,)): Params<(ethtypes::H256,)>,
+) -> JsonRpcResult<Option<ethtypes::Transaction>>
+where
+    C: Client + Sync + Send,
+{
+    let res = match data.find_tx(tx_hash).await? {
+        Some(res) => res,
+        None => return Ok(None),
+    };
+    let msg = match decode_signed(&res.tx) {
+        Some(msg) => msg,
+        None => return Ok(None),
+    };
+
+    let header: header::Response = data.tm().header(res.height).await?;
+    let block_hash = from_tm::to_eth_block_hash(&header.header.hash());
+    let state_params = data.state_params_during(res.height).await?;
+
+    let tx = data
+        .enrich_transaction(
+            &msg,
+            tx_hash,
+            &state_params,
+            block_hash,
+            res.height,
+            res.index as usize,
+        )
+        .await?;
+
+    Ok(Some(tx))
+}
+
+/// Returns the receipt of a transaction by transaction hash.
+///
+/// Returns `null` if the transaction is not known or hasn't been included in a block yet.
+pub async fn get_transaction_receipt<C>(
+    data: JsonRpcData<C>,
+    Params((tx_hash,)): Params<(ethtypes::H256,)>,
+) -> JsonRpcResult<Option<ethtypes::TransactionReceipt>>
+where
+    C: Client + Sync + Send,
+{
+    let res = match data.find_tx(tx_hash).await? {
+        Some(res) => res,
+        None => return Ok(None),
+    };
+    let msg = match decode_signed(&res.tx) {
+        Some(msg) => msg,
+        None => return Ok(None),
+    };
+
+    let block: block::Response = data.tm().block(res.height).await?;
+    let block_results: block_results::Response = data.tm().block_results(res.height).await?;
+    let block_hash = from_tm::to_eth_block_hash(&block.block.header.hash());
+    let state_params = data.state_params_during(res.height).await?;
+    let index = res.index as usize;
+
+    let tx = data
+        .enrich_transaction(&msg, tx_hash, &state_params, block_hash, res.height, index)
+        .await?;
+
+    // Gas used and logs emitted by this transaction and the ones before it in the block.
+    let results = block_results.txs_results.unwrap_or_default();
+    let cumulative_gas_used = results
+        .iter()
+        .take(index + 1)
+        .map(|r| r.gas_used.max(0) as u64)
+        .sum::<u64>();
+
+    let log_index = results
+        .iter()
+        .take(index)
+        .flat_map(|r| r.events.iter())
+        .filter(|e| {
+            from_tm::event_emitter(e).is_some()
+                && matches!(from_tm::to_eth_log(e, Default::default()), Ok(Some(_)))
+        })
+        .count();
+
+    let mut logs = data.to_eth_logs(&res.tx_result.events, res.height).await?;
+    for (i, log) in logs.iter_mut().enumerate() {
+        log.block_hash = Some(block_hash);
+        log.block_number = Some(ethtypes::U64::from(res.height.value()));
+        log.transaction_hash = Some(tx_hash);
+        log.transaction_index = Some(ethtypes::U64::from(index));
+        log.log_index = Some(ethtypes::U256::from(log_index + i));
+        log.transaction_log_index = Some(ethtypes::U256::from(i));
+    }
+
+    let contract_address = if tx.to.is_none() && res.tx_result.code.is_ok() {
+        let ret = decode_fevm_create(&res.tx_result)?;
+        Some(ethtypes::H160::from(ret.eth_address.0))
+    } else {
+        None
+    };
+
+    let receipt = from_tm::to_eth_receipt(
+        &tx,
+        &res.tx_result,
+        cumulative_gas_used,
+        contract_address,
+        logs,
+    );
+
+    Ok(Some(receipt))
+}
+
+/// Submits a signed legacy or EIP-1559 transaction, returning its hash.
+///
+/// The transaction is turned into an FVM message sent from the delegated address of the signer,
+/// and broadcast to the mempool through Tendermint without waiting for it to be included in a block.
+pub async fn send_raw_transaction<C>(
+    data: JsonRpcData<C>,
+    Params((tx,)): Params<(ethtypes::Bytes,)>,
+) -> JsonRpcResult<ethtypes::TxHash>
+where
+    C: Client + Sync + Send,
+{
+    let rlp = rlp::Rlp::new(tx.as_ref());
+    let (tx, sig): (TypedTransaction, ethtypes::Signature) = TypedTransaction::decode_signed(&rlp)
+        .context("failed to decode RLP as signed TypedTransaction")?;
+
+    let hash = tx.hash(&sig);
+    let msg = SignedMessage::new_eth(&tx, &sig)?;
+    let msg = ChainMessage::Signed(Box::new(msg));
+    let bz = fvm_ipld_encoding::to_vec(&msg).context("failed to encode message")?;
+
+    let res: tx_sync::Response = data.tm().broadcast_tx_sync(bz).await?;
+
+    if res.code.is_ok() {
+        data.notify_pending_tx(hash);
+        Ok(hash)
+    } else {
+        tracing::debug!(?res, "failed to broadcast transaction");
+        // The application error code goes into the data, so it's not mistaken for a JSON-RPC one.
+        Err(jsonrpc_v2::Error::Full {
+            code: SERVER_ERROR,
+            message: res.log,
+            data: Some(Box::new(res.code.value())),
+        })
+    }
+}
+
+/// Returns an array of all logs matching a given filter object.
+pub async fn get_logs<C>(
+    data: JsonRpcData<C>,
+    Params((filter,)): Params<(ethtypes::Filter,)>,
+) -> JsonRpcResult<Vec<ethtypes::Log>>
+where
+    C: Client + Sync + Send,
+{
+    let (from, to) = data.filter_range(&filter).await?;
+    data.logs_in_range(&filter, from, to).await
+}
+
+/// Creates a filter object, based on filter options, to notify when the state changes (logs).
+///
+/// To check if the state has changed, call `eth_getFilterChanges`.
+pub async fn new_filter<C>(
+    data: JsonRpcData<C>,
+    Params((filter,)): Params<(ethtypes::Filter,)>,
+) -> JsonRpcResult<FilterId>
+where
+    C: Client + Sync + Send,
+{
+    if filter.get_block_hash().is_some() {
+        return Err(anyhow!("filters cannot be installed for a block hash").into());
+    }
+    let latest = data.latest_height().await?;
+    Ok(data.install_filter(FilterKind::Logs(Box::new(filter)), latest))
+}
+
+/// Creates a filter in the node, to notify when a new block arrives.
+///
+/// To check if the state has changed, call `eth_getFilterChanges`.
+pub async fn new_block_filter<C>(data: JsonRpcData<C>) -> JsonRpcResult<FilterId>
+where
+    C: Client + Sync + Send,
+{
+    let latest = data.latest_height().await?;
+    Ok(data.install_filter(FilterKind::NewBlocks, latest))
+}
+
+/// Polling method for a filter, which returns an array of logs or block hashes
+/// which occurred since the last poll.
+///
+/// At most [`MAX_BLOCK_RANGE`] blocks are covered by a single poll; the rest is left for the next one.
+pub async fn get_filter_changes<C>(
+    data: JsonRpcData<C>,
+    Params((id,)): Params<(FilterId,)>,
+) -> JsonRpcResult<FilterChanges>
+where
+    C: Client + Sync + Send,
+{
+    let (kind, last_height) = data
+        .poll_filter(&id)
+        .ok_or_else(|| anyhow!("filter not found"))?;
+
+    let latest = data.latest_height().await?;
+    let from = last_height + 1;
+    let to = std::cmp::min(latest, last_height + MAX_BLOCK_RANGE);
+
+    let changes = match kind {
+        FilterKind::NewBlocks => {
+            let hashes = if from <= to {
+                data.block_hashes_in_range(from, to).await?
+            } else {
+                Vec::new()
+            };
+            FilterChanges::BlockHashes(hashes)
+        }
+        FilterKind::Logs(filter) => {
+            let from = std::cmp::max(from, filter.get_from_block().map_or(0, |n| n.as_u64()));
+            let until = filter.get_to_block().map_or(to, |n| n.as_u64());
+            let logs = if from <= std::cmp::min(to, until) {
+                data.logs_in_range(&filter, from, std::cmp::min(to, until))
+                    .await?
+            } else {
+                Vec::new()
+            };
+            FilterChanges::Logs(logs)
         }
     };
 
-    Ok(ethtypes::U64::from(block.data.len()))
+    data.update_filter(&id, std::cmp::max(last_height, to));
+
+    Ok(changes)
+}
+
+/// Returns an array of all logs matching the filter with the given ID.
+pub async fn get_filter_logs<C>(
+    data: JsonRpcData<C>,
+    Params((id,)): Params<(FilterId,)>,
+) -> JsonRpcResult<Vec<ethtypes::Log>>
+where
+    C: Client + Sync + Send,
+{
+    match data.poll_filter(&id) {
+        Some((FilterKind::Logs(filter), _)) => {
+            let (from, to) = data.filter_range(&filter).await?;
+            data.logs_in_range(&filter, from, to).await
+        }
+        _ => Err(anyhow!("filter not found").into()),
+    }
+}
+
+/// Uninstalls a filter with given ID, returning whether it existed.
+///
+/// Filters which aren't polled for a while are uninstalled automatically.
+pub async fn uninstall_filter<C>(
+    data: JsonRpcData<C>,
+    Params((id,)): Params<(FilterId,)>,
+) -> JsonRpcResult<bool>
+where
+    C: Client + Sync + Send,
+{
+    Ok(data.uninstall_filter(&id))
+}
+
+/// Parameters of `eth_call` and `eth_estimateGas`, where the block is optional and defaults to the latest.
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum CallParams {
+    AtBlock(ethtypes::TransactionRequest, ethtypes::BlockNumber),
+    Latest((ethtypes::TransactionRequest,)),
+}
+
+impl CallParams {
+    fn into_parts(self) -> (ethtypes::TransactionRequest, ethtypes::BlockNumber) {
+        match self {
+            CallParams::AtBlock(tx, block_number) => (tx, block_number),
+            CallParams::Latest((tx,)) => (tx, ethtypes::BlockNumber::Latest),
+        }
+    }
+}
+
+/// Multiplier applied to the gas used during estimation, to account for the costs
+/// of a real transaction, such as the signature verification, which a call doesn't incur.
+const GAS_OVER_ESTIMATION: f64 = 1.25;
+
+/// Executes a new message call immediately without creating a transaction on the block chain.
+///
+/// Returns the return value of the executed contract; contract creation returns nothing.
+pub async fn call<C>(
+    data: JsonRpcData<C>,
+    Params(params): Params<CallParams>,
+) -> JsonRpcResult<ethtypes::Bytes>
+where
+    C: Client + Sync + Send,
+{
+    let (tx, block_number) = params.into_parts();
+    let msg = to_fvm_call_message(&tx)?;
+    let is_invoke = msg.method_num == evm::Method::InvokeContract as u64;
+    let height = to_query_height(block_number)?;
+
+    let res = data.client.call(msg, height).await?;
+    let deliver_tx = res.value;
+
+    if deliver_tx.code.is_err() {
+        return Err(call_error(&deliver_tx));
+    }
+
+    if is_invoke && !deliver_tx.data.is_empty() {
+        let return_data = decode_fevm_invoke(&deliver_tx)?;
+        Ok(ethtypes::Bytes::from(return_data))
+    } else {
+        Ok(ethtypes::Bytes::default())
+    }
+}
+
+/// Generates and returns an estimate of how much gas is necessary to allow the transaction to complete.
+///
+/// If the transaction would fail, the error is the same as what `eth_call` would return.
+pub async fn estimate_gas<C>(
+    data: JsonRpcData<C>,
+    Params(params): Params<CallParams>,
+) -> JsonRpcResult<ethtypes::U256>
+where
+    C: Client + Sync + Send,
+{
+    let (tx, block_number) = params.into_parts();
+    let msg = to_fvm_call_message(&tx)?;
+    let height = to_query_height(block_number)?;
+
+    let res = data.client.estimate_gas(msg.clone(), height).await?;
+    let estimate = res.value;
+
+    if !estimate.exit_code.is_success() {
+        // Run it again as a call to get the return data, e.g. the revert reason.
+        let res = data.client.call(msg, height).await?;
+        return Err(call_error(&res.value));
+    }
+
+    let gas = (estimate.gas_limit as f64 * GAS_OVER_ESTIMATION) as u64;
+
+    Ok(ethtypes::U256::from(std::cmp::min(gas, BLOCK_GAS_LIMIT)))
+}
+
+/// Call a read-only method of an EVM contract.
+///
+/// Returns `None` if there is no contract at the address, that is, if there is no actor,
+/// or it doesn't have the method; any other failure is an error.
+async fn read_evm_actor<C>(
+    data: &JsonRpcData<C>,
+    addr: ethtypes::Address,
+    method: evm::Method,
+    params: RawBytes,
+    height: Option<Height>,
+) -> JsonRpcResult<Option<DeliverTx>>
+where
+    C: Client + Sync + Send,
+{
+    let to = to_fvm_address(addr);
+
+    if data.client.actor_state(&to, height).await?.value.is_none() {
+        return Ok(None);
+    }
+
+    let deliver_tx = data.read_actor(to, method as u64, params, height).await?;
+
+    if deliver_tx.code.value() == ExitCode::USR_UNHANDLED_MESSAGE.value() {
+        tracing::debug!(?addr, info = deliver_tx.info, "not an EVM contract");
+        return Ok(None);
+    }
+
+    if deliver_tx.code.is_err() {
+        return Err(call_error(&deliver_tx));
+    }
+
+    Ok(Some(deliver_tx))
+}
+
+/// Turn a failed call into an error.
+///
+/// Reverts are returned in the format Ethereum clients expect, with code 3 and the revert data,
+/// and the reason in the message if the contract reverted with `Error(string)`.
+///
+/// Other failures are server errors, with the FVM exit code in the data, so that it doesn't
+/// get mistaken for a JSON-RPC error code.
+fn call_error(deliver_tx: &DeliverTx) -> jsonrpc_v2::Error {
+    if deliver_tx.code.value() != evm::EVM_CONTRACT_REVERTED.value() {
+        return jsonrpc_v2::Error::Full {
+            code: SERVER_ERROR,
+            message: deliver_tx.info.clone(),
+            data: Some(Box::new(deliver_tx.code.value())),
+        };
+    }
+
+    let revert_data = if deliver_tx.data.is_empty() {
+        Vec::new()
+    } else {
+        decode_fevm_invoke(deliver_tx).unwrap_or_default()
+    };
+
+    let message = match decode_revert_reason(&revert_data) {
+        Some(reason) => format!("execution reverted: {reason}"),
+        None => "execution reverted".to_string(),
+    };
+
+    jsonrpc_v2::Error::Full {
+        code: 3,
+        message,
+        data: Some(Box::new(ethtypes::Bytes::from(revert_data))),
+    }
+}
+
+/// Decode the reason of a revert from the ABI encoding of `Error(string)`.
+fn decode_revert_reason(data: &[u8]) -> Option<String> {
+    const ERROR_SELECTOR: [u8; 4] = [0x08, 0xc3, 0x79, 0xa0];
+
+    if data.len() < 4 || data[..4] != ERROR_SELECTOR {
+        return None;
+    }
+    match abi::decode(&[ParamType::String], &data[4..]).ok()?.pop()? {
+        abi::Token::String(reason) => Some(reason),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU32;
+
+    use ethers_core::abi::{self, Token};
+    use ethers_core::types as ethtypes;
+    use fendermint_vm_actor_interface::evm::EVM_CONTRACT_REVERTED;
+    use fvm_shared::error::ExitCode;
+    use serde_json::json;
+    use tendermint::abci::{response::DeliverTx, Code};
+    use tendermint::Time;
+
+    use crate::jsonrpc::SERVER_ERROR;
+
+    use super::{call_error, decode_revert_reason, estimate_highest_block, CallParams};
+
+    #[test]
+    fn highest_block_extrapolated_from_block_rate() {
+        let time = |secs| Time::from_unix_timestamp(secs, 0).unwrap();
+
+        // One block every 2 seconds, and the latest block we have is 60 seconds old.
+        let highest = estimate_highest_block((100, time(1000)), (200, time(1200)), time(1260));
+        assert_eq!(highest, 230);
+
+        // Nothing to go by.
+        let highest = estimate_highest_block((200, time(1200)), (200, time(1200)), time(1260));
+        assert_eq!(highest, 200);
+
+        // Clocks out of sync.
+        let highest = estimate_highest_block((100, time(1000)), (200, time(1200)), time(1100));
+        assert_eq!(highest, 200);
+    }
+
+    #[test]
+    fn call_params_with_optional_block() {
+        let tx = json!({"to": "0x8888f1f195afa192cfee860698584c030f4c9db1", "data": "0x01"});
+
+        let params: CallParams = serde_json::from_value(json!([tx, "0x10"])).unwrap();
+        let (_, block_number) = params.into_parts();
+        assert_eq!(block_number, ethtypes::BlockNumber::Number(16.into()));
+
+        let params: CallParams = serde_json::from_value(json!([tx])).unwrap();
+        let (tx, block_number) = params.into_parts();
+        assert_eq!(block_number, ethtypes::BlockNumber::Latest);
+        assert_eq!(tx.data, Some(ethtypes::Bytes::from(vec![1u8])));
+    }
+
+    #[test]
+    fn call_error_codes() {
+        let deliver_tx = DeliverTx {
+            code: Code::Err(NonZeroU32::new(ExitCode::SYS_SENDER_INVALID.value()).unwrap()),
+            info: "sender invalid".to_string(),
+            ..Default::default()
+        };
+        let err = serde_json::to_value(call_error(&deliver_tx)).unwrap();
+        assert_eq!(err["code"], json!(SERVER_ERROR));
+        assert_eq!(err["data"], json!(ExitCode::SYS_SENDER_INVALID.value()));
+
+        let deliver_tx = DeliverTx {
+            code: Code::Err(NonZeroU32::new(EVM_CONTRACT_REVERTED.value()).unwrap()),
+            ..Default::default()
+        };
+        let err = serde_json::to_value(call_error(&deliver_tx)).unwrap();
+        assert_eq!(err["code"], json!(3));
+    }
+
+    #[test]
+    fn revert_reason() {
+        let mut data = vec![0x08, 0xc3, 0x79, 0xa0];
+        data.extend(abi::encode(&[Token::String("not enough funds".into())]));
+
+        assert_eq!(
+            decode_revert_reason(&data),
+            Some("not enough funds".to_string())
+        );
+        assert_eq!(decode_revert_reason(&data[4..]), None);
+        assert_eq!(decode_revert_reason(&[]), None);
+    }
 }
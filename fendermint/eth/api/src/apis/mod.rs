@@ -7,10 +7,13 @@ use jsonrpc_v2::{MapRouter, ServerBuilder};
 use paste::paste;
 use tendermint_rpc::HttpClient;
 
+mod debug;
 mod eth;
+mod net;
+mod web3;
 
 macro_rules! with_methods {
-    ($server:ident, $module:ident, { $($method:ident),* }) => {
+    ($server:ident, $module:ident, { $($method:ident),* $(,)? }) => {
         paste!{
             $server
                 $(.with_method(
@@ -25,51 +28,69 @@ pub fn register_methods(server: ServerBuilder<MapRouter>) -> ServerBuilder<MapRo
     // This is the list of eth methods. Apart from these Lotus implements 1 method from web3,
     // while Ethermint does more across web3, debug, miner, net, txpool, and personal.
     // The unimplemented ones are commented out, to make it easier to see where we're at.
-    with_methods!(server, eth, {
+    let server = with_methods!(server, eth, {
         accounts,
         blockNumber,
-        // eth_call
-        // eth_chainId
+        call,
+        chainId,
         // eth_coinbase
         // eth_compileLLL
         // eth_compileSerpent
         // eth_compileSolidity
-        // eth_estimateGas
-        // eth_gasPrice
-        // eth_getBalance
-        // eth_getBlockByHash
-        // eth_getBlockByNumber
+        estimateGas,
+        feeHistory,
+        gasPrice,
+        getBalance,
+        getBlockByHash,
+        getBlockByNumber,
         // eth_getBlockTransactionCountByHash
-        getBlockTransactionCountByNumber
-        // eth_getCode
+        getBlockTransactionCountByNumber,
+        getCode,
         // eth_getCompilers
-        // eth_getFilterChanges
-        // eth_getFilterLogs
-        // eth_getLogs
-        // eth_getStorageAt
+        getFilterChanges,
+        getFilterLogs,
+        getLogs,
+        getStorageAt,
         // eth_getTransactionByBlockHashAndIndex
         // eth_getTransactionByBlockNumberAndIndex
-        // eth_getTransactionByHash
-        // eth_getTransactionCount
-        // eth_getTransactionReceipt
+        getTransactionByHash,
+        getTransactionCount,
+        getTransactionReceipt,
         // eth_getUncleByBlockHashAndIndex
         // eth_getUncleByBlockNumberAndIndex
         // eth_getUncleCountByBlockHash
         // eth_getUncleCountByBlockNumber
         // eth_getWork
         // eth_hashrate
+        maxPriorityFeePerGas,
         // eth_mining
-        // eth_newBlockFilter
-        // eth_newFilter
+        newBlockFilter,
+        newFilter,
         // eth_newPendingTransactionFilter
         // eth_protocolVersion
-        // eth_sendRawTransaction
+        sendRawTransaction,
         // eth_sendTransaction
         // eth_sign
         // eth_signTransaction
         // eth_submitHashrate
         // eth_submitWork
-        // eth_syncing
-        // eth_uninstallFilter
+        syncing,
+        uninstallFilter,
+    });
+
+    let server = with_methods!(server, debug, {
+        traceCall,
+        traceTransaction,
+    });
+
+    let server = with_methods!(server, net, {
+        listening,
+        peerCount,
+        version,
+    });
+
+    with_methods!(server, web3, {
+        clientVersion,
+        // web3_sha3
     })
 }
@@ -0,0 +1,35 @@
+// Copyright 2022-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+use ethers_core::types as ethtypes;
+use fendermint_rpc::query::QueryClient;
+use tendermint_rpc::{endpoint::net_info, Client};
+
+use crate::{JsonRpcData, JsonRpcResult};
+
+/// Returns the current network ID, which is the same as the chain ID, as a decimal string.
+pub async fn version<C>(data: JsonRpcData<C>) -> JsonRpcResult<String>
+where
+    C: Client + Sync + Send,
+{
+    let res = data.client.state_params(None).await?;
+    Ok(res.value.chain_id.to_string())
+}
+
+/// Returns `true` if the Tendermint node is actively listening for network connections.
+pub async fn listening<C>(data: JsonRpcData<C>) -> JsonRpcResult<bool>
+where
+    C: Client + Sync + Send,
+{
+    let res: net_info::Response = data.tm().net_info().await?;
+    Ok(res.listening)
+}
+
+/// Returns the number of peers currently connected to the Tendermint node.
+pub async fn peer_count<C>(data: JsonRpcData<C>) -> JsonRpcResult<ethtypes::U64>
+where
+    C: Client + Sync + Send,
+{
+    let res: net_info::Response = data.tm().net_info().await?;
+    Ok(ethtypes::U64::from(res.n_peers))
+}
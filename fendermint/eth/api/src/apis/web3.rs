@@ -0,0 +1,20 @@
+// Copyright 2022-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+use tendermint_rpc::{endpoint::status, Client};
+
+use crate::{JsonRpcData, JsonRpcResult};
+
+/// Returns the current client version, along with the version of Tendermint it's running with.
+pub async fn client_version<C>(data: JsonRpcData<C>) -> JsonRpcResult<String>
+where
+    C: Client + Sync + Send,
+{
+    let res: status::Response = data.tm().status().await?;
+    let version = format!(
+        "fendermint/v{}/tendermint/v{}",
+        env!("CARGO_PKG_VERSION"),
+        res.node_info.version
+    );
+    Ok(version)
+}
@@ -0,0 +1,377 @@
+// Copyright 2022-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Access control of the JSON-RPC methods with bearer tokens and rate limits.
+//!
+//! The bearer token can be one of the static tokens in the configuration, or a JWT
+//! signed with HS256 using the configured secret, which lists the methods it can call
+//! in a `methods` claim, e.g. `{"sub": "alice", "methods": ["eth_get*"], "exp": 1700000000}`.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use axum::http::{header, HeaderMap, StatusCode};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+
+use crate::rate_limit::RateLimiter;
+
+/// A bearer token and the methods it can call.
+#[derive(Debug, Clone)]
+pub struct TokenAccess {
+    pub token: String,
+    /// Method names, or prefixes ending with `*`, e.g. `eth_get*`; `*` allows everything.
+    pub methods: Vec<String>,
+}
+
+/// Access control options.
+#[derive(Debug, Clone)]
+pub struct AccessConfig {
+    /// If there are any tokens, every request has to carry one of them.
+    pub tokens: Vec<TokenAccess>,
+    /// Secret to verify JWTs with; if there is one, every request has to carry a token.
+    pub jwt_secret: Option<Vec<u8>>,
+    /// Length of the windows in which the number of requests is limited.
+    pub rate_limit_window: Duration,
+    /// Maximum number of requests from the same IP address in a window; 0 means unlimited.
+    pub max_requests_per_ip: u32,
+    /// Maximum number of requests with the same token in a window; 0 means unlimited.
+    pub max_requests_per_token: u32,
+}
+
+impl Default for AccessConfig {
+    fn default() -> Self {
+        Self {
+            tokens: Vec::new(),
+            jwt_secret: None,
+            rate_limit_window: Duration::from_secs(1),
+            max_requests_per_ip: 0,
+            max_requests_per_token: 0,
+        }
+    }
+}
+
+/// Reasons to reject a request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AccessError {
+    /// The token is missing or unknown.
+    Unauthorized,
+    /// The token doesn't allow calling the method.
+    Forbidden(String),
+    /// Too many requests in the current window.
+    TooManyRequests,
+}
+
+impl AccessError {
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            AccessError::Unauthorized => StatusCode::UNAUTHORIZED,
+            AccessError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AccessError::TooManyRequests => StatusCode::TOO_MANY_REQUESTS,
+        }
+    }
+
+    pub fn to_rpc_error(&self) -> jsonrpc_v2::Error {
+        let (code, message) = match self {
+            AccessError::Unauthorized => (-32001, "unauthorized".to_string()),
+            AccessError::Forbidden(method) => (-32002, format!("method not allowed: {method}")),
+            AccessError::TooManyRequests => (-32005, "too many requests".to_string()),
+        };
+        jsonrpc_v2::Error::Full {
+            code,
+            message,
+            data: None,
+        }
+    }
+}
+
+/// An authenticated caller.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Caller {
+    /// Identifies the caller for rate limiting: the static token, or the subject of the JWT.
+    key: String,
+    /// Method names or prefixes the caller can call.
+    methods: Vec<String>,
+}
+
+/// The claims we care about in a JWT.
+#[derive(Deserialize)]
+struct JwtClaims {
+    #[serde(default)]
+    sub: Option<String>,
+    #[serde(default)]
+    exp: Option<u64>,
+    methods: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct JwtHeader {
+    alg: String,
+}
+
+/// Authenticate callers, check the methods they can call and limit their request rates.
+pub struct AccessControl {
+    tokens: HashMap<String, Vec<String>>,
+    jwt_secret: Option<Vec<u8>>,
+    ip_limiter: RateLimiter<IpAddr>,
+    token_limiter: RateLimiter<String>,
+}
+
+impl AccessControl {
+    pub fn new(config: AccessConfig) -> Self {
+        Self {
+            tokens: config
+                .tokens
+                .into_iter()
+                .map(|t| (t.token, t.methods))
+                .collect(),
+            jwt_secret: config.jwt_secret,
+            ip_limiter: RateLimiter::new(config.rate_limit_window, config.max_requests_per_ip),
+            token_limiter: RateLimiter::new(
+                config.rate_limit_window,
+                config.max_requests_per_token,
+            ),
+        }
+    }
+
+    /// Check the bearer token in the `Authorization` header, if tokens are required,
+    /// returning the caller who authenticated with it.
+    pub fn authenticate(&self, headers: &HeaderMap) -> Result<Option<Caller>, AccessError> {
+        if self.tokens.is_empty() && self.jwt_secret.is_none() {
+            return Ok(None);
+        }
+
+        let token = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .map(|t| t.trim())
+            .ok_or(AccessError::Unauthorized)?;
+
+        if let Some(methods) = self.tokens.get(token) {
+            return Ok(Some(Caller {
+                key: token.to_owned(),
+                methods: methods.clone(),
+            }));
+        }
+
+        if let Some(ref secret) = self.jwt_secret {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default();
+
+            if let Some(claims) = verify_jwt(secret, token, now) {
+                return Ok(Some(Caller {
+                    // Without a subject, every JWT is limited on its own.
+                    key: format!("jwt:{}", claims.sub.as_deref().unwrap_or(token)),
+                    methods: claims.methods,
+                }));
+            }
+        }
+
+        Err(AccessError::Unauthorized)
+    }
+
+    /// Count a number of calls against the limits of the IP address and the caller.
+    ///
+    /// Every call in a batch counts as a separate request.
+    pub fn check_rate(
+        &self,
+        ip: IpAddr,
+        caller: Option<&Caller>,
+        calls: u32,
+    ) -> Result<(), AccessError> {
+        if !self.ip_limiter.check(ip, calls) {
+            return Err(AccessError::TooManyRequests);
+        }
+        if let Some(caller) = caller {
+            if !self.token_limiter.check(caller.key.clone(), calls) {
+                return Err(AccessError::TooManyRequests);
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that the caller is allowed to call the method.
+    pub fn authorize(&self, caller: Option<&Caller>, method: &str) -> Result<(), AccessError> {
+        let caller = match caller {
+            // No tokens are configured, so everything is allowed.
+            None => return Ok(()),
+            Some(caller) => caller,
+        };
+
+        if caller.methods.iter().any(|p| matches_method(p, method)) {
+            Ok(())
+        } else {
+            Err(AccessError::Forbidden(method.to_owned()))
+        }
+    }
+}
+
+/// Check the signature and the expiry of a JWT signed with HS256, returning its claims.
+fn verify_jwt(secret: &[u8], token: &str, now: u64) -> Option<JwtClaims> {
+    let mut parts = token.split('.');
+    let (header, payload, signature) = (parts.next()?, parts.next()?, parts.next()?);
+    if parts.next().is_some() {
+        return None;
+    }
+
+    let decode = |part: &str| URL_SAFE_NO_PAD.decode(part).ok();
+
+    let jwt_header = serde_json::from_slice::<JwtHeader>(&decode(header)?).ok()?;
+    if jwt_header.alg != "HS256" {
+        return None;
+    }
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).ok()?;
+    mac.update(header.as_bytes());
+    mac.update(b".");
+    mac.update(payload.as_bytes());
+    mac.verify_slice(&decode(signature)?).ok()?;
+
+    let claims = serde_json::from_slice::<JwtClaims>(&decode(payload)?).ok()?;
+
+    match claims.exp {
+        Some(exp) if exp <= now => None,
+        _ => Some(claims),
+    }
+}
+
+fn matches_method(pattern: &str, method: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => method.starts_with(prefix),
+        None => pattern == method,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::IpAddr;
+
+    use axum::http::{header, HeaderMap, HeaderValue};
+    use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+    use base64::Engine;
+    use hmac::{Hmac, Mac};
+    use serde_json::json;
+    use sha2::Sha256;
+
+    use super::{AccessConfig, AccessControl, AccessError, TokenAccess};
+
+    fn bearer(token: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_str(&format!("Bearer {token}")).unwrap(),
+        );
+        headers
+    }
+
+    #[test]
+    fn open_without_tokens() {
+        let ac = AccessControl::new(AccessConfig::default());
+        assert_eq!(ac.authenticate(&HeaderMap::new()), Ok(None));
+        assert!(ac.authorize(None, "eth_sendRawTransaction").is_ok());
+    }
+
+    #[test]
+    fn tokens_with_allowed_methods() {
+        let ac = AccessControl::new(AccessConfig {
+            tokens: vec![
+                TokenAccess {
+                    token: "admin".into(),
+                    methods: vec!["*".into()],
+                },
+                TokenAccess {
+                    token: "reader".into(),
+                    methods: vec!["eth_get*".into(), "eth_call".into()],
+                },
+            ],
+            ..Default::default()
+        });
+
+        assert_eq!(
+            ac.authenticate(&HeaderMap::new()),
+            Err(AccessError::Unauthorized)
+        );
+        assert_eq!(
+            ac.authenticate(&bearer("unknown")),
+            Err(AccessError::Unauthorized)
+        );
+
+        let caller = ac.authenticate(&bearer("reader")).unwrap();
+        let caller = caller.as_ref();
+        assert!(ac.authorize(caller, "eth_getBalance").is_ok());
+        assert!(ac.authorize(caller, "eth_call").is_ok());
+        assert!(ac.authorize(caller, "eth_sendRawTransaction").is_err());
+
+        let caller = ac.authenticate(&bearer("admin")).unwrap();
+        assert!(ac
+            .authorize(caller.as_ref(), "eth_sendRawTransaction")
+            .is_ok());
+    }
+
+    fn jwt(secret: &[u8], claims: serde_json::Value) -> String {
+        let header = URL_SAFE_NO_PAD.encode(r#"{"alg":"HS256","typ":"JWT"}"#);
+        let payload = URL_SAFE_NO_PAD.encode(claims.to_string());
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret).unwrap();
+        mac.update(format!("{header}.{payload}").as_bytes());
+        let signature = URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes());
+        format!("{header}.{payload}.{signature}")
+    }
+
+    #[test]
+    fn jwt_with_allowed_methods() {
+        let secret = b"secret".to_vec();
+        let ac = AccessControl::new(AccessConfig {
+            jwt_secret: Some(secret.clone()),
+            ..Default::default()
+        });
+
+        assert_eq!(
+            ac.authenticate(&HeaderMap::new()),
+            Err(AccessError::Unauthorized)
+        );
+
+        let token = jwt(&secret, json!({"sub": "alice", "methods": ["eth_get*"]}));
+        let caller = ac.authenticate(&bearer(&token)).unwrap();
+        assert!(ac.authorize(caller.as_ref(), "eth_getBalance").is_ok());
+        assert!(ac
+            .authorize(caller.as_ref(), "eth_sendRawTransaction")
+            .is_err());
+
+        // Signed with a different secret.
+        let token = jwt(b"other", json!({"methods": ["*"]}));
+        assert_eq!(
+            ac.authenticate(&bearer(&token)),
+            Err(AccessError::Unauthorized)
+        );
+
+        // Expired.
+        let token = jwt(&secret, json!({"methods": ["*"], "exp": 1}));
+        assert_eq!(
+            ac.authenticate(&bearer(&token)),
+            Err(AccessError::Unauthorized)
+        );
+    }
+
+    #[test]
+    fn batch_calls_count_against_rate_limit() {
+        let ac = AccessControl::new(AccessConfig {
+            max_requests_per_ip: 10,
+            ..Default::default()
+        });
+        let ip = IpAddr::from([127, 0, 0, 1]);
+
+        assert!(ac.check_rate(ip, None, 8).is_ok());
+        assert_eq!(
+            ac.check_rate(ip, None, 3),
+            Err(AccessError::TooManyRequests)
+        );
+        assert!(ac.check_rate(ip, None, 2).is_ok());
+    }
+}
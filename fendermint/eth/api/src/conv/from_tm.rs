@@ -0,0 +1,477 @@
+// Copyright 2022-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Helper methods to convert between Tendermint and Ethereum types.
+
+use anyhow::Context;
+use ethers_core::abi::ethereum_types::BloomInput;
+use ethers_core::types as et;
+use ethers_core::utils::hex;
+use ethers_core::utils::keccak256;
+use fendermint_vm_actor_interface::{eam, evm};
+use fendermint_vm_message::conv::from_fvm::to_u256;
+use fendermint_vm_message::signed::{EthTransactionType, MessageSignature, SignedMessage};
+use fvm_ipld_encoding::BytesDe;
+use fvm_shared::{chainid::ChainID, econ::TokenAmount, ActorID, BLOCK_GAS_LIMIT};
+use tendermint::abci;
+use tendermint::crypto::Sha256;
+use tendermint_rpc::endpoint::block_results;
+
+/// Ethereum block hashes are the Tendermint block hashes, which are SHA256 digests.
+pub fn to_eth_block_hash(hash: &tendermint::Hash) -> et::H256 {
+    match hash {
+        tendermint::Hash::Sha256(bz) => et::H256::from(*bz),
+        tendermint::Hash::None => et::H256::zero(),
+    }
+}
+
+/// The hash Tendermint uses to look up transactions.
+pub fn to_tm_tx_hash(tx: &[u8]) -> tendermint::Hash {
+    tendermint::Hash::Sha256(tendermint::crypto::default::Sha256::digest(tx))
+}
+
+/// The hash of a transaction as seen by Ethereum clients.
+///
+/// Messages created from Ethereum transactions have the hash of the signed transaction,
+/// which is what `eth_sendRawTransaction` returned, while everything else has the
+/// SHA256 hash Tendermint uses.
+pub fn to_eth_tx_hash(
+    msg: &SignedMessage,
+    tx: &[u8],
+    chain_id: &ChainID,
+) -> anyhow::Result<et::H256> {
+    match msg.eth_tx_hash(chain_id)? {
+        Some(hash) => Ok(hash),
+        None => Ok(to_eth_block_hash(&to_tm_tx_hash(tx))),
+    }
+}
+
+/// Convert a signed message included in a block into an Ethereum transaction.
+///
+/// The sender and the recipient have to be resolved to their Ethereum form by the caller,
+/// because it might need a lookup of their actor ID.
+#[allow(clippy::too_many_arguments)]
+pub fn to_eth_transaction(
+    msg: &SignedMessage,
+    hash: et::H256,
+    from: et::H160,
+    to: Option<et::H160>,
+    chain_id: &ChainID,
+    base_fee: &TokenAmount,
+    block_hash: et::H256,
+    block_number: u64,
+    transaction_index: usize,
+) -> anyhow::Result<et::Transaction> {
+    let m = &msg.message;
+
+    let is_create =
+        m.to == eam::EAM_ACTOR_ADDR && m.method_num == eam::Method::CreateExternal as u64;
+    let is_invoke = m.method_num == evm::Method::InvokeContract as u64;
+
+    let input = if is_create || is_invoke {
+        let BytesDe(data) = m
+            .params
+            .deserialize()
+            .context("failed to decode calldata")?;
+        data
+    } else {
+        m.params.to_vec()
+    };
+
+    // The price the sender actually paid, which is capped by the fee cap.
+    let effective_gas_price = std::cmp::min(m.gas_fee_cap.clone(), base_fee + &m.gas_premium);
+
+    let (transaction_type, v, r, s) = match msg.signature() {
+        MessageSignature::Ethereum(sig) => {
+            let esig = sig.to_eth_signature(chain_id);
+            let tx_type = match sig.tx_type {
+                EthTransactionType::Legacy => 0u64,
+                EthTransactionType::Eip1559 => 2u64,
+            };
+            (tx_type, esig.v, esig.r, esig.s)
+        }
+        MessageSignature::Filecoin(_) => (2u64, 0, et::U256::zero(), et::U256::zero()),
+    };
+
+    let tx = et::Transaction {
+        hash,
+        nonce: et::U256::from(m.sequence),
+        block_hash: Some(block_hash),
+        block_number: Some(et::U64::from(block_number)),
+        transaction_index: Some(et::U64::from(transaction_index)),
+        from,
+        to: if is_create { None } else { to },
+        value: to_u256(&m.value)?,
+        gas_price: Some(to_u256(&effective_gas_price)?),
+        gas: et::U256::from(m.gas_limit),
+        input: et::Bytes::from(input),
+        v: et::U64::from(v),
+        r,
+        s,
+        transaction_type: Some(et::U64::from(transaction_type)),
+        access_list: None,
+        max_priority_fee_per_gas: Some(to_u256(&m.gas_premium)?),
+        max_fee_per_gas: Some(to_u256(&m.gas_fee_cap)?),
+        chain_id: Some(et::U256::from(u64::from(*chain_id))),
+        other: Default::default(),
+    };
+
+    Ok(tx)
+}
+
+/// Convert a Tendermint block into an Ethereum one, with the transactions already converted.
+///
+/// The gas used is summed up from the results of the transactions in the block.
+pub fn to_eth_block<T>(
+    block: &tendermint::Block,
+    block_results: &block_results::Response,
+    base_fee: &TokenAmount,
+    transactions: Vec<T>,
+) -> anyhow::Result<et::Block<T>>
+where
+    T: Default,
+{
+    let header = &block.header;
+
+    let gas_used = block_results
+        .txs_results
+        .as_ref()
+        .map(|rs| rs.iter().map(|r| r.gas_used.max(0) as u64).sum::<u64>())
+        .unwrap_or_default();
+
+    let app_hash = header.app_hash.as_bytes();
+    let state_root = if app_hash.len() == 32 {
+        et::H256::from_slice(app_hash)
+    } else {
+        et::H256::from(keccak256(app_hash))
+    };
+
+    let block = et::Block {
+        hash: Some(to_eth_block_hash(&block.header.hash())),
+        parent_hash: header
+            .last_block_id
+            .map(|id| to_eth_block_hash(&id.hash))
+            .unwrap_or_default(),
+        author: Some(et::H160::from_slice(header.proposer_address.as_bytes())),
+        state_root,
+        transactions_root: header
+            .data_hash
+            .map(|h| to_eth_block_hash(&h))
+            .unwrap_or_default(),
+        number: Some(et::U64::from(header.height.value())),
+        gas_used: et::U256::from(gas_used),
+        gas_limit: et::U256::from(BLOCK_GAS_LIMIT),
+        timestamp: et::U256::from(header.time.unix_timestamp().max(0) as u64),
+        transactions,
+        base_fee_per_gas: Some(to_u256(base_fee)?),
+        ..Default::default()
+    };
+
+    Ok(block)
+}
+
+/// The ID of the actor which emitted an event, if the event came from an actor.
+///
+/// See `to_events` in the app for how the events are created.
+pub fn event_emitter(event: &abci::Event) -> Option<ActorID> {
+    if event.kind != "message" {
+        return None;
+    }
+    event
+        .attributes
+        .iter()
+        .find(|a| a.key == "emitter")
+        .and_then(|a| a.value.parse().ok())
+}
+
+/// Reconstruct an Ethereum log from an event emitted by an actor, as long as it has the
+/// `t1`..`t4` topic and `d` data entries the EVM emits; otherwise return `None`.
+///
+/// The EVM leaves out the `d` entry when there is no data, so `LOG0` with empty data
+/// results in an event without any entries, which is still a log.
+///
+/// Only the address, the topics and the data are filled in.
+pub fn to_eth_log(event: &abci::Event, address: et::H160) -> anyhow::Result<Option<et::Log>> {
+    let mut topics = Vec::new();
+    let mut data = None;
+
+    for attr in event.attributes.iter() {
+        let decode = || {
+            hex::decode(&attr.value)
+                .with_context(|| format!("failed to decode event entry {}", attr.key))
+        };
+        match attr.key.as_str() {
+            "emitter" => {}
+            "t1" | "t2" | "t3" | "t4" => {
+                let bz = decode()?;
+                if bz.len() != 32 {
+                    return Ok(None);
+                }
+                topics.push((attr.key.clone(), et::H256::from_slice(&bz)));
+            }
+            "d" => data = Some(decode()?),
+            _ => return Ok(None),
+        }
+    }
+
+    topics.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let log = et::Log {
+        address,
+        topics: topics.into_iter().map(|(_, t)| t).collect(),
+        data: et::Bytes::from(data.unwrap_or_default()),
+        removed: Some(false),
+        ..Default::default()
+    };
+
+    Ok(Some(log))
+}
+
+/// Create the bloom filter of a set of logs.
+pub fn to_logs_bloom(logs: &[et::Log]) -> et::Bloom {
+    let mut bloom = et::Bloom::default();
+    for log in logs {
+        bloom.accrue(BloomInput::Raw(log.address.as_bytes()));
+        for topic in log.topics.iter() {
+            bloom.accrue(BloomInput::Raw(topic.as_bytes()));
+        }
+    }
+    bloom
+}
+
+/// Create the receipt of a transaction from the result of its execution.
+pub fn to_eth_receipt(
+    tx: &et::Transaction,
+    result: &abci::response::DeliverTx,
+    cumulative_gas_used: u64,
+    contract_address: Option<et::H160>,
+    logs: Vec<et::Log>,
+) -> et::TransactionReceipt {
+    et::TransactionReceipt {
+        transaction_hash: tx.hash,
+        transaction_index: tx.transaction_index.unwrap_or_default(),
+        block_hash: tx.block_hash,
+        block_number: tx.block_number,
+        from: tx.from,
+        to: tx.to,
+        cumulative_gas_used: et::U256::from(cumulative_gas_used),
+        gas_used: Some(et::U256::from(result.gas_used.max(0) as u64)),
+        contract_address,
+        logs_bloom: to_logs_bloom(&logs),
+        logs,
+        status: Some(et::U64::from(if result.code.is_ok() { 1 } else { 0 })),
+        root: None,
+        transaction_type: tx.transaction_type,
+        effective_gas_price: tx.gas_price,
+        other: Default::default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers_core::abi::ethereum_types::BloomInput;
+    use ethers_core::types as et;
+    use fendermint_vm_message::signed::{MessageSignature, SignedMessage};
+    use fvm_ipld_encoding::RawBytes;
+    use fvm_shared::{
+        address::Address, chainid::ChainID, crypto::signature::Signature, econ::TokenAmount,
+        message::Message, METHOD_SEND,
+    };
+    use tendermint::abci::{response::DeliverTx, Code, Event, EventAttribute};
+    use tendermint::crypto::Sha256;
+
+    use super::{
+        event_emitter, to_eth_block_hash, to_eth_log, to_eth_receipt, to_eth_transaction,
+        to_eth_tx_hash, to_tm_tx_hash,
+    };
+
+    fn event(attrs: &[(&str, &str)]) -> Event {
+        Event::new(
+            "message",
+            attrs
+                .iter()
+                .map(|(k, v)| EventAttribute {
+                    key: k.to_string(),
+                    value: v.to_string(),
+                    index: true,
+                })
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    #[test]
+    fn evm_event_to_log() {
+        let t1 = "11".repeat(32);
+        let t2 = "22".repeat(32);
+        // Attributes in a different order than the topics should be.
+        let e = event(&[("emitter", "1001"), ("t2", &t2), ("d", "abcd"), ("t1", &t1)]);
+
+        assert_eq!(event_emitter(&e), Some(1001));
+
+        let address = et::H160::repeat_byte(1);
+        let log = to_eth_log(&e, address).unwrap().expect("should be a log");
+
+        assert_eq!(log.address, address);
+        assert_eq!(
+            log.topics,
+            vec![et::H256::repeat_byte(0x11), et::H256::repeat_byte(0x22)]
+        );
+        assert_eq!(log.data.to_vec(), vec![0xab, 0xcd]);
+    }
+
+    #[test]
+    fn empty_evm_event_to_log() {
+        // What `LOG0` with no data looks like.
+        let e = event(&[("emitter", "1001")]);
+
+        let log = to_eth_log(&e, Default::default())
+            .unwrap()
+            .expect("should be a log");
+
+        assert!(log.topics.is_empty());
+        assert!(log.data.is_empty());
+    }
+
+    #[test]
+    fn non_evm_event_is_skipped() {
+        let e = event(&[("emitter", "1001"), ("foo", "01")]);
+        assert!(to_eth_log(&e, Default::default()).unwrap().is_none());
+
+        let e = Event::new("begin", vec![("emitter", "1001")]);
+        assert!(event_emitter(&e).is_none());
+    }
+
+    fn filecoin_message() -> SignedMessage {
+        let message = Message {
+            version: 0,
+            from: Address::new_id(100),
+            to: Address::new_id(101),
+            sequence: 5,
+            value: TokenAmount::from_atto(1000),
+            method_num: METHOD_SEND,
+            params: RawBytes::new(vec![1, 2, 3]),
+            gas_limit: 10_000_000,
+            gas_fee_cap: TokenAmount::from_atto(300),
+            gas_premium: TokenAmount::from_atto(50),
+        };
+        SignedMessage::new_unchecked(
+            message,
+            MessageSignature::Filecoin(Signature::new_secp256k1(vec![0; 65])),
+        )
+    }
+
+    #[test]
+    fn tendermint_hashes() {
+        assert_eq!(to_eth_block_hash(&tendermint::Hash::None), et::H256::zero());
+        assert_eq!(
+            to_eth_block_hash(&tendermint::Hash::Sha256([7; 32])),
+            et::H256::repeat_byte(7)
+        );
+
+        let tx = b"transaction";
+        let digest = tendermint::crypto::default::Sha256::digest(tx);
+        assert_eq!(to_tm_tx_hash(tx), tendermint::Hash::Sha256(digest));
+
+        // Messages not coming from Ethereum use the Tendermint hash.
+        let hash = to_eth_tx_hash(&filecoin_message(), tx, &ChainID::from(1)).unwrap();
+        assert_eq!(hash, et::H256::from(digest));
+    }
+
+    #[test]
+    fn filecoin_message_to_transaction() {
+        let msg = filecoin_message();
+        let from = et::H160::repeat_byte(1);
+        let to = et::H160::repeat_byte(2);
+        let block_hash = et::H256::repeat_byte(3);
+        let chain_id = ChainID::from(1234);
+
+        let check = |base_fee: u64, gas_price: u64| {
+            let tx = to_eth_transaction(
+                &msg,
+                et::H256::repeat_byte(4),
+                from,
+                Some(to),
+                &chain_id,
+                &TokenAmount::from_atto(base_fee),
+                block_hash,
+                10,
+                2,
+            )
+            .unwrap();
+
+            assert_eq!(tx.from, from);
+            assert_eq!(tx.to, Some(to));
+            assert_eq!(tx.nonce, et::U256::from(5));
+            assert_eq!(tx.value, et::U256::from(1000));
+            assert_eq!(tx.gas, et::U256::from(10_000_000));
+            assert_eq!(tx.input.to_vec(), vec![1, 2, 3]);
+            assert_eq!(tx.block_hash, Some(block_hash));
+            assert_eq!(tx.block_number, Some(et::U64::from(10)));
+            assert_eq!(tx.transaction_index, Some(et::U64::from(2)));
+            assert_eq!(tx.transaction_type, Some(et::U64::from(2)));
+            assert_eq!(tx.chain_id, Some(et::U256::from(1234)));
+            assert_eq!(tx.max_fee_per_gas, Some(et::U256::from(300)));
+            assert_eq!(tx.max_priority_fee_per_gas, Some(et::U256::from(50)));
+            assert_eq!(tx.gas_price, Some(et::U256::from(gas_price)));
+        };
+
+        // The base fee plus the premium, while it's below the cap.
+        check(100, 150);
+        // Otherwise the fee cap.
+        check(280, 300);
+    }
+
+    #[test]
+    fn receipt_from_result() {
+        let tx = et::Transaction {
+            hash: et::H256::repeat_byte(1),
+            block_hash: Some(et::H256::repeat_byte(2)),
+            block_number: Some(et::U64::from(10)),
+            transaction_index: Some(et::U64::from(3)),
+            from: et::H160::repeat_byte(4),
+            to: Some(et::H160::repeat_byte(5)),
+            gas_price: Some(et::U256::from(150)),
+            transaction_type: Some(et::U64::from(2)),
+            ..Default::default()
+        };
+
+        let log = et::Log {
+            address: et::H160::repeat_byte(6),
+            topics: vec![et::H256::repeat_byte(7)],
+            ..Default::default()
+        };
+
+        let result = DeliverTx {
+            gas_used: 1000,
+            ..Default::default()
+        };
+
+        let receipt = to_eth_receipt(&tx, &result, 5000, None, vec![log.clone()]);
+
+        assert_eq!(receipt.transaction_hash, tx.hash);
+        assert_eq!(receipt.transaction_index, et::U64::from(3));
+        assert_eq!(receipt.block_hash, tx.block_hash);
+        assert_eq!(receipt.block_number, tx.block_number);
+        assert_eq!(receipt.from, tx.from);
+        assert_eq!(receipt.to, tx.to);
+        assert_eq!(receipt.cumulative_gas_used, et::U256::from(5000));
+        assert_eq!(receipt.gas_used, Some(et::U256::from(1000)));
+        assert_eq!(receipt.effective_gas_price, Some(et::U256::from(150)));
+        assert_eq!(receipt.status, Some(et::U64::from(1)));
+        assert_eq!(receipt.logs, vec![log.clone()]);
+        assert!(receipt
+            .logs_bloom
+            .contains_input(BloomInput::Raw(log.address.as_bytes())));
+        assert!(receipt
+            .logs_bloom
+            .contains_input(BloomInput::Raw(log.topics[0].as_bytes())));
+
+        let result = DeliverTx {
+            code: Code::from(1),
+            ..Default::default()
+        };
+        let receipt = to_eth_receipt(&tx, &result, 5000, None, Vec::new());
+        assert_eq!(receipt.status, Some(et::U64::from(0)));
+        assert_eq!(receipt.logs_bloom, et::Bloom::default());
+    }
+}
@@ -0,0 +1,60 @@
+// Copyright 2022-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Cross-origin resource sharing, so that browser based applications can call the API.
+
+use std::sync::Arc;
+
+use axum::http::{header, HeaderValue, Method, Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+
+/// Origins allowed to make cross-origin requests; `*` allows any.
+#[derive(Debug, Clone, Default)]
+pub struct CorsOrigins(pub Vec<String>);
+
+impl CorsOrigins {
+    fn allows(&self, origin: &str) -> bool {
+        self.0.iter().any(|o| o == "*" || o == origin)
+    }
+}
+
+/// Answer preflight requests and add the CORS headers to responses for allowed origins.
+///
+/// Requests from other origins are served without the headers, which means browsers will block them.
+pub async fn handle<B>(
+    axum::extract::State(origins): axum::extract::State<Arc<CorsOrigins>>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let origin = match request.headers().get(header::ORIGIN) {
+        Some(origin) if origin.to_str().map_or(false, |o| origins.allows(o)) => origin.clone(),
+        _ => return next.run(request).await,
+    };
+
+    let mut response = if request.method() == Method::OPTIONS {
+        let mut response = StatusCode::NO_CONTENT.into_response();
+        let headers = response.headers_mut();
+        headers.insert(
+            header::ACCESS_CONTROL_ALLOW_METHODS,
+            HeaderValue::from_static("GET, POST, OPTIONS"),
+        );
+        headers.insert(
+            header::ACCESS_CONTROL_ALLOW_HEADERS,
+            HeaderValue::from_static("authorization, content-type"),
+        );
+        headers.insert(
+            header::ACCESS_CONTROL_MAX_AGE,
+            HeaderValue::from_static("600"),
+        );
+        response
+    } else {
+        next.run(request).await
+    };
+
+    let headers = response.headers_mut();
+    headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+    headers.insert(header::VARY, HeaderValue::from_static("Origin"));
+
+    response
+}
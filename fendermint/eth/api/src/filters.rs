@@ -0,0 +1,116 @@
+// Copyright 2022-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Server side filters, which clients poll for changes since their last call.
+
+use std::time::Instant;
+
+use ethers_core::types as et;
+use serde::Serialize;
+
+pub type FilterId = et::U256;
+
+/// What the filter is looking for.
+#[derive(Clone, Debug)]
+pub enum FilterKind {
+    /// Logs matching the criteria, emitted in blocks after the last poll.
+    Logs(Box<et::Filter>),
+    /// Hashes of blocks added since the last poll.
+    NewBlocks,
+}
+
+/// A filter installed by a client.
+#[derive(Debug)]
+pub struct FilterState {
+    pub kind: FilterKind,
+    /// The last block height included in the changes returned so far.
+    pub last_height: u64,
+    /// Filters which aren't polled for a while are uninstalled.
+    pub last_polled: Instant,
+}
+
+impl FilterState {
+    pub fn new(kind: FilterKind, last_height: u64) -> Self {
+        Self {
+            kind,
+            last_height,
+            last_polled: Instant::now(),
+        }
+    }
+}
+
+/// Result of polling a filter, which depends on its kind.
+#[derive(Serialize, Debug)]
+#[serde(untagged)]
+pub enum FilterChanges {
+    Logs(Vec<et::Log>),
+    BlockHashes(Vec<et::H256>),
+}
+
+/// Check whether an address is accepted by the filter.
+pub fn matches_address(filter: &et::Filter, address: &et::H160) -> bool {
+    match filter.address {
+        None => true,
+        Some(et::ValueOrArray::Value(ref a)) => a == address,
+        Some(et::ValueOrArray::Array(ref addrs)) => addrs.is_empty() || addrs.contains(address),
+    }
+}
+
+/// Check whether the topics of a log are accepted by the filter.
+///
+/// Each position in the filter is either empty, which matches anything,
+/// or a list of alternatives, one of which has to be at the same position in the log.
+pub fn matches_topics(filter: &et::Filter, topics: &[et::H256]) -> bool {
+    filter.topics.iter().enumerate().all(|(i, t)| {
+        let alternatives = match t {
+            None | Some(et::ValueOrArray::Value(None)) => return true,
+            Some(et::ValueOrArray::Value(Some(h))) => vec![*h],
+            Some(et::ValueOrArray::Array(hs)) => {
+                if hs.is_empty() || hs.iter().any(|h| h.is_none()) {
+                    return true;
+                }
+                hs.iter().flatten().cloned().collect()
+            }
+        };
+        match topics.get(i) {
+            None => false,
+            Some(topic) => alternatives.contains(topic),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers_core::types as et;
+
+    use super::{matches_address, matches_topics};
+
+    #[test]
+    fn filter_by_address() {
+        let a = et::H160::repeat_byte(1);
+        let b = et::H160::repeat_byte(2);
+
+        assert!(matches_address(&et::Filter::new(), &a));
+        assert!(matches_address(&et::Filter::new().address(a), &a));
+        assert!(!matches_address(&et::Filter::new().address(b), &a));
+        assert!(matches_address(&et::Filter::new().address(vec![b, a]), &a));
+    }
+
+    #[test]
+    fn filter_by_topics() {
+        let t1 = et::H256::repeat_byte(1);
+        let t2 = et::H256::repeat_byte(2);
+        let t3 = et::H256::repeat_byte(3);
+        let topics = vec![t1, t2];
+
+        assert!(matches_topics(&et::Filter::new(), &topics));
+        assert!(matches_topics(&et::Filter::new().topic0(t1), &topics));
+        assert!(matches_topics(&et::Filter::new().topic1(t2), &topics));
+        assert!(!matches_topics(&et::Filter::new().topic1(t1), &topics));
+        assert!(matches_topics(
+            &et::Filter::new().topic0(vec![t3, t1]).topic1(t2),
+            &topics
+        ));
+        assert!(!matches_topics(&et::Filter::new().topic2(t3), &topics));
+    }
+}
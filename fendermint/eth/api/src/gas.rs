@@ -0,0 +1,162 @@
+// Copyright 2022-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Helpers to suggest gas prices based on what was paid in recent blocks.
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use anyhow::bail;
+use fvm_shared::{econ::TokenAmount, message::Message};
+
+/// Number of blocks to keep the gas statistics of, enough to cover the longest fee history.
+pub const BLOCK_GAS_CACHE_SIZE: usize = 256;
+
+/// Gas statistics of a block.
+#[derive(Clone)]
+pub struct BlockGas {
+    /// Base fee in effect during the execution of the block.
+    pub base_fee: TokenAmount,
+    /// Total gas used by all transactions in the block.
+    pub gas_used: u64,
+    /// Effective premium and gas used of each signed message in the block.
+    pub rewards: Vec<(TokenAmount, u64)>,
+}
+
+/// Gas statistics of recent blocks by height.
+///
+/// Committed blocks never change, so their statistics can be reused by every gas price
+/// suggestion and fee history, instead of fetching the blocks and their results again.
+pub struct BlockGasCache {
+    capacity: usize,
+    blocks: Mutex<BTreeMap<u64, BlockGas>>,
+}
+
+impl BlockGasCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            blocks: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn get(&self, height: u64) -> Option<BlockGas> {
+        self.blocks
+            .lock()
+            .expect("block gas lock poisoned")
+            .get(&height)
+            .cloned()
+    }
+
+    /// Remember the statistics of a block, forgetting the lowest heights beyond the capacity.
+    pub fn insert(&self, height: u64, block_gas: BlockGas) {
+        let mut blocks = self.blocks.lock().expect("block gas lock poisoned");
+        blocks.insert(height, block_gas);
+        while blocks.len() > self.capacity {
+            blocks.pop_first();
+        }
+    }
+}
+
+/// The premium the sender of a message actually paid on top of the base fee, per unit of gas.
+pub fn effective_premium(msg: &Message, base_fee: &TokenAmount) -> TokenAmount {
+    let max_premium = if msg.gas_fee_cap > *base_fee {
+        &msg.gas_fee_cap - base_fee
+    } else {
+        TokenAmount::default()
+    };
+    std::cmp::min(msg.gas_premium.clone(), max_premium)
+}
+
+/// Check that the percentiles requested in `eth_feeHistory` are increasing values between 0 and 100.
+pub fn check_percentiles(percentiles: &[f64]) -> anyhow::Result<()> {
+    let mut prev = 0.0;
+    for p in percentiles {
+        if !(0.0..=100.0).contains(p) || *p < prev {
+            bail!("reward percentiles must be increasing values between 0 and 100");
+        }
+        prev = *p;
+    }
+    Ok(())
+}
+
+/// Calculate the premiums at the given percentiles of the gas used in a block.
+///
+/// The premiums are weighted by how much gas the messages used, the same way as `go-ethereum` does:
+/// the premium at the 50th percentile is the one paid by the message which pushes the gas used
+/// over half of the total, when the messages are ordered by ascending premium.
+pub fn reward_percentiles(
+    mut rewards: Vec<(TokenAmount, u64)>,
+    percentiles: &[f64],
+) -> Vec<TokenAmount> {
+    if rewards.is_empty() {
+        return vec![TokenAmount::default(); percentiles.len()];
+    }
+
+    rewards.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let total_gas_used = rewards.iter().map(|(_, g)| *g).sum::<u64>();
+    let mut idx = 0;
+    let mut sum_gas_used = rewards[0].1;
+
+    percentiles
+        .iter()
+        .map(|p| {
+            let threshold = (total_gas_used as f64 * p / 100.0) as u64;
+            while sum_gas_used < threshold && idx < rewards.len() - 1 {
+                idx += 1;
+                sum_gas_used += rewards[idx].1;
+            }
+            rewards[idx].0.clone()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use fvm_shared::econ::TokenAmount;
+
+    use super::{check_percentiles, reward_percentiles, BlockGas, BlockGasCache};
+
+    fn atto(n: u64) -> TokenAmount {
+        TokenAmount::from_atto(n)
+    }
+
+    #[test]
+    fn percentiles_weighted_by_gas() {
+        let rewards = vec![(atto(30), 100), (atto(10), 100), (atto(20), 200)];
+        let ps = reward_percentiles(rewards, &[0.0, 25.0, 50.0, 75.0, 100.0]);
+        assert_eq!(ps, vec![atto(10), atto(10), atto(20), atto(20), atto(30)]);
+    }
+
+    #[test]
+    fn percentiles_of_empty_block() {
+        let ps = reward_percentiles(Vec::new(), &[10.0, 90.0]);
+        assert_eq!(ps, vec![atto(0), atto(0)]);
+    }
+
+    #[test]
+    fn cache_keeps_highest_blocks() {
+        let cache = BlockGasCache::new(2);
+        let block_gas = |gas_used| BlockGas {
+            base_fee: atto(1),
+            gas_used,
+            rewards: Vec::new(),
+        };
+
+        cache.insert(2, block_gas(20));
+        cache.insert(1, block_gas(10));
+        cache.insert(3, block_gas(30));
+
+        assert!(cache.get(1).is_none());
+        assert_eq!(cache.get(2).map(|b| b.gas_used), Some(20));
+        assert_eq!(cache.get(3).map(|b| b.gas_used), Some(30));
+    }
+
+    #[test]
+    fn percentiles_validated() {
+        assert!(check_percentiles(&[0.0, 50.0, 100.0]).is_ok());
+        assert!(check_percentiles(&[50.0, 10.0]).is_err());
+        assert!(check_percentiles(&[101.0]).is_err());
+    }
+}
@@ -0,0 +1,278 @@
+// Copyright 2022-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Dispatching of single and batched JSON-RPC requests, shared by the HTTP and WebSocket handlers.
+
+use futures::future::join_all;
+use jsonrpc_v2::{Error, Id, RequestObject, ResponseObject, ResponseObjects};
+use serde::Serialize;
+use serde_json::{json, value::RawValue, Value};
+
+use crate::auth::Caller;
+use crate::AppState;
+
+/// Error code of failures which don't have a more specific code, e.g. a failed query.
+pub const SERVER_ERROR: i64 = -32000;
+
+/// Error code Ethereum clients expect when a call reverted.
+const EXECUTION_REVERTED: i64 = 3;
+
+/// Codes reserved by the JSON-RPC specification, including the server errors.
+const RESERVED_ERRORS: std::ops::RangeInclusive<i64> = -32768..=-32000;
+
+/// A request object parsed from a request body.
+pub struct Call {
+    request: RequestObject,
+    /// Notifications don't have an `id` and don't get a response, not even an error.
+    is_notification: bool,
+}
+
+/// The contents of a request body.
+pub enum Request {
+    One(Call),
+    /// Invalid members of a batch are answered individually, without failing the rest.
+    Batch(Vec<Result<Call, Error>>),
+}
+
+impl Request {
+    /// Number of calls in the request, each of which counts against the rate limits.
+    pub fn len(&self) -> usize {
+        match self {
+            Request::One(_) => 1,
+            Request::Batch(calls) => calls.len(),
+        }
+    }
+}
+
+/// Parse a request body with a single request object or a batch of them.
+///
+/// Batches with more than `max_batch_len` requests are rejected; 0 means unlimited.
+pub fn parse(body: &[u8], max_batch_len: usize) -> Result<Request, Error> {
+    let is_batch = body.iter().find(|b| !b.is_ascii_whitespace()) == Some(&b'[');
+    if is_batch {
+        let values =
+            serde_json::from_slice::<Vec<&RawValue>>(body).map_err(|_| Error::PARSE_ERROR)?;
+        if values.is_empty() {
+            return Err(Error::INVALID_REQUEST);
+        }
+        if max_batch_len > 0 && values.len() > max_batch_len {
+            return Err(Error::Full {
+                code: -32600,
+                message: format!(
+                    "batch of {} requests exceeds the limit of {max_batch_len}",
+                    values.len()
+                ),
+                data: None,
+            });
+        }
+        Ok(Request::Batch(values.into_iter().map(parse_call).collect()))
+    } else {
+        let value = serde_json::from_slice::<&RawValue>(body).map_err(|_| Error::PARSE_ERROR)?;
+        parse_call(value).map(Request::One)
+    }
+}
+
+fn parse_call(raw: &RawValue) -> Result<Call, Error> {
+    // Look at the fields the request object doesn't validate or expose.
+    let value = serde_json::from_str::<Value>(raw.get()).map_err(|_| Error::INVALID_REQUEST)?;
+    if !value.get("method").map_or(false, Value::is_string) {
+        return Err(Error::INVALID_REQUEST);
+    }
+    let is_notification = value.get("id").is_none();
+    // The request object can only be deserialized from text, not from a `Value`.
+    let request = serde_json::from_str(raw.get()).map_err(|_| Error::INVALID_REQUEST)?;
+    Ok(Call {
+        request,
+        is_notification,
+    })
+}
+
+/// Handle a parsed request, checking that the caller is allowed to call the methods.
+///
+/// Returns `None` if there's nothing to respond with because there were only notifications.
+pub async fn handle(state: &AppState, caller: Option<&Caller>, request: Request) -> Option<Value> {
+    match request {
+        Request::One(call) => handle_call(state, caller, call).await,
+        Request::Batch(calls) => {
+            let responses = join_all(calls.into_iter().map(|call| async move {
+                match call {
+                    Ok(call) => handle_call(state, caller, call).await,
+                    Err(e) => Some(error_response(Value::Null, e)),
+                }
+            }))
+            .await;
+
+            let responses = responses.into_iter().flatten().collect::<Vec<_>>();
+
+            if responses.is_empty() {
+                None
+            } else {
+                Some(Value::Array(responses))
+            }
+        }
+    }
+}
+
+async fn handle_call(state: &AppState, caller: Option<&Caller>, call: Call) -> Option<Value> {
+    let method = call.request.method_ref().to_owned();
+
+    if let Err(e) = state.access.authorize(caller, &method) {
+        tracing::debug!(method, error = ?e, "RPC call rejected");
+        if call.is_notification {
+            return None;
+        }
+        return Some(error_response(call.request.id_ref(), e.to_rpc_error()));
+    }
+
+    match state.rpc_server.handle(call.request).await {
+        ResponseObjects::One(response) => Some(to_value(&method, response)),
+        ResponseObjects::Many(_) => unreachable!("a single request has a single response"),
+        ResponseObjects::Empty => None,
+    }
+}
+
+/// Convert a response to JSON, making sure errors have a JSON-RPC error code.
+///
+/// Errors converted from `anyhow` and other `Display` types have the code 0, while
+/// application errors can have any code; these become server errors, with any
+/// non-zero original code moved into the data.
+fn to_value(method: &str, mut response: ResponseObject) -> Value {
+    let id = match response {
+        ResponseObject::Result { ref id, .. } => {
+            tracing::debug!(method, "RPC call success");
+            id.clone()
+        }
+        ResponseObject::Error {
+            ref id,
+            ref mut error,
+            ..
+        } => {
+            if let Error::Full {
+                code,
+                message,
+                data,
+            } = error
+            {
+                if !RESERVED_ERRORS.contains(code) && *code != EXECUTION_REVERTED {
+                    if *code != 0 && data.is_none() {
+                        *data = Some(Box::new(*code));
+                    }
+                    *code = SERVER_ERROR;
+                }
+                tracing::debug!(method, code, message, "RPC call failure");
+            }
+            id.clone()
+        }
+    };
+
+    serde_json::to_value(response).unwrap_or_else(|e| {
+        tracing::error!(
+            method,
+            error = e.to_string(),
+            "failed to serialize response"
+        );
+        error_response(id, Error::internal(e))
+    })
+}
+
+/// Create an error response.
+pub fn error_response<I: Serialize>(id: I, error: Error) -> Value {
+    json!({"jsonrpc": "2.0", "id": id, "error": error})
+}
+
+/// Convenience function to create an error response to a request without a known ID.
+pub fn error_response_without_id(error: Error) -> Value {
+    error_response(Option::<Id>::None, error)
+}
+
+#[cfg(test)]
+mod tests {
+    use jsonrpc_v2::{Error, Id, ResponseObject, V2};
+
+    use super::{parse, to_value, Request, SERVER_ERROR};
+
+    fn parse_one(body: &[u8]) -> Result<Request, Error> {
+        parse(body, 0)
+    }
+
+    #[test]
+    fn parse_requests() {
+        assert!(matches!(
+            parse_one(br#"{"jsonrpc":"2.0","id":1,"method":"eth_chainId"}"#),
+            Ok(Request::One(call)) if !call.is_notification
+        ));
+        assert!(matches!(
+            parse_one(br#"{"jsonrpc":"2.0","method":"eth_chainId"}"#),
+            Ok(Request::One(call)) if call.is_notification
+        ));
+        assert!(matches!(
+            parse_one(br#"[{"jsonrpc":"2.0","id":1,"method":"eth_chainId"}, 1, {"id":2}]"#),
+            Ok(Request::Batch(calls)) if calls.len() == 3 && calls[0].is_ok() && calls[1].is_err() && calls[2].is_err()
+        ));
+    }
+
+    #[test]
+    fn parse_errors() {
+        let code = |body: &[u8]| match parse_one(body) {
+            Err(jsonrpc_v2::Error::Provided { code, .. }) => code,
+            _ => panic!("expected an error"),
+        };
+        assert_eq!(code(b"{"), -32700);
+        assert_eq!(code(b"[]"), -32600);
+        assert_eq!(code(b"1"), -32600);
+        assert_eq!(code(br#"{"id":1}"#), -32600);
+    }
+
+    #[test]
+    fn errors_without_code() {
+        let response = ResponseObject::Error {
+            jsonrpc: V2,
+            error: Error::Full {
+                code: 0,
+                message: "unknown block".into(),
+                data: None,
+            },
+            id: Id::Num(1),
+        };
+        let value = to_value("eth_getBlockByHash", response);
+        assert_eq!(value["error"]["code"], SERVER_ERROR);
+        assert_eq!(value["error"]["message"], "unknown block");
+        assert_eq!(value["id"], 1);
+    }
+
+    #[test]
+    fn application_error_codes() {
+        let response = |code| ResponseObject::Error {
+            jsonrpc: V2,
+            error: Error::Full {
+                code,
+                message: "failed".into(),
+                data: None,
+            },
+            id: Id::Num(1),
+        };
+
+        // Application codes go into the data.
+        let value = to_value("eth_sendRawTransaction", response(5));
+        assert_eq!(value["error"]["code"], SERVER_ERROR);
+        assert_eq!(value["error"]["data"], 5);
+
+        // JSON-RPC and Ethereum codes stay as they are.
+        let value = to_value("eth_getBalance", response(-32602));
+        assert_eq!(value["error"]["code"], -32602);
+        assert!(value["error"].get("data").map_or(true, |d| d.is_null()));
+
+        let value = to_value("eth_call", response(3));
+        assert_eq!(value["error"]["code"], 3);
+    }
+
+    #[test]
+    fn batch_length_limit() {
+        let body = br#"[{"jsonrpc":"2.0","id":1,"method":"eth_chainId"},{"jsonrpc":"2.0","id":2,"method":"eth_chainId"}]"#;
+        assert!(parse(body, 2).is_ok());
+        assert!(matches!(
+            parse(body, 1),
+            Err(Error::Full { code: -32600, .. })
+        ));
+    }
+}
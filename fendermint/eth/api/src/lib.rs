@@ -2,53 +2,140 @@
 // SPDX-License-Identifier: Apache-2.0, MIT
 
 use anyhow::anyhow;
-use axum::routing::post;
+use axum::extract::DefaultBodyLimit;
+use axum::routing::{get, post};
 use jsonrpc_v2::Data;
-use std::{net::ToSocketAddrs, sync::Arc};
+use std::{
+    net::{SocketAddr, ToSocketAddrs},
+    sync::Arc,
+    time::Duration,
+};
 use tendermint_rpc::HttpClient;
 
 mod apis;
+mod auth;
+mod conv;
+mod cors;
+mod filters;
+mod gas;
+mod jsonrpc;
+mod rate_limit;
 mod rpc_http_handler;
+mod rpc_ws_handler;
+mod state;
+mod subscriptions;
+mod trace;
 
-// Made generic in the client type so we can mock it if we want to test API
-// methods without having to spin up a server. In those tests the methods
-// below would not be used, so those aren't generic; we'd directly invoke
-// e.g. `fendermint_eth_api::apis::eth::accounts` with some mock client.
-pub struct JsonRpcState<C> {
-    pub client: C,
-}
+pub use auth::{AccessConfig, TokenAccess};
+pub use cors::CorsOrigins;
+pub use state::JsonRpcState;
+
+use auth::AccessControl;
+use subscriptions::BlockFeed;
 
 type JsonRpcData<C> = Data<JsonRpcState<C>>;
 type JsonRpcServer = Arc<jsonrpc_v2::Server<jsonrpc_v2::MapRouter>>;
 type JsonRpcResult<T> = Result<T, jsonrpc_v2::Error>;
 
-/// Start listening to JSON-RPC requests.
-pub async fn listen<A: ToSocketAddrs>(listen_addr: A, client: HttpClient) -> anyhow::Result<()> {
-    if let Some(listen_addr) = listen_addr.to_socket_addrs()?.next() {
-        let state = JsonRpcState { client };
-        let server = make_server(state);
-        let router = make_router(server);
-        let server = axum::Server::try_bind(&listen_addr)?.serve(router.into_make_service());
-
-        tracing::info!(?listen_addr, "bound Ethereum API");
-        server.await?;
-        Ok(())
-    } else {
-        Err(anyhow!("failed to convert to any socket address"))
-    }
+/// Shared state of the HTTP and WebSocket handlers.
+#[derive(Clone)]
+pub struct AppState {
+    rpc_server: JsonRpcServer,
+    rpc_state: Arc<JsonRpcState<HttpClient>>,
+    access: Arc<AccessControl>,
+    /// Maximum number of requests in a batch; 0 means unlimited.
+    max_batch_len: usize,
+    /// New blocks for the subscriptions.
+    block_feed: Arc<BlockFeed>,
+    /// Maximum number of subscriptions per WebSocket connection; 0 means unlimited.
+    max_subscriptions: usize,
+}
+
+/// Options of the Ethereum API facade.
+#[derive(Debug, Clone)]
+pub struct ApiConfig {
+    /// Filters which aren't polled within this time are uninstalled.
+    pub filter_timeout: Duration,
+    /// Origins allowed to make cross-origin requests.
+    pub cors_origins: CorsOrigins,
+    /// Maximum size of a request body in bytes.
+    pub max_body_size: usize,
+    /// Maximum number of requests in a batch; 0 means unlimited.
+    pub max_batch_len: usize,
+    /// Maximum number of subscriptions per WebSocket connection; 0 means unlimited.
+    pub max_subscriptions: usize,
+    /// Authentication and rate limits.
+    pub access: AccessConfig,
+}
+
+/// Start listening to JSON-RPC requests over HTTP and WebSockets.
+pub async fn listen<A: ToSocketAddrs>(
+    http_addr: A,
+    ws_addr: A,
+    client: HttpClient,
+    config: ApiConfig,
+) -> anyhow::Result<()> {
+    let http_addr = to_socket_addr(http_addr)?;
+    let ws_addr = to_socket_addr(ws_addr)?;
+
+    let state = Arc::new(JsonRpcState::new(client, config.filter_timeout));
+    let server = make_server(state.clone());
+    let app_state = AppState {
+        rpc_server: server,
+        rpc_state: state.clone(),
+        access: Arc::new(AccessControl::new(config.access)),
+        max_batch_len: config.max_batch_len,
+        block_feed: Arc::new(BlockFeed::spawn(state)),
+        max_subscriptions: config.max_subscriptions,
+    };
+    let http_router =
+        make_http_router(app_state.clone(), config.cors_origins, config.max_body_size);
+    let ws_router = make_ws_router(app_state);
+
+    let http_server = axum::Server::try_bind(&http_addr)?
+        .serve(http_router.into_make_service_with_connect_info::<SocketAddr>());
+    tracing::info!(?http_addr, "bound Ethereum API");
+
+    let ws_server = axum::Server::try_bind(&ws_addr)?
+        .serve(ws_router.into_make_service_with_connect_info::<SocketAddr>());
+    tracing::info!(?ws_addr, "bound Ethereum WebSocket API");
+
+    tokio::try_join!(http_server, ws_server)?;
+    Ok(())
+}
+
+fn to_socket_addr<A: ToSocketAddrs>(addr: A) -> anyhow::Result<SocketAddr> {
+    addr.to_socket_addrs()?
+        .next()
+        .ok_or_else(|| anyhow!("failed to convert to any socket address"))
 }
 
 /// Register method handlers with the JSON-RPC server construct.
-fn make_server(state: JsonRpcState<HttpClient>) -> JsonRpcServer {
-    let server = jsonrpc_v2::Server::new().with_data(Data(Arc::new(state)));
+fn make_server(state: Arc<JsonRpcState<HttpClient>>) -> JsonRpcServer {
+    let server = jsonrpc_v2::Server::new().with_data(Data(state));
     let server = apis::register_methods(server);
     server.finish()
 }
 
-/// Register routes in the `axum` router to handle JSON-RPC and WebSocket calls.
-fn make_router(server: JsonRpcServer) -> axum::Router {
+/// Register routes in the `axum` router to handle JSON-RPC calls over HTTP.
+fn make_http_router(
+    state: AppState,
+    cors_origins: CorsOrigins,
+    max_body_size: usize,
+) -> axum::Router {
     axum::Router::new()
-        //.route("/", get(rpc_ws_handler::handle))
         .route("/", post(rpc_http_handler::handle))
-        .with_state(server)
+        .layer(DefaultBodyLimit::max(max_body_size))
+        .layer(axum::middleware::from_fn_with_state(
+            Arc::new(cors_origins),
+            cors::handle,
+        ))
+        .with_state(state)
+}
+
+/// Register routes in the `axum` router to handle WebSocket connections.
+fn make_ws_router(state: AppState) -> axum::Router {
+    axum::Router::new()
+        .route("/", get(rpc_ws_handler::handle))
+        .with_state(state)
 }
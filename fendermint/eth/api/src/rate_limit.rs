@@ -0,0 +1,105 @@
+// Copyright 2022-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Limit the number of requests a key (e.g. an IP address) can make in fixed time windows.
+pub struct RateLimiter<K> {
+    window: Duration,
+    /// Maximum number of requests per window; 0 means unlimited.
+    max_requests: u32,
+    state: Mutex<RateLimiterState<K>>,
+}
+
+struct RateLimiterState<K> {
+    /// The start of the current window of each key and the number of requests made in it.
+    counters: HashMap<K, (Instant, u32)>,
+    last_purge: Instant,
+}
+
+impl<K> RateLimiter<K>
+where
+    K: Hash + Eq,
+{
+    pub fn new(window: Duration, max_requests: u32) -> Self {
+        Self {
+            window,
+            max_requests,
+            state: Mutex::new(RateLimiterState {
+                counters: HashMap::new(),
+                last_purge: Instant::now(),
+            }),
+        }
+    }
+
+    /// Count a number of requests, returning whether they are within the limit.
+    ///
+    /// Requests over the limit are not counted.
+    pub fn check(&self, key: K, requests: u32) -> bool {
+        self.check_at(key, requests, Instant::now())
+    }
+
+    fn check_at(&self, key: K, requests: u32, now: Instant) -> bool {
+        if self.max_requests == 0 {
+            return true;
+        }
+
+        let mut state = self.state.lock().expect("rate limiter lock poisoned");
+
+        // Forget about the keys which haven't been seen in the last window.
+        if now.duration_since(state.last_purge) >= self.window {
+            let window = self.window;
+            state
+                .counters
+                .retain(|_, (start, _)| now.duration_since(*start) < window);
+            state.last_purge = now;
+        }
+
+        let (start, count) = state.counters.entry(key).or_insert((now, 0));
+
+        if now.duration_since(*start) >= self.window {
+            *start = now;
+            *count = 0;
+        }
+
+        if count.saturating_add(requests) <= self.max_requests {
+            *count += requests;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::RateLimiter;
+
+    #[test]
+    fn limit_per_window() {
+        let limiter = RateLimiter::new(Duration::from_secs(1), 2);
+        let t0 = Instant::now();
+
+        assert!(limiter.check_at("a", 1, t0));
+        assert!(limiter.check_at("a", 1, t0));
+        assert!(!limiter.check_at("a", 1, t0));
+        // Other keys have their own limit.
+        assert!(limiter.check_at("b", 1, t0));
+        // Batches count every request, all or nothing.
+        assert!(!limiter.check_at("b", 2, t0));
+        assert!(limiter.check_at("b", 1, t0));
+        // The next window starts over.
+        assert!(limiter.check_at("a", 1, t0 + Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn unlimited() {
+        let limiter = RateLimiter::new(Duration::from_secs(1), 0);
+        assert!((0..100).all(|_| limiter.check("a", 1)));
+    }
+}
@@ -4,42 +4,57 @@
 
 // Based on https://github.com/ChainSafe/forest/blob/v0.8.2/node/rpc/src/rpc_http_handler.rs
 
+use std::net::SocketAddr;
+
+use axum::body::Bytes;
+use axum::extract::ConnectInfo;
 use axum::http::{HeaderMap, StatusCode};
 use axum::response::IntoResponse;
-use jsonrpc_v2::RequestObject as JsonRpcRequestObject;
 
-use crate::JsonRpcServer;
+use crate::jsonrpc::{self, error_response_without_id};
+use crate::AppState;
 
-/// Handle JSON-RPC calls.
+/// Handle JSON-RPC calls, either a single request or a batch.
+///
+/// Authentication and rate limit errors are reflected in the HTTP status code,
+/// while errors of the individual calls are returned as JSON-RPC error objects
+/// with an HTTP 200 status.
 pub async fn handle(
-    _headers: HeaderMap,
-    axum::extract::State(server): axum::extract::State<JsonRpcServer>,
-    axum::Json(request): axum::Json<JsonRpcRequestObject>,
+    headers: HeaderMap,
+    ConnectInfo(remote_addr): ConnectInfo<SocketAddr>,
+    axum::extract::State(state): axum::extract::State<AppState>,
+    body: Bytes,
 ) -> impl IntoResponse {
     let response_headers = [("content-type", "application/json-rpc;charset=utf-8")];
 
-    // NOTE: Any authorization can come here.
+    let request = jsonrpc::parse(&body, state.max_batch_len);
 
-    let method = request.method_ref().to_owned();
+    // Every call in a batch counts against the rate limits.
+    let calls = request.as_ref().map_or(1, |r| r.len()) as u32;
 
-    match call_rpc_str(server.clone(), request).await {
-        Ok(result) => {
-            tracing::debug!(method, "RPC call success");
-            (StatusCode::OK, response_headers, result)
-        }
-        Err(err) => {
-            let msg = err.to_string();
-            tracing::error!(method, msg, "RPC call failure");
-            (StatusCode::INTERNAL_SERVER_ERROR, response_headers, msg)
+    let access = state.access.authenticate(&headers).and_then(|caller| {
+        state
+            .access
+            .check_rate(remote_addr.ip(), caller.as_ref(), calls)?;
+        Ok(caller)
+    });
+
+    let caller = match access {
+        Ok(caller) => caller,
+        Err(e) => {
+            tracing::debug!(?remote_addr, error = ?e, "RPC request rejected");
+            let body = error_response_without_id(e.to_rpc_error()).to_string();
+            return (e.status_code(), response_headers, body);
         }
-    }
-}
+    };
+
+    let response = match request {
+        Ok(request) => jsonrpc::handle(&state, caller.as_ref(), request).await,
+        Err(e) => Some(error_response_without_id(e)),
+    };
+
+    // Notifications don't have a response.
+    let body = response.map(|r| r.to_string()).unwrap_or_default();
 
-// Calls an RPC method and returns the full response as a string.
-pub async fn call_rpc_str(
-    server: JsonRpcServer,
-    request: JsonRpcRequestObject,
-) -> anyhow::Result<String> {
-    let response = server.handle(request).await;
-    Ok(serde_json::to_string(&response)?)
+    (StatusCode::OK, response_headers, body)
 }
@@ -0,0 +1,192 @@
+// Copyright 2022-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+use std::net::SocketAddr;
+
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::ConnectInfo;
+use axum::http::HeaderMap;
+use axum::response::{IntoResponse, Response};
+use futures::{SinkExt, StreamExt};
+use serde::Deserialize;
+use serde_json::json;
+use tendermint_rpc::HttpClient;
+
+use crate::auth::Caller;
+use crate::filters::FilterId;
+use crate::jsonrpc::{self, error_response, error_response_without_id};
+use crate::subscriptions::{Outbox, SubscriptionKind, Subscriptions};
+use crate::AppState;
+
+/// Maximum number of responses and notifications waiting to be sent to a client.
+const OUTBOX_CAPACITY: usize = 1024;
+
+/// The parts of a request needed to decide whether it's about subscriptions.
+#[derive(Deserialize)]
+struct MethodCall {
+    #[serde(default)]
+    id: serde_json::Value,
+    method: String,
+    #[serde(default)]
+    params: serde_json::Value,
+}
+
+/// The caller on the other end of a WebSocket connection.
+struct Connection {
+    remote_addr: SocketAddr,
+    /// The caller who opened the connection, if tokens are required.
+    caller: Option<Caller>,
+}
+
+/// Handle WebSocket connections, which can call the same methods as JSON-RPC over HTTP,
+/// and in addition `eth_subscribe` and `eth_unsubscribe`.
+///
+/// The token is checked when the connection is opened, while the rate limits and the
+/// allowed methods are checked with every message.
+pub async fn handle(
+    ws: WebSocketUpgrade,
+    headers: HeaderMap,
+    ConnectInfo(remote_addr): ConnectInfo<SocketAddr>,
+    axum::extract::State(state): axum::extract::State<AppState>,
+) -> Response {
+    let caller = match state.access.authenticate(&headers) {
+        Ok(caller) => caller,
+        Err(e) => {
+            tracing::debug!(?remote_addr, error = ?e, "WebSocket connection rejected");
+            return e.status_code().into_response();
+        }
+    };
+    let conn = Connection {
+        remote_addr,
+        caller,
+    };
+    ws.on_upgrade(move |socket| handle_socket(state, conn, socket))
+}
+
+async fn handle_socket(state: AppState, conn: Connection, socket: WebSocket) {
+    let (mut sender, mut receiver) = socket.split();
+
+    // Responses and notifications are funneled into the socket by a single task.
+    let (outbox, mut rx) = Outbox::new(OUTBOX_CAPACITY);
+
+    let forward = tokio::spawn(async move {
+        while let Some(msg) = rx.recv().await {
+            if let Err(e) = sender.send(Message::Text(msg)).await {
+                tracing::debug!(error = e.to_string(), "failed to send WebSocket message");
+                break;
+            }
+        }
+    });
+
+    let mut subscriptions = Subscriptions::new(
+        state.rpc_state.clone(),
+        state.block_feed.clone(),
+        outbox.clone(),
+        state.max_subscriptions,
+    );
+
+    loop {
+        let msg = tokio::select! {
+            msg = receiver.next() => match msg {
+                Some(msg) => msg,
+                None => break,
+            },
+            _ = outbox.too_slow() => {
+                tracing::debug!(remote_addr = ?conn.remote_addr, "WebSocket client too slow; disconnecting");
+                break;
+            }
+        };
+
+        let text = match msg {
+            Ok(Message::Text(text)) => text,
+            Ok(Message::Close(_)) => break,
+            Ok(_) => continue,
+            Err(e) => {
+                tracing::debug!(error = e.to_string(), "failed to receive WebSocket message");
+                break;
+            }
+        };
+
+        if let Some(response) = handle_text(&state, &conn, &mut subscriptions, &text).await {
+            if !outbox.send(response) {
+                break;
+            }
+        }
+    }
+
+    // Stop the subscriptions before the forwarding.
+    drop(subscriptions);
+    forward.abort();
+}
+
+/// Handle a single message, returning the response to send back, if any.
+async fn handle_text(
+    state: &AppState,
+    conn: &Connection,
+    subscriptions: &mut Subscriptions<HttpClient>,
+    text: &str,
+) -> Option<String> {
+    let caller = conn.caller.as_ref();
+    let request = jsonrpc::parse(text.as_bytes(), state.max_batch_len);
+
+    // Every call in a batch counts against the rate limits.
+    let calls = request.as_ref().map_or(1, |r| r.len()) as u32;
+
+    if let Err(e) = state
+        .access
+        .check_rate(conn.remote_addr.ip(), caller, calls)
+    {
+        tracing::debug!(remote_addr = ?conn.remote_addr, error = ?e, "RPC request rejected");
+        return Some(error_response_without_id(e.to_rpc_error()).to_string());
+    }
+
+    if let Ok(call) = serde_json::from_str::<MethodCall>(text) {
+        let is_subscription = matches!(call.method.as_str(), "eth_subscribe" | "eth_unsubscribe");
+
+        if is_subscription {
+            if let Err(e) = state.access.authorize(caller, &call.method) {
+                return Some(to_response::<()>(call.id, Err(e.to_rpc_error())));
+            }
+        }
+
+        match call.method.as_str() {
+            "eth_subscribe" => {
+                let res = match SubscriptionKind::from_params(call.params) {
+                    Ok(kind) => subscriptions.subscribe(kind),
+                    Err(e) => Err(jsonrpc_v2::Error::Full {
+                        code: -32602,
+                        message: e.to_string(),
+                        data: None,
+                    }),
+                };
+                return Some(to_response(call.id, res));
+            }
+            "eth_unsubscribe" => {
+                let res = match serde_json::from_value::<(FilterId,)>(call.params) {
+                    Ok((id,)) => Ok(subscriptions.unsubscribe(&id)),
+                    Err(_) => Err(jsonrpc_v2::Error::INVALID_PARAMS),
+                };
+                return Some(to_response(call.id, res));
+            }
+            _ => {}
+        }
+    }
+
+    let response = match request {
+        Ok(request) => jsonrpc::handle(state, caller, request).await,
+        Err(e) => Some(error_response_without_id(e)),
+    };
+
+    response.map(|r| r.to_string())
+}
+
+fn to_response<T: serde::Serialize>(
+    id: serde_json::Value,
+    res: Result<T, jsonrpc_v2::Error>,
+) -> String {
+    match res {
+        Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+        Err(error) => error_response(id, error),
+    }
+    .to_string()
+}
@@ -0,0 +1,600 @@
+// Copyright 2022-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Tendermint RPC helper methods for the implementation of the APIs.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context};
+use ethers_core::types as et;
+use fendermint_rpc::client::{FendermintClient, TendermintClient};
+use fendermint_rpc::query::QueryClient;
+use fendermint_vm_actor_interface::{eam::EthAddress, system};
+use fendermint_vm_message::conv::to_eth_address;
+use fendermint_vm_message::query::StateParams;
+use fendermint_vm_message::{chain::ChainMessage, signed::SignedMessage};
+use fvm_ipld_encoding::RawBytes;
+use fvm_shared::{
+    address::Address, chainid::ChainID, econ::TokenAmount, message::Message, ActorID,
+    BLOCK_GAS_LIMIT,
+};
+use tendermint::abci::{self, response::DeliverTx};
+use tendermint::block::Height;
+use tendermint_rpc::{
+    endpoint::{block, block_results, tx},
+    query::Query,
+    Client, Order,
+};
+use tokio::sync::broadcast;
+
+use crate::conv::from_tm;
+use crate::filters::{matches_address, matches_topics, FilterId, FilterKind, FilterState};
+use crate::gas::{effective_premium, BlockGas, BlockGasCache, BLOCK_GAS_CACHE_SIZE};
+use crate::JsonRpcResult;
+
+/// Number of pending transaction hashes buffered for slow subscribers.
+const PENDING_TX_CAPACITY: usize = 1000;
+
+/// Maximum number of blocks a single query for logs or block hashes can span.
+pub const MAX_BLOCK_RANGE: u64 = 1000;
+
+/// State shared by the API methods, generic in the client type so it can be mocked.
+pub struct JsonRpcState<C> {
+    pub client: FendermintClient<C>,
+    filter_timeout: Duration,
+    filters: Mutex<HashMap<FilterId, FilterState>>,
+    pending_txs: broadcast::Sender<et::H256>,
+    block_gas: BlockGasCache,
+    /// Height of the latest block when the node was first seen catching up, until it caught up.
+    sync_start: Mutex<Option<u64>>,
+}
+
+impl<C> JsonRpcState<C> {
+    pub fn new(client: C, filter_timeout: Duration) -> Self
+    where
+        C: Client,
+    {
+        Self {
+            client: FendermintClient::new(client),
+            filter_timeout,
+            filters: Mutex::new(HashMap::new()),
+            pending_txs: broadcast::channel(PENDING_TX_CAPACITY).0,
+            block_gas: BlockGasCache::new(BLOCK_GAS_CACHE_SIZE),
+            sync_start: Mutex::new(None),
+        }
+    }
+
+    /// The height at which the node started catching up, if it's catching up.
+    pub fn sync_start(&self, catching_up: bool, latest_height: u64) -> Option<u64> {
+        let mut sync_start = self.sync_start.lock().expect("sync start lock poisoned");
+        if !catching_up {
+            *sync_start = None;
+            return None;
+        }
+        Some(*sync_start.get_or_insert(latest_height))
+    }
+
+    /// Notify subscribers about a transaction sent to the mempool.
+    pub fn notify_pending_tx(&self, hash: et::H256) {
+        // It's only an error if there are no subscribers.
+        let _ = self.pending_txs.send(hash);
+    }
+
+    /// Receive the hashes of transactions sent to the mempool through this API.
+    pub fn subscribe_pending_txs(&self) -> broadcast::Receiver<et::H256> {
+        self.pending_txs.subscribe()
+    }
+
+    /// Install a filter which reports changes after the given height, returning its ID.
+    pub fn install_filter(&self, kind: FilterKind, last_height: u64) -> FilterId {
+        let mut filters = self.lock_filters();
+        let id = loop {
+            let id = FilterId::from(rand::random::<u128>());
+            if !filters.contains_key(&id) {
+                break id;
+            }
+        };
+        filters.insert(id, FilterState::new(kind, last_height));
+        id
+    }
+
+    /// Remove a filter, returning whether it was installed.
+    pub fn uninstall_filter(&self, id: &FilterId) -> bool {
+        self.lock_filters().remove(id).is_some()
+    }
+
+    /// Get the criteria of a filter and the last height it reported, resetting its expiry.
+    pub fn poll_filter(&self, id: &FilterId) -> Option<(FilterKind, u64)> {
+        let mut filters = self.lock_filters();
+        let filter = filters.get_mut(id)?;
+        filter.last_polled = Instant::now();
+        Some((filter.kind.clone(), filter.last_height))
+    }
+
+    /// Remember the last height a filter reported, unless it has been uninstalled in the meantime.
+    pub fn update_filter(&self, id: &FilterId, last_height: u64) {
+        if let Some(filter) = self.lock_filters().get_mut(id) {
+            filter.last_height = last_height;
+        }
+    }
+
+    /// Lock the filters, removing the ones which expired.
+    fn lock_filters(&self) -> std::sync::MutexGuard<HashMap<FilterId, FilterState>> {
+        let mut filters = self.filters.lock().expect("filters lock poisoned");
+        filters.retain(|_, f| f.last_polled.elapsed() < self.filter_timeout);
+        filters
+    }
+}
+
+impl<C> JsonRpcState<C>
+where
+    C: Client + Sync + Send,
+{
+    /// The underlying Tendermint RPC client.
+    pub fn tm(&self) -> &C {
+        self.client.underlying()
+    }
+
+    /// Fetch the Tendermint block at an Ethereum block number or tag.
+    pub async fn block_by_height(
+        &self,
+        block_number: et::BlockNumber,
+    ) -> JsonRpcResult<tendermint::Block> {
+        let block = match block_number {
+            et::BlockNumber::Number(height) => {
+                let height = Height::try_from(height.as_u64())?;
+                let res: block::Response = self.tm().block(height).await?;
+                res.block
+            }
+            et::BlockNumber::Finalized
+            | et::BlockNumber::Latest
+            | et::BlockNumber::Safe
+            | et::BlockNumber::Pending => {
+                let res: block::Response = self.tm().latest_block().await?;
+                res.block
+            }
+            et::BlockNumber::Earliest => {
+                let res: block::Response = self.tm().block(Height::from(1u32)).await?;
+                res.block
+            }
+        };
+        Ok(block)
+    }
+
+    /// The height of the latest block.
+    pub async fn latest_height(&self) -> JsonRpcResult<u64> {
+        let res: block::Response = self.tm().latest_block().await?;
+        Ok(res.block.header.height.value())
+    }
+
+    /// The state parameters which were in effect while executing the block at a given height,
+    /// which is the state committed by the previous block.
+    pub async fn state_params_during(&self, height: Height) -> anyhow::Result<StateParams> {
+        let params_height = if height.value() > 1 {
+            Height::try_from(height.value() - 1)?
+        } else {
+            height
+        };
+        let res = self.client.state_params(Some(params_height)).await?;
+        Ok(res.value)
+    }
+
+    /// Run a read-only call to an actor method on behalf of the system actor,
+    /// which isn't an account, so the call is executed implicitly.
+    pub async fn read_actor(
+        &self,
+        to: Address,
+        method_num: u64,
+        params: RawBytes,
+        height: Option<Height>,
+    ) -> anyhow::Result<DeliverTx> {
+        let msg = Message {
+            version: 0,
+            from: system::SYSTEM_ACTOR_ADDR,
+            to,
+            sequence: 0,
+            value: TokenAmount::default(),
+            method_num,
+            params,
+            gas_limit: BLOCK_GAS_LIMIT,
+            gas_fee_cap: TokenAmount::default(),
+            gas_premium: TokenAmount::default(),
+        };
+        let res = self.client.call(msg, height).await?;
+        Ok(res.value)
+    }
+
+    /// Collect the gas statistics of the block at a given height.
+    pub async fn block_gas(&self, height: Height) -> JsonRpcResult<BlockGas> {
+        if let Some(block_gas) = self.block_gas.get(height.value()) {
+            return Ok(block_gas);
+        }
+
+        let block: block::Response = self.tm().block(height).await?;
+        let block_results: block_results::Response = self.tm().block_results(height).await?;
+        let state_params = self.state_params_during(height).await?;
+        let results = block_results.txs_results.unwrap_or_default();
+        let base_fee = state_params.base_fee;
+
+        let gas_used = |i: usize| results.get(i).map_or(0, |r| r.gas_used.max(0) as u64);
+
+        let rewards = block
+            .block
+            .data
+            .iter()
+            .enumerate()
+            .filter_map(|(i, tx)| {
+                decode_signed(tx)
+                    .map(|msg| (effective_premium(&msg.message, &base_fee), gas_used(i)))
+            })
+            .collect();
+
+        let block_gas = BlockGas {
+            gas_used: (0..results.len()).map(gas_used).sum(),
+            base_fee,
+            rewards,
+        };
+
+        self.block_gas.insert(height.value(), block_gas.clone());
+
+        Ok(block_gas)
+    }
+
+    /// Find the Ethereum form of an address, looking up the actor ID if necessary.
+    pub async fn lookup_eth_address(
+        &self,
+        addr: &Address,
+        height: Height,
+    ) -> anyhow::Result<Option<et::H160>> {
+        if let Some(addr) = to_eth_address(addr) {
+            return Ok(Some(addr));
+        }
+        let res = self.client.actor_state(addr, Some(height)).await?;
+        Ok(res
+            .value
+            .map(|(id, _)| et::H160::from(EthAddress::from_id(id).0)))
+    }
+
+    /// Find the Ethereum address of the actor which emitted an event: its delegated address if it has one,
+    /// otherwise the masked form of its ID.
+    pub async fn lookup_emitter_address(
+        &self,
+        id: ActorID,
+        height: Height,
+    ) -> anyhow::Result<et::H160> {
+        let res = self
+            .client
+            .actor_state(&Address::new_id(id), Some(height))
+            .await?;
+
+        let addr = res
+            .value
+            .and_then(|(_, state)| state.delegated_address)
+            .and_then(|addr| to_eth_address(&addr))
+            .unwrap_or_else(|| et::H160::from(EthAddress::from_id(id).0));
+
+        Ok(addr)
+    }
+
+    /// Find the Ethereum address of an event emitter, remembering it for the following events.
+    async fn lookup_emitter_address_cached(
+        &self,
+        emitters: &mut HashMap<ActorID, et::H160>,
+        id: ActorID,
+        height: Height,
+    ) -> anyhow::Result<et::H160> {
+        if let Some(address) = emitters.get(&id) {
+            return Ok(*address);
+        }
+        let address = self
+            .lookup_emitter_address(id, height)
+            .await
+            .context("failed to look up event emitter")?;
+        emitters.insert(id, address);
+        Ok(address)
+    }
+
+    /// Find a transaction by its Ethereum hash.
+    ///
+    /// Messages created from Ethereum transactions are indexed by the hash of the transaction,
+    /// everything else by the hash Tendermint uses.
+    pub async fn find_tx(&self, tx_hash: et::H256) -> JsonRpcResult<Option<tx::Response>> {
+        let hex_hash = ethers_core::utils::hex::encode(tx_hash.as_bytes());
+
+        // Searching rather than getting by hash, because a missing transaction is an empty result,
+        // whereas Tendermint doesn't have a dedicated error for it.
+        let queries = [
+            Query::eq("eth.hash", hex_hash.clone()),
+            Query::eq("tx.hash", hex_hash.to_uppercase()),
+        ];
+
+        for query in queries {
+            let res = self
+                .tm()
+                .tx_search(query, false, 1, 1, Order::Ascending)
+                .await?;
+
+            if let Some(tx) = res.txs.into_iter().next() {
+                return Ok(Some(tx));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Turn a signed message in a block into an Ethereum transaction.
+    pub async fn enrich_transaction(
+        &self,
+        msg: &SignedMessage,
+        hash: et::H256,
+        state_params: &StateParams,
+        block_hash: et::H256,
+        height: Height,
+        index: usize,
+    ) -> JsonRpcResult<et::Transaction> {
+        // The sender paid for the gas, so it must exist at the height the message was included.
+        let from = self
+            .lookup_eth_address(&msg.message.from, height)
+            .await?
+            .ok_or_else(|| anyhow!("sender {} not found at height {height}", msg.message.from))?;
+
+        let to = self.lookup_eth_address(&msg.message.to, height).await?;
+
+        let tx = from_tm::to_eth_transaction(
+            msg,
+            hash,
+            from,
+            to,
+            &ChainID::from(state_params.chain_id),
+            &state_params.base_fee,
+            block_hash,
+            height.value(),
+            index,
+        )?;
+
+        Ok(tx)
+    }
+
+    /// Turn a Tendermint block into an Ethereum one, with the transactions either as full objects or as hashes.
+    ///
+    /// Only the signed messages are transactions; the CIDs proposed by validators are left out.
+    pub async fn enrich_block(
+        &self,
+        block: &tendermint::Block,
+        full_tx: bool,
+    ) -> JsonRpcResult<et::Block<serde_json::Value>> {
+        let height = block.header.height;
+        let block_hash = from_tm::to_eth_block_hash(&block.header.hash());
+        let block_results = self.tm().block_results(height).await?;
+        let state_params = self.state_params_during(height).await?;
+        let chain_id = ChainID::from(state_params.chain_id);
+
+        let mut transactions = Vec::new();
+
+        for (index, tx) in block.data.iter().enumerate() {
+            let msg = match decode_signed(tx) {
+                Some(msg) => msg,
+                None => continue,
+            };
+
+            let hash = from_tm::to_eth_tx_hash(&msg, tx, &chain_id)?;
+
+            if full_tx {
+                let tx = self
+                    .enrich_transaction(&msg, hash, &state_params, block_hash, height, index)
+                    .await?;
+                transactions.push(serde_json::to_value(tx)?);
+            } else {
+                transactions.push(serde_json::to_value(hash)?);
+            }
+        }
+
+        let block =
+            from_tm::to_eth_block(block, &block_results, &state_params.base_fee, transactions)?;
+
+        Ok(block)
+    }
+
+    /// Turn the events emitted by an executed transaction into Ethereum logs, skipping those
+    /// which weren't emitted by the EVM.
+    ///
+    /// The transaction and block metadata is left for the caller to fill in.
+    pub async fn to_eth_logs(
+        &self,
+        events: &[abci::Event],
+        height: Height,
+    ) -> anyhow::Result<Vec<et::Log>> {
+        let mut emitters: HashMap<ActorID, et::H160> = HashMap::new();
+        let mut logs = Vec::new();
+
+        for event in events {
+            let emitter = match from_tm::event_emitter(event) {
+                Some(emitter) => emitter,
+                None => continue,
+            };
+
+            let address = self
+                .lookup_emitter_address_cached(&mut emitters, emitter, height)
+                .await?;
+
+            if let Some(log) = from_tm::to_eth_log(event, address)? {
+                logs.push(log);
+            }
+        }
+
+        Ok(logs)
+    }
+
+    /// Resolve the block range of a log filter to heights, capped at the latest block.
+    pub async fn filter_range(&self, filter: &et::Filter) -> JsonRpcResult<(u64, u64)> {
+        match filter.block_option {
+            et::FilterBlockOption::AtBlockHash(hash) => {
+                let res = self
+                    .tm()
+                    .block_by_hash(tendermint::Hash::Sha256(hash.0))
+                    .await?;
+                match res.block {
+                    Some(block) => {
+                        let height = block.header.height.value();
+                        Ok((height, height))
+                    }
+                    None => Err(anyhow!("unknown block").into()),
+                }
+            }
+            et::FilterBlockOption::Range {
+                from_block,
+                to_block,
+            } => {
+                let latest = self.latest_height().await?;
+                let from = resolve_height(from_block, latest);
+                let to = resolve_height(to_block, latest);
+                Ok((from, to))
+            }
+        }
+    }
+
+    /// Collect the logs matching a filter in a range of block heights.
+    pub async fn logs_in_range(
+        &self,
+        filter: &et::Filter,
+        from: u64,
+        to: u64,
+    ) -> JsonRpcResult<Vec<et::Log>> {
+        check_block_range(from, to)?;
+        let mut logs = Vec::new();
+        for height in from..=to {
+            logs.extend(self.logs_at_height(filter, height).await?);
+        }
+        Ok(logs)
+    }
+
+    /// Collect the logs matching a filter in a block, with the transaction and block metadata filled in.
+    pub async fn logs_at_height(
+        &self,
+        filter: &et::Filter,
+        height: u64,
+    ) -> JsonRpcResult<Vec<et::Log>> {
+        let height = Height::try_from(height)?;
+        let block_results: block_results::Response = self.tm().block_results(height).await?;
+        let results = block_results.txs_results.unwrap_or_default();
+
+        let mut emitters = HashMap::new();
+        let mut matches = Vec::new();
+        // The index of the log among all the EVM logs in the block, matching or not.
+        let mut log_index = 0;
+
+        for (tx_index, result) in results.iter().enumerate() {
+            let mut tx_log_index = 0;
+            for event in result.events.iter() {
+                let emitter = match from_tm::event_emitter(event) {
+                    Some(emitter) => emitter,
+                    None => continue,
+                };
+                let mut log = match from_tm::to_eth_log(event, Default::default())? {
+                    Some(log) => log,
+                    None => continue,
+                };
+                if matches_topics(filter, &log.topics) {
+                    let address = self
+                        .lookup_emitter_address_cached(&mut emitters, emitter, height)
+                        .await?;
+
+                    if matches_address(filter, &address) {
+                        log.address = address;
+                        log.transaction_index = Some(et::U64::from(tx_index));
+                        log.log_index = Some(et::U256::from(log_index));
+                        log.transaction_log_index = Some(et::U256::from(tx_log_index));
+                        matches.push((tx_index, log));
+                    }
+                }
+                log_index += 1;
+                tx_log_index += 1;
+            }
+        }
+
+        if matches.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let block: block::Response = self.tm().block(height).await?;
+        let block_hash = from_tm::to_eth_block_hash(&block.block.header.hash());
+        let chain_id = ChainID::from(self.state_params_during(height).await?.chain_id);
+        let txs = block.block.data;
+
+        let mut logs = Vec::new();
+        for (tx_index, mut log) in matches {
+            let tx = txs
+                .get(tx_index)
+                .ok_or_else(|| anyhow!("transaction {tx_index} missing from block {height}"))?;
+
+            let tx_hash = match decode_signed(tx) {
+                Some(msg) => from_tm::to_eth_tx_hash(&msg, tx, &chain_id)?,
+                None => from_tm::to_eth_block_hash(&from_tm::to_tm_tx_hash(tx)),
+            };
+
+            log.block_hash = Some(block_hash);
+            log.block_number = Some(et::U64::from(height.value()));
+            log.transaction_hash = Some(tx_hash);
+            logs.push(log);
+        }
+
+        Ok(logs)
+    }
+
+    /// Collect the hashes of the blocks in a range of heights.
+    pub async fn block_hashes_in_range(&self, from: u64, to: u64) -> JsonRpcResult<Vec<et::H256>> {
+        check_block_range(from, to)?;
+        let mut hashes = Vec::new();
+        for height in from..=to {
+            let height = Height::try_from(height)?;
+            let res: block::Response = self.tm().block(height).await?;
+            hashes.push(from_tm::to_eth_block_hash(&res.block.header.hash()));
+        }
+        Ok(hashes)
+    }
+}
+
+/// Turn a block number or tag into a height, with everything other than a number
+/// or `earliest` meaning the latest block.
+pub fn resolve_height(block_number: Option<et::BlockNumber>, latest: u64) -> u64 {
+    match block_number {
+        Some(et::BlockNumber::Number(n)) => std::cmp::min(n.as_u64(), latest),
+        Some(et::BlockNumber::Earliest) => 1,
+        _ => latest,
+    }
+}
+
+/// Turn a block number or tag into the height to run a query at, with `None` meaning the latest state.
+pub fn to_query_height(block_number: et::BlockNumber) -> JsonRpcResult<Option<Height>> {
+    match block_number {
+        et::BlockNumber::Number(n) => Ok(Some(Height::try_from(n.as_u64())?)),
+        et::BlockNumber::Earliest => Ok(Some(Height::from(1u32))),
+        _ => Ok(None),
+    }
+}
+
+/// Reject queries spanning too many blocks.
+fn check_block_range(from: u64, to: u64) -> JsonRpcResult<()> {
+    if to >= from && to - from >= MAX_BLOCK_RANGE {
+        Err(anyhow!("block range exceeds the maximum of {MAX_BLOCK_RANGE} blocks").into())
+    } else {
+        Ok(())
+    }
+}
+
+/// Decode a transaction in a block as a signed message, or `None` if it's something else.
+pub fn decode_signed(tx: &[u8]) -> Option<Box<SignedMessage>> {
+    match fvm_ipld_encoding::from_slice::<ChainMessage>(tx) {
+        Ok(ChainMessage::Signed(msg)) => Some(msg),
+        Ok(_) => None,
+        Err(e) => {
+            tracing::warn!(
+                error = e.to_string(),
+                "failed to decode transaction in block"
+            );
+            None
+        }
+    }
+}
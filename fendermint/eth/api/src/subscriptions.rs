@@ -0,0 +1,477 @@
+//! Subscriptions of a WebSocket connection, pushing notifications to the client.
+//!
+//! New blocks are polled by a single [BlockFeed] shared by all connections, and each
+//! connection has a bounded outbox; clients which don't keep up with it are disconnected.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use ethers_core::types as et;
+use serde::Serialize;
+use tendermint_rpc::Client;
+use tokio::sync::{broadcast, mpsc, Notify};
+use tokio::task::JoinHandle;
+
+use crate::filters::FilterId;
+use crate::{JsonRpcResult, JsonRpcState};
+
+/// How often to look for new blocks.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How many new blocks subscribers can fall behind before they start missing some.
+const BLOCK_FEED_CAPACITY: usize = 64;
+
+/// Fields of a block which are not part of its header.
+const BODY_FIELDS: &[&str] = &[
+    "transactions",
+    "uncles",
+    "size",
+    "totalDifficulty",
+    "sealFields",
+];
+/// What the client subscribed to with `eth_subscribe`.
+#[derive(Debug, Clone)]
+pub enum SubscriptionKind {
+    /// Headers of new blocks.
+    NewHeads,
+    /// Logs matching a filter, in new blocks.
+    Logs(Box<et::Filter>),
+    /// Hashes of transactions sent to the mempool through this API.
+    NewPendingTransactions,
+}
+
+impl SubscriptionKind {
+    /// Parse the parameters of `eth_subscribe`, e.g. `["logs", {"address": "0x..."}]`.
+    pub fn from_params(params: serde_json::Value) -> anyhow::Result<Self> {
+        let (kind, filter): (String, Option<et::Filter>) = match params {
+            serde_json::Value::Array(mut ps) if !ps.is_empty() && ps.len() <= 2 => {
+                let filter = if ps.len() == 2 {
+                    Some(serde_json::from_value(ps.remove(1))?)
+                } else {
+                    None
+                };
+                (serde_json::from_value(ps.remove(0))?, filter)
+            }
+            _ => {
+                return Err(anyhow!(
+                    "expected the subscription type and an optional filter"
+                ))
+            }
+        };
+
+        match kind.as_str() {
+            "newHeads" => Ok(Self::NewHeads),
+            "logs" => Ok(Self::Logs(Box::new(filter.unwrap_or_default()))),
+            "newPendingTransactions" => Ok(Self::NewPendingTransactions),
+            other => Err(anyhow!("unsupported subscription type: {other}")),
+        }
+    }
+}
+
+/// Notification about a subscription, sent as a JSON-RPC request without an ID.
+#[derive(Serialize)]
+struct Notification<T> {
+    jsonrpc: &'static str,
+    method: &'static str,
+    params: NotificationParams<T>,
+}
+
+#[derive(Serialize)]
+struct NotificationParams<T> {
+    subscription: FilterId,
+    result: T,
+}
+
+/// Polls for new blocks once for all subscriptions, and only while there are any.
+pub struct BlockFeed {
+    /// Headers of new blocks, only created if there is someone to receive them.
+    heads: broadcast::Sender<Arc<serde_json::Value>>,
+    /// Heights of new blocks.
+    heights: broadcast::Sender<u64>,
+    poller: JoinHandle<()>,
+}
+
+impl BlockFeed {
+    /// Start polling for new blocks in the background.
+    pub fn spawn<C>(state: Arc<JsonRpcState<C>>) -> Self
+    where
+        C: Client + Sync + Send + 'static,
+    {
+        let heads = broadcast::channel(BLOCK_FEED_CAPACITY).0;
+        let heights = broadcast::channel(BLOCK_FEED_CAPACITY).0;
+        let poller = tokio::spawn(poll_blocks(state, heads.clone(), heights.clone()));
+
+        Self {
+            heads,
+            heights,
+            poller,
+        }
+    }
+
+    pub fn subscribe_heads(&self) -> broadcast::Receiver<Arc<serde_json::Value>> {
+        self.heads.subscribe()
+    }
+
+    pub fn subscribe_heights(&self) -> broadcast::Receiver<u64> {
+        self.heights.subscribe()
+    }
+}
+
+impl Drop for BlockFeed {
+    fn drop(&mut self) {
+        self.poller.abort();
+    }
+}
+
+/// Messages waiting to be sent to a WebSocket client.
+///
+/// The outbox is bounded, and the client is considered too slow if it fills up.
+#[derive(Clone)]
+pub struct Outbox {
+    tx: mpsc::Sender<String>,
+    slow: Arc<Notify>,
+}
+
+impl Outbox {
+    pub fn new(capacity: usize) -> (Self, mpsc::Receiver<String>) {
+        let (tx, rx) = mpsc::channel(capacity);
+        let outbox = Self {
+            tx,
+            slow: Arc::new(Notify::new()),
+        };
+        (outbox, rx)
+    }
+
+    /// Queue a message, returning `false` if the client is gone or too slow to keep up.
+    pub fn send(&self, msg: String) -> bool {
+        match self.tx.try_send(msg) {
+            Ok(()) => true,
+            Err(mpsc::error::TrySendError::Full(_)) => {
+                self.slow.notify_one();
+                false
+            }
+            Err(mpsc::error::TrySendError::Closed(_)) => false,
+        }
+    }
+
+    /// Wait until the outbox fills up, so the client can be disconnected.
+    pub async fn too_slow(&self) {
+        self.slow.notified().await
+    }
+}
+
+/// Subscriptions of a single WebSocket connection, each with its own background task.
+///
+/// The tasks are stopped when the subscriptions are dropped, ie. when the connection is closed.
+pub struct Subscriptions<C> {
+    state: Arc<JsonRpcState<C>>,
+    feed: Arc<BlockFeed>,
+    outbox: Outbox,
+    /// Maximum number of subscriptions; 0 means unlimited.
+    max_subscriptions: usize,
+    tasks: HashMap<FilterId, JoinHandle<()>>,
+}
+
+impl<C> Subscriptions<C>
+where
+    C: Client + Sync + Send + 'static,
+{
+    pub fn new(
+        state: Arc<JsonRpcState<C>>,
+        feed: Arc<BlockFeed>,
+        outbox: Outbox,
+        max_subscriptions: usize,
+    ) -> Self {
+        Self {
+            state,
+            feed,
+            outbox,
+            max_subscriptions,
+            tasks: HashMap::new(),
+        }
+    }
+
+    /// Start pushing notifications about the subscription, returning its ID.
+    pub fn subscribe(&mut self, kind: SubscriptionKind) -> JsonRpcResult<FilterId> {
+        if self.max_subscriptions > 0 && self.tasks.len() >= self.max_subscriptions {
+            return Err(jsonrpc_v2::Error::Full {
+                code: -32005,
+                message: format!(
+                    "the connection already has {} subscriptions",
+                    self.max_subscriptions
+                ),
+                data: None,
+            });
+        }
+
+        let id = loop {
+            let id = FilterId::from(rand::random::<u128>());
+            if !self.tasks.contains_key(&id) {
+                break id;
+            }
+        };
+
+        let outbox = self.outbox.clone();
+
+        let task = match kind {
+            SubscriptionKind::NewPendingTransactions => {
+                let rx = self.state.subscribe_pending_txs();
+                tokio::spawn(push_all(id, rx, outbox))
+            }
+            SubscriptionKind::NewHeads => {
+                let rx = self.feed.subscribe_heads();
+                tokio::spawn(push_all(id, rx, outbox))
+            }
+            SubscriptionKind::Logs(filter) => {
+                let rx = self.feed.subscribe_heights();
+                tokio::spawn(push_logs(id, *filter, rx, self.state.clone(), outbox))
+            }
+        };
+
+        self.tasks.insert(id, task);
+
+        Ok(id)
+    }
+
+    /// Stop a subscription, returning whether it existed.
+    pub fn unsubscribe(&mut self, id: &FilterId) -> bool {
+        match self.tasks.remove(id) {
+            Some(task) => {
+                task.abort();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl<C> Drop for Subscriptions<C> {
+    fn drop(&mut self) {
+        for (_, task) in self.tasks.drain() {
+            task.abort();
+        }
+    }
+}
+
+/// Send a notification to the client, returning `false` if the connection is gone or too slow.
+fn send<T: Serialize>(outbox: &Outbox, id: FilterId, result: T) -> bool {
+    let notification = Notification {
+        jsonrpc: "2.0",
+        method: "eth_subscription",
+        params: NotificationParams {
+            subscription: id,
+            result,
+        },
+    };
+    match serde_json::to_string(&notification) {
+        Ok(json) => outbox.send(json),
+        Err(e) => {
+            tracing::error!(error = e.to_string(), "failed to serialize notification");
+            true
+        }
+    }
+}
+
+/// Poll for new blocks while anyone is listening, broadcasting their heights and headers.
+async fn poll_blocks<C>(
+    state: Arc<JsonRpcState<C>>,
+    heads: broadcast::Sender<Arc<serde_json::Value>>,
+    heights: broadcast::Sender<u64>,
+) where
+    C: Client + Sync + Send,
+{
+    let mut last_height = None;
+
+    loop {
+        tokio::time::sleep(POLL_INTERVAL).await;
+
+        if heads.receiver_count() == 0 && heights.receiver_count() == 0 {
+            // Start from the latest block when someone subscribes again.
+            last_height = None;
+            continue;
+        }
+
+        let latest = match state.latest_height().await {
+            Ok(latest) => latest,
+            Err(e) => {
+                tracing::warn!(error = error_message(&e), "failed to get the latest height");
+                continue;
+            }
+        };
+
+        let mut height = last_height.unwrap_or(latest);
+
+        while height < latest {
+            let next = height + 1;
+
+            if heads.receiver_count() > 0 {
+                match block_header(&state, next).await {
+                    Ok(header) => {
+                        let _ = heads.send(Arc::new(header));
+                    }
+                    Err(e) => {
+                        tracing::warn!(
+                            error = error_message(&e),
+                            height = next,
+                            "failed to get block header"
+                        );
+                        break;
+                    }
+                }
+            }
+            let _ = heights.send(next);
+
+            height = next;
+        }
+
+        last_height = Some(height);
+    }
+}
+
+/// The header of the block at a given height, as sent to `newHeads` subscribers.
+async fn block_header<C>(state: &JsonRpcState<C>, height: u64) -> JsonRpcResult<serde_json::Value>
+where
+    C: Client + Sync + Send,
+{
+    let block = state
+        .block_by_height(et::BlockNumber::Number(et::U64::from(height)))
+        .await?;
+    let block = state.enrich_block(&block, false).await?;
+    Ok(to_header(block)?)
+}
+
+/// Serialize a block without its body.
+fn to_header<T>(block: et::Block<T>) -> anyhow::Result<serde_json::Value>
+where
+    T: Serialize + serde::de::DeserializeOwned,
+{
+    let mut header = serde_json::to_value(block)?;
+    if let Some(fields) = header.as_object_mut() {
+        for field in BODY_FIELDS {
+            fields.remove(*field);
+        }
+    }
+    Ok(header)
+}
+
+/// Push the logs matching the filter in every new block.
+async fn push_logs<C>(
+    id: FilterId,
+    filter: et::Filter,
+    mut heights: broadcast::Receiver<u64>,
+    state: Arc<JsonRpcState<C>>,
+    outbox: Outbox,
+) where
+    C: Client + Sync + Send,
+{
+    loop {
+        let height = match heights.recv().await {
+            Ok(height) => height,
+            Err(broadcast::error::RecvError::Lagged(n)) => {
+                tracing::warn!(n, "log subscriber lagged behind");
+                continue;
+            }
+            Err(broadcast::error::RecvError::Closed) => return,
+        };
+
+        match state.logs_at_height(&filter, height).await {
+            Ok(logs) => {
+                if !logs.into_iter().all(|log| send(&outbox, id, log)) {
+                    return;
+                }
+            }
+            Err(e) => {
+                tracing::warn!(
+                    error = error_message(&e),
+                    height,
+                    "failed to push log notifications"
+                );
+            }
+        }
+    }
+}
+
+fn error_message(e: &jsonrpc_v2::Error) -> String {
+    match e {
+        jsonrpc_v2::Error::Full { message, .. } => message.clone(),
+        jsonrpc_v2::Error::Provided { message, .. } => message.to_string(),
+    }
+}
+
+/// Push everything that's broadcast, e.g. the hashes of transactions sent to the mempool.
+async fn push_all<T>(id: FilterId, mut rx: broadcast::Receiver<T>, outbox: Outbox)
+where
+    T: Serialize + Clone,
+{
+    loop {
+        match rx.recv().await {
+            Ok(item) => {
+                if !send(&outbox, id, item) {
+                    return;
+                }
+            }
+            Err(broadcast::error::RecvError::Lagged(n)) => {
+                tracing::warn!(n, "subscriber lagged behind");
+            }
+            Err(broadcast::error::RecvError::Closed) => return,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers_core::types as et;
+    use serde_json::json;
+
+    use super::{to_header, Outbox, SubscriptionKind};
+
+    #[test]
+    fn parse_subscription_params() {
+        assert!(matches!(
+            SubscriptionKind::from_params(json!(["newHeads"])),
+            Ok(SubscriptionKind::NewHeads)
+        ));
+        assert!(matches!(
+            SubscriptionKind::from_params(json!(["newPendingTransactions"])),
+            Ok(SubscriptionKind::NewPendingTransactions)
+        ));
+        match SubscriptionKind::from_params(json!([
+            "logs",
+            {"address": "0x8888f1f195afa192cfee860698584c030f4c9db1"}
+        ])) {
+            Ok(SubscriptionKind::Logs(filter)) => assert!(filter.address.is_some()),
+            other => panic!("unexpected: {other:?}"),
+        }
+        assert!(SubscriptionKind::from_params(json!(["syncing"])).is_err());
+        assert!(SubscriptionKind::from_params(json!([])).is_err());
+    }
+
+    #[test]
+    fn header_without_body() {
+        let block = et::Block {
+            number: Some(et::U64::from(1)),
+            transactions: vec![et::H256::zero()],
+            ..Default::default()
+        };
+        let header = to_header(block).unwrap();
+        assert_eq!(header["number"], "0x1");
+        assert!(header.get("transactions").is_none());
+        assert!(header.get("uncles").is_none());
+        assert!(header.get("parentHash").is_some());
+    }
+
+    #[tokio::test]
+    async fn outbox_detects_slow_clients() {
+        let (outbox, mut rx) = Outbox::new(1);
+        assert!(outbox.send("a".into()));
+        assert!(!outbox.send("b".into()));
+        // The slowness is remembered until the connection checks it.
+        outbox.too_slow().await;
+
+        assert_eq!(rx.recv().await.as_deref(), Some("a"));
+        drop(rx);
+        assert!(!outbox.send("c".into()));
+    }
+}
@@ -0,0 +1,209 @@
+// Copyright 2022-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Execution traces in the format returned by the `debug` namespace.
+//!
+//! The traces reflect the calls between FVM actors, rather than the EVM opcodes
+//! `go-ethereum` would return, because that's what the FVM records.
+
+use ethers_core::types as et;
+use fendermint_vm_actor_interface::{eam, evm};
+use fendermint_vm_message::conv::from_fvm::to_u256;
+use fendermint_vm_message::conv::to_eth_address;
+use fendermint_vm_message::query::{CallTrace, TraceBlock, TraceResult};
+use fvm_ipld_encoding::{BytesDe, DAG_CBOR};
+use serde::Serialize;
+
+/// The outcome and the call tree of a traced transaction.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionTrace {
+    pub failed: bool,
+    pub exit_code: u32,
+    pub gas_used: et::U256,
+    pub return_value: et::Bytes,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failure_info: Option<String>,
+    /// Missing if the transaction failed before the recipient was called, e.g. because of a wrong nonce.
+    pub call: Option<CallFrame>,
+}
+
+/// A call from one actor to another.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CallFrame {
+    /// The caller, as an ID based Ethereum address.
+    pub from: et::H160,
+    /// The recipient, if it's referred to with an address that has an Ethereum form.
+    pub to: Option<et::H160>,
+    /// The recipient, as an FVM address.
+    pub to_address: String,
+    pub method: u64,
+    pub value: et::U256,
+    /// Parameters of the call; calldata in case of EVM invocations.
+    pub input: et::Bytes,
+    /// Return value of the call; return data in case of EVM invocations.
+    pub output: et::Bytes,
+    /// Missing if the call never returned, e.g. it ran out of gas.
+    pub exit_code: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Gas used by the call, including the nested calls.
+    pub gas_used: u64,
+    pub gas_charges: Vec<GasCharge>,
+    pub calls: Vec<CallFrame>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GasCharge {
+    pub name: String,
+    pub compute_milligas: u64,
+    pub other_milligas: u64,
+}
+
+pub fn to_transaction_trace(res: TraceResult) -> anyhow::Result<TransactionTrace> {
+    let is_invoke = res
+        .call
+        .as_ref()
+        .map_or(false, |c| c.method == evm::Method::InvokeContract as u64);
+
+    let return_value = if is_invoke {
+        decode_bytes(res.return_data.bytes())
+    } else {
+        res.return_data.to_vec()
+    };
+
+    Ok(TransactionTrace {
+        failed: !res.exit_code.is_success(),
+        exit_code: res.exit_code.value(),
+        gas_used: et::U256::from(res.gas_used),
+        return_value: et::Bytes::from(return_value),
+        failure_info: res.failure_info,
+        call: res.call.map(to_call_frame).transpose()?,
+    })
+}
+
+fn to_call_frame(call: CallTrace) -> anyhow::Result<CallFrame> {
+    // EVM invocations and contract creations wrap the EVM data into CBOR bytes.
+    let is_evm = call.method == evm::Method::InvokeContract as u64
+        || call.method == eam::Method::CreateExternal as u64;
+
+    let unwrap = |block: Option<TraceBlock>| match block {
+        None => Vec::new(),
+        Some(block) if is_evm && block.codec == DAG_CBOR => decode_bytes(block.data.bytes()),
+        Some(block) => block.data.to_vec(),
+    };
+
+    let own_milligas = call
+        .gas_charges
+        .iter()
+        .map(|c| c.compute_milligas.saturating_add(c.other_milligas))
+        .sum::<u64>();
+
+    let calls = call
+        .calls
+        .into_iter()
+        .map(to_call_frame)
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    // Round the own gas up, like the FVM does when charging for a message.
+    let gas_used = calls.iter().map(|c| c.gas_used).sum::<u64>() + (own_milligas + 999) / 1000;
+
+    Ok(CallFrame {
+        from: et::H160::from(eam::EthAddress::from_id(call.from).0),
+        to: to_eth_address(&call.to),
+        to_address: call.to.to_string(),
+        method: call.method,
+        value: to_u256(&call.value)?,
+        input: et::Bytes::from(unwrap(call.params)),
+        output: et::Bytes::from(unwrap(call.return_data)),
+        exit_code: call.exit_code.map(|c| c.value()),
+        error: call.error,
+        gas_used,
+        gas_charges: call
+            .gas_charges
+            .into_iter()
+            .map(|c| GasCharge {
+                name: c.name,
+                compute_milligas: c.compute_milligas,
+                other_milligas: c.other_milligas,
+            })
+            .collect(),
+        calls,
+    })
+}
+
+/// Decode CBOR bytes, falling back to the raw data if it's something else.
+fn decode_bytes(data: &[u8]) -> Vec<u8> {
+    match fvm_ipld_encoding::from_slice::<BytesDe>(data) {
+        Ok(BytesDe(bz)) => bz,
+        Err(_) => data.to_vec(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fendermint_vm_actor_interface::evm;
+    use fendermint_vm_message::query::{CallTrace, GasChargeTrace, TraceBlock, TraceResult};
+    use fvm_ipld_encoding::{BytesSer, RawBytes, DAG_CBOR};
+    use fvm_shared::{address::Address, econ::TokenAmount, error::ExitCode};
+
+    use super::to_transaction_trace;
+
+    fn cbor_bytes(bz: &[u8]) -> RawBytes {
+        RawBytes::serialize(BytesSer(bz)).unwrap()
+    }
+
+    fn call(method: u64, charges: &[u64], calls: Vec<CallTrace>) -> CallTrace {
+        CallTrace {
+            from: 100,
+            to: Address::new_id(200),
+            method,
+            params: Some(TraceBlock {
+                codec: DAG_CBOR,
+                data: cbor_bytes(&[1, 2, 3]),
+            }),
+            value: TokenAmount::from_atto(0),
+            exit_code: Some(ExitCode::OK),
+            return_data: None,
+            error: None,
+            gas_charges: charges
+                .iter()
+                .map(|g| GasChargeTrace {
+                    name: "test".into(),
+                    compute_milligas: *g,
+                    other_milligas: 0,
+                })
+                .collect(),
+            calls,
+        }
+    }
+
+    #[test]
+    fn evm_invocation() {
+        let invoke = evm::Method::InvokeContract as u64;
+        let res = TraceResult {
+            exit_code: ExitCode::OK,
+            gas_used: 10,
+            return_data: cbor_bytes(&[4, 5]),
+            failure_info: None,
+            call: Some(call(invoke, &[1500], vec![call(2, &[2000, 500], vec![])])),
+        };
+
+        let trace = to_transaction_trace(res).unwrap();
+        assert!(!trace.failed);
+        assert_eq!(trace.return_value.to_vec(), vec![4, 5]);
+
+        let root = trace.call.unwrap();
+        // The calldata is unwrapped for EVM invocations, but not for other methods.
+        assert_eq!(root.input.to_vec(), vec![1, 2, 3]);
+        assert_eq!(
+            root.calls[0].input.to_vec(),
+            cbor_bytes(&[1, 2, 3]).to_vec()
+        );
+        // 2 for the own charges of the caller, 3 for the nested call.
+        assert_eq!(root.calls[0].gas_used, 3);
+        assert_eq!(root.gas_used, 5);
+    }
+}
@@ -5,7 +5,9 @@ use std::sync::Arc;
 use anyhow::anyhow;
 use cid::Cid;
 use fvm_ipld_blockstore::Blockstore;
-use rocksdb::{BoundColumnFamily, OptimisticTransactionDB, WriteBatchWithTransaction};
+use rocksdb::{
+    BoundColumnFamily, IteratorMode, OptimisticTransactionDB, WriteBatchWithTransaction,
+};
 
 use crate::RocksDb;
 
@@ -66,6 +68,32 @@ impl NamespaceBlockstore {
             .cf_handle(&self.ns)
             .ok_or_else(|| anyhow!("namespace {} does not exist!", self.ns))
     }
+
+    /// Visit all the keys in the namespace, as they were when the iteration started.
+    ///
+    /// Used by garbage collection, which needs to find blocks that are no longer reachable.
+    pub fn for_each_key<F>(&self, mut f: F) -> anyhow::Result<()>
+    where
+        F: FnMut(Cid) -> anyhow::Result<()>,
+    {
+        let cf = self.cf()?;
+        for kv in self.db.iterator_cf(&cf, IteratorMode::Start) {
+            let (k, _) = kv?;
+            let cid = Cid::try_from(k.as_ref())?;
+            f(cid)?;
+        }
+        Ok(())
+    }
+
+    /// Delete multiple blocks in one batch.
+    pub fn delete_many(&self, keys: &[Cid]) -> anyhow::Result<()> {
+        let cf = self.cf()?;
+        let mut batch = WriteBatchWithTransaction::<true>::default();
+        for cid in keys {
+            batch.delete_cf(&cf, cid.to_bytes());
+        }
+        Ok(self.db.write(batch)?)
+    }
 }
 
 impl Blockstore for NamespaceBlockstore {
@@ -14,7 +14,8 @@ use cid::Cid;
 use fvm_shared::ActorID;
 use fvm_shared::{address::Address, error::ExitCode};
 
-use fendermint_vm_message::query::{ActorState, FvmQuery, GasEstimate, StateParams};
+use fendermint_vm_genesis::Validator;
+use fendermint_vm_message::query::{ActorState, FvmQuery, GasEstimate, StateParams, TraceResult};
 
 use crate::response::encode_data;
 
@@ -113,6 +114,58 @@ pub trait QueryClient: Send + Sync {
         Ok(QueryResponse { height, value })
     }
 
+    /// Current validator set with their power.
+    async fn validators(
+        &self,
+        height: Option<Height>,
+    ) -> anyhow::Result<QueryResponse<Vec<Validator>>> {
+        let res = self.perform(FvmQuery::Validators, height).await?;
+        let height = res.height;
+        let value = extract(res, |res| {
+            fvm_ipld_encoding::from_slice(&res.value)
+                .context("failed to decode validators from query")
+        })?;
+        Ok(QueryResponse { height, value })
+    }
+
+    /// Run messages one after the other with execution tracing enabled, returning the trace of the last one.
+    ///
+    /// To trace a message included in a block, the messages preceding it in the block have to be
+    /// replayed on top of the state the block was executed on, which is the state of the previous block.
+    async fn trace(
+        &self,
+        messages: Vec<Message>,
+        height: Option<Height>,
+    ) -> anyhow::Result<QueryResponse<TraceResult>> {
+        let res = self.perform(FvmQuery::Trace(messages), height).await?;
+        let height = res.height;
+        let value = extract(res, |res| {
+            fvm_ipld_encoding::from_slice(&res.value)
+                .context("failed to decode TraceResult from query")
+        })?;
+        Ok(QueryResponse { height, value })
+    }
+
+    /// Re-execute a transaction included in a block with execution tracing enabled.
+    ///
+    /// The node recreates the state the block was executed on and replays everything before the
+    /// transaction in the block, which it can only do in archive mode.
+    async fn trace_tx(
+        &self,
+        height: Height,
+        index: usize,
+    ) -> anyhow::Result<QueryResponse<TraceResult>> {
+        let res = self
+            .perform(FvmQuery::TraceTx(index.try_into()?), Some(height))
+            .await?;
+        let height = res.height;
+        let value = extract(res, |res| {
+            fvm_ipld_encoding::from_slice(&res.value)
+                .context("failed to decode TraceResult from query")
+        })?;
+        Ok(QueryResponse { height, value })
+    }
+
     /// Run an ABCI query.
     async fn perform(&self, query: FvmQuery, height: Option<Height>) -> anyhow::Result<AbciQuery>;
 }
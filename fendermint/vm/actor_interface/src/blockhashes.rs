@@ -0,0 +1,85 @@
+// Copyright 2022-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+//! The hashes of recent blocks are kept on the ledger, so that every node,
+//! including the ones bootstrapped from a snapshot, sees the same ones.
+//!
+//! This is not a real actor: its state is maintained by the interpreter, and
+//! it has the code of the placeholder actor, so messages sent to it have no effect.
+
+use anyhow::Context;
+use cid::{multihash::Code, Cid};
+use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_encoding::{tuple::*, CborStore, RawBytes};
+use fvm_ipld_hamt::{BytesKey, Hamt};
+use fvm_shared::{address::Address, clock::ChainEpoch, ActorID, HAMT_BIT_WIDTH};
+
+/// Not one of the builtin singletons, but below the first non-singleton ID.
+pub const BLOCK_HASHES_ACTOR_ID: ActorID = 92;
+pub const BLOCK_HASHES_ACTOR_ADDR: Address = Address::new_id(BLOCK_HASHES_ACTOR_ID);
+
+#[derive(Serialize_tuple, Deserialize_tuple, Debug, Clone)]
+pub struct State {
+    /// HAMT of block hashes by height.
+    pub hashes: Cid,
+}
+
+impl State {
+    pub fn new<BS: Blockstore>(store: &BS) -> anyhow::Result<Self> {
+        let hashes = Hamt::<_, RawBytes>::new_with_bit_width(store, HAMT_BIT_WIDTH)
+            .flush()
+            .context("failed to create empty block hashes")?;
+
+        Ok(Self { hashes })
+    }
+
+    pub fn load<BS: Blockstore>(store: &BS, state: &Cid) -> anyhow::Result<Self> {
+        store
+            .get_cbor(state)?
+            .ok_or_else(|| anyhow::anyhow!("block hashes state not found: {state}"))
+    }
+
+    /// Store the state, returning its CID.
+    pub fn save<BS: Blockstore>(&self, store: &BS) -> anyhow::Result<Cid> {
+        store.put_cbor(self, Code::Blake2b256)
+    }
+
+    pub fn get<BS: Blockstore>(
+        &self,
+        store: &BS,
+        height: ChainEpoch,
+    ) -> anyhow::Result<Option<Vec<u8>>> {
+        let hashes = Hamt::<_, RawBytes>::load_with_bit_width(&self.hashes, store, HAMT_BIT_WIDTH)
+            .context("failed to load block hashes")?;
+
+        let hash = hashes.get(&height_key(height))?;
+
+        Ok(hash.map(|h| h.to_vec()))
+    }
+
+    /// Record the hash of the block at a height, forgetting the one at `prune_height`, if any.
+    pub fn set<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        height: ChainEpoch,
+        hash: Vec<u8>,
+        prune_height: Option<ChainEpoch>,
+    ) -> anyhow::Result<()> {
+        let mut hashes =
+            Hamt::<_, RawBytes>::load_with_bit_width(&self.hashes, store, HAMT_BIT_WIDTH)
+                .context("failed to load block hashes")?;
+
+        hashes.set(height_key(height), RawBytes::new(hash))?;
+
+        if let Some(prune_height) = prune_height {
+            hashes.delete(&height_key(prune_height))?;
+        }
+
+        self.hashes = hashes.flush().context("failed to flush block hashes")?;
+
+        Ok(())
+    }
+}
+
+fn height_key(height: ChainEpoch) -> BytesKey {
+    BytesKey::from(height.to_be_bytes().to_vec())
+}
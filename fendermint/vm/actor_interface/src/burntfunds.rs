@@ -0,0 +1,8 @@
+// Copyright 2022-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+//! The FVM sends the base fee and the gas over-estimation penalty here.
+//!
+//! Like in Lotus, it's an account actor with an ID address, which nobody can sign for.
+
+// The code is the account actor.
+define_singleton!(BURNT_FUNDS { id: 99, code_id: 4 });
@@ -1,10 +1,18 @@
 // Copyright 2022-2023 Protocol Labs
 // SPDX-License-Identifier: Apache-2.0, MIT
 
-use fvm_shared::METHOD_CONSTRUCTOR;
+use cid::Cid;
+use fvm_ipld_encoding::{
+    strict_bytes,
+    tuple::{Deserialize_tuple, Serialize_tuple},
+};
+use fvm_shared::{error::ExitCode, METHOD_CONSTRUCTOR};
 
 define_code!(EVM { code_id: 14 });
 
+/// The exit code of a call where the contract reverted; the return data is the revert reason.
+pub const EVM_CONTRACT_REVERTED: ExitCode = ExitCode::new(33);
+
 #[repr(u64)]
 pub enum Method {
     Constructor = METHOD_CONSTRUCTOR,
@@ -19,3 +27,18 @@ pub enum Method {
     //InvokeContract = frc42_dispatch::method_hash!("InvokeEVM"),
     InvokeContract = 3844450837,
 }
+
+/// Return value of [`Method::GetBytecode`]: the CID of the raw bytecode, if the contract has any.
+#[derive(Serialize_tuple, Deserialize_tuple, Debug, Clone)]
+pub struct BytecodeReturn {
+    pub code: Option<Cid>,
+}
+
+/// Parameters of [`Method::GetStorageAt`], which can only be called by the system actor.
+///
+/// The return value is the big-endian value stored at the key, as bytes.
+#[derive(Serialize_tuple, Deserialize_tuple, Debug, Clone)]
+pub struct GetStorageAtParams {
+    #[serde(with = "strict_bytes")]
+    pub storage_key: [u8; 32],
+}
@@ -34,9 +34,14 @@ macro_rules! define_singleton {
 }
 
 pub mod account;
+pub mod blockhashes;
+pub mod burntfunds;
 pub mod cron;
 pub mod eam;
 pub mod evm;
 pub mod init;
 pub mod multisig;
+pub mod resolution;
+pub mod reward;
 pub mod system;
+pub mod validators;
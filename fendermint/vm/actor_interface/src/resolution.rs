@@ -0,0 +1,89 @@
+// Copyright 2022-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+//! The pool of CIDs proposed by validators for resolution is kept on the ledger,
+//! so that we remember who proposed them, even after a restart.
+//!
+//! This is not a real actor: its state is maintained by the interpreter, and
+//! it has the code of the placeholder actor, so messages sent to it have no effect.
+
+use anyhow::Context;
+use cid::{multihash::Code, Cid};
+use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_encoding::{strict_bytes, tuple::*, CborStore};
+use fvm_ipld_hamt::{BytesKey, Hamt};
+use fvm_shared::{address::Address, clock::ChainEpoch, ActorID, HAMT_BIT_WIDTH};
+
+/// Not one of the builtin singletons, but below the first non-singleton ID.
+pub const RESOLUTION_POOL_ACTOR_ID: ActorID = 90;
+pub const RESOLUTION_POOL_ACTOR_ADDR: Address = Address::new_id(RESOLUTION_POOL_ACTOR_ID);
+
+/// A CID proposed for resolution by a validator.
+#[derive(Serialize_tuple, Deserialize_tuple, Debug, Clone, PartialEq, Eq)]
+pub struct PoolEntry {
+    /// Address of the validator who proposed the CID, as it appears in the block header.
+    #[serde(with = "strict_bytes")]
+    pub proposer: Vec<u8>,
+    /// Height of the block where the CID was proposed for resolution.
+    pub proposed_at: ChainEpoch,
+    /// Height of the block where the message was executed, if it has been.
+    ///
+    /// Executed entries are kept, so that the same message cannot be executed again.
+    pub executed_at: Option<ChainEpoch>,
+    /// Link to the content included in the block which executed the message,
+    /// so that it's kept in the state store along with the rest of the ledger.
+    pub content: Option<Cid>,
+}
+
+#[derive(Serialize_tuple, Deserialize_tuple, Debug, Clone)]
+pub struct State {
+    /// HAMT of [PoolEntry] by CID.
+    pub entries: Cid,
+}
+
+impl State {
+    pub fn new<BS: Blockstore>(store: &BS) -> anyhow::Result<Self> {
+        let entries = Hamt::<_, PoolEntry>::new_with_bit_width(store, HAMT_BIT_WIDTH)
+            .flush()
+            .context("failed to create empty pool")?;
+
+        Ok(Self { entries })
+    }
+
+    pub fn load<BS: Blockstore>(store: &BS, state: &Cid) -> anyhow::Result<Self> {
+        store
+            .get_cbor(state)?
+            .ok_or_else(|| anyhow::anyhow!("resolution pool state not found: {state}"))
+    }
+
+    /// Store the state, returning its CID.
+    pub fn save<BS: Blockstore>(&self, store: &BS) -> anyhow::Result<Cid> {
+        store.put_cbor(self, Code::Blake2b256)
+    }
+
+    pub fn get<BS: Blockstore>(&self, store: &BS, cid: &Cid) -> anyhow::Result<Option<PoolEntry>> {
+        let entries =
+            Hamt::<_, PoolEntry>::load_with_bit_width(&self.entries, store, HAMT_BIT_WIDTH)
+                .context("failed to load pool")?;
+
+        let entry = entries.get(&BytesKey::from(cid.to_bytes()))?;
+
+        Ok(entry.cloned())
+    }
+
+    pub fn set<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        cid: &Cid,
+        entry: PoolEntry,
+    ) -> anyhow::Result<()> {
+        let mut entries =
+            Hamt::<_, PoolEntry>::load_with_bit_width(&self.entries, store, HAMT_BIT_WIDTH)
+                .context("failed to load pool")?;
+
+        entries.set(BytesKey::from(cid.to_bytes()), entry)?;
+
+        self.entries = entries.flush().context("failed to flush pool")?;
+
+        Ok(())
+    }
+}
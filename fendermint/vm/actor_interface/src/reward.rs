@@ -0,0 +1,38 @@
+// Copyright 2022-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+//! The FVM credits the gas premium of every message to the reward actor.
+//!
+//! We deploy the builtin reward actor, so anyone calling it finds a real actor, but since it's
+//! concerned with storage miners, its state is left empty and it never pays out anything itself;
+//! instead the tips accumulate in its balance during the block, and the interpreter pays them out
+//! to the validator who proposed it at the end.
+
+use fvm_ipld_encoding::tuple::*;
+use fvm_shared::bigint::{bigint_ser, BigInt};
+use fvm_shared::clock::ChainEpoch;
+use fvm_shared::econ::TokenAmount;
+use fvm_shared::smooth::FilterEstimate;
+
+define_singleton!(REWARD { id: 2, code_id: 10 });
+
+/// Reward actor state, copied from the builtin actors, so that its code can load it.
+///
+/// See [State](https://github.com/filecoin-project/builtin-actors/blob/v11.0.0/actors/reward/src/state.rs)
+#[derive(Serialize_tuple, Deserialize_tuple, Debug, Clone, Default)]
+pub struct State {
+    #[serde(with = "bigint_ser")]
+    pub cumsum_baseline: BigInt,
+    #[serde(with = "bigint_ser")]
+    pub cumsum_realized: BigInt,
+    pub effective_network_time: ChainEpoch,
+    #[serde(with = "bigint_ser")]
+    pub effective_baseline_power: BigInt,
+    pub this_epoch_reward: TokenAmount,
+    pub this_epoch_reward_smoothed: FilterEstimate,
+    #[serde(with = "bigint_ser")]
+    pub this_epoch_baseline_power: BigInt,
+    pub epoch: ChainEpoch,
+    pub total_storage_power_reward: TokenAmount,
+    pub simple_total: TokenAmount,
+    pub baseline_total: TokenAmount,
+}
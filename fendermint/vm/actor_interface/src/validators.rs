@@ -0,0 +1,78 @@
+// Copyright 2022-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+//! The validator set is kept in the state tree, seeded from the genesis validators,
+//! so that it can be changed by transactions and the changes can be reported to
+//! CometBFT at the end of the block.
+//!
+//! Like the resolution pool, this is not a real actor: it has the code of the
+//! placeholder actor, and the messages sent to it are handled by the interpreter.
+//! The validator set can be changed by messages the system actor sends on behalf
+//! of the validators, for example relayed from the parent subnet, or by signed
+//! messages from the admin account set at genesis.
+
+use fendermint_vm_genesis::{Power, Validator, ValidatorKey};
+use fvm_ipld_encoding::tuple::*;
+use fvm_shared::{address::Address, METHOD_CONSTRUCTOR};
+
+// The code is the placeholder actor.
+define_singleton!(VALIDATORS {
+    id: 91,
+    code_id: 13
+});
+
+/// The highest total power CometBFT accepts for the validator set, `MaxInt64 / 8`.
+pub const MAX_TOTAL_POWER: u64 = (i64::MAX / 8) as u64;
+
+/// Validator set methods available.
+#[repr(u64)]
+pub enum Method {
+    Constructor = METHOD_CONSTRUCTOR,
+    SetPower = 2,
+}
+
+/// Set the power of a validator; zero power removes them from the set.
+#[derive(Serialize_tuple, Deserialize_tuple, Debug, Clone, PartialEq, Eq)]
+pub struct SetPowerParams {
+    pub public_key: ValidatorKey,
+    pub power: Power,
+}
+
+/// The current validators with their power.
+#[derive(Default, Serialize_tuple, Deserialize_tuple, Debug, Clone)]
+pub struct State {
+    pub validators: Vec<Validator>,
+    /// Account allowed to set the power of validators with signed messages.
+    pub admin: Option<Address>,
+}
+
+impl State {
+    pub fn new(validators: Vec<Validator>, admin: Option<Address>) -> Self {
+        let mut state = Self {
+            validators: Vec::new(),
+            admin,
+        };
+        for v in validators {
+            state.set_power(v);
+        }
+        state
+    }
+
+    /// Add, update or remove a validator.
+    pub fn set_power(&mut self, validator: Validator) {
+        let existing = self
+            .validators
+            .iter()
+            .position(|v| v.public_key == validator.public_key);
+
+        match existing {
+            Some(i) if validator.power.0 == 0 => {
+                self.validators.remove(i);
+            }
+            Some(i) => {
+                self.validators[i] = validator;
+            }
+            None if validator.power.0 == 0 => {}
+            None => self.validators.push(validator),
+        }
+    }
+}
@@ -78,6 +78,12 @@ pub trait HasChainID {
     fn chain_id(&self) -> &ChainID;
 }
 
+impl HasChainID for ChainID {
+    fn chain_id(&self) -> &ChainID {
+        self
+    }
+}
+
 /// Extract the root chain ID _iff_ the name is in the format of "/r<chain-id>".
 fn just_root_id(name: &str) -> Option<u64> {
     ROOT_RE.captures_iter(name).next().and_then(|cap| {
@@ -1,7 +1,8 @@
 // Copyright 2022-2023 Protocol Labs
 // SPDX-License-Identifier: Apache-2.0, MIT
 use crate::{
-    Account, Actor, ActorMeta, Genesis, Multisig, Power, SignerAddr, Validator, ValidatorKey,
+    Account, Actor, ActorMeta, BaseFeeParams, Genesis, Multisig, Power, SignerAddr, Validator,
+    ValidatorKey,
 };
 use fendermint_testing::arb::{ArbAddress, ArbTokenAmount};
 use fendermint_vm_core::Timestamp;
@@ -59,6 +60,15 @@ impl Arbitrary for Validator {
     }
 }
 
+impl Arbitrary for BaseFeeParams {
+    fn arbitrary(g: &mut Gen) -> Self {
+        Self {
+            gas_target: u64::arbitrary(g).max(1),
+            max_change_denominator: u64::arbitrary(g).max(1),
+        }
+    }
+}
+
 impl Arbitrary for Genesis {
     fn arbitrary(g: &mut Gen) -> Self {
         let nv = usize::arbitrary(g) % 10 + 1;
@@ -68,8 +78,11 @@ impl Arbitrary for Genesis {
             chain_name: String::arbitrary(g),
             network_version: NetworkVersion::new(*g.choose(&[18u32]).unwrap()),
             base_fee: ArbTokenAmount::arbitrary(g).0,
+            base_fee_params: Option::<BaseFeeParams>::arbitrary(g),
+            block_reward: ArbTokenAmount::arbitrary(g).0,
             validators: (0..nv).map(|_| Arbitrary::arbitrary(g)).collect(),
             accounts: (0..na).map(|_| Arbitrary::arbitrary(g)).collect(),
+            validator_admin: Option::<ArbAddress>::arbitrary(g).map(|a| SignerAddr(a.0)),
         }
     }
 }
@@ -95,6 +95,18 @@ pub struct Validator {
     pub power: Power,
 }
 
+/// Parameters of the EIP-1559 style adjustment of the base fee after each block.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct BaseFeeParams {
+    /// The amount of gas used per block that the base fee aims for.
+    ///
+    /// The base fee goes up when blocks use more gas than this, and down when they use less.
+    /// Blocks can use up to the block gas limit; the ratio of the two is the elasticity.
+    pub gas_target: u64,
+    /// The base fee can change by at most 1/denominator of itself from one block to the next.
+    pub max_change_denominator: u64,
+}
+
 #[serde_as]
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
 pub struct Genesis {
@@ -107,8 +119,19 @@ pub struct Genesis {
     pub network_version: NetworkVersion,
     #[serde_as(as = "IsHumanReadable")]
     pub base_fee: TokenAmount,
+    /// Adjust the base fee after each block; if missing, the base fee stays fixed.
+    #[serde(default)]
+    pub base_fee_params: Option<BaseFeeParams>,
+    /// Amount minted in each block for the validator who proposed it, on top of the gas premiums.
+    #[serde_as(as = "IsHumanReadable")]
+    #[serde(default)]
+    pub block_reward: TokenAmount,
     pub validators: Vec<Validator>,
     pub accounts: Vec<Actor>,
+    /// Account allowed to change the power of validators with signed messages; if missing,
+    /// only messages proposed for execution by the validators can change the validator set.
+    #[serde(default)]
+    pub validator_admin: Option<SignerAddr>,
 }
 
 #[cfg(test)]
@@ -9,7 +9,7 @@ use fendermint_vm_message::chain::ChainMessage;
 use crate::{
     chain::{ChainMessageApplyRet, ChainMessageCheckRet},
     fvm::{FvmQuery, FvmQueryRet},
-    CheckInterpreter, ExecInterpreter, GenesisInterpreter, QueryInterpreter,
+    CheckInterpreter, ExecInterpreter, GenesisInterpreter, ProposalInterpreter, QueryInterpreter,
 };
 
 pub type BytesMessageApplyRet = Result<ChainMessageApplyRet, fvm_ipld_encoding::Error>;
@@ -100,6 +100,52 @@ where
     }
 }
 
+#[async_trait]
+impl<I> ProposalInterpreter for BytesMessageInterpreter<I>
+where
+    I: ProposalInterpreter<Message = ChainMessage>,
+{
+    type State = I::State;
+    type Message = Vec<u8>;
+
+    async fn prepare(
+        &self,
+        state: Self::State,
+        msgs: Vec<Self::Message>,
+    ) -> anyhow::Result<Vec<Self::Message>> {
+        // Drop anything we cannot decode; it would only fail during delivery.
+        let msgs = msgs
+            .iter()
+            .filter_map(|msg| fvm_ipld_encoding::from_slice::<ChainMessage>(msg).ok())
+            .collect();
+
+        let msgs = self.inner.prepare(state, msgs).await?;
+
+        let msgs = msgs
+            .iter()
+            .map(fvm_ipld_encoding::to_vec)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(msgs)
+    }
+
+    async fn process(&self, state: Self::State, msgs: Vec<Self::Message>) -> anyhow::Result<bool> {
+        let mut chain_msgs = Vec::with_capacity(msgs.len());
+
+        for msg in msgs {
+            match fvm_ipld_encoding::from_slice::<ChainMessage>(&msg) {
+                Err(_) => {
+                    // The proposer put rubbish in the block, we should not vote for it.
+                    return Ok(false);
+                }
+                Ok(msg) => chain_msgs.push(msg),
+            }
+        }
+
+        self.inner.process(state, chain_msgs).await
+    }
+}
+
 #[async_trait]
 impl<I> QueryInterpreter for BytesMessageInterpreter<I>
 where
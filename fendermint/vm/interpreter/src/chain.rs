@@ -1,43 +1,180 @@
-use anyhow::anyhow;
 // Copyright 2022-2023 Protocol Labs
 // SPDX-License-Identifier: Apache-2.0, MIT
+use std::collections::HashSet;
+
+use anyhow::Context;
 use async_trait::async_trait;
+use cid::Cid;
 
+use fendermint_vm_actor_interface::resolution::PoolEntry;
 use fendermint_vm_message::{chain::ChainMessage, signed::SignedMessage};
+use fendermint_vm_resolver::{verify_content, ResolvePool, Resolver};
+use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_encoding::RawBytes;
+use fvm_shared::chainid::ChainID;
 
 use crate::{
-    signed::{SignedMessageApplyRet, SignedMessageCheckRet},
-    CheckInterpreter, ExecInterpreter, GenesisInterpreter, QueryInterpreter,
+    fvm::{state::FvmExecState, FvmApplyRet},
+    signed::{InvalidSignature, SignedMessageApplyRet, SignedMessageCheckRet},
+    CheckInterpreter, ExecInterpreter, GenesisInterpreter, ProposalInterpreter, QueryInterpreter,
 };
 
 /// A message a user is not supposed to send.
 pub struct IllegalMessage;
 
-// For now this is the only option, later we can expand.
+/// A CID proposed for execution which cannot be executed, for a reason every validator agrees on.
+pub struct IllegalExecution(pub String);
+
+pub type ForExecutionApplyRet = Result<FvmApplyRet, IllegalExecution>;
+
 pub enum ChainMessageApplyRet {
     Signed(SignedMessageApplyRet),
+    /// The CID has been added to the pool of messages to resolve.
+    ///
+    /// Proposing a CID which is already in the pool is not an error, it's just ignored.
+    ForResolution(Cid),
+    /// The resolved message has been executed, or rejected without executing it.
+    ForExecution(ForExecutionApplyRet),
 }
 
 /// We only allow signed messages into the mempool.
 pub type ChainMessageCheckRet = Result<SignedMessageCheckRet, IllegalMessage>;
 
-/// Interpreter working on chain messages; in the future it will schedule
-/// CID lookups to turn references into self-contained user or cross messages.
+/// Interpreter working on chain messages, scheduling CID lookups to turn references
+/// into self-contained messages, and executing them once they have been resolved.
+///
+/// The only messages which can be relayed this way are the ones signed by their sender,
+/// so the validators proposing them cannot execute anything on anyone else's behalf.
 #[derive(Clone)]
-pub struct ChainMessageInterpreter<I> {
+pub struct ChainMessageInterpreter<I, R> {
     inner: I,
+    pool: ResolvePool<R>,
+    /// Whether the blocks are being replayed, in which case the CIDs in them have been
+    /// dealt with when they were first executed, and the local pool is left alone.
+    is_replay: bool,
 }
 
-impl<I> ChainMessageInterpreter<I> {
-    pub fn new(inner: I) -> Self {
-        Self { inner }
+impl<I, R> ChainMessageInterpreter<I, R> {
+    pub fn new(inner: I, pool: ResolvePool<R>) -> Self {
+        Self {
+            inner,
+            pool,
+            is_replay: false,
+        }
+    }
+
+    /// Create an interpreter to replay blocks which have already been executed,
+    /// without adding their CIDs to the pool again or removing them from it.
+    pub fn for_replay(&self) -> Self
+    where
+        I: Clone,
+    {
+        Self {
+            inner: self.inner.clone(),
+            pool: self.pool.clone(),
+            is_replay: true,
+        }
+    }
+}
+
+impl<I, R, DB> ChainMessageInterpreter<I, R>
+where
+    I: ExecInterpreter<
+        Message = SignedMessage,
+        DeliverOutput = SignedMessageApplyRet,
+        State = FvmExecState<DB>,
+    >,
+    R: Resolver,
+    DB: Blockstore + 'static + Send + Sync,
+{
+    /// Execute a message the validators proposed for execution, with the content included in the block.
+    ///
+    /// Only the ledger and the content decide whether the message can be executed,
+    /// so that every validator reaches the same conclusion without resolving the CID.
+    async fn execute_relayed(
+        &self,
+        mut state: FvmExecState<DB>,
+        cid: Cid,
+        content: RawBytes,
+    ) -> anyhow::Result<(FvmExecState<DB>, ForExecutionApplyRet)> {
+        let illegal = |state, e: String| Ok((state, Err(IllegalExecution(e))));
+
+        let mut entry = match state.get_pool_entry(&cid)? {
+            None => {
+                return illegal(
+                    state,
+                    format!("CID {cid} proposed for execution was never proposed for resolution"),
+                )
+            }
+            Some(entry) if entry.executed_at.is_some() => {
+                return illegal(
+                    state,
+                    format!("CID {cid} proposed for execution has already been executed"),
+                )
+            }
+            Some(entry) => entry,
+        };
+
+        // Someone else might still propose the right content.
+        if !verify_content(&cid, &content).unwrap_or_default() {
+            return illegal(
+                state,
+                format!("content proposed for execution does not match CID {cid}"),
+            );
+        }
+
+        // Whether it can be executed or not, the CID is done with.
+        entry.executed_at = Some(state.block_height());
+
+        if !self.is_replay {
+            self.pool.remove(&cid);
+        }
+
+        let msg = match fvm_ipld_encoding::from_slice::<SignedMessage>(&content) {
+            Ok(msg) => msg,
+            Err(e) => {
+                state.set_pool_entry(&cid, entry)?;
+                return illegal(
+                    state,
+                    format!("CID {cid} does not resolve to a signed message: {e}"),
+                );
+            }
+        };
+
+        // Keep the content in the state, so what has been executed doesn't depend on the resolver.
+        state
+            .state_tree_mut()
+            .store()
+            .put_keyed(&cid, &content)
+            .context("failed to store resolved content")?;
+
+        entry.content = Some(cid);
+        state.set_pool_entry(&cid, entry)?;
+
+        // The validators only relayed the message; the sender signed it and pays for the gas.
+        let (state, ret) = self.inner.deliver(state, msg).await?;
+
+        let ret = match ret {
+            Ok(ret) => Ok(ret.ret),
+            Err(InvalidSignature(e)) => Err(IllegalExecution(format!(
+                "CID {cid} resolves to a message with an invalid signature: {e}"
+            ))),
+        };
+
+        Ok((state, ret))
     }
 }
 
 #[async_trait]
-impl<I> ExecInterpreter for ChainMessageInterpreter<I>
+impl<I, R, DB> ExecInterpreter for ChainMessageInterpreter<I, R>
 where
-    I: ExecInterpreter<Message = SignedMessage, DeliverOutput = SignedMessageApplyRet>,
+    I: ExecInterpreter<
+        Message = SignedMessage,
+        DeliverOutput = SignedMessageApplyRet,
+        State = FvmExecState<DB>,
+    >,
+    R: Resolver,
+    DB: Blockstore + 'static + Send + Sync,
 {
     type State = I::State;
     type Message = ChainMessage;
@@ -47,7 +184,7 @@ where
 
     async fn deliver(
         &self,
-        state: Self::State,
+        mut state: Self::State,
         msg: Self::Message,
     ) -> anyhow::Result<(Self::State, Self::DeliverOutput)> {
         match msg {
@@ -55,12 +192,34 @@ where
                 let (state, ret) = self.inner.deliver(state, *msg).await?;
                 Ok((state, ChainMessageApplyRet::Signed(ret)))
             }
-            ChainMessage::ForExecution(_) | ChainMessage::ForResolution(_) => {
-                // This only happens if a validator is malicious or we have made a programming error.
-                // I expect for now that we don't run with untrusted validators, so it's okay to quit.
-                Err(anyhow!(
-                    "The handling of ForExecution and ForResolution is not yet implemented."
-                ))
+            ChainMessage::ForResolution(cid) => {
+                let entry = state.get_pool_entry(&cid)?;
+
+                if entry.is_none() {
+                    let entry = PoolEntry {
+                        proposer: state.block_producer().unwrap_or_default().to_vec(),
+                        proposed_at: state.block_height(),
+                        executed_at: None,
+                        content: None,
+                    };
+                    state.set_pool_entry(&cid, entry)?;
+                }
+
+                if !self.is_replay {
+                    match entry {
+                        // Even if it has been proposed before, we might not have it locally, e.g. after a restart.
+                        None => self.pool.add(cid),
+                        Some(entry) if entry.executed_at.is_none() => self.pool.add(cid),
+                        // There is nothing left to resolve, just stop proposing it.
+                        Some(_) => self.pool.set_proposed(cid),
+                    }
+                }
+
+                Ok((state, ChainMessageApplyRet::ForResolution(cid)))
+            }
+            ChainMessage::ForExecution(cid, content) => {
+                let (state, ret) = self.execute_relayed(state, cid, content).await?;
+                Ok((state, ChainMessageApplyRet::ForExecution(ret)))
             }
         }
     }
@@ -75,9 +234,10 @@ where
 }
 
 #[async_trait]
-impl<I> CheckInterpreter for ChainMessageInterpreter<I>
+impl<I, R> CheckInterpreter for ChainMessageInterpreter<I, R>
 where
     I: CheckInterpreter<Message = SignedMessage, Output = SignedMessageCheckRet>,
+    R: Send + Sync + 'static,
 {
     type State = I::State;
     type Message = ChainMessage;
@@ -95,7 +255,7 @@ where
 
                 Ok((state, Ok(ret)))
             }
-            ChainMessage::ForExecution(_) | ChainMessage::ForResolution(_) => {
+            ChainMessage::ForExecution(_, _) | ChainMessage::ForResolution(_) => {
                 // Users cannot send these messages, only validators can propose them in blocks.
                 Ok((state, Err(IllegalMessage)))
             }
@@ -104,9 +264,130 @@ where
 }
 
 #[async_trait]
-impl<I> QueryInterpreter for ChainMessageInterpreter<I>
+impl<I, R> ProposalInterpreter for ChainMessageInterpreter<I, R>
+where
+    I: ProposalInterpreter<Message = SignedMessage, State = ChainID>,
+    R: Resolver,
+{
+    type State = I::State;
+    type Message = ChainMessage;
+
+    /// Propose the resolution of the candidates offered by the resolver, and the execution
+    /// of every CID this node has resolved along with the signed messages.
+    async fn prepare(
+        &self,
+        chain_id: Self::State,
+        msgs: Vec<Self::Message>,
+    ) -> anyhow::Result<Vec<Self::Message>> {
+        // The pool only has CIDs which were proposed for resolution on the ledger and not yet executed.
+        // Content which isn't a relayable message would not get the votes of the other validators.
+        let relayed = self
+            .pool
+            .resolved()
+            .into_iter()
+            .filter_map(|cid| {
+                let content = self.pool.get(&cid)?;
+                let msg = relayed_message(&cid, &content, &chain_id)?;
+                Some((cid, content, msg))
+            })
+            .collect::<Vec<_>>();
+
+        let is_relayed = |msg: &SignedMessage| relayed.iter().find(|(_, _, r)| r == msg);
+
+        // The transactions come from the mempool, where users cannot put anything but signed messages.
+        // A message which is also relayed can only be executed once.
+        let msgs = msgs
+            .into_iter()
+            .filter_map(|msg| match msg {
+                ChainMessage::Signed(msg) if is_relayed(&msg).is_none() => Some(*msg),
+                ChainMessage::Signed(_)
+                | ChainMessage::ForResolution(_)
+                | ChainMessage::ForExecution(_, _) => None,
+            })
+            .chain(relayed.iter().map(|(_, _, msg)| msg.clone()))
+            .collect();
+
+        // The relayed messages compete for the gas in the block with the rest.
+        let msgs = self.inner.prepare(chain_id, msgs).await?;
+
+        let msgs = msgs.into_iter().map(|msg| match is_relayed(&msg) {
+            Some((cid, content, _)) => {
+                ChainMessage::ForExecution(*cid, RawBytes::new(content.clone()))
+            }
+            None => ChainMessage::Signed(Box::new(msg)),
+        });
+
+        let candidates = self
+            .pool
+            .candidates()
+            .await
+            .into_iter()
+            .map(ChainMessage::ForResolution);
+
+        Ok(candidates.chain(msgs).collect())
+    }
+
+    /// Only vote for the block if every signed message and CID in it is proposed once,
+    /// and the content proposed for execution is what the CID refers to, which has to be
+    /// a message signed by its sender. The relayed messages are checked by the inner
+    /// interpreter along with the signed ones.
+    async fn process(
+        &self,
+        chain_id: Self::State,
+        msgs: Vec<Self::Message>,
+    ) -> anyhow::Result<bool> {
+        let mut signed_msgs = Vec::with_capacity(msgs.len());
+        let mut cids = HashSet::new();
+
+        for msg in msgs {
+            match msg {
+                ChainMessage::Signed(msg) => signed_msgs.push(*msg),
+                ChainMessage::ForResolution(cid) => {
+                    // Proposing something that's already being resolved is ignored, but repeating it is spam.
+                    if !cids.insert(cid) {
+                        return Ok(false);
+                    }
+                }
+                ChainMessage::ForExecution(cid, content) => {
+                    if !cids.insert(cid) {
+                        return Ok(false);
+                    }
+                    match relayed_message(&cid, &content, &chain_id) {
+                        Some(msg) => signed_msgs.push(msg),
+                        None => return Ok(false),
+                    }
+                }
+            }
+        }
+
+        // At most one of the messages using the same nonce could be executed.
+        let mut nonces = HashSet::new();
+        for msg in signed_msgs.iter() {
+            if !nonces.insert((msg.message.from, msg.message.sequence)) {
+                return Ok(false);
+            }
+        }
+
+        self.inner.process(chain_id, signed_msgs).await
+    }
+}
+
+/// Decode resolved content into a message which can be relayed: the content has to match
+/// the CID, and it has to be a message with a valid signature from its sender.
+fn relayed_message(cid: &Cid, content: &[u8], chain_id: &ChainID) -> Option<SignedMessage> {
+    if !verify_content(cid, content).unwrap_or_default() {
+        return None;
+    }
+    let msg = fvm_ipld_encoding::from_slice::<SignedMessage>(content).ok()?;
+    msg.verify(chain_id).ok()?;
+    Some(msg)
+}
+
+#[async_trait]
+impl<I, R> QueryInterpreter for ChainMessageInterpreter<I, R>
 where
     I: QueryInterpreter,
+    R: Send + Sync + 'static,
 {
     type State = I::State;
     type Query = I::Query;
@@ -122,9 +403,10 @@ where
 }
 
 #[async_trait]
-impl<I> GenesisInterpreter for ChainMessageInterpreter<I>
+impl<I, R> GenesisInterpreter for ChainMessageInterpreter<I, R>
 where
     I: GenesisInterpreter,
+    R: Send + Sync + 'static,
 {
     type State = I::State;
     type Genesis = I::Genesis;
@@ -138,3 +420,258 @@ where
         self.inner.init(state, genesis).await
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    use async_trait::async_trait;
+    use cid::{
+        multihash::{Code, MultihashDigest},
+        Cid,
+    };
+    use fendermint_vm_message::{chain::ChainMessage, signed::SignedMessage};
+    use fendermint_vm_resolver::{ResolvePool, Resolver};
+    use fvm_ipld_encoding::{RawBytes, DAG_CBOR};
+    use fvm_shared::{address::Address, chainid::ChainID, crypto::signature::Signature};
+
+    use crate::{bytes::BytesMessageInterpreter, fvm::FvmMessage, ProposalInterpreter};
+
+    use super::ChainMessageInterpreter;
+
+    const CHAIN_ID: u64 = 1234;
+
+    /// Resolves whatever content it has been given, and offers some CIDs as candidates.
+    struct MemoryResolver {
+        contents: HashMap<Cid, Vec<u8>>,
+        candidates: Vec<Cid>,
+    }
+
+    #[async_trait]
+    impl Resolver for MemoryResolver {
+        async fn resolve(&self, cid: &Cid) -> anyhow::Result<Option<Vec<u8>>> {
+            Ok(self.contents.get(cid).cloned())
+        }
+
+        async fn candidates(&self) -> anyhow::Result<Vec<Cid>> {
+            Ok(self.candidates.clone())
+        }
+    }
+
+    /// Accepts all signed messages.
+    struct AcceptAll;
+
+    #[async_trait]
+    impl ProposalInterpreter for AcceptAll {
+        type State = ChainID;
+        type Message = SignedMessage;
+
+        async fn prepare(
+            &self,
+            _: ChainID,
+            msgs: Vec<SignedMessage>,
+        ) -> anyhow::Result<Vec<SignedMessage>> {
+            Ok(msgs)
+        }
+
+        async fn process(&self, _: ChainID, _: Vec<SignedMessage>) -> anyhow::Result<bool> {
+            Ok(true)
+        }
+    }
+
+    type TestInterpreter = ChainMessageInterpreter<AcceptAll, MemoryResolver>;
+
+    fn chain_id() -> ChainID {
+        ChainID::from(CHAIN_ID)
+    }
+
+    fn message(from: Address, sequence: u64) -> FvmMessage {
+        FvmMessage {
+            version: Default::default(),
+            from,
+            to: Address::new_id(1),
+            sequence,
+            value: Default::default(),
+            method_num: 0,
+            params: Default::default(),
+            gas_limit: 1000,
+            gas_fee_cap: Default::default(),
+            gas_premium: Default::default(),
+        }
+    }
+
+    fn unsigned(from: u64, sequence: u64) -> SignedMessage {
+        SignedMessage {
+            message: message(Address::new_id(from), sequence),
+            signature: Signature::new_secp256k1(Vec::new()).into(),
+        }
+    }
+
+    fn signed(from: u64, sequence: u64) -> ChainMessage {
+        ChainMessage::Signed(Box::new(unsigned(from, sequence)))
+    }
+
+    /// A message with a valid signature of the sender.
+    fn relayable() -> SignedMessage {
+        let sk = libsecp256k1::SecretKey::parse(&[1; 32]).unwrap();
+        let pk = libsecp256k1::PublicKey::from_secret_key(&sk);
+        let from = Address::new_secp256k1(&pk.serialize()).unwrap();
+
+        SignedMessage::new_secp256k1(message(from, 0), &sk, &chain_id()).unwrap()
+    }
+
+    fn content_cid(content: &[u8]) -> Cid {
+        Cid::new_v1(DAG_CBOR, Code::Blake2b256.digest(content))
+    }
+
+    /// Propose the execution of a CID with the content this node resolved it to.
+    fn for_execution(interpreter: &TestInterpreter, cid: Cid) -> ChainMessage {
+        let content = interpreter.pool.get(&cid).expect("CID resolved");
+        ChainMessage::ForExecution(cid, RawBytes::new(content))
+    }
+
+    /// Create an interpreter with a pool in which the content of the returned CIDs is resolved:
+    /// a message with a valid signature, a message with an invalid signature, and rubbish.
+    ///
+    /// The resolver offers a fourth CID as a candidate for resolution.
+    async fn interpreter() -> (TestInterpreter, [Cid; 4]) {
+        let contents = [
+            fvm_ipld_encoding::to_vec(&relayable()).unwrap(),
+            fvm_ipld_encoding::to_vec(&unsigned(100, 0)).unwrap(),
+            b"rubbish".to_vec(),
+        ];
+        let cids = contents.clone().map(|c| content_cid(&c));
+        let candidate = content_cid(b"candidate");
+
+        let resolver = MemoryResolver {
+            contents: cids.into_iter().zip(contents).collect(),
+            candidates: vec![candidate],
+        };
+        let pool = ResolvePool::new(resolver, Duration::from_secs(1));
+        for cid in cids {
+            pool.set_proposed(cid);
+            pool.resolve_now(&cid).await.unwrap();
+        }
+
+        let [relayed, illegal, rubbish] = cids;
+
+        (
+            ChainMessageInterpreter::new(AcceptAll, pool),
+            [relayed, illegal, rubbish, candidate],
+        )
+    }
+
+    #[tokio::test]
+    async fn process_accepts_valid_proposal() {
+        let (interpreter, [relayed, _, _, _]) = interpreter().await;
+        let msgs = vec![
+            for_execution(&interpreter, relayed),
+            ChainMessage::ForResolution(content_cid(b"new")),
+            signed(100, 0),
+            signed(100, 1),
+        ];
+        assert!(interpreter.process(chain_id(), msgs).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn process_rejects_mismatching_content() {
+        let (interpreter, [relayed, _, _, _]) = interpreter().await;
+        let content = interpreter.pool.get(&relayed).unwrap();
+        let msgs = vec![ChainMessage::ForExecution(
+            content_cid(b"unknown"),
+            RawBytes::new(content),
+        )];
+        assert!(!interpreter.process(chain_id(), msgs).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn process_accepts_resolution_in_pool() {
+        let (interpreter, [relayed, _, _, _]) = interpreter().await;
+        let msgs = vec![ChainMessage::ForResolution(relayed)];
+        assert!(interpreter.process(chain_id(), msgs).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn process_rejects_undecodable_execution() {
+        let (interpreter, [_, _, rubbish, _]) = interpreter().await;
+        let msgs = vec![for_execution(&interpreter, rubbish)];
+        assert!(!interpreter.process(chain_id(), msgs).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn process_rejects_illegal_execution() {
+        let (interpreter, [_, illegal, _, _]) = interpreter().await;
+        let msgs = vec![for_execution(&interpreter, illegal)];
+        assert!(!interpreter.process(chain_id(), msgs).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn process_rejects_duplicates() {
+        let (interpreter, [relayed, _, _, _]) = interpreter().await;
+
+        let msgs = vec![
+            for_execution(&interpreter, relayed),
+            for_execution(&interpreter, relayed),
+        ];
+        assert!(!interpreter.process(chain_id(), msgs).await.unwrap());
+
+        let cid = content_cid(b"new");
+        let msgs = vec![
+            ChainMessage::ForResolution(cid),
+            ChainMessage::ForResolution(cid),
+        ];
+        assert!(!interpreter.process(chain_id(), msgs).await.unwrap());
+
+        let msgs = vec![signed(100, 0), signed(100, 0)];
+        assert!(!interpreter.process(chain_id(), msgs).await.unwrap());
+
+        // The relayed message is also sent as a signed message.
+        let msgs = vec![
+            for_execution(&interpreter, relayed),
+            ChainMessage::Signed(Box::new(relayable())),
+        ];
+        assert!(!interpreter.process(chain_id(), msgs).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn process_rejects_undecodable_bytes() {
+        let (interpreter, [relayed, _, _, _]) = interpreter().await;
+        let valid = fvm_ipld_encoding::to_vec(&for_execution(&interpreter, relayed)).unwrap();
+        let interpreter = BytesMessageInterpreter::new(interpreter);
+
+        assert!(interpreter
+            .process(chain_id(), vec![valid.clone()])
+            .await
+            .unwrap());
+
+        let msgs = vec![valid, b"rubbish".to_vec()];
+        assert!(!interpreter.process(chain_id(), msgs).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn prepare_skips_unexecutable_content() {
+        let (interpreter, [relayed, _, _, candidate]) = interpreter().await;
+        let msgs = interpreter.prepare(chain_id(), Vec::new()).await.unwrap();
+        assert_eq!(
+            msgs,
+            vec![
+                ChainMessage::ForResolution(candidate),
+                for_execution(&interpreter, relayed)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn prepare_skips_relayed_mempool_messages() {
+        let (interpreter, [relayed, _, _, candidate]) = interpreter().await;
+        interpreter.pool.set_proposed(candidate);
+
+        let msgs = vec![ChainMessage::Signed(Box::new(relayable())), signed(100, 0)];
+        let msgs = interpreter.prepare(chain_id(), msgs).await.unwrap();
+        assert_eq!(
+            msgs,
+            vec![signed(100, 0), for_execution(&interpreter, relayed)]
+        );
+    }
+}
@@ -4,6 +4,14 @@
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
+use fendermint_vm_genesis::Genesis;
+use fvm_ipld_blockstore::Blockstore;
+
+use crate::GenesisInterpreter;
+
+use super::state::{FvmGenesisState, FvmStateParams};
+use super::FvmMessageInterpreter;
+
 fn workspace_dir() -> PathBuf {
     let output = std::process::Command::new(env!("CARGO"))
         .arg("locate-project")
@@ -27,3 +35,31 @@ pub fn bundle_path() -> PathBuf {
 
     PathBuf::from_str(&bundle_path).expect("malformed bundle path")
 }
+
+/// Create the genesis state in the store from the builtin-actor bundle, intended to be used in tests.
+pub async fn genesis_state<DB>(store: DB, genesis: Genesis) -> FvmStateParams
+where
+    DB: Blockstore + Clone + Send + Sync + 'static,
+{
+    let bundle = std::fs::read(bundle_path()).expect("failed to read bundle");
+
+    let state = FvmGenesisState::new(store, &bundle)
+        .await
+        .expect("failed to create state");
+
+    let (state, out) = FvmMessageInterpreter::<DB>::new()
+        .init(state, genesis)
+        .await
+        .expect("failed to create actors");
+
+    FvmStateParams {
+        state_root: state.commit().expect("failed to commit"),
+        timestamp: out.timestamp,
+        network_version: out.network_version,
+        base_fee: out.base_fee,
+        base_fee_params: out.base_fee_params,
+        block_reward: out.block_reward,
+        circ_supply: out.circ_supply,
+        chain_id: out.chain_id.into(),
+    }
+}
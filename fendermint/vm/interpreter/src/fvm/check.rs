@@ -4,11 +4,15 @@
 use async_trait::async_trait;
 
 use fvm_ipld_blockstore::Blockstore;
-use fvm_shared::{address::Address, error::ExitCode};
+use fvm_shared::{address::Address, econ::TokenAmount, error::ExitCode, BLOCK_GAS_LIMIT};
+use num_traits::ToPrimitive;
 
 use crate::CheckInterpreter;
 
-use super::{state::FvmCheckState, FvmMessage, FvmMessageInterpreter};
+use super::{
+    state::{Admitted, FvmCheckState},
+    FvmMessage, FvmMessageInterpreter,
+};
 
 /// Transaction check results are expressed by the exit code, so that hopefully
 /// they would result in the same error code if they were applied.
@@ -16,6 +20,10 @@ pub struct FvmCheckRet {
     pub sender: Address,
     pub gas_limit: u64,
     pub exit_code: ExitCode,
+    /// Explanation of why the transaction was rejected, if the exit code isn't descriptive enough.
+    pub info: Option<String>,
+    /// Priority of the transaction in the mempool, based on the gas premium the block producer would get.
+    pub priority: i64,
 }
 
 #[async_trait]
@@ -28,39 +36,441 @@ where
     type Output = FvmCheckRet;
 
     /// Check that:
+    /// * the gas limit fits into a block
+    /// * the gas fee cap covers the base fee and the node's minimum
     /// * sender exists
-    /// * sender nonce matches the message sequence
+    /// * sender doesn't have too many transactions pending already
+    /// * the message sequence is the next nonce of the sender, or within the allowed gap after it
     /// * sender has enough funds to cover the gas cost
+    ///
+    /// Messages with a future nonce are queued in the check state, and the expected
+    /// nonce moves past them once the transactions filling the gap are admitted.
+    ///
+    /// A pending transaction can be replaced by one with the same nonce and a higher
+    /// gas fee cap and premium.
+    ///
+    /// During a recheck, transactions which are still admitted in the check state
+    /// after it has been rebased on the committed state are accepted as they are,
+    /// unless they no longer cover the fees, in which case they are forgotten.
+    /// Transactions which have been replaced are rejected.
     async fn check(
         &self,
         mut state: Self::State,
         msg: Self::Message,
-        _is_recheck: bool,
+        is_recheck: bool,
     ) -> anyhow::Result<(Self::State, Self::Output)> {
-        let checked = |state, exit_code| {
+        let checked = |state, exit_code, info: Option<String>, priority| {
             let ret = FvmCheckRet {
                 sender: msg.from,
                 gas_limit: msg.gas_limit,
                 exit_code,
+                info,
+                priority,
             };
             Ok((state, ret))
         };
 
         // NOTE: This would be a great place for let-else, but clippy runs into a compilation bug.
-        let state_tree = state.state_tree_mut();
-        if let Some(id) = state_tree.lookup_id(&msg.from)? {
-            if let Some(mut actor) = state_tree.get_actor(id)? {
-                let balance_needed = msg.gas_fee_cap * msg.gas_limit;
-                if actor.balance < balance_needed || actor.sequence != msg.sequence {
-                    return checked(state, ExitCode::SYS_SENDER_STATE_INVALID);
-                } else {
-                    actor.sequence += 1;
-                    actor.balance -= balance_needed;
-                    state_tree.set_actor(id, actor);
-                    return checked(state, ExitCode::OK);
-                }
+        let id = match state.state_tree_mut().lookup_id(&msg.from)? {
+            Some(id) => id,
+            None => return checked(state, ExitCode::SYS_SENDER_INVALID, None, 0),
+        };
+
+        // A rechecked transaction which doesn't pass any more releases its nonce and balance.
+        let is_admitted = state
+            .admitted(id, msg.sequence)
+            .map_or(false, |tx| is_same_fee(tx, &msg));
+
+        if is_recheck && is_admitted {
+            if let Some(info) = check_fees(&state, &msg) {
+                state.remove_admitted(id, msg.sequence)?;
+                return checked(state, ExitCode::USR_ILLEGAL_ARGUMENT, Some(info), 0);
             }
+            let priority = priority(&msg, state.base_fee());
+            return checked(state, ExitCode::OK, None, priority);
         }
-        return checked(state, ExitCode::SYS_SENDER_INVALID);
+
+        if let Some(info) = check_fees(&state, &msg) {
+            return checked(state, ExitCode::USR_ILLEGAL_ARGUMENT, Some(info), 0);
+        }
+
+        let priority = priority(&msg, state.base_fee());
+        let max_pending = state.policy().max_pending_per_sender;
+        let max_nonce_gap = state.policy().max_nonce_gap;
+        let balance_needed = msg.gas_fee_cap.clone() * msg.gas_limit;
+
+        let mut actor = match state.state_tree_mut().get_actor(id)? {
+            Some(actor) => actor,
+            None => return checked(state, ExitCode::SYS_SENDER_INVALID, None, 0),
+        };
+
+        if let Some(tx) = state.admitted(id, msg.sequence) {
+            if is_recheck {
+                let info = format!("nonce {} has been replaced", msg.sequence);
+                return checked(state, ExitCode::SYS_SENDER_STATE_INVALID, Some(info), 0);
+            }
+            if msg.gas_fee_cap <= tx.gas_fee_cap || msg.gas_premium <= tx.gas_premium {
+                let info = format!(
+                    "nonce {} has already been used; a replacement needs a higher gas fee cap and premium",
+                    msg.sequence
+                );
+                return checked(state, ExitCode::SYS_SENDER_STATE_INVALID, Some(info), 0);
+            }
+            if actor.balance.clone() + &tx.reserved < balance_needed {
+                return checked(state, ExitCode::SYS_SENDER_STATE_INVALID, None, 0);
+            }
+            state.remove_admitted(id, msg.sequence)?;
+            actor = match state.state_tree_mut().get_actor(id)? {
+                Some(actor) => actor,
+                None => return checked(state, ExitCode::SYS_SENDER_INVALID, None, 0),
+            };
+        }
+
+        if max_pending > 0 && state.pending(id) >= max_pending {
+            let info = format!("sender already has {max_pending} pending transactions");
+            return checked(state, ExitCode::SYS_SENDER_STATE_INVALID, Some(info), 0);
+        }
+
+        if msg.sequence < actor.sequence {
+            let info = format!("nonce {} has already been used", msg.sequence);
+            return checked(state, ExitCode::SYS_SENDER_STATE_INVALID, Some(info), 0);
+        }
+
+        if msg.sequence - actor.sequence > max_nonce_gap {
+            let info = format!(
+                "nonce {} is too far ahead of the expected nonce {}",
+                msg.sequence, actor.sequence
+            );
+            return checked(state, ExitCode::SYS_SENDER_STATE_INVALID, Some(info), 0);
+        }
+
+        if actor.balance < balance_needed {
+            return checked(state, ExitCode::SYS_SENDER_STATE_INVALID, None, 0);
+        }
+
+        actor.balance -= &balance_needed;
+
+        let admitted = Admitted {
+            reserved: balance_needed,
+            gas_fee_cap: msg.gas_fee_cap.clone(),
+            gas_premium: msg.gas_premium.clone(),
+            age: 0,
+        };
+        state.add_admitted(id, msg.sequence, admitted);
+
+        // Promote the queued transactions if this one closed the gap.
+        while state.admitted(id, actor.sequence).is_some() {
+            actor.sequence += 1;
+        }
+
+        state.state_tree_mut().set_actor(id, actor);
+        checked(state, ExitCode::OK, None, priority)
+    }
+}
+
+/// Check the gas limit and the fee cap, returning the reason if the message is rejected.
+fn check_fees<DB>(state: &FvmCheckState<DB>, msg: &FvmMessage) -> Option<String>
+where
+    DB: Blockstore + 'static,
+{
+    if msg.gas_limit > BLOCK_GAS_LIMIT {
+        return Some(format!(
+            "gas limit {} is above the block gas limit {}",
+            msg.gas_limit, BLOCK_GAS_LIMIT
+        ));
+    }
+
+    let min_gas_fee_cap = std::cmp::max(state.base_fee(), &state.policy().min_gas_fee_cap);
+    if msg.gas_fee_cap < *min_gas_fee_cap {
+        return Some(format!(
+            "gas fee cap {} is below the minimum {}",
+            msg.gas_fee_cap.atto(),
+            min_gas_fee_cap.atto()
+        ));
+    }
+
+    None
+}
+
+/// Tell whether an admitted transaction has the same fees as a message with the same nonce,
+/// which means it hasn't been replaced.
+fn is_same_fee(tx: &Admitted, msg: &FvmMessage) -> bool {
+    tx.gas_fee_cap == msg.gas_fee_cap && tx.gas_premium == msg.gas_premium
+}
+
+/// The gas premium the block producer would get per unit of gas, which is the
+/// premium, unless the fee cap leaves less room above the base fee.
+fn priority(msg: &FvmMessage, base_fee: &TokenAmount) -> i64 {
+    let room = msg.gas_fee_cap.clone() - base_fee;
+    std::cmp::min(&msg.gas_premium, &room)
+        .atto()
+        .to_i64()
+        .unwrap_or(i64::MAX)
+        .max(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use cid::Cid;
+    use fvm::state_tree::ActorState;
+    use fvm::EMPTY_ARR_CID;
+    use fvm_shared::{
+        address::Address, chainid::ChainID, econ::TokenAmount, error::ExitCode, message::Message,
+        BLOCK_GAS_LIMIT,
+    };
+
+    use crate::fvm::state::{empty_state_tree, CheckPolicy, FvmCheckState};
+    use crate::fvm::store::memory::MemoryBlockstore;
+    use crate::fvm::FvmMessageInterpreter;
+    use crate::CheckInterpreter;
+
+    const SENDER: u64 = 100;
+
+    fn check_state(base_fee: u64, policy: CheckPolicy) -> FvmCheckState<MemoryBlockstore> {
+        let store = MemoryBlockstore::new();
+        let state_root = genesis_state_root(store.clone());
+
+        FvmCheckState::new(
+            store,
+            state_root,
+            ChainID::from(1),
+            TokenAmount::from_atto(base_fee),
+            policy,
+        )
+        .unwrap()
+    }
+
+    /// Create a state tree with a sender that has some funds.
+    fn genesis_state_root(store: MemoryBlockstore) -> Cid {
+        let mut state_tree = empty_state_tree(store).unwrap();
+        let actor = ActorState::new(
+            *EMPTY_ARR_CID,
+            *EMPTY_ARR_CID,
+            TokenAmount::from_whole(1000),
+            0,
+            None,
+        );
+        state_tree.set_actor(SENDER, actor);
+        state_tree.flush().unwrap()
+    }
+
+    fn message(sequence: u64, gas_limit: u64, gas_fee_cap: u64, gas_premium: u64) -> Message {
+        Message {
+            version: 0,
+            from: Address::new_id(SENDER),
+            to: Address::new_id(SENDER + 1),
+            sequence,
+            value: TokenAmount::from_atto(0),
+            method_num: 0,
+            params: Default::default(),
+            gas_limit,
+            gas_fee_cap: TokenAmount::from_atto(gas_fee_cap),
+            gas_premium: TokenAmount::from_atto(gas_premium),
+        }
+    }
+
+    #[tokio::test]
+    async fn check_policy() {
+        let interpreter = FvmMessageInterpreter::new();
+        let policy = CheckPolicy {
+            min_gas_fee_cap: TokenAmount::from_atto(200),
+            max_pending_per_sender: 2,
+            max_nonce_gap: 0,
+            max_pending_blocks: 0,
+        };
+        let state = check_state(100, policy);
+
+        // Gas limit above the block gas limit.
+        let msg = message(0, BLOCK_GAS_LIMIT + 1, 1000, 10);
+        let (state, ret) = interpreter.check(state, msg, false).await.unwrap();
+        assert_eq!(ret.exit_code, ExitCode::USR_ILLEGAL_ARGUMENT);
+
+        // Fee cap covers the base fee but not the local minimum.
+        let msg = message(0, 1000, 150, 10);
+        let (state, ret) = interpreter.check(state, msg, false).await.unwrap();
+        assert_eq!(ret.exit_code, ExitCode::USR_ILLEGAL_ARGUMENT);
+
+        // The priority is the premium, capped by what the fee cap leaves above the base fee.
+        let msg = message(0, 1000, 1000, 10);
+        let (state, ret) = interpreter.check(state, msg, false).await.unwrap();
+        assert_eq!(ret.exit_code, ExitCode::OK);
+        assert_eq!(ret.priority, 10);
+
+        let msg = message(1, 1000, 250, 500);
+        let (state, ret) = interpreter.check(state, msg, false).await.unwrap();
+        assert_eq!(ret.exit_code, ExitCode::OK);
+        assert_eq!(ret.priority, 150);
+
+        // Too many pending transactions from the same sender.
+        let msg = message(2, 1000, 1000, 10);
+        let (_, ret) = interpreter.check(state, msg, false).await.unwrap();
+        assert_eq!(ret.exit_code, ExitCode::SYS_SENDER_STATE_INVALID);
+        assert!(ret.info.is_some());
+    }
+
+    #[tokio::test]
+    async fn check_nonce_gap() {
+        let interpreter = FvmMessageInterpreter::new();
+        let policy = CheckPolicy {
+            max_nonce_gap: 2,
+            ..Default::default()
+        };
+        let state = check_state(100, policy);
+
+        // Too far ahead of the expected nonce.
+        let msg = message(3, 1000, 1000, 10);
+        let (state, ret) = interpreter.check(state, msg, false).await.unwrap();
+        assert_eq!(ret.exit_code, ExitCode::SYS_SENDER_STATE_INVALID);
+
+        // Queued until the gap closes.
+        let msg = message(2, 1000, 1000, 10);
+        let (state, ret) = interpreter.check(state, msg, false).await.unwrap();
+        assert_eq!(ret.exit_code, ExitCode::OK);
+
+        let msg = message(0, 1000, 1000, 10);
+        let (state, ret) = interpreter.check(state, msg, false).await.unwrap();
+        assert_eq!(ret.exit_code, ExitCode::OK);
+
+        // The same nonce can't be used twice.
+        let msg = message(2, 1000, 1000, 10);
+        let (state, ret) = interpreter.check(state, msg, false).await.unwrap();
+        assert_eq!(ret.exit_code, ExitCode::SYS_SENDER_STATE_INVALID);
+
+        // Filling the gap promotes the queued transaction.
+        let msg = message(1, 1000, 1000, 10);
+        let (mut state, ret) = interpreter.check(state, msg, false).await.unwrap();
+        assert_eq!(ret.exit_code, ExitCode::OK);
+
+        let actor = state.state_tree_mut().get_actor(SENDER).unwrap().unwrap();
+        assert_eq!(actor.sequence, 3);
+        assert_eq!(state.pending(SENDER), 3);
+
+        // Rebasing on the same committed state keeps the admitted transactions,
+        // which pass a recheck, while new ones continue after them.
+        let state_root = genesis_state_root(MemoryBlockstore::new());
+        state
+            .rebase(state_root, TokenAmount::from_atto(100))
+            .unwrap();
+        assert_eq!(state.pending(SENDER), 3);
+
+        let msg = message(1, 1000, 1000, 10);
+        let (state, ret) = interpreter.check(state, msg, true).await.unwrap();
+        assert_eq!(ret.exit_code, ExitCode::OK);
+
+        let msg = message(3, 1000, 1000, 10);
+        let (_, ret) = interpreter.check(state, msg, false).await.unwrap();
+        assert_eq!(ret.exit_code, ExitCode::OK);
+    }
+
+    #[tokio::test]
+    async fn check_replacement() {
+        let interpreter = FvmMessageInterpreter::new();
+        let state = check_state(100, CheckPolicy::default());
+
+        let msg = message(0, 1000, 1000, 10);
+        let (state, ret) = interpreter.check(state, msg, false).await.unwrap();
+        assert_eq!(ret.exit_code, ExitCode::OK);
+
+        // The same fees don't replace the pending transaction.
+        let msg = message(0, 1000, 1000, 10);
+        let (state, ret) = interpreter.check(state, msg, false).await.unwrap();
+        assert_eq!(ret.exit_code, ExitCode::SYS_SENDER_STATE_INVALID);
+
+        // Higher fees do, without using up the next nonce.
+        let msg = message(0, 1000, 2000, 20);
+        let (mut state, ret) = interpreter.check(state, msg, false).await.unwrap();
+        assert_eq!(ret.exit_code, ExitCode::OK);
+        assert_eq!(state.pending(SENDER), 1);
+
+        let actor = state.state_tree_mut().get_actor(SENDER).unwrap().unwrap();
+        assert_eq!(actor.sequence, 1);
+        assert_eq!(
+            actor.balance,
+            TokenAmount::from_whole(1000) - TokenAmount::from_atto(2000 * 1000)
+        );
+
+        // The replaced transaction fails its recheck, while the replacement passes.
+        let msg = message(0, 1000, 1000, 10);
+        let (state, ret) = interpreter.check(state, msg, true).await.unwrap();
+        assert_eq!(ret.exit_code, ExitCode::SYS_SENDER_STATE_INVALID);
+        assert_eq!(state.pending(SENDER), 1);
+
+        let msg = message(0, 1000, 2000, 20);
+        let (_, ret) = interpreter.check(state, msg, true).await.unwrap();
+        assert_eq!(ret.exit_code, ExitCode::OK);
+    }
+
+    #[tokio::test]
+    async fn check_recheck_failure() {
+        let interpreter = FvmMessageInterpreter::new();
+        let policy = CheckPolicy {
+            max_nonce_gap: 2,
+            ..Default::default()
+        };
+        let mut state = check_state(100, policy);
+
+        for (sequence, gas_fee_cap) in [(0, 1000), (1, 200), (2, 1000)] {
+            let msg = message(sequence, 1000, gas_fee_cap, 10);
+            let (s, ret) = interpreter.check(state, msg, false).await.unwrap();
+            assert_eq!(ret.exit_code, ExitCode::OK);
+            state = s;
+        }
+
+        // The base fee goes up, so the transaction in the middle doesn't cover it any more.
+        let state_root = genesis_state_root(MemoryBlockstore::new());
+        state
+            .rebase(state_root, TokenAmount::from_atto(500))
+            .unwrap();
+
+        let msg = message(1, 1000, 200, 10);
+        let (mut state, ret) = interpreter.check(state, msg, true).await.unwrap();
+        assert_eq!(ret.exit_code, ExitCode::USR_ILLEGAL_ARGUMENT);
+
+        // Its nonce is released, while the one after it stays queued.
+        assert_eq!(state.pending(SENDER), 2);
+        let actor = state.state_tree_mut().get_actor(SENDER).unwrap().unwrap();
+        assert_eq!(actor.sequence, 1);
+
+        let msg = message(1, 1000, 1000, 10);
+        let (mut state, ret) = interpreter.check(state, msg, false).await.unwrap();
+        assert_eq!(ret.exit_code, ExitCode::OK);
+        let actor = state.state_tree_mut().get_actor(SENDER).unwrap().unwrap();
+        assert_eq!(actor.sequence, 3);
+    }
+
+    #[tokio::test]
+    async fn check_expiry() {
+        let interpreter = FvmMessageInterpreter::new();
+        let policy = CheckPolicy {
+            max_pending_per_sender: 1,
+            max_pending_blocks: 2,
+            ..Default::default()
+        };
+        let state = check_state(100, policy);
+
+        let msg = message(0, 1000, 1000, 10);
+        let (mut state, ret) = interpreter.check(state, msg, false).await.unwrap();
+        assert_eq!(ret.exit_code, ExitCode::OK);
+
+        // The transaction never gets committed, nor rechecked.
+        let state_root = genesis_state_root(MemoryBlockstore::new());
+        state
+            .rebase(state_root, TokenAmount::from_atto(100))
+            .unwrap();
+        assert_eq!(state.pending(SENDER), 1);
+
+        let msg = message(1, 1000, 1000, 10);
+        let (mut state, ret) = interpreter.check(state, msg, false).await.unwrap();
+        assert_eq!(ret.exit_code, ExitCode::SYS_SENDER_STATE_INVALID);
+
+        state
+            .rebase(state_root, TokenAmount::from_atto(100))
+            .unwrap();
+        assert_eq!(state.pending(SENDER), 0);
+
+        // The nonce can be used again.
+        let msg = message(0, 1000, 1000, 10);
+        let (_, ret) = interpreter.check(state, msg, false).await.unwrap();
+        assert_eq!(ret.exit_code, ExitCode::OK);
     }
 }
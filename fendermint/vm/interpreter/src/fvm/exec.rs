@@ -3,10 +3,11 @@
 
 use async_trait::async_trait;
 
-use fendermint_vm_actor_interface::{cron, system};
+use fendermint_vm_actor_interface::{cron, reward, system};
+use fendermint_vm_genesis::Validator;
 use fvm::executor::ApplyRet;
 use fvm_ipld_blockstore::Blockstore;
-use fvm_shared::{address::Address, MethodNum, BLOCK_GAS_LIMIT};
+use fvm_shared::{address::Address, MethodNum, BLOCK_GAS_LIMIT, METHOD_SEND};
 
 use crate::ExecInterpreter;
 
@@ -39,12 +40,16 @@ where
     type Message = FvmMessage;
     type BeginOutput = FvmApplyRet;
     type DeliverOutput = FvmApplyRet;
-    type EndOutput = ();
+    /// Changes in the validator set.
+    type EndOutput = Vec<Validator>;
 
     async fn begin(
         &self,
         mut state: Self::State,
     ) -> anyhow::Result<(Self::State, Self::BeginOutput)> {
+        // Make the hash of this block available to the ones after it.
+        state.record_block_hash()?;
+
         // Block height (FVM epoch) as sequence is intentional
         let height = state.block_height();
         // Arbitrarily large gas limit for cron (matching how Forest does it, which matches Lotus).
@@ -108,8 +113,210 @@ where
         Ok((state, ret))
     }
 
-    async fn end(&self, state: Self::State) -> anyhow::Result<(Self::State, Self::EndOutput)> {
+    async fn end(&self, mut state: Self::State) -> anyhow::Result<(Self::State, Self::EndOutput)> {
         // TODO: Epoch transitions for checkpointing.
-        Ok((state, ()))
+
+        // The validator who proposed the block gets the gas premiums and the block reward.
+        // If we don't know who they are, the premiums stay in the reward actor until the next block.
+        if let Some(addr) = state.block_producer_addr() {
+            state.mint_block_reward()?;
+
+            let value = state.reward_balance()?;
+
+            if !value.is_zero() {
+                let msg = FvmMessage {
+                    from: reward::REWARD_ACTOR_ADDR,
+                    to: addr,
+                    sequence: state.block_height() as u64,
+                    gas_limit: BLOCK_GAS_LIMIT,
+                    method_num: METHOD_SEND,
+                    params: Default::default(),
+                    value,
+                    version: Default::default(),
+                    gas_fee_cap: Default::default(),
+                    gas_premium: Default::default(),
+                };
+
+                let apply_ret = state.execute_implicit(msg)?;
+
+                // The funds stay in the reward actor, to be paid out with the next block.
+                if let Some(err) = apply_ret.failure_info {
+                    tracing::error!(
+                        addr = addr.to_string(),
+                        error = err.to_string(),
+                        "failed to pay the block producer"
+                    );
+                }
+            }
+        }
+
+        let updates = state.take_validator_updates();
+        Ok((state, updates))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fendermint_vm_actor_interface::validators::{
+        self, SetPowerParams, MAX_TOTAL_POWER, VALIDATORS_ACTOR_ADDR,
+    };
+    use fendermint_vm_genesis::{Account, Actor, ActorMeta, Genesis, Power, SignerAddr, Validator};
+    use fvm::engine::MultiEngine;
+    use fvm_ipld_encoding::RawBytes;
+    use fvm_shared::{address::Address, econ::TokenAmount, error::ExitCode};
+    use quickcheck::Arbitrary;
+
+    use crate::{
+        fvm::{
+            bundle::genesis_state, state::FvmExecState, store::memory::MemoryBlockstore,
+            FvmMessage, FvmMessageInterpreter,
+        },
+        ExecInterpreter,
+    };
+
+    /// The account of a validator, which is derived from their public key.
+    fn validator_addr(v: &Validator) -> Address {
+        Address::new_secp256k1(&v.public_key.0.serialize()).unwrap()
+    }
+
+    fn account(owner: Address) -> Actor {
+        Actor {
+            meta: ActorMeta::Account(Account {
+                owner: SignerAddr(owner),
+            }),
+            balance: TokenAmount::from_whole(1000),
+        }
+    }
+
+    fn set_power(from: Address, sequence: u64, validator: &Validator) -> FvmMessage {
+        let params = SetPowerParams {
+            public_key: validator.public_key.clone(),
+            power: validator.power.clone(),
+        };
+        FvmMessage {
+            version: Default::default(),
+            from,
+            to: VALIDATORS_ACTOR_ADDR,
+            sequence,
+            value: TokenAmount::from_atto(0),
+            method_num: validators::Method::SetPower as u64,
+            params: RawBytes::serialize(params).unwrap(),
+            gas_limit: 10_000_000,
+            gas_fee_cap: TokenAmount::from_atto(200),
+            gas_premium: TokenAmount::from_atto(0),
+        }
+    }
+
+    #[tokio::test]
+    async fn validator_power_updates_at_end_block() {
+        let mut g = quickcheck::Gen::new(5);
+
+        let admin = Validator::arbitrary(&mut g);
+        let other = loop {
+            let v = Validator::arbitrary(&mut g);
+            if v.public_key != admin.public_key {
+                break v;
+            }
+        };
+        let admin_addr = validator_addr(&admin);
+        let other_addr = validator_addr(&other);
+
+        let mut genesis = Genesis::arbitrary(&mut g);
+        genesis.base_fee = TokenAmount::from_atto(100);
+        genesis.base_fee_params = None;
+        genesis.validators = vec![admin.clone()];
+        genesis.accounts = vec![account(admin_addr), account(other_addr)];
+        genesis.validator_admin = Some(SignerAddr(admin_addr));
+
+        let store = MemoryBlockstore::new();
+        let state_params = genesis_state(store.clone(), genesis).await;
+
+        let multi_engine = MultiEngine::new(1);
+        let state = FvmExecState::new(store, &multi_engine, 1, state_params)
+            .expect("failed to create state");
+
+        let interpreter = FvmMessageInterpreter::<MemoryBlockstore>::new();
+        let (state, _) = interpreter.begin(state).await.expect("failed to begin");
+
+        let added = Validator {
+            public_key: other.public_key.clone(),
+            power: Power(7),
+        };
+        let removed = Validator {
+            public_key: admin.public_key.clone(),
+            power: Power(0),
+        };
+
+        // Only the admin can change the validator set.
+        let msg = set_power(other_addr, 0, &added);
+        let (state, ret) = interpreter.deliver(state, msg).await.unwrap();
+        assert_eq!(ret.apply_ret.msg_receipt.exit_code, ExitCode::USR_FORBIDDEN);
+
+        // The validator set is not an account which could hold funds.
+        let mut msg = set_power(admin_addr, 0, &added);
+        msg.value = TokenAmount::from_atto(1);
+        let (state, ret) = interpreter.deliver(state, msg).await.unwrap();
+        assert_eq!(
+            ret.apply_ret.msg_receipt.exit_code,
+            ExitCode::USR_ILLEGAL_ARGUMENT
+        );
+
+        let msg = set_power(admin_addr, 0, &added);
+        let (state, ret) = interpreter.deliver(state, msg).await.unwrap();
+        assert_eq!(ret.apply_ret.msg_receipt.exit_code, ExitCode::OK);
+        assert!(ret.apply_ret.msg_receipt.gas_used > 0);
+
+        // The nonce has been used up, so the same message can't be replayed.
+        let msg = set_power(admin_addr, 0, &removed);
+        let (state, ret) = interpreter.deliver(state, msg).await.unwrap();
+        assert_eq!(
+            ret.apply_ret.msg_receipt.exit_code,
+            ExitCode::SYS_SENDER_STATE_INVALID
+        );
+
+        let msg = set_power(admin_addr, 1, &removed);
+        let (mut state, ret) = interpreter.deliver(state, msg).await.unwrap();
+        assert_eq!(ret.apply_ret.msg_receipt.exit_code, ExitCode::OK);
+
+        // Changes CometBFT would not accept are rejected.
+        let last = Validator {
+            public_key: other.public_key.clone(),
+            power: Power(0),
+        };
+        let too_much = Validator {
+            public_key: other.public_key.clone(),
+            power: Power(MAX_TOTAL_POWER + 1),
+        };
+        for (sequence, v) in [(2, &removed), (3, &last), (4, &too_much)] {
+            let msg = set_power(admin_addr, sequence, v);
+            let (s, ret) = interpreter.deliver(state, msg).await.unwrap();
+            assert_eq!(
+                ret.apply_ret.msg_receipt.exit_code,
+                ExitCode::USR_ILLEGAL_ARGUMENT
+            );
+            state = s;
+        }
+
+        // Setting the same power again is not an update.
+        let msg = set_power(admin_addr, 5, &added);
+        let (mut state, ret) = interpreter.deliver(state, msg).await.unwrap();
+        assert_eq!(ret.apply_ret.msg_receipt.exit_code, ExitCode::OK);
+
+        // Nothing has been transferred to the validator set.
+        assert!(state
+            .state_tree_mut()
+            .get_actor(validators::VALIDATORS_ACTOR_ID)
+            .unwrap()
+            .expect("validator set exists")
+            .balance
+            .is_zero());
+
+        let (mut state, updates) = interpreter.end(state).await.expect("failed to end");
+
+        assert_eq!(updates, vec![added.clone(), removed]);
+        assert_eq!(state.validators().unwrap(), vec![added]);
+
+        // Reported only once.
+        assert!(state.take_validator_updates().is_empty());
     }
 }
@@ -1,34 +1,126 @@
 // Copyright 2022-2023 Protocol Labs
 // SPDX-License-Identifier: Apache-2.0, MIT
-use cid::Cid;
+use anyhow::anyhow;
+use cid::{multihash::Multihash, Cid};
+use fendermint_vm_actor_interface::blockhashes::{self, BLOCK_HASHES_ACTOR_ID};
 use fvm::externs::{Chain, Consensus, Externs, Rand};
+use fvm::state_tree::StateTree;
+use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_encoding::IPLD_RAW;
 use fvm_shared::clock::ChainEpoch;
 
-/// Dummy externs - these are related to Expected Consensus,
+/// How far back the block hashes can be looked up, same as the finality in Filecoin,
+/// which is the limit the FVM enforces on looking up tipset CIDs.
+pub const CHAIN_FINALITY: ChainEpoch = 900;
+
+/// Multihash code of SHA2-256, which CometBFT uses to hash blocks.
+const SHA2_256: u64 = 0x12;
+
+/// Domain separation tag mixed into the block hashes to draw beacon randomness,
+/// so that it's independent of the chain randomness drawn from the same hashes.
+const BEACON_DST: &[u8] = b"fendermint/beacon";
+
+/// Externs exposing the hashes of recent blocks to the FVM.
+///
+/// The rest are related to Expected Consensus,
 /// which I believe we have nothing to do with.
-pub struct FendermintExterns;
+pub struct FendermintExterns<DB> {
+    /// Height of the block being executed.
+    block_height: ChainEpoch,
+    store: DB,
+    /// Hashes of the blocks before the one being executed, as recorded in the ledger;
+    /// `None` if no block hash has been recorded yet.
+    block_hashes: Option<blockhashes::State>,
+}
+
+impl<DB> FendermintExterns<DB>
+where
+    DB: Blockstore,
+{
+    /// Create externs for executing a block on top of the given state root.
+    pub fn new(store: DB, state_root: Cid, block_height: ChainEpoch) -> anyhow::Result<Self> {
+        let state_tree = StateTree::new_from_root(&store, &state_root)?;
+
+        let block_hashes = match state_tree.get_actor(BLOCK_HASHES_ACTOR_ID)? {
+            Some(actor) => Some(blockhashes::State::load(&store, &actor.state)?),
+            None => None,
+        };
+
+        Ok(Self {
+            block_height,
+            store,
+            block_hashes,
+        })
+    }
+
+    /// Look up the hash of a past block in the ledger.
+    fn block_hash(&self, height: ChainEpoch) -> anyhow::Result<Option<Vec<u8>>> {
+        match self.block_hashes {
+            None => Ok(None),
+            Some(ref block_hashes) => block_hashes.get(&self.store, height),
+        }
+    }
+
+    /// Look up the hash of the block at a past round to draw randomness from.
+    ///
+    /// Only the last [`CHAIN_FINALITY`] block hashes are kept, so every node gives the same
+    /// answer, or the same error, regardless of its own history.
+    fn round_hash(&self, round: ChainEpoch) -> anyhow::Result<Vec<u8>> {
+        if round < 0 {
+            return Err(anyhow!(
+                "cannot draw randomness from negative round {round}"
+            ));
+        }
 
-impl Rand for FendermintExterns {
+        if round >= self.block_height {
+            return Err(anyhow!(
+                "cannot draw randomness from future round {round} at height {}",
+                self.block_height
+            ));
+        }
+
+        self.block_hash(round)?.ok_or_else(|| {
+            anyhow!("cannot draw randomness from round {round}: block hash not available")
+        })
+    }
+}
+
+impl<DB> Rand for FendermintExterns<DB>
+where
+    DB: Blockstore,
+{
+    /// Derive randomness from the hash of the block at the given round, as recorded in the ledger,
+    /// the same way Lotus draws randomness from a ticket.
     fn get_chain_randomness(
         &self,
-        _pers: i64,
-        _round: ChainEpoch,
-        _entropy: &[u8],
+        pers: i64,
+        round: ChainEpoch,
+        entropy: &[u8],
     ) -> anyhow::Result<[u8; 32]> {
-        todo!("might need randomness")
+        let block_hash = self.round_hash(round)?;
+
+        Ok(draw_randomness(pers, round, &block_hash, entropy))
     }
 
+    /// There is no drand beacon; derive the randomness from the same block hashes
+    /// as the chain randomness, separated by [`BEACON_DST`].
     fn get_beacon_randomness(
         &self,
-        _pers: i64,
-        _round: ChainEpoch,
-        _entropy: &[u8],
+        pers: i64,
+        round: ChainEpoch,
+        entropy: &[u8],
     ) -> anyhow::Result<[u8; 32]> {
-        unimplemented!("not expecting to use the beacon")
+        let block_hash = self.round_hash(round)?;
+        let beacon = [BEACON_DST, &block_hash].concat();
+
+        Ok(draw_randomness(pers, round, &beacon, entropy))
     }
 }
 
-impl Consensus for FendermintExterns {
+impl<DB> Consensus for FendermintExterns<DB>
+where
+    DB: Blockstore,
+{
     fn verify_consensus_fault(
         &self,
         _h1: &[u8],
@@ -39,10 +131,156 @@ impl Consensus for FendermintExterns {
     }
 }
 
-impl Chain for FendermintExterns {
-    fn get_tipset_cid(&self, _epoch: ChainEpoch) -> anyhow::Result<Cid> {
-        unimplemented!("not expecting to use tipsets")
+impl<DB> Chain for FendermintExterns<DB>
+where
+    DB: Blockstore,
+{
+    /// Every block is its own tipset; the CID wraps the hash of the block with the given height.
+    ///
+    /// Heights without a recorded block hash, e.g. the ones before the first block, get a CID
+    /// wrapping an all-zero hash, rather than an error, which the FVM would treat as fatal.
+    fn get_tipset_cid(&self, epoch: ChainEpoch) -> anyhow::Result<Cid> {
+        if epoch < 0 {
+            return Err(anyhow!(
+                "cannot look up the block hash of negative height {epoch}"
+            ));
+        }
+        if epoch >= self.block_height {
+            return Err(anyhow!(
+                "cannot look up the block hash of height {epoch} at height {}",
+                self.block_height
+            ));
+        }
+        if self.block_height - epoch >= CHAIN_FINALITY {
+            return Err(anyhow!(
+                "cannot look up the block hash of height {epoch}: too far in the past"
+            ));
+        }
+
+        let hash = self.block_hash(epoch)?.unwrap_or_else(|| vec![0u8; 32]);
+
+        let mh = Multihash::wrap(SHA2_256, &hash)?;
+
+        Ok(Cid::new_v1(IPLD_RAW, mh))
     }
 }
 
-impl Externs for FendermintExterns {}
+impl<DB> Externs for FendermintExterns<DB> where DB: Blockstore {}
+
+/// Mix the personalization, the round and the entropy into the hash of the round.
+///
+/// See [Lotus](https://github.com/filecoin-project/lotus/blob/v1.20.4/chain/rand/rand.go#L272-L294).
+fn draw_randomness(pers: i64, round: ChainEpoch, rbase: &[u8], entropy: &[u8]) -> [u8; 32] {
+    let mut state = blake2b_simd::Params::new().hash_length(32).to_state();
+    state.update(&pers.to_be_bytes());
+    state.update(
+        blake2b_simd::Params::new()
+            .hash_length(32)
+            .hash(rbase)
+            .as_bytes(),
+    );
+    state.update(&round.to_be_bytes());
+    state.update(entropy);
+
+    let mut ret = [0u8; 32];
+    ret.copy_from_slice(state.finalize().as_bytes());
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use cid::Cid;
+    use fendermint_vm_actor_interface::blockhashes::{self, BLOCK_HASHES_ACTOR_ID};
+    use fvm::externs::{Chain, Rand};
+    use fvm::state_tree::{ActorState, StateTree};
+    use fvm_ipld_blockstore::MemoryBlockstore;
+    use fvm_shared::{clock::ChainEpoch, state::StateTreeVersion};
+
+    use super::FendermintExterns;
+
+    /// Create a state tree which has the hashes of the given heights recorded.
+    fn state_root(store: &MemoryBlockstore, heights: impl Iterator<Item = ChainEpoch>) -> Cid {
+        let mut state_tree = StateTree::new(store, StateTreeVersion::V5).unwrap();
+        let mut block_hashes = blockhashes::State::new(store).unwrap();
+        for h in heights {
+            block_hashes
+                .set(store, h, [h.to_be_bytes(); 4].concat(), None)
+                .unwrap();
+        }
+        let state = block_hashes.save(store).unwrap();
+        let actor = ActorState::new_empty(Cid::default(), None);
+        state_tree.set_actor(BLOCK_HASHES_ACTOR_ID, ActorState { state, ..actor });
+        state_tree.flush().unwrap()
+    }
+
+    fn externs(block_height: ChainEpoch) -> FendermintExterns<MemoryBlockstore> {
+        // Only keeping the last 5 heights.
+        let store = MemoryBlockstore::new();
+        let state_root = state_root(&store, block_height - 5..block_height);
+
+        FendermintExterns::new(store, state_root, block_height).unwrap()
+    }
+
+    #[test]
+    fn randomness_is_reproducible() {
+        let r1 = externs(10).get_chain_randomness(1, 7, b"foo").unwrap();
+        let r2 = externs(11).get_chain_randomness(1, 7, b"foo").unwrap();
+        assert_eq!(r1, r2);
+
+        let r3 = externs(10).get_chain_randomness(1, 8, b"foo").unwrap();
+        let r4 = externs(10).get_chain_randomness(2, 7, b"foo").unwrap();
+        let r5 = externs(10).get_chain_randomness(1, 7, b"bar").unwrap();
+        assert_ne!(r1, r3);
+        assert_ne!(r1, r4);
+        assert_ne!(r1, r5);
+    }
+
+    #[test]
+    fn randomness_unavailable() {
+        let externs = externs(10);
+        assert!(externs.get_chain_randomness(1, 10, &[]).is_err());
+        assert!(externs.get_chain_randomness(1, 11, &[]).is_err());
+        assert!(externs.get_chain_randomness(1, 4, &[]).is_err());
+        assert!(externs.get_chain_randomness(1, -1, &[]).is_err());
+        assert!(externs.get_beacon_randomness(1, 10, &[]).is_err());
+        assert!(externs.get_beacon_randomness(1, 4, &[]).is_err());
+    }
+
+    #[test]
+    fn beacon_randomness_is_separate() {
+        let r1 = externs(10).get_beacon_randomness(1, 7, b"foo").unwrap();
+        let r2 = externs(11).get_beacon_randomness(1, 7, b"foo").unwrap();
+        assert_eq!(r1, r2);
+
+        let r3 = externs(10).get_chain_randomness(1, 7, b"foo").unwrap();
+        assert_ne!(r1, r3);
+    }
+
+    #[test]
+    fn tipset_cid_lookback() {
+        let externs = externs(10);
+        let c1 = externs.get_tipset_cid(9).unwrap();
+        let c2 = externs.get_tipset_cid(8).unwrap();
+        assert_ne!(c1, c2);
+        assert!(externs.get_tipset_cid(10).is_err());
+        assert!(externs.get_tipset_cid(-1).is_err());
+    }
+
+    #[test]
+    fn tipset_cid_missing_height() {
+        // Heights without a recorded hash get the same default CID on every node.
+        let c1 = externs(10).get_tipset_cid(4).unwrap();
+        let c2 = externs(10).get_tipset_cid(0).unwrap();
+        assert_eq!(c1, c2);
+        assert_ne!(c1, externs(10).get_tipset_cid(5).unwrap());
+
+        // Before the first block hash has been recorded.
+        let store = MemoryBlockstore::new();
+        let state_root = StateTree::new(&store, StateTreeVersion::V5)
+            .unwrap()
+            .flush()
+            .unwrap();
+        let externs = FendermintExterns::new(store, state_root, 1).unwrap();
+        assert_eq!(externs.get_tipset_cid(0).unwrap(), c1);
+    }
+}
@@ -2,9 +2,11 @@
 // SPDX-License-Identifier: Apache-2.0, MIT
 
 use async_trait::async_trait;
-use fendermint_vm_actor_interface::{cron, eam, init, system};
+use fendermint_vm_actor_interface::{
+    account, burntfunds, cron, eam, init, reward, system, validators,
+};
 use fendermint_vm_core::{chainid, Timestamp};
-use fendermint_vm_genesis::{ActorMeta, Genesis, Validator};
+use fendermint_vm_genesis::{ActorMeta, BaseFeeParams, Genesis, Validator};
 use fvm_ipld_blockstore::Blockstore;
 use fvm_shared::chainid::ChainID;
 use fvm_shared::econ::TokenAmount;
@@ -21,6 +23,8 @@ pub struct FvmGenesisOutput {
     pub timestamp: Timestamp,
     pub network_version: NetworkVersion,
     pub base_fee: TokenAmount,
+    pub base_fee_params: Option<BaseFeeParams>,
+    pub block_reward: TokenAmount,
     pub circ_supply: TokenAmount,
     pub validators: Vec<Validator>,
 }
@@ -44,9 +48,11 @@ where
     /// * init
     /// * cron
     /// * EAM
+    /// * validators
+    /// * reward
+    /// * burnt funds
     ///
     /// TODO:
-    /// * burnt funds?
     /// * faucet?
     /// * IPC
     ///
@@ -63,15 +69,28 @@ where
         //       presumably Tendermint checks that its peers have the same.
         let chain_id = chainid::from_str_hashed(&genesis.chain_name)?;
 
-        // Currently we just pass them back as they are, but later we should
-        // store them in the IPC actors; or in case of a snapshot restore them
-        // from the state.
+        if let Some(ref params) = genesis.base_fee_params {
+            if params.gas_target == 0 || params.max_change_denominator == 0 {
+                anyhow::bail!(
+                    "the base fee gas target and max change denominator must be positive"
+                );
+            }
+        }
+
+        // The validators are stored in the state as well, so they can be changed by transactions.
+        let validators_state = validators::State::new(
+            genesis.validators.clone(),
+            genesis.validator_admin.as_ref().map(|a| a.0),
+        );
+
         let output = FvmGenesisOutput {
             chain_id,
             timestamp: genesis.timestamp,
             network_version: genesis.network_version,
             circ_supply: circ_supply(&genesis),
             base_fee: genesis.base_fee,
+            base_fee_params: genesis.base_fee_params,
+            block_reward: genesis.block_reward,
             validators: genesis.validators,
         };
 
@@ -117,6 +136,33 @@ where
             TokenAmount::zero(),
         )?;
 
+        // Validator set, which isn't a real actor, just a place in the state tree.
+        state.create_actor(
+            validators::VALIDATORS_ACTOR_CODE_ID,
+            validators::VALIDATORS_ACTOR_ID,
+            &validators_state,
+            TokenAmount::zero(),
+        )?;
+
+        // Reward actor, where the FVM puts the gas premiums until they are paid to the block producer.
+        state.create_actor(
+            reward::REWARD_ACTOR_CODE_ID,
+            reward::REWARD_ACTOR_ID,
+            &reward::State::default(),
+            TokenAmount::zero(),
+        )?;
+
+        // Burnt funds actor, where the FVM sends the base fee.
+        let burnt_funds_state = account::State {
+            address: burntfunds::BURNT_FUNDS_ACTOR_ADDR,
+        };
+        state.create_actor(
+            burntfunds::BURNT_FUNDS_ACTOR_CODE_ID,
+            burntfunds::BURNT_FUNDS_ACTOR_ID,
+            &burnt_funds_state,
+            TokenAmount::zero(),
+        )?;
+
         // Create accounts
         let mut next_id = init::FIRST_NON_SINGLETON_ADDR + addr_to_id.len() as u64;
         for a in genesis.accounts {
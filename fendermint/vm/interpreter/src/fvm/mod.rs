@@ -9,15 +9,19 @@ mod genesis;
 mod query;
 pub mod state;
 mod store;
+mod trace;
+pub mod upgrades;
 
 #[cfg(any(test, feature = "bundle"))]
 pub mod bundle;
 
 pub use check::FvmCheckRet;
 pub use exec::FvmApplyRet;
+pub use externs::CHAIN_FINALITY;
 pub use fendermint_vm_message::query::FvmQuery;
 pub use genesis::FvmGenesisOutput;
 pub use query::FvmQueryRet;
+pub use trace::to_trace_result;
 
 pub type FvmMessage = fvm_shared::message::Message;
 
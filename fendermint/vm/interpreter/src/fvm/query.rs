@@ -1,13 +1,15 @@
 // Copyright 2022-2023 Protocol Labs
 // SPDX-License-Identifier: Apache-2.0, MIT
+use anyhow::anyhow;
 use async_trait::async_trait;
-use fendermint_vm_message::query::{ActorState, FvmQuery, GasEstimate, StateParams};
+use fendermint_vm_genesis::Validator;
+use fendermint_vm_message::query::{ActorState, FvmQuery, GasEstimate, StateParams, TraceResult};
 use fvm_ipld_blockstore::Blockstore;
 use fvm_shared::{ActorID, BLOCK_GAS_LIMIT};
 
 use crate::QueryInterpreter;
 
-use super::{state::FvmQueryState, FvmApplyRet, FvmMessageInterpreter};
+use super::{state::FvmQueryState, trace::to_trace_result, FvmApplyRet, FvmMessageInterpreter};
 
 /// Internal return type for queries. It will never be serialized
 /// and sent over the wire as it is, only its internal parts are
@@ -24,6 +26,10 @@ pub enum FvmQueryRet {
     EstimateGas(GasEstimate),
     /// Current state parameters.
     StateParams(StateParams),
+    /// Current validator set.
+    Validators(Vec<Validator>),
+    /// The results and the execution trace of the last traced message.
+    Trace(Box<TraceResult>),
 }
 
 #[async_trait]
@@ -89,7 +95,75 @@ where
                 };
                 FvmQueryRet::StateParams(state_params)
             }
+            FvmQuery::Validators => FvmQueryRet::Validators(state.validators()?),
+            FvmQuery::Trace(msgs) => {
+                let apply_ret = state.trace(msgs)?;
+                FvmQueryRet::Trace(Box::new(to_trace_result(apply_ret)))
+            }
+            FvmQuery::TraceTx(_) => {
+                // The query state doesn't know about blocks; the application has to replay them.
+                return Err(anyhow!(
+                    "transactions can only be traced by the application"
+                ));
+            }
         };
         Ok((state, res))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use fendermint_vm_actor_interface::{eam::EAM_ACTOR_ID, system::SYSTEM_ACTOR_ADDR};
+    use fendermint_vm_genesis::Genesis;
+    use fvm::engine::MultiEngine;
+    use fvm_shared::{address::Address, econ::TokenAmount, METHOD_SEND};
+    use quickcheck::Arbitrary;
+
+    use crate::fvm::{
+        bundle::genesis_state, state::FvmQueryState, store::memory::MemoryBlockstore, FvmMessage,
+    };
+
+    async fn query_state() -> FvmQueryState<MemoryBlockstore> {
+        let mut g = quickcheck::Gen::new(5);
+        let genesis = Genesis::arbitrary(&mut g);
+        let store = MemoryBlockstore::new();
+        let state_params = genesis_state(store.clone(), genesis).await;
+
+        FvmQueryState::new(store, Arc::new(MultiEngine::new(1)), 1, state_params)
+            .expect("failed to create query state")
+    }
+
+    fn call_message(from: Address) -> FvmMessage {
+        FvmMessage {
+            version: 0,
+            from,
+            to: SYSTEM_ACTOR_ADDR,
+            sequence: 0,
+            value: TokenAmount::default(),
+            method_num: METHOD_SEND,
+            params: Default::default(),
+            gas_limit: fvm_shared::BLOCK_GAS_LIMIT,
+            gas_fee_cap: TokenAmount::default(),
+            gas_premium: TokenAmount::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn call_without_sender() {
+        let state = query_state().await;
+
+        // What `eth_call` sends when the request has no `from`.
+        let ret = state.call(call_message(SYSTEM_ACTOR_ADDR)).unwrap();
+        assert!(ret.msg_receipt.exit_code.is_success(), "{ret:?}");
+
+        // An Ethereum address nobody has sent anything to yet.
+        let from = Address::new_delegated(EAM_ACTOR_ID, &[1u8; 20]).unwrap();
+        let ret = state.call(call_message(from)).unwrap();
+        assert!(ret.msg_receipt.exit_code.is_success(), "{ret:?}");
+
+        // An ID address can't be created out of thin air.
+        assert!(state.call(call_message(Address::new_id(u64::MAX))).is_err());
+    }
+}
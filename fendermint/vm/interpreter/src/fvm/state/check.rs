@@ -1,32 +1,95 @@
 // Copyright 2022-2023 Protocol Labs
 // SPDX-License-Identifier: Apache-2.0, MIT
 
+use std::collections::{BTreeMap, HashMap};
+
 use anyhow::{anyhow, Context};
 
 use cid::Cid;
 use fendermint_vm_core::chainid::HasChainID;
 use fvm::state_tree::StateTree;
 use fvm_ipld_blockstore::Blockstore;
-use fvm_shared::chainid::ChainID;
+use fvm_shared::{chainid::ChainID, econ::TokenAmount, ActorID};
 
 use crate::fvm::store::ReadOnlyBlockstore;
 
-/// A state we create for the execution of all the messages in a block.
+/// Node-local rules about which transactions are admitted into the mempool.
+#[derive(Debug, Clone, Default)]
+pub struct CheckPolicy {
+    /// Minimum gas fee cap of transactions, on top of it having to cover the current base fee.
+    pub min_gas_fee_cap: TokenAmount,
+    /// Maximum number of transactions a sender can have in the mempool; 0 means unlimited.
+    pub max_pending_per_sender: u64,
+    /// How far ahead of the next expected nonce a transaction can be and still be queued
+    /// until the transactions filling the gap arrive; 0 means nonces have to be consecutive.
+    pub max_nonce_gap: u64,
+    /// Number of blocks after which admitted transactions which haven't been committed
+    /// are forgotten, in case the mempool dropped them without a recheck; 0 means never.
+    pub max_pending_blocks: u64,
+}
+
+/// A transaction admitted into the mempool, which hasn't been committed yet.
+#[derive(Debug, Clone)]
+pub struct Admitted {
+    /// Balance set aside to cover the gas of the transaction.
+    pub reserved: TokenAmount,
+    /// Gas fee cap of the transaction, which a replacement has to exceed.
+    pub gas_fee_cap: TokenAmount,
+    /// Gas premium of the transaction, which a replacement has to exceed.
+    pub gas_premium: TokenAmount,
+    /// Number of blocks committed since the transaction was admitted.
+    pub age: u64,
+}
+
+/// A state we create for checking the transactions submitted to the mempool.
+///
+/// The actors in the state tree reflect the admitted transactions on top of the
+/// last committed state: their sequence is the next nonce expected after the
+/// consecutive transactions admitted so far, and their balance is reduced by
+/// the gas of every admitted transaction, including the queued ones.
 pub struct FvmCheckState<DB>
 where
     DB: Blockstore + 'static,
 {
     state_tree: StateTree<ReadOnlyBlockstore<DB>>,
     chain_id: ChainID,
+    /// Base fee of the next block, based on the last committed state.
+    base_fee: TokenAmount,
+    policy: CheckPolicy,
+    /// Transactions admitted but not committed yet, per sender, by nonce.
+    admitted: HashMap<ActorID, BTreeMap<u64, Admitted>>,
 }
 
 impl<DB> FvmCheckState<DB>
 where
     DB: Blockstore + 'static,
 {
-    pub fn new(blockstore: DB, state_root: Cid, chain_id: ChainID) -> anyhow::Result<Self> {
+    pub fn new(
+        blockstore: DB,
+        state_root: Cid,
+        chain_id: ChainID,
+        base_fee: TokenAmount,
+        policy: CheckPolicy,
+    ) -> anyhow::Result<Self> {
+        let state_tree = Self::load_state_tree(ReadOnlyBlockstore::new(blockstore), state_root)?;
+
+        let state = Self {
+            state_tree,
+            chain_id,
+            base_fee,
+            policy,
+            admitted: HashMap::new(),
+        };
+
+        Ok(state)
+    }
+
+    fn load_state_tree(
+        bstore: ReadOnlyBlockstore<DB>,
+        state_root: Cid,
+    ) -> anyhow::Result<StateTree<ReadOnlyBlockstore<DB>>> {
         // Sanity check that the blockstore contains the supplied state root.
-        if !blockstore
+        if !bstore
             .has(&state_root)
             .context("failed to load initial state-root")?
         {
@@ -37,22 +100,111 @@ where
         }
 
         // Create a new state tree from the supplied root.
-        let state_tree = {
-            let bstore = ReadOnlyBlockstore::new(blockstore);
-            StateTree::new_from_root(bstore, &state_root)?
-        };
+        let state_tree = StateTree::new_from_root(bstore, &state_root)?;
 
-        let state = Self {
-            state_tree,
-            chain_id,
-        };
+        Ok(state_tree)
+    }
 
-        Ok(state)
+    /// Move the state on top of a newly committed state, keeping the transactions
+    /// which haven't been included in a block yet.
+    ///
+    /// Transactions with nonces the committed state has already moved past are
+    /// forgotten, and so are those the sender can no longer afford, and those
+    /// which have been pending for longer than the policy allows.
+    pub fn rebase(&mut self, state_root: Cid, base_fee: TokenAmount) -> anyhow::Result<()>
+    where
+        DB: Clone,
+    {
+        self.state_tree = Self::load_state_tree(self.state_tree.store().clone(), state_root)?;
+        self.base_fee = base_fee;
+
+        let admitted = std::mem::take(&mut self.admitted);
+        let max_age = self.policy.max_pending_blocks;
+
+        for (id, txs) in admitted {
+            let mut actor = match self.state_tree.get_actor(id)? {
+                Some(actor) => actor,
+                None => continue,
+            };
+            let mut kept = BTreeMap::new();
+            for (sequence, mut tx) in txs {
+                tx.age += 1;
+                if sequence < actor.sequence
+                    || actor.balance < tx.reserved
+                    || (max_age > 0 && tx.age >= max_age)
+                {
+                    continue;
+                }
+                actor.balance -= &tx.reserved;
+                kept.insert(sequence, tx);
+            }
+            while kept.contains_key(&actor.sequence) {
+                actor.sequence += 1;
+            }
+            self.state_tree.set_actor(id, actor);
+            if !kept.is_empty() {
+                self.admitted.insert(id, kept);
+            }
+        }
+
+        Ok(())
     }
 
     pub fn state_tree_mut(&mut self) -> &mut StateTree<ReadOnlyBlockstore<DB>> {
         &mut self.state_tree
     }
+
+    pub fn base_fee(&self) -> &TokenAmount {
+        &self.base_fee
+    }
+
+    pub fn policy(&self) -> &CheckPolicy {
+        &self.policy
+    }
+
+    /// Number of transactions of a sender admitted but not committed yet.
+    pub fn pending(&self, id: ActorID) -> u64 {
+        self.admitted.get(&id).map_or(0, |txs| txs.len() as u64)
+    }
+
+    /// The admitted transaction of a sender with a given nonce, if any.
+    pub fn admitted(&self, id: ActorID, sequence: u64) -> Option<&Admitted> {
+        self.admitted.get(&id).and_then(|txs| txs.get(&sequence))
+    }
+
+    /// Record a transaction of the sender as admitted.
+    ///
+    /// The caller is responsible for adjusting the balance and sequence of the actor.
+    pub fn add_admitted(&mut self, id: ActorID, sequence: u64, tx: Admitted) {
+        self.admitted.entry(id).or_default().insert(sequence, tx);
+    }
+
+    /// Forget an admitted transaction of the sender, releasing the balance it reserved.
+    ///
+    /// If the expected nonce has already moved past the transaction, it goes back to it,
+    /// so it can be used again, while the transactions after it stay queued.
+    pub fn remove_admitted(&mut self, id: ActorID, sequence: u64) -> anyhow::Result<()> {
+        let tx = match self.admitted.get_mut(&id) {
+            Some(txs) => {
+                let tx = txs.remove(&sequence);
+                if txs.is_empty() {
+                    self.admitted.remove(&id);
+                }
+                tx
+            }
+            None => None,
+        };
+
+        if let Some(tx) = tx {
+            if let Some(mut actor) = self.state_tree.get_actor(id)? {
+                actor.balance += tx.reserved;
+                actor.sequence = std::cmp::min(actor.sequence, sequence);
+                self.state_tree.set_actor(id, actor);
+            }
+        }
+
+        Ok(())
+    }
 }
 
 impl<DB> HasChainID for FvmCheckState<DB>
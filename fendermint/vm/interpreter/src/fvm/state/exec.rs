@@ -1,22 +1,43 @@
 // Copyright 2022-2023 Protocol Labs
 // SPDX-License-Identifier: Apache-2.0, MIT
 
-use cid::Cid;
+use anyhow::{anyhow, Context};
+use cid::{multihash::Code, Cid};
+use fendermint_vm_actor_interface::{
+    blockhashes::{self, BLOCK_HASHES_ACTOR_ID},
+    resolution::{self, PoolEntry, RESOLUTION_POOL_ACTOR_ID},
+    reward::REWARD_ACTOR_ID,
+    system,
+    validators::{
+        self, SetPowerParams, MAX_TOTAL_POWER, VALIDATORS_ACTOR_ADDR, VALIDATORS_ACTOR_ID,
+    },
+};
+use fendermint_vm_genesis::{BaseFeeParams, Validator};
 use fvm::{
     call_manager::DefaultCallManager,
     engine::MultiEngine,
-    executor::{ApplyKind, ApplyRet, DefaultExecutor, Executor},
+    executor::{ApplyFailure, ApplyKind, ApplyRet, DefaultExecutor, Executor},
     machine::{DefaultMachine, Machine, NetworkConfig},
+    state_tree::{ActorState, StateTree},
     DefaultKernel,
 };
 use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_encoding::{CborStore, RawBytes};
 use fvm_shared::{
-    chainid::ChainID, clock::ChainEpoch, econ::TokenAmount, message::Message,
+    address::{Address, Protocol},
+    chainid::ChainID,
+    clock::ChainEpoch,
+    econ::TokenAmount,
+    error::ExitCode,
+    message::Message,
+    receipt::Receipt,
     version::NetworkVersion,
+    ActorID, METHOD_SEND,
 };
+use num_traits::Zero;
 use serde::{Deserialize, Serialize};
 
-use crate::fvm::externs::FendermintExterns;
+use crate::fvm::externs::{FendermintExterns, CHAIN_FINALITY};
 use fendermint_vm_core::{chainid::HasChainID, Timestamp};
 
 /// Parts of the state which evolve during the lifetime of the chain.
@@ -25,7 +46,14 @@ pub struct FvmStateParams {
     pub state_root: Cid,
     pub timestamp: Timestamp,
     pub network_version: NetworkVersion,
+    /// The base fee for the next block, evolving according to the `base_fee_params`.
     pub base_fee: TokenAmount,
+    /// Parameters of the base fee adjustment, set at genesis; if missing, the base fee stays fixed.
+    #[serde(default)]
+    pub base_fee_params: Option<BaseFeeParams>,
+    /// Amount minted in each block for the validator who proposed it.
+    #[serde(default)]
+    pub block_reward: TokenAmount,
     pub circ_supply: TokenAmount,
     /// The [`ChainID`] is stored here to hint at the possibility that
     /// a chain ID might change during the lifetime of a chain, in case
@@ -35,13 +63,29 @@ pub struct FvmStateParams {
     pub chain_id: u64,
 }
 
+type FvmMachine<DB> = DefaultMachine<DB, FendermintExterns<DB>>;
+type FvmExecutor<DB> = DefaultExecutor<DefaultKernel<DefaultCallManager<FvmMachine<DB>>>>;
+
 /// A state we create for the execution of all the messages in a block.
 pub struct FvmExecState<DB>
 where
     DB: Blockstore + 'static,
 {
-    executor:
-        DefaultExecutor<DefaultKernel<DefaultCallManager<DefaultMachine<DB, FendermintExterns>>>>,
+    executor: FvmExecutor<DB>,
+    /// Address of the validator who proposed the block, as it appears in the block header.
+    block_producer: Option<Vec<u8>>,
+    /// Hash of the block being executed, as it appears in the block header.
+    block_hash: Option<Vec<u8>>,
+    /// Account of the validator who proposed the block, where the rewards are paid.
+    block_producer_addr: Option<Address>,
+    /// Amount to mint for the block producer.
+    block_reward: TokenAmount,
+    /// Amount minted during the block, which adds to the circulating supply.
+    minted: TokenAmount,
+    /// Changes in the validator set during the execution of the block.
+    validator_updates: Vec<Validator>,
+    /// Gas used by the explicit messages in the block, which drives the base fee.
+    block_gas_used: u64,
 }
 
 impl<DB> FvmExecState<DB>
@@ -53,26 +97,73 @@ where
         multi_engine: &MultiEngine,
         block_height: ChainEpoch,
         params: FvmStateParams,
-    ) -> anyhow::Result<Self> {
+    ) -> anyhow::Result<Self>
+    where
+        DB: Clone,
+    {
+        Self::new_with_tracing(blockstore, multi_engine, block_height, params, false)
+    }
+
+    /// Create a state which optionally records the execution trace of each message,
+    /// which is expensive, so only meant for debugging.
+    pub fn new_with_tracing(
+        blockstore: DB,
+        multi_engine: &MultiEngine,
+        block_height: ChainEpoch,
+        params: FvmStateParams,
+        tracing: bool,
+    ) -> anyhow::Result<Self>
+    where
+        DB: Clone,
+    {
         let mut nc = NetworkConfig::new(params.network_version);
         nc.chain_id = ChainID::from(params.chain_id);
 
-        // TODO: Configure:
-        // * circ_supply; by default it's for Filecoin
-        // * base_fee; by default it's zero
         let mut mc = nc.for_epoch(block_height, params.timestamp.0, params.state_root);
         mc.set_base_fee(params.base_fee);
+        let block_reward = params.block_reward;
         mc.set_circulating_supply(params.circ_supply);
+        if tracing {
+            mc.enable_tracing();
+        }
 
         // Creating a new machine every time is prohibitively slow.
         // let ec = EngineConfig::from(&nc);
         // let engine = EnginePool::new_default(ec)?;
 
         let engine = multi_engine.get(&nc)?;
-        let machine = DefaultMachine::new(&mc, blockstore, FendermintExterns)?;
+        let externs = FendermintExterns::new(blockstore.clone(), params.state_root, block_height)?;
+        let machine = DefaultMachine::new(&mc, blockstore, externs)?;
         let executor = DefaultExecutor::new(engine, machine)?;
 
-        Ok(Self { executor })
+        Ok(Self {
+            executor,
+            block_producer: None,
+            block_hash: None,
+            block_producer_addr: None,
+            block_reward,
+            minted: TokenAmount::zero(),
+            validator_updates: Vec::new(),
+            block_gas_used: 0,
+        })
+    }
+
+    /// Set the validator who proposed the block being executed.
+    pub fn with_block_producer(mut self, block_producer: Vec<u8>) -> Self {
+        self.block_producer = Some(block_producer);
+        self
+    }
+
+    /// Set the hash of the block being executed.
+    pub fn with_block_hash(mut self, block_hash: Vec<u8>) -> Self {
+        self.block_hash = Some(block_hash);
+        self
+    }
+
+    /// Set the account of the validator who proposed the block being executed.
+    pub fn with_block_producer_addr(mut self, addr: Address) -> Self {
+        self.block_producer_addr = Some(addr);
+        self
     }
 
     /// Execute message implicitly.
@@ -86,9 +177,28 @@ where
     }
 
     pub fn execute_message(&mut self, msg: Message, kind: ApplyKind) -> anyhow::Result<ApplyRet> {
+        if msg.to == VALIDATORS_ACTOR_ADDR {
+            if kind == ApplyKind::Explicit {
+                return self.apply_signed_validators_message(msg);
+            }
+            if msg.from == system::SYSTEM_ACTOR_ADDR {
+                return self.apply_validators_message(msg);
+            }
+        }
+        self.execute_fvm_message(msg, kind)
+    }
+
+    /// Execute a message with the FVM.
+    fn execute_fvm_message(&mut self, msg: Message, kind: ApplyKind) -> anyhow::Result<ApplyRet> {
         // TODO: We could preserve the message length by changing the input type.
         let raw_length = fvm_ipld_encoding::to_vec(&msg).map(|bz| bz.len())?;
-        self.executor.execute_message(msg, kind, raw_length)
+        let ret = self.executor.execute_message(msg, kind, raw_length)?;
+
+        if kind == ApplyKind::Explicit {
+            self.block_gas_used = self.block_gas_used.saturating_add(ret.msg_receipt.gas_used);
+        }
+
+        Ok(ret)
     }
 
     /// Commit the state. It must not fail, but we're returning a result so that error
@@ -111,6 +221,398 @@ where
     pub fn timestamp(&self) -> Timestamp {
         Timestamp(self.executor.context().timestamp)
     }
+
+    /// The network version the block is executed with.
+    pub fn network_version(&self) -> NetworkVersion {
+        self.executor.context().network.network_version
+    }
+
+    /// Gas used by the explicit messages executed in the block so far.
+    pub fn block_gas_used(&self) -> u64 {
+        self.block_gas_used
+    }
+
+    /// The validator who proposed the currently executing block, if known.
+    pub fn block_producer(&self) -> Option<&[u8]> {
+        self.block_producer.as_deref()
+    }
+
+    /// The hash of the currently executing block, if known.
+    pub fn block_hash(&self) -> Option<&[u8]> {
+        self.block_hash.as_deref()
+    }
+
+    /// The account of the validator who proposed the currently executing block, if known.
+    pub fn block_producer_addr(&self) -> Option<Address> {
+        self.block_producer_addr
+    }
+
+    /// Direct access to the state tree, for migrations which can't be expressed as messages.
+    pub fn state_tree_mut(&mut self) -> &mut StateTree<<FvmMachine<DB> as Machine>::Blockstore> {
+        self.executor.state_tree_mut()
+    }
+
+    /// Check if an actor with the given code can send explicit messages, which is the case
+    /// for accounts, and for placeholders which can turn into Ethereum accounts.
+    pub fn is_valid_sender(&self, code: &Cid) -> bool {
+        let builtin_actors = self.executor.builtin_actors();
+        builtin_actors.is_account_actor(code)
+            || builtin_actors.is_ethaccount_actor(code)
+            || builtin_actors.is_placeholder_actor(code)
+    }
+
+    /// Create a placeholder actor for an address which doesn't exist in the state tree yet,
+    /// the same way the FVM would if it received funds, so it can act as a sender.
+    pub fn create_placeholder(&mut self, addr: &Address) -> anyhow::Result<ActorID> {
+        if addr.protocol() == Protocol::ID {
+            return Err(anyhow!("actor {addr} does not exist"));
+        }
+        let placeholder_code = *self.executor.builtin_actors().get_placeholder_code();
+        let delegated_address = if addr.protocol() == Protocol::Delegated {
+            Some(*addr)
+        } else {
+            None
+        };
+        let state_tree = self.executor.state_tree_mut();
+        let id = state_tree.register_new_address(addr)?;
+        state_tree.set_actor(
+            id,
+            ActorState::new_empty(placeholder_code, delegated_address),
+        );
+        Ok(id)
+    }
+
+    /// Amount minted during the block so far.
+    pub fn minted(&self) -> &TokenAmount {
+        &self.minted
+    }
+
+    /// Mint the block reward into the reward actor, so it can be paid out along with the gas premiums.
+    pub fn mint_block_reward(&mut self) -> anyhow::Result<()> {
+        if self.block_reward.is_zero() {
+            return Ok(());
+        }
+        let reward = self.block_reward.clone();
+
+        let state_tree = self.executor.state_tree_mut();
+
+        let mut actor = state_tree
+            .get_actor(REWARD_ACTOR_ID)?
+            .ok_or_else(|| anyhow!("reward actor not found in the state tree"))?;
+
+        actor.deposit_funds(&reward);
+        state_tree.set_actor(REWARD_ACTOR_ID, actor);
+
+        self.minted += reward;
+
+        Ok(())
+    }
+
+    /// Balance of the reward actor, which is the amount due to the block producer.
+    pub fn reward_balance(&self) -> anyhow::Result<TokenAmount> {
+        let actor = self
+            .executor
+            .state_tree()
+            .get_actor(REWARD_ACTOR_ID)?
+            .ok_or_else(|| anyhow!("reward actor not found in the state tree"))?;
+
+        Ok(actor.balance)
+    }
+
+    /// The current validator set; empty if the state doesn't have one.
+    pub fn validators(&self) -> anyhow::Result<Vec<Validator>> {
+        let state_tree = self.executor.state_tree();
+
+        let actor = match state_tree.get_actor(VALIDATORS_ACTOR_ID)? {
+            Some(actor) => actor,
+            None => return Ok(Vec::new()),
+        };
+
+        let state: validators::State = state_tree
+            .store()
+            .get_cbor(&actor.state)?
+            .ok_or_else(|| anyhow!("validator set state not found: {}", actor.state))?;
+
+        Ok(state.validators)
+    }
+
+    /// Take the changes to the validator set made during the block so far.
+    pub fn take_validator_updates(&mut self) -> Vec<Validator> {
+        std::mem::take(&mut self.validator_updates)
+    }
+
+    /// Handle a signed message to the validator set, which is only allowed from the admin account.
+    ///
+    /// Messages which would not be allowed are rejected before anything is executed, so the
+    /// validator set, which is not a real actor, never receives any funds. The rest are executed
+    /// by the FVM as a plain transfer first, so the nonce is checked and the gas is charged
+    /// like for any other message, then the change is applied.
+    fn apply_signed_validators_message(&mut self, msg: Message) -> anyhow::Result<ApplyRet> {
+        if !msg.value.is_zero() {
+            return Ok(handled_apply_ret(
+                ExitCode::USR_ILLEGAL_ARGUMENT,
+                Some("cannot send funds to the validator set".to_owned()),
+            ));
+        }
+
+        if !self.is_validator_admin(&msg.from)? {
+            return Ok(handled_apply_ret(
+                ExitCode::USR_FORBIDDEN,
+                Some(format!(
+                    "{} is not allowed to change the validator set",
+                    msg.from
+                )),
+            ));
+        }
+
+        let transfer = Message {
+            method_num: METHOD_SEND,
+            params: RawBytes::default(),
+            ..msg.clone()
+        };
+
+        let mut ret = self.execute_fvm_message(transfer, ApplyKind::Explicit)?;
+
+        if !ret.msg_receipt.exit_code.is_success() {
+            return Ok(ret);
+        }
+
+        let handled = self.apply_validators_message(msg)?;
+
+        ret.msg_receipt.exit_code = handled.msg_receipt.exit_code;
+        ret.failure_info = handled.failure_info;
+
+        Ok(ret)
+    }
+
+    /// Check whether an address belongs to the account allowed to change the validator set.
+    fn is_validator_admin(&self, addr: &Address) -> anyhow::Result<bool> {
+        let state_tree = self.executor.state_tree();
+
+        let actor = match state_tree.get_actor(VALIDATORS_ACTOR_ID)? {
+            Some(actor) => actor,
+            None => return Ok(false),
+        };
+
+        let state: validators::State = state_tree
+            .store()
+            .get_cbor(&actor.state)?
+            .ok_or_else(|| anyhow!("validator set state not found: {}", actor.state))?;
+
+        let admin = match state.admin {
+            Some(admin) => admin,
+            None => return Ok(false),
+        };
+
+        // Either address could be given in a different form, so compare the actor IDs.
+        match (state_tree.lookup_id(addr)?, state_tree.lookup_id(&admin)?) {
+            (Some(id), Some(admin_id)) => Ok(id == admin_id),
+            _ => Ok(false),
+        }
+    }
+
+    /// Handle a message sent by the system actor to the validator set,
+    /// which is not a real actor, so the FVM can't execute it.
+    ///
+    /// Changes CometBFT would refuse are rejected, and setting the power a validator
+    /// already has is not reported as an update.
+    fn apply_validators_message(&mut self, msg: Message) -> anyhow::Result<ApplyRet> {
+        if msg.method_num != validators::Method::SetPower as u64 {
+            return Ok(handled_apply_ret(
+                ExitCode::USR_UNHANDLED_MESSAGE,
+                Some(format!("unknown validator set method: {}", msg.method_num)),
+            ));
+        }
+
+        let params = match msg.params.deserialize::<SetPowerParams>() {
+            Ok(params) => params,
+            Err(e) => {
+                return Ok(handled_apply_ret(
+                    ExitCode::USR_ILLEGAL_ARGUMENT,
+                    Some(format!("invalid validator power parameters: {e}")),
+                ))
+            }
+        };
+
+        let validator = Validator {
+            public_key: params.public_key,
+            power: params.power,
+        };
+
+        let state_tree = self.executor.state_tree_mut();
+
+        let mut actor = match state_tree.get_actor(VALIDATORS_ACTOR_ID)? {
+            Some(actor) => actor,
+            None => {
+                return Ok(handled_apply_ret(
+                    ExitCode::USR_ILLEGAL_STATE,
+                    Some("validator set not found in the state tree".to_owned()),
+                ))
+            }
+        };
+
+        let mut state: validators::State = state_tree
+            .store()
+            .get_cbor(&actor.state)?
+            .ok_or_else(|| anyhow!("validator set state not found: {}", actor.state))?;
+
+        let illegal = |e: &str| {
+            Ok(handled_apply_ret(
+                ExitCode::USR_ILLEGAL_ARGUMENT,
+                Some(e.to_owned()),
+            ))
+        };
+
+        let existing = state
+            .validators
+            .iter()
+            .find(|v| v.public_key == validator.public_key);
+
+        match existing {
+            None if validator.power.0 == 0 => {
+                return illegal("cannot remove a validator which is not in the set")
+            }
+            Some(_) if validator.power.0 == 0 && state.validators.len() == 1 => {
+                return illegal("cannot remove the last validator")
+            }
+            // Nothing to report to CometBFT.
+            Some(v) if v.power == validator.power => {
+                return Ok(handled_apply_ret(ExitCode::OK, None))
+            }
+            _ => {}
+        }
+
+        let total_power = state
+            .validators
+            .iter()
+            .filter(|v| v.public_key != validator.public_key)
+            .map(|v| v.power.0 as u128)
+            .sum::<u128>()
+            + validator.power.0 as u128;
+
+        if total_power > MAX_TOTAL_POWER as u128 {
+            return illegal(&format!(
+                "total validator power would be {total_power}, above the maximum of {MAX_TOTAL_POWER}"
+            ));
+        }
+
+        state.set_power(validator.clone());
+
+        actor.state = state_tree
+            .store()
+            .put_cbor(&state, Code::Blake2b256)
+            .context("failed to save validator set")?;
+
+        state_tree.set_actor(VALIDATORS_ACTOR_ID, actor);
+
+        // Only the last change of a validator in the block matters.
+        self.validator_updates
+            .retain(|v| v.public_key != validator.public_key);
+        self.validator_updates.push(validator);
+
+        Ok(handled_apply_ret(ExitCode::OK, None))
+    }
+
+    /// Record the hash of the block being executed in the ledger, if it's known,
+    /// forgetting the one which is now beyond the lookback limit.
+    ///
+    /// The record is created with the first block hash.
+    pub fn record_block_hash(&mut self) -> anyhow::Result<()> {
+        let hash = match self.block_hash {
+            Some(ref hash) => hash.clone(),
+            None => return Ok(()),
+        };
+        let height = self.block_height();
+        let prune_height = Some(height - CHAIN_FINALITY).filter(|h| *h >= 0);
+
+        let placeholder_code = *self.executor.builtin_actors().get_placeholder_code();
+        let state_tree = self.executor.state_tree_mut();
+        let store = state_tree.store();
+
+        let (mut actor, mut block_hashes) = match state_tree.get_actor(BLOCK_HASHES_ACTOR_ID)? {
+            Some(actor) => {
+                let block_hashes = blockhashes::State::load(store, &actor.state)?;
+                (actor, block_hashes)
+            }
+            None => {
+                let block_hashes = blockhashes::State::new(store)?;
+                let actor = ActorState::new_empty(placeholder_code, None);
+                (actor, block_hashes)
+            }
+        };
+
+        block_hashes.set(store, height, hash, prune_height)?;
+        actor.state = block_hashes
+            .save(store)
+            .context("failed to save block hashes")?;
+
+        state_tree.set_actor(BLOCK_HASHES_ACTOR_ID, actor);
+
+        Ok(())
+    }
+
+    /// Look up a CID in the pool of messages proposed for resolution.
+    pub fn get_pool_entry(&self, cid: &Cid) -> anyhow::Result<Option<PoolEntry>> {
+        let state_tree = self.executor.state_tree();
+
+        match state_tree.get_actor(RESOLUTION_POOL_ACTOR_ID)? {
+            None => Ok(None),
+            Some(actor) => {
+                let pool = resolution::State::load(state_tree.store(), &actor.state)?;
+                pool.get(state_tree.store(), cid)
+            }
+        }
+    }
+
+    /// Insert or update a CID in the pool of messages proposed for resolution.
+    ///
+    /// The pool is created the first time an entry is added to it.
+    pub fn set_pool_entry(&mut self, cid: &Cid, entry: PoolEntry) -> anyhow::Result<()> {
+        let placeholder_code = *self.executor.builtin_actors().get_placeholder_code();
+        let state_tree = self.executor.state_tree_mut();
+        let store = state_tree.store();
+
+        let (mut actor, mut pool) = match state_tree.get_actor(RESOLUTION_POOL_ACTOR_ID)? {
+            Some(actor) => {
+                let pool = resolution::State::load(store, &actor.state)?;
+                (actor, pool)
+            }
+            None => {
+                let pool = resolution::State::new(store)?;
+                let actor = ActorState::new_empty(placeholder_code, None);
+                (actor, pool)
+            }
+        };
+
+        pool.set(store, cid, entry)?;
+        actor.state = pool.save(store).context("failed to save pool state")?;
+
+        state_tree.set_actor(RESOLUTION_POOL_ACTOR_ID, actor);
+
+        Ok(())
+    }
+}
+
+/// Result of a message handled by the interpreter instead of the FVM, which doesn't use any gas.
+fn handled_apply_ret(exit_code: ExitCode, error: Option<String>) -> ApplyRet {
+    ApplyRet {
+        msg_receipt: Receipt {
+            exit_code,
+            return_data: RawBytes::default(),
+            gas_used: 0,
+            events_root: None,
+        },
+        penalty: TokenAmount::zero(),
+        miner_tip: TokenAmount::zero(),
+        base_fee_burn: TokenAmount::zero(),
+        over_estimation_burn: TokenAmount::zero(),
+        refund: TokenAmount::zero(),
+        gas_refund: 0,
+        gas_burned: 0,
+        failure_info: error.map(ApplyFailure::PreValidation),
+        exec_trace: Vec::new(),
+        events: Vec::new(),
+    }
 }
 
 impl<DB> HasChainID for FvmExecState<DB>
@@ -0,0 +1,68 @@
+// Copyright 2022-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+use fendermint_vm_genesis::BaseFeeParams;
+use fvm_shared::{bigint::BigInt, econ::TokenAmount};
+
+/// Calculate the base fee for the next block, based on how much gas the current one used,
+/// the same way as [EIP-1559](https://eips.ethereum.org/EIPS/eip-1559) does.
+pub fn next_base_fee(base_fee: &TokenAmount, gas_used: u64, params: &BaseFeeParams) -> TokenAmount {
+    let gas_target = params.gas_target;
+    let denominator = params.max_change_denominator;
+
+    // These are validated at genesis, but let's not divide by zero either way.
+    if gas_used == gas_target || gas_target == 0 || denominator == 0 {
+        return base_fee.clone();
+    }
+
+    let gas_delta = BigInt::from(gas_used.abs_diff(gas_target));
+    let fee_delta = base_fee.atto() * gas_delta / gas_target / denominator;
+
+    if gas_used > gas_target {
+        // Make sure the base fee can increase even when it's tiny.
+        let fee_delta = fee_delta.max(BigInt::from(1));
+        TokenAmount::from_atto(base_fee.atto() + fee_delta)
+    } else {
+        TokenAmount::from_atto(base_fee.atto() - fee_delta)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fendermint_vm_genesis::BaseFeeParams;
+    use fvm_shared::econ::TokenAmount;
+
+    use super::next_base_fee;
+
+    const PARAMS: BaseFeeParams = BaseFeeParams {
+        gas_target: 1000,
+        max_change_denominator: 8,
+    };
+
+    #[test]
+    fn base_fee_follows_gas_used() {
+        let base_fee = TokenAmount::from_atto(800);
+
+        assert_eq!(next_base_fee(&base_fee, 1000, &PARAMS), base_fee);
+        // Full blocks at twice the target increase the fee by 1/8.
+        assert_eq!(
+            next_base_fee(&base_fee, 2000, &PARAMS),
+            TokenAmount::from_atto(900)
+        );
+        // Empty blocks decrease the fee by 1/8.
+        assert_eq!(
+            next_base_fee(&base_fee, 0, &PARAMS),
+            TokenAmount::from_atto(700)
+        );
+    }
+
+    #[test]
+    fn base_fee_increases_from_zero() {
+        let base_fee = TokenAmount::from_atto(0);
+        assert_eq!(
+            next_base_fee(&base_fee, 1001, &PARAMS),
+            TokenAmount::from_atto(1)
+        );
+        assert_eq!(next_base_fee(&base_fee, 0, &PARAMS), base_fee);
+    }
+}
@@ -22,6 +22,38 @@ pub fn empty_state_tree<DB: Blockstore>(store: DB) -> anyhow::Result<StateTree<D
     Ok(state_tree)
 }
 
+/// Load the actor bundle CAR into the store.
+///
+/// Returns the CID of the manifest data, which the system actor refers to, and the manifest itself.
+pub async fn load_actor_bundle<DB: Blockstore>(
+    store: &DB,
+    bundle: &[u8],
+) -> anyhow::Result<(Cid, Manifest)> {
+    let bundle_roots = load_car_unchecked(store, bundle).await?;
+    let bundle_root = match bundle_roots.as_slice() {
+        [root] => root,
+        roots => {
+            return Err(anyhow!(
+                "expected one root in actor bundle; got {}",
+                roots.len()
+            ))
+        }
+    };
+
+    let (manifest_version, manifest_data_cid): (u32, Cid) = match store.get_cbor(bundle_root)? {
+        Some(vd) => vd,
+        None => {
+            return Err(anyhow!(
+                "no manifest information in bundle root {}",
+                bundle_root
+            ))
+        }
+    };
+    let manifest = Manifest::load(store, &manifest_data_cid, manifest_version)?;
+
+    Ok((manifest_data_cid, manifest))
+}
+
 /// A state we create for the execution of genesis initialisation.
 pub struct FvmGenesisState<DB>
 where
@@ -37,28 +69,7 @@ where
     DB: Blockstore,
 {
     pub async fn new(store: DB, bundle: &[u8]) -> anyhow::Result<Self> {
-        // Load the actor bundle.
-        let bundle_roots = load_car_unchecked(&store, bundle).await?;
-        let bundle_root = match bundle_roots.as_slice() {
-            [root] => root,
-            roots => {
-                return Err(anyhow!(
-                    "expected one root in actor bundle; got {}",
-                    roots.len()
-                ))
-            }
-        };
-
-        let (manifest_version, manifest_data_cid): (u32, Cid) = match store.get_cbor(bundle_root)? {
-            Some(vd) => vd,
-            None => {
-                return Err(anyhow!(
-                    "no manifest information in bundle root {}",
-                    bundle_root
-                ))
-            }
-        };
-        let manifest = Manifest::load(&store, &manifest_data_cid, manifest_version)?;
+        let (manifest_data_cid, manifest) = load_actor_bundle(&store, bundle).await?;
         let state_tree = empty_state_tree(store)?;
 
         let state = Self {
@@ -3,10 +3,12 @@
 
 mod check;
 mod exec;
+mod fee;
 mod genesis;
 mod query;
 
-pub use check::FvmCheckState;
+pub use check::{Admitted, CheckPolicy, FvmCheckState};
 pub use exec::{FvmExecState, FvmStateParams};
-pub use genesis::{empty_state_tree, FvmGenesisState};
+pub use fee::next_base_fee;
+pub use genesis::{empty_state_tree, load_actor_bundle, FvmGenesisState};
 pub use query::FvmQueryState;
@@ -6,9 +6,12 @@ use std::{cell::RefCell, sync::Arc};
 use anyhow::{anyhow, Context};
 
 use cid::Cid;
+use fendermint_vm_actor_interface::validators::{self, VALIDATORS_ACTOR_ID};
+use fendermint_vm_genesis::Validator;
 use fendermint_vm_message::query::ActorState;
 use fvm::{engine::MultiEngine, executor::ApplyRet, state_tree::StateTree};
 use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_encoding::CborStore;
 use fvm_shared::{address::Address, clock::ChainEpoch, ActorID};
 use num_traits::Zero;
 
@@ -137,22 +140,99 @@ where
         })
     }
 
+    /// Get the current validator set.
+    pub fn validators(&self) -> anyhow::Result<Vec<Validator>> {
+        self.with_state_tree(
+            |state_tree| match state_tree.get_actor(VALIDATORS_ACTOR_ID)? {
+                None => Ok(Vec::new()),
+                Some(actor) => {
+                    let state: validators::State = state_tree
+                        .store()
+                        .get_cbor(&actor.state)?
+                        .ok_or_else(|| anyhow!("validator set state not found: {}", actor.state))?;
+                    Ok(state.validators)
+                }
+            },
+        )
+    }
+
     /// Run a "read-only" message.
     ///
     /// The results are never going to be flushed, so it's semantically read-only,
     /// but it might write into the buffered block store the FVM creates. Running
     /// multiple such messages results in their buffered effects stacking up.
-    pub fn call(&self, mut msg: FvmMessage) -> anyhow::Result<ApplyRet> {
-        // If the sequence is zero, treat it as a signal to use whatever is in the state.
-        if msg.sequence.is_zero() {
-            if let Some((_, state)) = self.actor_state(&msg.from)? {
-                msg.sequence = state.sequence;
-            }
+    pub fn call(&self, msg: FvmMessage) -> anyhow::Result<ApplyRet> {
+        let sender = self.actor_state(&msg.from)?.map(|(_, state)| state);
+        self.with_exec_state(|s| call_message(s, sender, msg))
+    }
+
+    /// Run "read-only" messages one after the other with execution tracing enabled,
+    /// returning the results of the last one.
+    ///
+    /// The messages are executed on a fresh execution state, unaffected by previous calls.
+    pub fn trace(&self, msgs: Vec<FvmMessage>) -> anyhow::Result<ApplyRet> {
+        let mut exec_state = FvmExecState::new_with_tracing(
+            self.store.clone(),
+            self.multi_engine.as_ref(),
+            self.block_height,
+            self.state_params.clone(),
+            true,
+        )
+        .context("error creating execution state")?;
+
+        let mut ret = None;
+        for msg in msgs {
+            // Same as with calls, but the sender is looked up in the state before the messages.
+            let sender = self.actor_state(&msg.from)?.map(|(_, state)| state);
+            ret = Some(call_message(&mut exec_state, sender, msg)?);
         }
-        self.with_exec_state(|s| s.execute_explicit(msg))
+
+        ret.ok_or_else(|| anyhow!("no messages to trace"))
     }
 
     pub fn state_params(&self) -> &FvmStateParams {
         &self.state_params
     }
+
+    /// Drop the execution state, discarding the buffered effects of previous calls,
+    /// so the next query starts from the committed state again.
+    ///
+    /// The state tree is kept, as it is never written to.
+    pub fn clear_exec_state(&self) {
+        *self.exec_state.borrow_mut() = None;
+    }
+}
+
+/// Execute a read-only message.
+///
+/// Messages from accounts are executed explicitly, so they are subject to the same checks as
+/// transactions; if the sequence is zero, it's treated as a signal to use whatever is in the state.
+///
+/// Anyone else, such as the system actor, a contract, or an address which doesn't exist,
+/// cannot send transactions, but can still make calls, which are executed implicitly,
+/// without checking the balance or the nonce. The FVM needs an existing sender even then,
+/// so addresses which don't exist get a placeholder in the buffered state, which is discarded.
+fn call_message<DB>(
+    exec_state: &mut FvmExecState<DB>,
+    sender: Option<ActorState>,
+    mut msg: FvmMessage,
+) -> anyhow::Result<ApplyRet>
+where
+    DB: Blockstore + 'static,
+{
+    match sender {
+        Some(sender) if exec_state.is_valid_sender(&sender.code) => {
+            if msg.sequence.is_zero() {
+                msg.sequence = sender.sequence;
+            }
+            exec_state.execute_explicit(msg)
+        }
+        Some(_) => exec_state.execute_implicit(msg),
+        None => {
+            exec_state
+                .create_placeholder(&msg.from)
+                .context("failed to create placeholder for the sender")?;
+            exec_state.execute_implicit(msg)
+        }
+    }
 }
@@ -0,0 +1,170 @@
+// Copyright 2022-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Conversion of FVM execution traces into call trees.
+
+use fendermint_vm_message::query::{CallTrace, GasChargeTrace, TraceBlock, TraceResult};
+use fvm::executor::ApplyRet;
+use fvm::trace::{ExecutionEvent, ExecutionTrace};
+use fvm_ipld_encoding::ipld_block::IpldBlock;
+
+/// Summarize the results of a message executed with tracing enabled.
+pub fn to_trace_result(ret: ApplyRet) -> TraceResult {
+    TraceResult {
+        exit_code: ret.msg_receipt.exit_code,
+        gas_used: ret.msg_receipt.gas_used,
+        return_data: ret.msg_receipt.return_data,
+        failure_info: ret.failure_info.map(|f| f.to_string()),
+        call: to_call_trace(ret.exec_trace),
+    }
+}
+
+/// Build the tree of calls from the flat list of events recorded during the execution of a message.
+///
+/// Gas charged outside the top level call, e.g. for the inclusion of the message, is attributed to it.
+pub fn to_call_trace(trace: ExecutionTrace) -> Option<CallTrace> {
+    let mut root: Option<CallTrace> = None;
+    // Calls which haven't returned yet, the innermost last.
+    let mut stack: Vec<CallTrace> = Vec::new();
+    // Gas charged before the top level call started.
+    let mut charges = Vec::new();
+
+    for event in trace {
+        match event {
+            ExecutionEvent::GasCharge(charge) => {
+                let charge = GasChargeTrace {
+                    name: charge.name.into_owned(),
+                    compute_milligas: charge.compute_gas.as_milligas(),
+                    other_milligas: charge.other_gas.as_milligas(),
+                };
+                match (stack.last_mut(), root.as_mut()) {
+                    (Some(call), _) | (None, Some(call)) => call.gas_charges.push(charge),
+                    (None, None) => charges.push(charge),
+                }
+            }
+            ExecutionEvent::Call {
+                from,
+                to,
+                method,
+                params,
+                value,
+            } => stack.push(CallTrace {
+                from,
+                to,
+                method,
+                params: params.map(to_trace_block),
+                value,
+                exit_code: None,
+                return_data: None,
+                error: None,
+                gas_charges: std::mem::take(&mut charges),
+                calls: Vec::new(),
+            }),
+            ExecutionEvent::CallReturn(exit_code, data) => {
+                if let Some(mut call) = stack.pop() {
+                    call.exit_code = Some(exit_code);
+                    call.return_data = data.map(to_trace_block);
+                    finish_call(&mut stack, &mut root, call);
+                }
+            }
+            ExecutionEvent::CallError(e) => {
+                if let Some(mut call) = stack.pop() {
+                    call.error = Some(e.to_string());
+                    finish_call(&mut stack, &mut root, call);
+                }
+            }
+            // Events added in future versions of the FVM.
+            #[allow(unreachable_patterns)]
+            _ => {}
+        }
+    }
+
+    // Calls that never returned, because the execution was aborted, e.g. running out of gas.
+    while let Some(call) = stack.pop() {
+        finish_call(&mut stack, &mut root, call);
+    }
+
+    root
+}
+
+/// Add a finished call to its caller, or make it the root if it was the top level call.
+fn finish_call(stack: &mut [CallTrace], root: &mut Option<CallTrace>, call: CallTrace) {
+    match stack.last_mut() {
+        Some(caller) => caller.calls.push(call),
+        None => *root = Some(call),
+    }
+}
+
+fn to_trace_block(block: IpldBlock) -> TraceBlock {
+    TraceBlock {
+        codec: block.codec,
+        data: block.data.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fvm::gas::{Gas, GasCharge};
+    use fvm::kernel::SyscallError;
+    use fvm::trace::ExecutionEvent;
+    use fvm_shared::{address::Address, econ::TokenAmount, error::ErrorNumber, error::ExitCode};
+
+    use super::to_call_trace;
+
+    fn call(from: u64, to: u64, method: u64) -> ExecutionEvent {
+        ExecutionEvent::Call {
+            from,
+            to: Address::new_id(to),
+            method,
+            params: None,
+            value: TokenAmount::from_atto(0),
+        }
+    }
+
+    fn charge(name: &'static str, gas: u64) -> ExecutionEvent {
+        ExecutionEvent::GasCharge(GasCharge::new(name, Gas::new(gas), Gas::new(0)))
+    }
+
+    #[test]
+    fn empty_trace() {
+        assert!(to_call_trace(Vec::new()).is_none());
+    }
+
+    #[test]
+    fn nested_calls() {
+        let trace = vec![
+            charge("OnChainMessage", 10),
+            call(100, 200, 2),
+            charge("OnBlockOpen", 1),
+            call(200, 300, 3),
+            ExecutionEvent::CallReturn(ExitCode::OK, None),
+            call(200, 400, 4),
+            ExecutionEvent::CallError(SyscallError::new(ErrorNumber::NotFound, "actor not found")),
+            ExecutionEvent::CallReturn(ExitCode::USR_ILLEGAL_ARGUMENT, None),
+        ];
+
+        let root = to_call_trace(trace).expect("there is a top level call");
+
+        assert_eq!(root.from, 100);
+        assert_eq!(root.to, Address::new_id(200));
+        assert_eq!(root.exit_code, Some(ExitCode::USR_ILLEGAL_ARGUMENT));
+        assert_eq!(root.gas_charges.len(), 2);
+        assert_eq!(root.gas_charges[0].compute_milligas, 10_000);
+        assert_eq!(root.calls.len(), 2);
+        assert_eq!(root.calls[0].method, 3);
+        assert_eq!(root.calls[0].exit_code, Some(ExitCode::OK));
+        assert_eq!(root.calls[1].exit_code, None);
+        assert!(root.calls[1].error.is_some());
+    }
+
+    #[test]
+    fn aborted_calls() {
+        let trace = vec![call(100, 200, 2), call(200, 300, 3)];
+
+        let root = to_call_trace(trace).expect("there is a top level call");
+
+        assert_eq!(root.exit_code, None);
+        assert_eq!(root.calls.len(), 1);
+        assert_eq!(root.calls[0].exit_code, None);
+    }
+}
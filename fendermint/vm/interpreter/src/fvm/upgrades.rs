@@ -0,0 +1,490 @@
+// Copyright 2022-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+//! Network upgrades, which switch the network version and the builtin actors at a given height.
+//!
+//! Every node must have the same schedule, otherwise they will fork at the upgrade height.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Context};
+use cid::Cid;
+use fendermint_vm_actor_interface::system;
+use fvm::{machine::Manifest, state_tree::StateTree};
+use fvm_ipld_blockstore::{Blockstore, MemoryBlockstore};
+use fvm_ipld_encoding::CborStore;
+use fvm_shared::{clock::ChainEpoch, version::NetworkVersion};
+
+use super::state::{load_actor_bundle, FvmExecState, FvmStateParams};
+
+/// Migrate the state at the beginning of the upgrade block, after the network
+/// version was switched and the actors were moved over to the new bundle.
+pub type MigrationFn<DB> = fn(&mut FvmExecState<DB>) -> anyhow::Result<()>;
+
+/// A network upgrade scheduled for a given block height.
+pub struct Upgrade<DB>
+where
+    DB: Blockstore + 'static,
+{
+    pub block_height: ChainEpoch,
+    /// The network version to execute the block at the upgrade height with, and every block after.
+    pub network_version: NetworkVersion,
+    /// Contents of a new builtin actor bundle CAR file, if the actors change.
+    ///
+    /// Loaded with [`read_bundle`] at startup, so a missing or broken bundle is
+    /// found out before the node gets anywhere near the upgrade height.
+    pub bundle: Option<Arc<Vec<u8>>>,
+    /// State migration specific to this upgrade, if any.
+    pub migration: Option<MigrationFn<DB>>,
+}
+
+impl<DB> Clone for Upgrade<DB>
+where
+    DB: Blockstore + 'static,
+{
+    fn clone(&self) -> Self {
+        Self {
+            block_height: self.block_height,
+            network_version: self.network_version,
+            bundle: self.bundle.clone(),
+            migration: self.migration,
+        }
+    }
+}
+
+impl<DB> Upgrade<DB>
+where
+    DB: Blockstore + Clone + 'static,
+{
+    /// Switch the parameters of the state committed by the block before the upgrade
+    /// to the new network version, and the actors to the new bundle, if there is one.
+    ///
+    /// This has to happen before the state to execute the upgrade block is created.
+    pub async fn apply(
+        &self,
+        store: &DB,
+        mut state_params: FvmStateParams,
+    ) -> anyhow::Result<FvmStateParams> {
+        let height = self.block_height;
+
+        if self.network_version <= state_params.network_version {
+            bail!(
+                "cannot upgrade from network version {} to {} at height {height}",
+                state_params.network_version,
+                self.network_version
+            );
+        }
+
+        tracing::info!(
+            height,
+            network_version = self.network_version.to_string(),
+            "upgrading network"
+        );
+
+        state_params.network_version = self.network_version;
+
+        if let Some(ref bundle) = self.bundle {
+            state_params.state_root = upgrade_actors(store, state_params.state_root, bundle)
+                .await
+                .with_context(|| format!("failed to upgrade actors at height {height}"))?;
+        }
+
+        Ok(state_params)
+    }
+
+    /// Run the migration of the upgrade, if it has one, in the state of the upgrade block.
+    pub fn migrate(&self, state: &mut FvmExecState<DB>) -> anyhow::Result<()> {
+        if let Some(migration) = self.migration {
+            migration(state).with_context(|| {
+                format!(
+                    "failed to migrate state to {} at height {}",
+                    self.network_version, self.block_height
+                )
+            })?;
+        }
+        Ok(())
+    }
+}
+
+/// State migrations known to this version of the software, keyed by the network version they upgrade to.
+///
+/// The operators decide when to upgrade, but the migration that goes with a network version is
+/// part of the code, so every node running the same version applies the same migration.
+pub struct MigrationRegistry<DB>
+where
+    DB: Blockstore + 'static,
+{
+    migrations: BTreeMap<NetworkVersion, MigrationFn<DB>>,
+}
+
+impl<DB> Default for MigrationRegistry<DB>
+where
+    DB: Blockstore + 'static,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<DB> MigrationRegistry<DB>
+where
+    DB: Blockstore + 'static,
+{
+    pub fn new() -> Self {
+        Self {
+            migrations: BTreeMap::new(),
+        }
+    }
+
+    /// Register the migration to run when upgrading to a network version.
+    pub fn register(
+        &mut self,
+        network_version: NetworkVersion,
+        migration: MigrationFn<DB>,
+    ) -> anyhow::Result<()> {
+        if self.migrations.contains_key(&network_version) {
+            bail!("there is already a migration registered for {network_version}");
+        }
+        self.migrations.insert(network_version, migration);
+        Ok(())
+    }
+
+    /// The migration to run when upgrading to a network version, if any.
+    pub fn get(&self, network_version: NetworkVersion) -> Option<MigrationFn<DB>> {
+        self.migrations.get(&network_version).copied()
+    }
+}
+
+/// The migrations shipped with this version of the software.
+///
+/// None of the upgrades need a migration beyond switching the actors yet.
+pub fn migrations<DB>() -> MigrationRegistry<DB>
+where
+    DB: Blockstore + 'static,
+{
+    MigrationRegistry::new()
+}
+
+/// Read a builtin actor bundle CAR file and check that it has a manifest the actors can be upgraded to.
+pub async fn read_bundle(path: &Path) -> anyhow::Result<Vec<u8>> {
+    let bundle = std::fs::read(path)
+        .with_context(|| format!("failed to read actor bundle {}", path.to_string_lossy()))?;
+
+    load_actor_bundle(&MemoryBlockstore::new(), &bundle)
+        .await
+        .with_context(|| format!("invalid actor bundle {}", path.to_string_lossy()))?;
+
+    Ok(bundle)
+}
+
+/// Upgrades indexed by block height.
+pub struct UpgradeScheduler<DB>
+where
+    DB: Blockstore + 'static,
+{
+    upgrades: BTreeMap<ChainEpoch, Upgrade<DB>>,
+}
+
+impl<DB> Clone for UpgradeScheduler<DB>
+where
+    DB: Blockstore + 'static,
+{
+    fn clone(&self) -> Self {
+        Self {
+            upgrades: self.upgrades.clone(),
+        }
+    }
+}
+
+impl<DB> Default for UpgradeScheduler<DB>
+where
+    DB: Blockstore + 'static,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<DB> UpgradeScheduler<DB>
+where
+    DB: Blockstore + 'static,
+{
+    pub fn new() -> Self {
+        Self {
+            upgrades: BTreeMap::new(),
+        }
+    }
+
+    /// Schedule an upgrade. The network version has to increase with the height.
+    pub fn add(&mut self, upgrade: Upgrade<DB>) -> anyhow::Result<()> {
+        let height = upgrade.block_height;
+        let nv = upgrade.network_version;
+
+        if self.upgrades.contains_key(&height) {
+            bail!("there is already an upgrade scheduled at height {height}");
+        }
+        if let Some((h, u)) = self.upgrades.range(..height).next_back() {
+            if u.network_version >= nv {
+                bail!(
+                    "upgrade at height {height} to {nv} is not after the upgrade at height {h} to {}",
+                    u.network_version
+                );
+            }
+        }
+        if let Some((h, u)) = self.upgrades.range(height..).next() {
+            if u.network_version <= nv {
+                bail!(
+                    "upgrade at height {height} to {nv} is not before the upgrade at height {h} to {}",
+                    u.network_version
+                );
+            }
+        }
+
+        self.upgrades.insert(height, upgrade);
+        Ok(())
+    }
+
+    /// The upgrade scheduled at the given height, if any.
+    pub fn get(&self, height: ChainEpoch) -> Option<&Upgrade<DB>> {
+        self.upgrades.get(&height)
+    }
+}
+
+/// Load a new actor bundle into the store, move every builtin actor over to the
+/// code of the same kind in the new bundle, and point the system actor at it.
+///
+/// Returns the new state root.
+pub async fn upgrade_actors<DB>(store: &DB, state_root: Cid, bundle: &[u8]) -> anyhow::Result<Cid>
+where
+    DB: Blockstore + Clone,
+{
+    let (new_manifest_data_cid, new_manifest) = load_actor_bundle(store, bundle)
+        .await
+        .context("failed to load new actor bundle")?;
+
+    let mut state_tree = StateTree::new_from_root(store.clone(), &state_root)?;
+
+    let mut system_actor = state_tree
+        .get_actor(system::SYSTEM_ACTOR_ID)?
+        .ok_or_else(|| anyhow!("system actor not found"))?;
+
+    let mut system_state: system::State = store
+        .get_cbor(&system_actor.state)?
+        .ok_or_else(|| anyhow!("system actor state not found"))?;
+
+    // The FVM only supports version 1 of the manifest.
+    let old_manifest = Manifest::load(store, &system_state.builtin_actors, 1)
+        .context("failed to load current actor manifest")?;
+
+    let mut moved = Vec::new();
+
+    state_tree.for_each(|addr, actor| {
+        let code_id = old_manifest.id_by_code(&actor.code);
+        // Zero means it's not a builtin actor.
+        if code_id == 0 {
+            return Ok(());
+        }
+        let id = addr
+            .id()
+            .map_err(|e| anyhow!("actor address is not an ID: {e}"))?;
+
+        let code = new_manifest
+            .code_by_id(code_id)
+            .ok_or_else(|| anyhow!("new actor bundle doesn't have code {code_id}"))?;
+
+        let mut actor = actor.clone();
+        actor.code = *code;
+        moved.push((id, actor));
+        Ok(())
+    })?;
+
+    for (id, actor) in moved {
+        state_tree.set_actor(id, actor);
+    }
+
+    system_state.builtin_actors = new_manifest_data_cid;
+    system_actor.state = state_tree
+        .store()
+        .put_cbor(&system_state, cid::multihash::Code::Blake2b256)?;
+    state_tree.set_actor(system::SYSTEM_ACTOR_ID, system_actor);
+
+    let state_root = state_tree.flush()?;
+
+    Ok(state_root)
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::anyhow;
+    use fendermint_vm_actor_interface::burntfunds::BURNT_FUNDS_ACTOR_ID;
+    use fendermint_vm_genesis::Genesis;
+    use fvm::engine::MultiEngine;
+    use fvm::state_tree::StateTree;
+    use fvm_shared::{econ::TokenAmount, version::NetworkVersion};
+    use quickcheck::Arbitrary;
+
+    use crate::{
+        fvm::{
+            bundle::{bundle_path, genesis_state},
+            state::{FvmExecState, FvmStateParams},
+            store::memory::MemoryBlockstore,
+            FvmMessageInterpreter,
+        },
+        ExecInterpreter,
+    };
+
+    use super::{read_bundle, MigrationRegistry, Upgrade, UpgradeScheduler};
+
+    fn upgrade(block_height: i64, network_version: NetworkVersion) -> Upgrade<MemoryBlockstore> {
+        Upgrade {
+            block_height,
+            network_version,
+            bundle: None,
+            migration: None,
+        }
+    }
+
+    /// Deposit a token in the burnt funds actor, so we can tell the migration ran, and only once.
+    fn mark_migrated(state: &mut FvmExecState<MemoryBlockstore>) -> anyhow::Result<()> {
+        let state_tree = state.state_tree_mut();
+        let mut actor = state_tree
+            .get_actor(BURNT_FUNDS_ACTOR_ID)?
+            .ok_or_else(|| anyhow!("burnt funds actor not found"))?;
+        actor.deposit_funds(&TokenAmount::from_atto(1));
+        state_tree.set_actor(BURNT_FUNDS_ACTOR_ID, actor);
+        Ok(())
+    }
+
+    /// Execute an empty block the same way the application does, applying any scheduled upgrade.
+    async fn execute_block(
+        scheduler: &UpgradeScheduler<MemoryBlockstore>,
+        store: &MemoryBlockstore,
+        multi_engine: &MultiEngine,
+        height: i64,
+        mut state_params: FvmStateParams,
+    ) -> FvmStateParams {
+        let upgrade = scheduler.get(height);
+
+        if let Some(upgrade) = upgrade {
+            state_params = upgrade
+                .apply(store, state_params)
+                .await
+                .expect("failed to apply upgrade");
+        }
+
+        let mut state =
+            FvmExecState::new(store.clone(), multi_engine, height, state_params.clone())
+                .expect("failed to create state");
+
+        if let Some(upgrade) = upgrade {
+            upgrade.migrate(&mut state).expect("failed to migrate");
+        }
+
+        let interpreter = FvmMessageInterpreter::<MemoryBlockstore>::new();
+        let (state, _) = interpreter.begin(state).await.expect("failed to begin");
+        let (state, _) = interpreter.end(state).await.expect("failed to end");
+
+        state_params.network_version = state.network_version();
+        state_params.state_root = state.commit().expect("failed to commit");
+        state_params
+    }
+
+    fn burnt_funds(store: &MemoryBlockstore, state_params: &FvmStateParams) -> TokenAmount {
+        let state_tree = StateTree::new_from_root(store.clone(), &state_params.state_root)
+            .expect("failed to load state tree");
+        state_tree
+            .get_actor(BURNT_FUNDS_ACTOR_ID)
+            .expect("failed to get actor")
+            .expect("burnt funds actor not found")
+            .balance
+    }
+
+    #[test]
+    fn network_version_increases_with_height() {
+        let mut scheduler = UpgradeScheduler::new();
+        scheduler.add(upgrade(100, NetworkVersion::V18)).unwrap();
+        scheduler.add(upgrade(200, NetworkVersion::V20)).unwrap();
+        scheduler.add(upgrade(150, NetworkVersion::V19)).unwrap();
+
+        assert!(scheduler.add(upgrade(100, NetworkVersion::V20)).is_err());
+        assert!(scheduler.add(upgrade(120, NetworkVersion::V18)).is_err());
+        assert!(scheduler.add(upgrade(120, NetworkVersion::V19)).is_err());
+        assert!(scheduler.add(upgrade(300, NetworkVersion::V20)).is_err());
+
+        assert_eq!(
+            scheduler.get(150).map(|u| u.network_version),
+            Some(NetworkVersion::V19)
+        );
+        assert!(scheduler.get(151).is_none());
+    }
+
+    #[test]
+    fn migrations_registered_once_per_version() {
+        let mut registry = MigrationRegistry::<MemoryBlockstore>::new();
+        registry
+            .register(NetworkVersion::V19, mark_migrated)
+            .unwrap();
+
+        assert!(registry
+            .register(NetworkVersion::V19, mark_migrated)
+            .is_err());
+        assert!(registry.get(NetworkVersion::V19).is_some());
+        assert!(registry.get(NetworkVersion::V20).is_none());
+    }
+
+    #[tokio::test]
+    async fn execute_across_upgrade_height() {
+        let mut g = quickcheck::Gen::new(5);
+        let genesis = Genesis::arbitrary(&mut g);
+        let store = MemoryBlockstore::new();
+        let mut state_params = genesis_state(store.clone(), genesis).await;
+
+        let genesis_nv = state_params.network_version;
+        let upgrade_nv = NetworkVersion::new(u32::from(genesis_nv) + 1);
+
+        let mut registry = MigrationRegistry::new();
+        registry.register(upgrade_nv, mark_migrated).unwrap();
+
+        // Upgrade to the same bundle, which is enough to see it reloaded.
+        let bundle = read_bundle(&bundle_path())
+            .await
+            .expect("failed to read bundle");
+
+        let mut scheduler = UpgradeScheduler::new();
+        scheduler
+            .add(Upgrade {
+                block_height: 2,
+                network_version: upgrade_nv,
+                bundle: Some(std::sync::Arc::new(bundle)),
+                migration: registry.get(upgrade_nv),
+            })
+            .unwrap();
+
+        let multi_engine = MultiEngine::new(1);
+        let burnt_before = burnt_funds(&store, &state_params);
+
+        for height in 1..=3 {
+            state_params =
+                execute_block(&scheduler, &store, &multi_engine, height, state_params).await;
+
+            let migrated = burnt_funds(&store, &state_params) - &burnt_before;
+
+            if height < 2 {
+                assert_eq!(state_params.network_version, genesis_nv);
+                assert_eq!(migrated, TokenAmount::from_atto(0));
+            } else {
+                assert_eq!(state_params.network_version, upgrade_nv);
+                assert_eq!(migrated, TokenAmount::from_atto(1));
+            }
+        }
+
+        // Upgrading the same way again would go backwards.
+        assert!(scheduler
+            .get(2)
+            .unwrap()
+            .apply(&store, state_params)
+            .await
+            .is_err());
+    }
+}
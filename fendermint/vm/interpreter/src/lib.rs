@@ -102,6 +102,30 @@ pub trait CheckInterpreter: Sync + Send {
     ) -> anyhow::Result<(Self::State, Self::Output)>;
 }
 
+/// Prepare and process block proposals.
+///
+/// The validator whose turn it is to propose a block gets to select and order
+/// the transactions from its mempool, while the others get to inspect the
+/// proposal before voting on it, so that a Byzantine proposer cannot get
+/// messages into a block which would fail to be delivered.
+#[async_trait]
+pub trait ProposalInterpreter: Sync + Send {
+    type State: Send;
+    type Message: Send;
+
+    /// Called when we are the proposer, to select the messages to put in the block, in order.
+    ///
+    /// Messages which cannot be included are dropped.
+    async fn prepare(
+        &self,
+        state: Self::State,
+        msgs: Vec<Self::Message>,
+    ) -> anyhow::Result<Vec<Self::Message>>;
+
+    /// Called when another validator proposed a block, to decide whether to accept it.
+    async fn process(&self, state: Self::State, msgs: Vec<Self::Message>) -> anyhow::Result<bool>;
+}
+
 /// Run a query over the ledger.
 #[async_trait]
 pub trait QueryInterpreter: Sync + Send {
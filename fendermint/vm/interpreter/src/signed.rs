@@ -1,20 +1,30 @@
 // Copyright 2022-2023 Protocol Labs
 // SPDX-License-Identifier: Apache-2.0, MIT
+use std::collections::{HashMap, HashSet};
+
 use anyhow::anyhow;
 use async_trait::async_trait;
 
 use fendermint_vm_core::chainid::HasChainID;
 use fendermint_vm_message::signed::{SignedMessage, SignedMessageError};
+use fvm_shared::{address::Address, chainid::ChainID, BLOCK_GAS_LIMIT};
 
 use crate::{
     fvm::{FvmApplyRet, FvmCheckRet, FvmMessage},
-    CheckInterpreter, ExecInterpreter, GenesisInterpreter, QueryInterpreter,
+    CheckInterpreter, ExecInterpreter, GenesisInterpreter, ProposalInterpreter, QueryInterpreter,
 };
 
 /// Message validation failed due to an invalid signature.
 pub struct InvalidSignature(pub String);
 
-pub type SignedMessageApplyRet = Result<FvmApplyRet, InvalidSignature>;
+/// Outcome of executing a message with a valid signature.
+pub struct SignedApplyRet {
+    pub ret: FvmApplyRet,
+    /// Hash of the Ethereum transaction the message was created from, if it was.
+    pub eth_hash: Option<[u8; 32]>,
+}
+
+pub type SignedMessageApplyRet = Result<SignedApplyRet, InvalidSignature>;
 pub type SignedMessageCheckRet = Result<FvmCheckRet, InvalidSignature>;
 
 /// Interpreter working on signed messages, validating their signature before sending
@@ -55,8 +65,10 @@ where
                 Ok((state, Err(InvalidSignature(s))))
             }
             Ok(()) => {
+                // Computed here, where the message is already decoded, so the hash can be indexed.
+                let eth_hash = msg.eth_tx_hash(chain_id).ok().flatten().map(|h| h.0);
                 let (state, ret) = self.inner.deliver(state, msg.message).await?;
-                Ok((state, Ok(ret)))
+                Ok((state, Ok(SignedApplyRet { ret, eth_hash })))
             }
         }
     }
@@ -107,6 +119,104 @@ where
     }
 }
 
+/// Proposals are not passed on to the inner interpreter: the signatures and gas limits
+/// can be checked here, while nonces and balances are only checked during execution.
+#[async_trait]
+impl<I> ProposalInterpreter for SignedMessageInterpreter<I>
+where
+    I: Sync + Send,
+{
+    type State = ChainID;
+    type Message = SignedMessage;
+
+    async fn prepare(
+        &self,
+        state: Self::State,
+        msgs: Vec<Self::Message>,
+    ) -> anyhow::Result<Vec<Self::Message>> {
+        let msgs = msgs
+            .into_iter()
+            .filter(|msg| msg.verify(&state).is_ok())
+            .collect();
+
+        let msgs = order_by_premium(msgs);
+        let msgs = select_within_gas_limit(msgs, BLOCK_GAS_LIMIT);
+
+        Ok(msgs)
+    }
+
+    async fn process(&self, state: Self::State, msgs: Vec<Self::Message>) -> anyhow::Result<bool> {
+        let mut total_gas_limit: u64 = 0;
+
+        for msg in msgs {
+            match msg.verify(&state) {
+                Err(SignedMessageError::Ipld(e)) => return Err(anyhow!(e)),
+                Err(SignedMessageError::InvalidSignature(_)) => return Ok(false),
+                Ok(()) => {}
+            }
+            total_gas_limit = total_gas_limit.saturating_add(msg.message.gas_limit);
+        }
+
+        Ok(total_gas_limit <= BLOCK_GAS_LIMIT)
+    }
+}
+
+/// Order messages by descending gas premium, while keeping the messages of each sender in nonce order.
+///
+/// The messages of a sender take up the positions their premiums earned them, in ascending nonce order.
+fn order_by_premium(mut msgs: Vec<SignedMessage>) -> Vec<SignedMessage> {
+    msgs.sort_by(|a, b| b.message.gas_premium.cmp(&a.message.gas_premium));
+
+    let mut positions: HashMap<Address, Vec<usize>> = HashMap::new();
+    for (i, msg) in msgs.iter().enumerate() {
+        positions.entry(msg.message.from).or_default().push(i);
+    }
+
+    let mut slots = msgs.into_iter().map(Some).collect::<Vec<_>>();
+
+    for positions in positions.values().filter(|ps| ps.len() > 1) {
+        let mut sender_msgs = positions
+            .iter()
+            .map(|i| slots[*i].take().expect("each slot is taken once"))
+            .collect::<Vec<_>>();
+
+        sender_msgs.sort_by_key(|msg| msg.message.sequence);
+
+        for (i, msg) in positions.iter().zip(sender_msgs) {
+            slots[*i] = Some(msg);
+        }
+    }
+
+    slots.into_iter().flatten().collect()
+}
+
+/// Take messages in order as long as their total gas limit fits into the block, skipping the rest.
+///
+/// Once a message is skipped, the later messages of the same sender are skipped as well,
+/// because they would fail on their nonce.
+fn select_within_gas_limit(msgs: Vec<SignedMessage>, block_gas_limit: u64) -> Vec<SignedMessage> {
+    let mut total_gas_limit: u64 = 0;
+    let mut skipped = HashSet::new();
+    let mut selected = Vec::new();
+
+    for msg in msgs {
+        if skipped.contains(&msg.message.from) {
+            continue;
+        }
+        match total_gas_limit.checked_add(msg.message.gas_limit) {
+            Some(total) if total <= block_gas_limit => {
+                total_gas_limit = total;
+                selected.push(msg);
+            }
+            _ => {
+                skipped.insert(msg.message.from);
+            }
+        }
+    }
+
+    selected
+}
+
 #[async_trait]
 impl<I> QueryInterpreter for SignedMessageInterpreter<I>
 where
@@ -142,3 +252,61 @@ where
         self.inner.init(state, genesis).await
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use fendermint_vm_message::signed::SignedMessage;
+    use fvm_shared::{address::Address, crypto::signature::Signature, econ::TokenAmount};
+
+    use crate::fvm::FvmMessage;
+
+    use super::{order_by_premium, select_within_gas_limit};
+
+    fn msg(from: u64, sequence: u64, gas_premium: u64, gas_limit: u64) -> SignedMessage {
+        SignedMessage {
+            message: FvmMessage {
+                version: Default::default(),
+                from: Address::new_id(from),
+                to: Address::new_id(1),
+                sequence,
+                value: Default::default(),
+                method_num: 0,
+                params: Default::default(),
+                gas_limit,
+                gas_fee_cap: TokenAmount::from_atto(gas_premium),
+                gas_premium: TokenAmount::from_atto(gas_premium),
+            },
+            signature: Signature::new_secp256k1(Vec::new()).into(),
+        }
+    }
+
+    fn summary(msgs: &[SignedMessage]) -> Vec<(u64, u64)> {
+        msgs.iter()
+            .map(|m| (m.message.from.id().unwrap(), m.message.sequence))
+            .collect()
+    }
+
+    #[test]
+    fn order_by_premium_keeps_nonce_order() {
+        let msgs = vec![
+            msg(100, 0, 1, 10),
+            msg(100, 1, 5, 10),
+            msg(101, 0, 3, 10),
+            msg(102, 0, 4, 10),
+        ];
+        let msgs = order_by_premium(msgs);
+        assert_eq!(summary(&msgs), vec![(100, 0), (102, 0), (101, 0), (100, 1)]);
+    }
+
+    #[test]
+    fn select_skips_rest_of_sender() {
+        let msgs = vec![
+            msg(100, 0, 1, 50),
+            msg(101, 0, 1, 60),
+            msg(101, 1, 1, 10),
+            msg(102, 0, 1, 40),
+        ];
+        let msgs = select_within_gas_limit(msgs, 100);
+        assert_eq!(summary(&msgs), vec![(100, 0), (102, 0)]);
+    }
+}
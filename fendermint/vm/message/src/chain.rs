@@ -1,6 +1,7 @@
 // Copyright 2022-2023 Protocol Labs
 // SPDX-License-Identifier: Apache-2.0, MIT
 use cid::Cid;
+use fvm_ipld_encoding::RawBytes;
 use serde::{Deserialize, Serialize};
 
 use crate::signed::SignedMessage;
@@ -16,26 +17,33 @@ use crate::signed::SignedMessage;
 pub enum ChainMessage {
     /// A message that can be passed on to the FVM as-is.
     Signed(Box<SignedMessage>),
-    // TODO: ForResolution - A message CID proposed for async resolution.
-    // This will not need a signature, it is proposed by the validator who made the block.
-    // We might want to add a `from` and a signature anyway if we want to reward relayers.
-    // Or the validator itself can be rewarded for inclusion, since a message like this
-    // will be a top-down or bottom-up message, and this incentivises them to do the relaying.
+    /// A message CID proposed for async resolution.
+    ///
+    /// This does not need a signature, it is proposed by the validator who made the block.
+    /// The CID is recorded on the ledger along with the proposer, so that they can be rewarded
+    /// when the message is eventually executed, which incentivises them to do the relaying of
+    /// top-down and bottom-up messages.
     ForResolution(Cid),
 
-    // TODO: ForExecution - A message CID proposed for execution in the containing block, assumed to be resolvable.
-    // This will again not need a signature, it is proposed by the validator who made the block.
-    // The reward for this should have two parts:
-    // (1) go to the validator who originally proposed the resolution of this CID, and
-    // (2) go to the validator who proposed the execution.
-    // This should ensure that even if low-power validator poposed a CID, the others aren't neglecting it.
-    // To remember after a restart who the original proposer was, the proposed CIDs have to go onto the ledger.
-    ForExecution(Cid),
+    /// A message CID proposed for execution in the containing block, along with the content
+    /// the proposer resolved it to.
+    ///
+    /// This again does not need a signature, it is proposed by the validator who made the block.
+    /// The CID must have been proposed for resolution in an earlier block, and not executed yet.
+    /// The content is part of the block, so that every node can execute it without having to
+    /// resolve the CID itself, e.g. while catching up with the chain.
+    ///
+    /// TODO: The reward for this should have two parts:
+    /// (1) go to the validator who originally proposed the resolution of this CID, and
+    /// (2) go to the validator who proposed the execution.
+    /// This should ensure that even if low-power validator poposed a CID, the others aren't neglecting it.
+    ForExecution(Cid, RawBytes),
 }
 
 #[cfg(feature = "arb")]
 mod arb {
     use fendermint_testing::arb::ArbCid;
+    use fvm_ipld_encoding::RawBytes;
 
     use super::ChainMessage;
     use crate::signed::SignedMessage;
@@ -45,7 +53,10 @@ mod arb {
             match u8::arbitrary(g) % 3 {
                 0 => ChainMessage::Signed(Box::new(SignedMessage::arbitrary(g))),
                 1 => ChainMessage::ForResolution(ArbCid::arbitrary(g).0),
-                _ => ChainMessage::ForExecution(ArbCid::arbitrary(g).0),
+                _ => ChainMessage::ForExecution(
+                    ArbCid::arbitrary(g).0,
+                    RawBytes::new(Vec::arbitrary(g)),
+                ),
             }
         }
     }
@@ -0,0 +1,140 @@
+// Copyright 2022-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+//! Turn Ethereum transactions into FVM messages.
+
+use anyhow::{anyhow, bail};
+use ethers_core::types as et;
+use ethers_core::types::transaction::eip2718::TypedTransaction;
+use fendermint_vm_actor_interface::{eam, evm, system};
+use fvm_ipld_encoding::{BytesSer, RawBytes};
+use fvm_shared::{
+    address::Address,
+    bigint::{BigInt, Sign},
+    econ::TokenAmount,
+    message::Message,
+    BLOCK_GAS_LIMIT, METHOD_SEND,
+};
+
+use super::to_fvm_address;
+
+/// Turn a legacy or EIP-1559 transaction into an FVM message.
+///
+/// The sender has to be filled in, which is the case for transactions decoded
+/// with `TypedTransaction::decode_signed`, which recovers it from the signature.
+///
+/// * Contract creation becomes a `CreateExternal` call to the EAM.
+/// * A transaction without data becomes a plain transfer.
+/// * Everything else becomes an `InvokeContract` call to the EVM actor.
+pub fn to_fvm_message(tx: &TypedTransaction) -> anyhow::Result<Message> {
+    let (gas_fee_cap, gas_premium) = match tx {
+        TypedTransaction::Legacy(tx) => {
+            if tx.chain_id.is_none() {
+                bail!("legacy transactions must have replay protection (EIP-155)");
+            }
+            let gas_price = tx.gas_price.unwrap_or_default();
+            (gas_price, gas_price)
+        }
+        TypedTransaction::Eip1559(tx) => {
+            if !tx.access_list.0.is_empty() {
+                bail!("access lists are not supported");
+            }
+            (
+                tx.max_fee_per_gas.unwrap_or_default(),
+                tx.max_priority_fee_per_gas.unwrap_or_default(),
+            )
+        }
+        TypedTransaction::Eip2930(_) => bail!("EIP-2930 transactions are not supported"),
+    };
+
+    let from = tx
+        .from()
+        .ok_or_else(|| anyhow!("the transaction sender is unknown"))?;
+
+    let data = tx.data().map(|d| d.to_vec()).unwrap_or_default();
+    let (to, method_num, params) = to_fvm_target(tx.to(), &data)?;
+
+    let msg = Message {
+        version: 0,
+        from: to_fvm_address(*from),
+        to,
+        sequence: to_u64(tx.nonce().cloned().unwrap_or_default(), "nonce")?,
+        value: to_token_amount(tx.value().cloned().unwrap_or_default()),
+        method_num,
+        params,
+        gas_limit: to_u64(tx.gas().cloned().unwrap_or_default(), "gas limit")?,
+        gas_fee_cap: to_token_amount(gas_fee_cap),
+        gas_premium: to_token_amount(gas_premium),
+    };
+
+    Ok(msg)
+}
+
+/// Turn the request of `eth_call` or `eth_estimateGas` into an FVM message to be executed
+/// without being included in a block.
+///
+/// The sender defaults to the system actor, which, like any other sender that isn't an account,
+/// gets the call executed implicitly; the gas limit defaults to the block gas limit, and a zero
+/// nonce means whatever the sender's current sequence is.
+pub fn to_fvm_call_message(tx: &et::TransactionRequest) -> anyhow::Result<Message> {
+    let data = tx.data.as_ref().map(|d| d.to_vec()).unwrap_or_default();
+    let (to, method_num, params) = to_fvm_target(tx.to.as_ref(), &data)?;
+
+    let msg = Message {
+        version: 0,
+        from: tx
+            .from
+            .map(to_fvm_address)
+            .unwrap_or(system::SYSTEM_ACTOR_ADDR),
+        to,
+        sequence: to_u64(tx.nonce.unwrap_or_default(), "nonce")?,
+        value: to_token_amount(tx.value.unwrap_or_default()),
+        method_num,
+        params,
+        gas_limit: match tx.gas {
+            Some(gas) => to_u64(gas, "gas limit")?,
+            None => BLOCK_GAS_LIMIT,
+        },
+        gas_fee_cap: to_token_amount(tx.gas_price.unwrap_or_default()),
+        gas_premium: TokenAmount::default(),
+    };
+
+    Ok(msg)
+}
+
+/// The recipient, method and parameters of a message based on the target and the data of a transaction.
+fn to_fvm_target(
+    to: Option<&et::NameOrAddress>,
+    data: &[u8],
+) -> anyhow::Result<(Address, u64, RawBytes)> {
+    let target = match to {
+        None => (
+            eam::EAM_ACTOR_ADDR,
+            eam::Method::CreateExternal as u64,
+            RawBytes::serialize(BytesSer(data))?,
+        ),
+        Some(et::NameOrAddress::Address(to)) if data.is_empty() => {
+            (to_fvm_address(*to), METHOD_SEND, RawBytes::default())
+        }
+        Some(et::NameOrAddress::Address(to)) => (
+            to_fvm_address(*to),
+            evm::Method::InvokeContract as u64,
+            RawBytes::serialize(BytesSer(data))?,
+        ),
+        Some(et::NameOrAddress::Name(name)) => bail!("ENS names are not supported: {name}"),
+    };
+    Ok(target)
+}
+
+/// Amounts in Ethereum are in wei, which is the same as atto in Filecoin.
+pub fn to_token_amount(value: et::U256) -> TokenAmount {
+    let mut bz = [0u8; 32];
+    value.to_big_endian(&mut bz);
+    TokenAmount::from_atto(BigInt::from_bytes_be(Sign::Plus, &bz))
+}
+
+fn to_u64(value: et::U256, what: &str) -> anyhow::Result<u64> {
+    if value > et::U256::from(u64::MAX) {
+        bail!("the {what} does not fit into 64 bits");
+    }
+    Ok(value.as_u64())
+}
@@ -0,0 +1,93 @@
+// Copyright 2022-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+//! Turn FVM messages back into the Ethereum transactions they were created from.
+
+use anyhow::{anyhow, bail};
+use ethers_core::types as et;
+use ethers_core::types::transaction::eip2718::TypedTransaction;
+use fendermint_vm_actor_interface::{eam, evm};
+use fvm_ipld_encoding::BytesDe;
+use fvm_shared::{chainid::ChainID, econ::TokenAmount, message::Message, METHOD_SEND};
+
+use crate::signed::EthTransactionType;
+
+use super::to_eth_address;
+
+/// Reconstruct the Ethereum transaction an FVM message was created from by [`super::from_eth::to_fvm_message`].
+///
+/// This is what an Ethereum signature needs to be checked against.
+pub fn to_eth_transaction(
+    msg: &Message,
+    tx_type: EthTransactionType,
+    chain_id: &ChainID,
+) -> anyhow::Result<TypedTransaction> {
+    let from = to_eth_address(&msg.from)
+        .ok_or_else(|| anyhow!("the sender doesn't have an Ethereum address: {}", msg.from))?;
+
+    let (to, data) = if msg.to == eam::EAM_ACTOR_ADDR
+        && msg.method_num == eam::Method::CreateExternal as u64
+    {
+        (None, params_to_data(msg)?)
+    } else {
+        let to = to_eth_address(&msg.to)
+            .ok_or_else(|| anyhow!("the recipient doesn't have an Ethereum address: {}", msg.to))?;
+        let data = if msg.method_num == METHOD_SEND {
+            if !msg.params.is_empty() {
+                bail!("plain transfers have no parameters");
+            }
+            Vec::new()
+        } else if msg.method_num == evm::Method::InvokeContract as u64 {
+            params_to_data(msg)?
+        } else {
+            bail!("method {} has no Ethereum equivalent", msg.method_num);
+        };
+        (Some(et::NameOrAddress::Address(to)), data)
+    };
+
+    let chain_id = et::U64::from(u64::from(*chain_id));
+
+    let tx = match tx_type {
+        EthTransactionType::Legacy => TypedTransaction::Legacy(et::TransactionRequest {
+            from: Some(from),
+            to,
+            gas: Some(msg.gas_limit.into()),
+            gas_price: Some(to_u256(&msg.gas_fee_cap)?),
+            value: Some(to_u256(&msg.value)?),
+            data: Some(data.into()),
+            nonce: Some(msg.sequence.into()),
+            chain_id: Some(chain_id),
+        }),
+        EthTransactionType::Eip1559 => TypedTransaction::Eip1559(et::Eip1559TransactionRequest {
+            from: Some(from),
+            to,
+            gas: Some(msg.gas_limit.into()),
+            value: Some(to_u256(&msg.value)?),
+            data: Some(data.into()),
+            nonce: Some(msg.sequence.into()),
+            access_list: Default::default(),
+            max_priority_fee_per_gas: Some(to_u256(&msg.gas_premium)?),
+            max_fee_per_gas: Some(to_u256(&msg.gas_fee_cap)?),
+            chain_id: Some(chain_id),
+        }),
+    };
+
+    Ok(tx)
+}
+
+/// Amounts in Ethereum are in wei, which is the same as atto in Filecoin.
+pub fn to_u256(amount: &TokenAmount) -> anyhow::Result<et::U256> {
+    let (sign, bz) = amount.atto().to_bytes_be();
+    if sign == fvm_shared::bigint::Sign::Minus {
+        bail!("negative amounts have no Ethereum equivalent");
+    }
+    if bz.len() > 32 {
+        bail!("the amount does not fit into 256 bits");
+    }
+    Ok(et::U256::from_big_endian(&bz))
+}
+
+/// The calldata or the initcode is CBOR encoded bytes in the parameters.
+fn params_to_data(msg: &Message) -> anyhow::Result<Vec<u8>> {
+    let BytesDe(data) = msg.params.deserialize()?;
+    Ok(data)
+}
@@ -0,0 +1,62 @@
+// Copyright 2022-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+//! Conversions between Ethereum transactions and FVM messages.
+
+use ethers_core::types as et;
+use fendermint_vm_actor_interface::eam::{EthAddress, EAM_ACTOR_ID};
+use fvm_shared::address::{Address, Payload};
+
+pub mod from_eth;
+pub mod from_fvm;
+
+/// Turn an Ethereum address into an FVM one.
+///
+/// Masked ID addresses, ie. `0xff` followed by 11 zeroes and the big-endian actor ID,
+/// become ID addresses; everything else is a delegated address in the EAM namespace.
+pub fn to_fvm_address(addr: et::H160) -> Address {
+    let bz = addr.as_bytes();
+    if bz[0] == 0xff && bz[1..12].iter().all(|b| *b == 0) {
+        let id = u64::from_be_bytes(bz[12..].try_into().expect("8 bytes remain"));
+        Address::new_id(id)
+    } else {
+        Address::new_delegated(EAM_ACTOR_ID, bz).expect("20 bytes fit into a delegated address")
+    }
+}
+
+/// Turn an FVM address into an Ethereum one, if it has an Ethereum form.
+///
+/// This is the case for ID addresses and the delegated addresses in the EAM namespace.
+pub fn to_eth_address(addr: &Address) -> Option<et::H160> {
+    match addr.payload() {
+        Payload::ID(id) => Some(et::H160::from(EthAddress::from_id(*id).0)),
+        Payload::Delegated(d) if d.namespace() == EAM_ACTOR_ID && d.subaddress().len() == 20 => {
+            Some(et::H160::from_slice(d.subaddress()))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers_core::types as et;
+    use fvm_shared::address::Address;
+    use quickcheck_macros::quickcheck;
+
+    use super::{to_eth_address, to_fvm_address};
+
+    #[quickcheck]
+    fn prop_eth_address_roundtrip(bz: Vec<u8>) -> bool {
+        let mut bytes = [0u8; 20];
+        for (i, b) in bz.into_iter().take(20).enumerate() {
+            bytes[i] = b;
+        }
+        let addr = et::H160::from(bytes);
+        to_eth_address(&to_fvm_address(addr)) == Some(addr)
+    }
+
+    #[quickcheck]
+    fn prop_id_address_roundtrip(id: u64) -> bool {
+        let addr = Address::new_id(id);
+        to_eth_address(&addr).map(to_fvm_address) == Some(addr)
+    }
+}
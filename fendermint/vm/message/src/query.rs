@@ -1,9 +1,10 @@
 // Copyright 2022-2023 Protocol Labs
 // SPDX-License-Identifier: Apache-2.0, MIT
 use cid::Cid;
+use fvm_ipld_encoding::RawBytes;
 use fvm_shared::{
     address::Address, econ::TokenAmount, error::ExitCode, message::Message as FvmMessage,
-    version::NetworkVersion,
+    version::NetworkVersion, ActorID, MethodNum,
 };
 use serde::{Deserialize, Serialize};
 use serde_with::serde_as;
@@ -35,6 +36,26 @@ pub enum FvmQuery {
     EstimateGas(Box<FvmMessage>),
     /// Retrieve the slowly changing state parameters that aren't part of the state tree.
     StateParams,
+    /// Retrieve the current validator set with their power.
+    ///
+    /// The response is IPLD encoded `Vec<Validator>`.
+    Validators,
+    /// Execute messages one after the other with execution tracing enabled,
+    /// without adding them to the blockchain.
+    ///
+    /// The preceding messages are there to recreate the state in which a historical
+    /// message was executed, by replaying the messages before it in its block.
+    ///
+    /// The response is IPLD encoded `TraceResult` of the last message.
+    Trace(Vec<FvmMessage>),
+    /// Re-execute the transaction at the given index in the block at the query height
+    /// with execution tracing enabled, on top of the state the block was executed on,
+    /// after everything that was executed before it in the block.
+    ///
+    /// Only available in archive mode, where the blocks are kept to be replayed.
+    ///
+    /// The response is IPLD encoded `TraceResult`.
+    TraceTx(u64),
 }
 
 /// State of all actor implementations.
@@ -77,13 +98,74 @@ pub struct GasEstimate {
     pub gas_limit: u64,
 }
 
+/// Result of executing a message with tracing enabled.
+#[serde_as]
+#[derive(PartialEq, Eq, Clone, Debug, Serialize, Deserialize)]
+pub struct TraceResult {
+    /// Exit code of the message.
+    pub exit_code: ExitCode,
+    /// Gas used by the message.
+    pub gas_used: u64,
+    /// Return value of the message.
+    pub return_data: RawBytes,
+    /// Reason the message failed, if it did.
+    pub failure_info: Option<String>,
+    /// The tree of calls, unless the message failed before the recipient was called.
+    pub call: Option<CallTrace>,
+}
+
+/// An actor calling a method of another actor, along with everything happening during the call.
+#[serde_as]
+#[derive(PartialEq, Eq, Clone, Debug, Serialize, Deserialize)]
+pub struct CallTrace {
+    /// ID of the calling actor.
+    pub from: ActorID,
+    /// Address of the called actor, the way the caller referred to it.
+    #[serde_as(as = "IsHumanReadable")]
+    pub to: Address,
+    pub method: MethodNum,
+    pub params: Option<TraceBlock>,
+    /// Tokens sent along with the call.
+    #[serde_as(as = "IsHumanReadable")]
+    pub value: TokenAmount,
+    /// Exit code of the call; missing if it never returned, e.g. it ran out of gas, or if it failed with an error.
+    pub exit_code: Option<ExitCode>,
+    pub return_data: Option<TraceBlock>,
+    /// Syscall error which stopped the call, e.g. because the recipient didn't exist.
+    pub error: Option<String>,
+    /// Gas charged during the call, excluding the charges of the nested calls.
+    pub gas_charges: Vec<GasChargeTrace>,
+    /// Calls made during this call, in order.
+    pub calls: Vec<CallTrace>,
+}
+
+/// Parameters or return value of a call, as an IPLD block.
+#[derive(PartialEq, Eq, Clone, Debug, Serialize, Deserialize)]
+pub struct TraceBlock {
+    /// IPLD codec of the data, e.g. DAG-CBOR.
+    pub codec: u64,
+    pub data: RawBytes,
+}
+
+/// A gas charge during the execution of a call.
+#[derive(PartialEq, Eq, Clone, Debug, Serialize, Deserialize)]
+pub struct GasChargeTrace {
+    /// Name of the operation the gas was charged for.
+    pub name: String,
+    /// Gas charged for immediate computation, in milligas.
+    pub compute_milligas: u64,
+    /// Gas charged for storage and deferred computation, in milligas.
+    pub other_milligas: u64,
+}
+
 /// Slowly changing state parameters outside the state tree.
 #[serde_as]
 #[derive(PartialEq, Eq, Clone, Debug, Serialize, Deserialize)]
 pub struct StateParams {
     /// Base fee.
     ///
-    /// Its evolution can depend on the size of blocks, contention, etc.
+    /// If enabled at genesis, it evolves after each block depending on how much gas it used,
+    /// relative to a target.
     #[serde_as(as = "IsHumanReadable")]
     pub base_fee: TokenAmount,
     /// Circulating supply.
@@ -109,12 +191,15 @@ mod arb {
 
     impl quickcheck::Arbitrary for FvmQuery {
         fn arbitrary(g: &mut quickcheck::Gen) -> Self {
-            match u8::arbitrary(g) % 5 {
+            match u8::arbitrary(g) % 8 {
                 0 => FvmQuery::Ipld(ArbCid::arbitrary(g).0),
                 1 => FvmQuery::ActorState(ArbAddress::arbitrary(g).0),
                 2 => FvmQuery::Call(Box::new(SignedMessage::arbitrary(g).into_message())),
                 3 => FvmQuery::EstimateGas(Box::new(SignedMessage::arbitrary(g).into_message())),
-                _ => FvmQuery::StateParams,
+                4 => FvmQuery::StateParams,
+                5 => FvmQuery::Trace(vec![SignedMessage::arbitrary(g).into_message()]),
+                6 => FvmQuery::TraceTx(u64::arbitrary(g)),
+                _ => FvmQuery::Validators,
             }
         }
     }
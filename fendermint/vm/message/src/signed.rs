@@ -2,14 +2,22 @@
 // Copyright 2019-2022 ChainSafe Systems
 // SPDX-License-Identifier: Apache-2.0, MIT
 
+use std::borrow::Cow;
+
 use cid::Cid;
+use ethers_core::types as et;
+use ethers_core::types::transaction::eip2718::TypedTransaction;
 use fvm_ipld_encoding::tuple::{Deserialize_tuple, Serialize_tuple};
+use fvm_ipld_encoding::{de, ser, strict_bytes};
 use fvm_shared::chainid::ChainID;
 use fvm_shared::crypto::signature::{Signature, SignatureType};
 use fvm_shared::message::Message;
+use num_traits::FromPrimitive;
 
 use thiserror::Error;
 
+use crate::conv::{from_fvm::to_eth_transaction, to_fvm_address};
+
 #[derive(Error, Debug)]
 pub enum SignedMessageError {
     #[error("message cannot be serialized")]
@@ -18,6 +26,148 @@ pub enum SignedMessageError {
     InvalidSignature(String),
 }
 
+/// Signature type byte of Ethereum signatures, the same as the _delegated_ signature type in
+/// [FIP-0055](https://github.com/filecoin-project/FIPs/blob/master/FIPS/fip-0055.md).
+const ETH_SIGNATURE_TYPE: u8 = 3;
+
+/// The kinds of Ethereum transactions we accept, which determines what their signature is over.
+///
+/// The discriminants are the EIP-2718 transaction types.
+#[derive(PartialEq, Clone, Copy, Debug, Hash, Eq)]
+#[repr(u8)]
+pub enum EthTransactionType {
+    Legacy = 0,
+    Eip1559 = 2,
+}
+
+/// Signature over the keccak256 hash of the RLP encoding of an Ethereum transaction.
+///
+/// The transaction itself can be reconstructed from the FVM message it was turned into.
+#[derive(PartialEq, Clone, Copy, Debug, Hash, Eq)]
+pub struct EthSignature {
+    pub tx_type: EthTransactionType,
+    pub r: et::U256,
+    pub s: et::U256,
+    /// Parity of the y coordinate of the curve point, either 0 or 1.
+    pub y_parity: u8,
+}
+
+impl EthSignature {
+    /// Normalize the `v` value, which legacy transactions combine with the chain ID as per EIP-155.
+    pub fn new(tx_type: EthTransactionType, signature: &et::Signature) -> Self {
+        let y_parity = if signature.v <= 1 {
+            signature.v
+        } else {
+            (signature.v + 1) % 2
+        };
+        Self {
+            tx_type,
+            r: signature.r,
+            s: signature.s,
+            y_parity: y_parity as u8,
+        }
+    }
+
+    /// The signature with the `v` value as it appears in the RLP encoding of the transaction.
+    pub fn to_eth_signature(&self, chain_id: &ChainID) -> et::Signature {
+        let v = match self.tx_type {
+            EthTransactionType::Legacy => 35 + 2 * u64::from(*chain_id) + self.y_parity as u64,
+            EthTransactionType::Eip1559 => self.y_parity as u64,
+        };
+        et::Signature {
+            r: self.r,
+            s: self.s,
+            v,
+        }
+    }
+
+    fn to_bytes(self) -> Vec<u8> {
+        let mut bz = vec![0u8; 67];
+        bz[0] = ETH_SIGNATURE_TYPE;
+        bz[1] = self.tx_type as u8;
+        self.r.to_big_endian(&mut bz[2..34]);
+        self.s.to_big_endian(&mut bz[34..66]);
+        bz[66] = self.y_parity;
+        bz
+    }
+
+    fn from_bytes(bz: &[u8]) -> Result<Self, String> {
+        if bz.len() != 67 {
+            return Err(format!(
+                "unexpected Ethereum signature length: {}",
+                bz.len()
+            ));
+        }
+        let tx_type = match bz[1] {
+            0 => EthTransactionType::Legacy,
+            2 => EthTransactionType::Eip1559,
+            t => return Err(format!("unexpected Ethereum transaction type: {t}")),
+        };
+        if bz[66] > 1 {
+            return Err(format!("unexpected y parity: {}", bz[66]));
+        }
+        Ok(Self {
+            tx_type,
+            r: et::U256::from_big_endian(&bz[2..34]),
+            s: et::U256::from_big_endian(&bz[34..66]),
+            y_parity: bz[66],
+        })
+    }
+}
+
+/// Signature of a message: either one of the signatures the FVM supports,
+/// or the signature of the Ethereum transaction the message was created from.
+///
+/// Serialized as bytes starting with the signature type, the same way as [`Signature`],
+/// so messages with Filecoin signatures look the same as they did before Ethereum
+/// transactions were supported.
+#[derive(PartialEq, Clone, Debug, Hash, Eq)]
+pub enum MessageSignature {
+    Filecoin(Signature),
+    Ethereum(EthSignature),
+}
+
+impl From<Signature> for MessageSignature {
+    fn from(value: Signature) -> Self {
+        Self::Filecoin(value)
+    }
+}
+
+impl ser::Serialize for MessageSignature {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: ser::Serializer,
+    {
+        match self {
+            Self::Filecoin(sig) => sig.serialize(serializer),
+            Self::Ethereum(sig) => strict_bytes::Serialize::serialize(&sig.to_bytes(), serializer),
+        }
+    }
+}
+
+impl<'de> de::Deserialize<'de> for MessageSignature {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        let bz: Cow<'de, [u8]> = strict_bytes::Deserialize::deserialize(deserializer)?;
+        match bz.first() {
+            None => Err(de::Error::custom("cannot deserialize empty bytes")),
+            Some(&ETH_SIGNATURE_TYPE) => EthSignature::from_bytes(&bz)
+                .map(Self::Ethereum)
+                .map_err(de::Error::custom),
+            Some(t) => {
+                let sig_type = SignatureType::from_u8(*t)
+                    .ok_or_else(|| de::Error::custom(format!("invalid signature type: {t}")))?;
+                Ok(Self::Filecoin(Signature {
+                    sig_type,
+                    bytes: bz[1..].to_vec(),
+                }))
+            }
+        }
+    }
+}
+
 /// Represents a wrapped message with signature bytes.
 ///
 /// This is the message that the client needs to send, but only the `message`
@@ -28,14 +178,14 @@ pub enum SignedMessageError {
 #[derive(PartialEq, Clone, Debug, Serialize_tuple, Deserialize_tuple, Hash, Eq)]
 pub struct SignedMessage {
     pub message: Message,
-    pub signature: Signature,
+    pub signature: MessageSignature,
 }
 
 impl SignedMessage {
     /// Generate a new signed message from fields.
     ///
     /// The signature will not be verified.
-    pub fn new_unchecked(message: Message, signature: Signature) -> SignedMessage {
+    pub fn new_unchecked(message: Message, signature: MessageSignature) -> SignedMessage {
         SignedMessage { message, signature }
     }
 
@@ -44,7 +194,7 @@ impl SignedMessage {
     /// The signature will be verified.
     pub fn new_checked(
         message: Message,
-        signature: Signature,
+        signature: MessageSignature,
         chain_id: &ChainID,
     ) -> Result<SignedMessage, SignedMessageError> {
         Self::verify_signature(&message, &signature, chain_id)?;
@@ -63,6 +213,25 @@ impl SignedMessage {
             sig_type: SignatureType::Secp256k1,
             bytes: sign_secp256k1(sk, &data).to_vec(),
         };
+        Ok(Self {
+            message,
+            signature: MessageSignature::Filecoin(signature),
+        })
+    }
+
+    /// Create a message from a signed Ethereum transaction, sent from the delegated address of the signer.
+    ///
+    /// The signature is checked when the message is verified, against the transaction reconstructed from the message.
+    pub fn new_eth(tx: &TypedTransaction, signature: &et::Signature) -> anyhow::Result<Self> {
+        let tx_type = match tx {
+            TypedTransaction::Legacy(_) => EthTransactionType::Legacy,
+            TypedTransaction::Eip1559(_) => EthTransactionType::Eip1559,
+            TypedTransaction::Eip2930(_) => {
+                anyhow::bail!("EIP-2930 transactions are not supported")
+            }
+        };
+        let message = crate::conv::from_eth::to_fvm_message(tx)?;
+        let signature = MessageSignature::Ethereum(EthSignature::new(tx_type, signature));
         Ok(Self { message, signature })
     }
 
@@ -85,16 +254,63 @@ impl SignedMessage {
     }
 
     /// Verify that the message CID was signed by the `from` address.
+    ///
+    /// Ethereum signatures are verified by recovering the signer of the transaction the message was created from,
+    /// which has to be the `from` address in its delegated form.
     pub fn verify_signature(
         message: &Message,
-        signature: &Signature,
+        signature: &MessageSignature,
         chain_id: &ChainID,
     ) -> Result<(), SignedMessageError> {
-        let data = Self::bytes_to_sign(message, chain_id)?;
+        match signature {
+            MessageSignature::Filecoin(signature) => {
+                let data = Self::bytes_to_sign(message, chain_id)?;
 
-        signature
-            .verify(&data, &message.from)
-            .map_err(SignedMessageError::InvalidSignature)
+                signature
+                    .verify(&data, &message.from)
+                    .map_err(SignedMessageError::InvalidSignature)
+            }
+            MessageSignature::Ethereum(signature) => {
+                // Legacy transactions only have a gas price, which becomes both the fee cap and the premium;
+                // the transaction rebuilt from the message only has the fee cap, so the premium isn't signed.
+                if signature.tx_type == EthTransactionType::Legacy
+                    && message.gas_premium != message.gas_fee_cap
+                {
+                    return Err(SignedMessageError::InvalidSignature(
+                        "the gas premium of a legacy transaction must equal its gas price".into(),
+                    ));
+                }
+
+                let tx = to_eth_transaction(message, signature.tx_type, chain_id)
+                    .map_err(|e| SignedMessageError::InvalidSignature(e.to_string()))?;
+
+                let signer = signature
+                    .to_eth_signature(chain_id)
+                    .recover(tx.sighash())
+                    .map_err(|e| SignedMessageError::InvalidSignature(e.to_string()))?;
+
+                if to_fvm_address(signer) != message.from {
+                    return Err(SignedMessageError::InvalidSignature(format!(
+                        "the transaction was signed by {signer:?}, not {}",
+                        message.from
+                    )));
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// The hash of the Ethereum transaction the message was created from, if it came from Ethereum.
+    ///
+    /// This is the hash `eth_sendRawTransaction` returned to the client.
+    pub fn eth_tx_hash(&self, chain_id: &ChainID) -> anyhow::Result<Option<et::H256>> {
+        match self.signature {
+            MessageSignature::Filecoin(_) => Ok(None),
+            MessageSignature::Ethereum(ref sig) => {
+                let tx = to_eth_transaction(&self.message, sig.tx_type, chain_id)?;
+                Ok(Some(tx.hash(&sig.to_eth_signature(chain_id))))
+            }
+        }
     }
 
     /// Verifies that the from address of the message generated the signature.
@@ -108,7 +324,7 @@ impl SignedMessage {
     }
 
     /// Returns signature of the signed message.
-    pub fn signature(&self) -> &Signature {
+    pub fn signature(&self) -> &MessageSignature {
         &self.signature
     }
 
@@ -119,12 +335,17 @@ impl SignedMessage {
 
     /// Checks if the signed message is a BLS message.
     pub fn is_bls(&self) -> bool {
-        self.signature.signature_type() == SignatureType::BLS
+        matches!(&self.signature, MessageSignature::Filecoin(s) if s.signature_type() == SignatureType::BLS)
     }
 
     /// Checks if the signed message is a SECP message.
     pub fn is_secp256k1(&self) -> bool {
-        self.signature.signature_type() == SignatureType::Secp256k1
+        matches!(&self.signature, MessageSignature::Filecoin(s) if s.signature_type() == SignatureType::Secp256k1)
+    }
+
+    /// Checks if the signed message was created from an Ethereum transaction.
+    pub fn is_eth(&self) -> bool {
+        matches!(self.signature, MessageSignature::Ethereum(_))
     }
 }
 
@@ -161,7 +382,7 @@ mod arb {
     use fendermint_testing::arb::{ArbAddress, ArbTokenAmount};
     use fvm_shared::{crypto::signature::Signature, message::Message};
 
-    use super::SignedMessage;
+    use super::{MessageSignature, SignedMessage};
 
     /// An arbitrary `SignedMessage` that is at least as consistent as required for serialization.
     impl quickcheck::Arbitrary for SignedMessage {
@@ -175,7 +396,7 @@ mod arb {
 
             Self {
                 message,
-                signature: Signature::arbitrary(g),
+                signature: MessageSignature::Filecoin(Signature::arbitrary(g)),
             }
         }
     }
@@ -183,11 +404,41 @@ mod arb {
 
 #[cfg(test)]
 mod tests {
-    use fvm_shared::{address::Address, chainid::ChainID};
+    use ethers_core::types as et;
+    use ethers_core::types::transaction::eip2718::TypedTransaction;
+    use ethers_core::utils::keccak256;
+    use fvm_shared::{address::Address, chainid::ChainID, econ::TokenAmount};
     use quickcheck_macros::quickcheck;
     use rand::{rngs::StdRng, SeedableRng};
 
-    use super::SignedMessage;
+    use super::{EthSignature, EthTransactionType, MessageSignature, SignedMessage};
+
+    /// Sign an Ethereum transaction the way a wallet would, returning the sender address as well.
+    fn sign_eth(
+        tx: &mut TypedTransaction,
+        sk: &libsecp256k1::SecretKey,
+        chain_id: u64,
+    ) -> et::Signature {
+        let pk = libsecp256k1::PublicKey::from_secret_key(sk);
+        let from = et::H160::from_slice(&keccak256(&pk.serialize()[1..])[12..]);
+        tx.set_from(from);
+        tx.set_chain_id(chain_id);
+
+        let hash = tx.sighash();
+        let (sig, recovery_id) = libsecp256k1::sign(&libsecp256k1::Message::parse(&hash.0), sk);
+
+        let bz = sig.serialize();
+        let parity = recovery_id.serialize() as u64;
+        let v = match tx {
+            TypedTransaction::Legacy(_) => 35 + 2 * chain_id + parity,
+            _ => parity,
+        };
+        et::Signature {
+            r: et::U256::from_big_endian(&bz[..32]),
+            s: et::U256::from_big_endian(&bz[32..]),
+            v,
+        }
+    }
 
     #[quickcheck]
     fn chain_id_in_signature(msg: SignedMessage, chain_id: u64, seed: u64) -> Result<(), String> {
@@ -214,4 +465,113 @@ mod tests {
         }
         Ok(())
     }
+
+    #[quickcheck]
+    fn eth_signature_roundtrip(
+        seed: u64,
+        chain_id: u32,
+        nonce: u64,
+        value: u64,
+        data: Vec<u8>,
+        legacy: bool,
+    ) -> Result<(), String> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let sk = libsecp256k1::SecretKey::random(&mut rng);
+        let chain_id = chain_id as u64;
+
+        let to = et::H160::from_low_u64_be(seed);
+        let mut tx: TypedTransaction = if legacy {
+            et::TransactionRequest::new()
+                .to(to)
+                .nonce(nonce)
+                .value(value)
+                .data(data)
+                .gas(1_000_000)
+                .gas_price(100)
+                .into()
+        } else {
+            et::Eip1559TransactionRequest::new()
+                .to(to)
+                .nonce(nonce)
+                .value(value)
+                .data(data)
+                .gas(1_000_000)
+                .max_fee_per_gas(100)
+                .max_priority_fee_per_gas(10)
+                .into()
+        };
+
+        let sig = sign_eth(&mut tx, &sk, chain_id);
+        let signed = SignedMessage::new_eth(&tx, &sig).map_err(|e| e.to_string())?;
+
+        signed
+            .verify(&ChainID::from(chain_id))
+            .map_err(|e| format!("verifying failed: {e}"))?;
+
+        if signed.verify(&ChainID::from(chain_id + 1)).is_ok() {
+            return Err("verifying with a different chain ID should fail".into());
+        }
+
+        let bz = fvm_ipld_encoding::to_vec(&signed).map_err(|e| e.to_string())?;
+        let decoded: SignedMessage =
+            fvm_ipld_encoding::from_slice(&bz).map_err(|e| e.to_string())?;
+
+        if decoded != signed {
+            return Err("the signed message doesn't survive a serialization roundtrip".into());
+        }
+
+        // The transaction hash can be reconstructed from the message.
+        if let MessageSignature::Ethereum(esig) = signed.signature {
+            let chain_id = ChainID::from(chain_id);
+            let tx1 =
+                crate::conv::from_fvm::to_eth_transaction(&signed.message, esig.tx_type, &chain_id)
+                    .map_err(|e| e.to_string())?;
+            if tx1.hash(&esig.to_eth_signature(&chain_id)) != tx.hash(&sig) {
+                return Err("the reconstructed transaction hash differs".into());
+            }
+        } else {
+            return Err("expected an Ethereum signature".into());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn eth_legacy_premium_tampering() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let sk = libsecp256k1::SecretKey::random(&mut rng);
+        let chain_id = 314u64;
+
+        let mut tx: TypedTransaction = et::TransactionRequest::new()
+            .to(et::H160::from_low_u64_be(1))
+            .nonce(0)
+            .gas(1_000_000)
+            .gas_price(100)
+            .into();
+
+        let sig = sign_eth(&mut tx, &sk, chain_id);
+        let mut signed = SignedMessage::new_eth(&tx, &sig).unwrap();
+        signed.verify(&ChainID::from(chain_id)).unwrap();
+
+        // Lowering the premium would not change the transaction the signature is checked against.
+        signed.message.gas_premium = TokenAmount::from_atto(1);
+        assert!(signed.verify(&ChainID::from(chain_id)).is_err());
+    }
+
+    #[test]
+    fn eth_signature_normalizes_v() {
+        let sig = |v| et::Signature {
+            r: et::U256::one(),
+            s: et::U256::one(),
+            v,
+        };
+        let chain_id = ChainID::from(314u64);
+        let legacy = EthSignature::new(EthTransactionType::Legacy, &sig(35 + 2 * 314 + 1));
+        assert_eq!(legacy.y_parity, 1);
+        assert_eq!(legacy.to_eth_signature(&chain_id).v, 35 + 2 * 314 + 1);
+
+        let eip1559 = EthSignature::new(EthTransactionType::Eip1559, &sig(0));
+        assert_eq!(eip1559.y_parity, 0);
+        assert_eq!(eip1559.to_eth_signature(&chain_id).v, 0);
+    }
 }
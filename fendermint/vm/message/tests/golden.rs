@@ -19,7 +19,7 @@ mod chain {
 
     golden_cbor! { "chain", for_execution, |g| {
         loop {
-            if let msg @ ChainMessage::ForExecution(_) = ChainMessage::arbitrary(g) {
+            if let msg @ ChainMessage::ForExecution(_, _) = ChainMessage::arbitrary(g) {
                 return msg
             }
         }
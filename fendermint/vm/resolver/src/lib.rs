@@ -0,0 +1,38 @@
+// Copyright 2022-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+//! Resolution of CIDs proposed by validators with `ChainMessage::ForResolution`
+//! into the content they refer to, so that the messages can be executed once
+//! a validator proposes them with `ChainMessage::ForExecution`.
+use anyhow::anyhow;
+use async_trait::async_trait;
+use cid::{
+    multihash::{Code, MultihashDigest},
+    Cid,
+};
+
+mod local;
+mod pool;
+
+pub use local::LocalDirResolver;
+pub use pool::ResolvePool;
+
+/// Fetch the content a CID refers to from somewhere outside the ledger.
+#[async_trait]
+pub trait Resolver: Send + Sync + 'static {
+    /// Try to fetch the content; `None` means it's not available, at least not yet.
+    async fn resolve(&self, cid: &Cid) -> anyhow::Result<Option<Vec<u8>>>;
+
+    /// List the CIDs this node would like to see resolved, e.g. messages waiting to be relayed,
+    /// which it can propose for resolution when it makes a block.
+    async fn candidates(&self) -> anyhow::Result<Vec<Cid>> {
+        Ok(Vec::new())
+    }
+}
+
+/// Check that the content hashes to the digest in the CID.
+pub fn verify_content(cid: &Cid, content: &[u8]) -> anyhow::Result<bool> {
+    let code =
+        Code::try_from(cid.hash().code()).map_err(|e| anyhow!("unsupported hash in {cid}: {e}"))?;
+
+    Ok(code.digest(content) == *cid.hash())
+}
@@ -0,0 +1,69 @@
+// Copyright 2022-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+use std::path::PathBuf;
+
+use anyhow::Context;
+use async_trait::async_trait;
+use cid::Cid;
+
+use crate::Resolver;
+
+/// Resolve CIDs from files in a directory, named after the CID they contain.
+///
+/// Meant for testing, where the content can be put in place by hand;
+/// every file in the directory is a candidate for resolution.
+#[derive(Clone, Debug)]
+pub struct LocalDirResolver {
+    dir: PathBuf,
+}
+
+impl LocalDirResolver {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+}
+
+#[async_trait]
+impl Resolver for LocalDirResolver {
+    async fn resolve(&self, cid: &Cid) -> anyhow::Result<Option<Vec<u8>>> {
+        let path = self.dir.join(cid.to_string());
+
+        if !tokio::fs::try_exists(&path).await.unwrap_or_default() {
+            return Ok(None);
+        }
+
+        let content = tokio::fs::read(&path)
+            .await
+            .with_context(|| format!("failed to read {}", path.to_string_lossy()))?;
+
+        Ok(Some(content))
+    }
+
+    async fn candidates(&self) -> anyhow::Result<Vec<Cid>> {
+        let mut cids = Vec::new();
+
+        let mut entries = match tokio::fs::read_dir(&self.dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(cids),
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("failed to list {}", self.dir.to_string_lossy()))
+            }
+        };
+
+        while let Some(entry) = entries.next_entry().await? {
+            // Anything not named after a CID is not for us.
+            if let Some(cid) = entry
+                .file_name()
+                .to_str()
+                .and_then(|name| Cid::try_from(name).ok())
+            {
+                cids.push(cid);
+            }
+        }
+
+        cids.sort();
+
+        Ok(cids)
+    }
+}
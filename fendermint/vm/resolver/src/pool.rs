@@ -0,0 +1,260 @@
+// Copyright 2022-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use cid::Cid;
+
+use crate::{verify_content, Resolver};
+
+/// Tracks the resolution of CIDs by this node, retrying in the background until the content is found.
+///
+/// Unlike the pool of CIDs recorded on the ledger, this is local state: whether a CID has been
+/// resolved only affects what this node is willing to propose or vote for, not the execution.
+pub struct ResolvePool<R> {
+    resolver: Arc<R>,
+    /// CIDs being resolved, with the content once it's available.
+    items: Arc<Mutex<HashMap<Cid, Option<Vec<u8>>>>>,
+    /// CIDs seen proposed for resolution on the ledger, which are no longer candidates.
+    proposed: Arc<Mutex<HashSet<Cid>>>,
+    /// How long to wait before trying to resolve a CID again.
+    retry_delay: Duration,
+}
+
+// Not derived, so `R` doesn't need to be `Clone`.
+impl<R> Clone for ResolvePool<R> {
+    fn clone(&self) -> Self {
+        Self {
+            resolver: self.resolver.clone(),
+            items: self.items.clone(),
+            proposed: self.proposed.clone(),
+            retry_delay: self.retry_delay,
+        }
+    }
+}
+
+impl<R> ResolvePool<R>
+where
+    R: Resolver,
+{
+    pub fn new(resolver: R, retry_delay: Duration) -> Self {
+        Self {
+            resolver: Arc::new(resolver),
+            items: Default::default(),
+            proposed: Default::default(),
+            retry_delay,
+        }
+    }
+
+    /// Start resolving a CID in the background, unless it's already in the pool.
+    pub fn add(&self, cid: Cid) {
+        self.set_proposed(cid);
+        {
+            let mut items = self.items.lock().expect("mutex poisoned");
+            if items.contains_key(&cid) {
+                return;
+            }
+            items.insert(cid, None);
+        }
+
+        let pool = self.clone();
+
+        tokio::spawn(async move {
+            // Stop if the item has been removed, or resolved by someone else.
+            while pool.is_pending(&cid) {
+                match resolve_once(pool.resolver.as_ref(), &cid).await {
+                    Ok(Some(content)) => {
+                        pool.set_content(cid, content);
+                        tracing::debug!(cid = cid.to_string(), "resolved CID");
+                        return;
+                    }
+                    Ok(None) => {}
+                    Err(e) => {
+                        tracing::warn!(
+                            cid = cid.to_string(),
+                            error = e.to_string(),
+                            "failed to resolve CID"
+                        );
+                    }
+                }
+                tokio::time::sleep(pool.retry_delay).await;
+            }
+        });
+    }
+
+    /// Resolve a CID right away, without waiting for the background resolution.
+    pub async fn resolve_now(&self, cid: &Cid) -> anyhow::Result<Option<Vec<u8>>> {
+        if let Some(content) = self.get(cid) {
+            return Ok(Some(content));
+        }
+        let content = resolve_once(self.resolver.as_ref(), cid).await?;
+
+        if let Some(ref content) = content {
+            self.set_content(*cid, content.clone());
+        }
+        Ok(content)
+    }
+
+    /// Remember that a CID has been proposed for resolution on the ledger,
+    /// so this node doesn't propose it again.
+    pub fn set_proposed(&self, cid: Cid) {
+        let mut proposed = self.proposed.lock().expect("mutex poisoned");
+        proposed.insert(cid);
+    }
+
+    /// List the CIDs offered by the resolver which haven't been proposed for resolution yet.
+    pub async fn candidates(&self) -> Vec<Cid> {
+        let cids = match self.resolver.candidates().await {
+            Ok(cids) => cids,
+            Err(e) => {
+                tracing::warn!(error = e.to_string(), "failed to list candidate CIDs");
+                return Vec::new();
+            }
+        };
+        let proposed = self.proposed.lock().expect("mutex poisoned");
+        cids.into_iter()
+            .filter(|cid| !proposed.contains(cid))
+            .collect()
+    }
+
+    /// Get the content of a resolved CID.
+    pub fn get(&self, cid: &Cid) -> Option<Vec<u8>> {
+        let items = self.items.lock().expect("mutex poisoned");
+        items.get(cid).cloned().flatten()
+    }
+
+    /// Check if a CID is in the pool, whether it has been resolved or not.
+    pub fn contains(&self, cid: &Cid) -> bool {
+        let items = self.items.lock().expect("mutex poisoned");
+        items.contains_key(cid)
+    }
+
+    /// Check if the content of a CID is available.
+    pub fn is_resolved(&self, cid: &Cid) -> bool {
+        let items = self.items.lock().expect("mutex poisoned");
+        matches!(items.get(cid), Some(Some(_)))
+    }
+
+    /// List the CIDs which have been resolved, in a stable order.
+    pub fn resolved(&self) -> Vec<Cid> {
+        let items = self.items.lock().expect("mutex poisoned");
+        let mut cids = items
+            .iter()
+            .filter_map(|(cid, content)| content.as_ref().map(|_| *cid))
+            .collect::<Vec<_>>();
+        cids.sort();
+        cids
+    }
+
+    /// Forget about a CID, e.g. after it has been executed; stops any ongoing resolution.
+    pub fn remove(&self, cid: &Cid) {
+        let mut items = self.items.lock().expect("mutex poisoned");
+        items.remove(cid);
+    }
+
+    fn is_pending(&self, cid: &Cid) -> bool {
+        let items = self.items.lock().expect("mutex poisoned");
+        matches!(items.get(cid), Some(None))
+    }
+
+    fn set_content(&self, cid: Cid, content: Vec<u8>) {
+        let mut items = self.items.lock().expect("mutex poisoned");
+        items.insert(cid, Some(content));
+    }
+}
+
+/// Try to resolve a CID, ignoring any content that doesn't match it.
+async fn resolve_once<R: Resolver>(resolver: &R, cid: &Cid) -> anyhow::Result<Option<Vec<u8>>> {
+    match resolver.resolve(cid).await? {
+        Some(content) if verify_content(cid, &content)? => Ok(Some(content)),
+        Some(_) => {
+            tracing::warn!(
+                cid = cid.to_string(),
+                "resolved content doesn't match the CID"
+            );
+            Ok(None)
+        }
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use cid::{
+        multihash::{Code, MultihashDigest},
+        Cid,
+    };
+    use fvm_ipld_encoding::DAG_CBOR;
+
+    use crate::{LocalDirResolver, ResolvePool};
+
+    fn content_cid(content: &[u8]) -> Cid {
+        Cid::new_v1(DAG_CBOR, Code::Blake2b256.digest(content))
+    }
+
+    #[tokio::test]
+    async fn resolve_in_background() {
+        let dir = tempfile::tempdir().unwrap();
+        let pool = ResolvePool::new(
+            LocalDirResolver::new(dir.path().to_path_buf()),
+            Duration::from_millis(10),
+        );
+
+        let content = fvm_ipld_encoding::to_vec(&"foo").unwrap();
+        let cid = content_cid(&content);
+
+        pool.add(cid);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!pool.is_resolved(&cid));
+
+        std::fs::write(dir.path().join(cid.to_string()), &content).unwrap();
+
+        for _ in 0..100 {
+            if pool.is_resolved(&cid) {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(pool.get(&cid), Some(content));
+        assert_eq!(pool.resolved(), vec![cid]);
+
+        pool.remove(&cid);
+        assert!(pool.resolved().is_empty());
+    }
+
+    #[tokio::test]
+    async fn list_candidates_until_proposed() {
+        let dir = tempfile::tempdir().unwrap();
+        let pool = ResolvePool::new(
+            LocalDirResolver::new(dir.path().to_path_buf()),
+            Duration::from_millis(10),
+        );
+
+        let cid = content_cid(b"foo");
+        std::fs::write(dir.path().join(cid.to_string()), b"foo").unwrap();
+        std::fs::write(dir.path().join("README"), b"not a CID").unwrap();
+
+        assert_eq!(pool.candidates().await, vec![cid]);
+
+        pool.set_proposed(cid);
+        assert!(pool.candidates().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn reject_mismatching_content() {
+        let dir = tempfile::tempdir().unwrap();
+        let pool = ResolvePool::new(
+            LocalDirResolver::new(dir.path().to_path_buf()),
+            Duration::from_millis(10),
+        );
+
+        let cid = content_cid(b"foo");
+        std::fs::write(dir.path().join(cid.to_string()), b"bar").unwrap();
+
+        let content = pool.resolve_now(&cid).await.unwrap();
+        assert!(content.is_none());
+    }
+}
@@ -0,0 +1,132 @@
+// Copyright 2022-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::Path;
+
+use anyhow::Context;
+use cid::Cid;
+use futures::io::AllowStdIo;
+use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_car::{load_car, CarHeader};
+
+use crate::dag::DagWalk;
+
+/// Write all blocks reachable from the `root` into a CAR file.
+///
+/// This is a blocking operation; the caller should run it on a dedicated thread.
+///
+/// Returns the number of blocks exported.
+pub fn export_car<BS: Blockstore>(store: &BS, root: Cid, path: &Path) -> anyhow::Result<usize> {
+    let file = File::create(path)
+        .with_context(|| format!("failed to create CAR file {}", path.to_string_lossy()))?;
+
+    let mut writer = AllowStdIo::new(BufWriter::new(file));
+    let header = CarHeader::new(vec![root], 1);
+
+    let mut error = None;
+    let mut count = 0;
+    {
+        // The stream cannot fail, so stop at the first error and remember it.
+        let blocks = DagWalk::new(store, [root]).map_while(|res| match res {
+            Ok(block) => {
+                count += 1;
+                Some(block)
+            }
+            Err(e) => {
+                error = Some(e);
+                None
+            }
+        });
+        let mut stream = futures::stream::iter(blocks);
+
+        futures::executor::block_on(header.write_stream_async(&mut writer, &mut stream))
+            .context("failed to write CAR file")?;
+    }
+
+    if let Some(e) = error {
+        return Err(e);
+    }
+
+    writer
+        .into_inner()
+        .flush()
+        .context("failed to flush CAR file")?;
+
+    Ok(count)
+}
+
+/// Load all blocks from a CAR file into the store, checking that their CIDs match their content.
+///
+/// This is a blocking operation; the caller should run it on a dedicated thread.
+///
+/// Returns the roots listed in the CAR header.
+pub fn import_car<BS: Blockstore>(store: &BS, path: &Path) -> anyhow::Result<Vec<Cid>> {
+    let file = File::open(path)
+        .with_context(|| format!("failed to open CAR file {}", path.to_string_lossy()))?;
+
+    let reader = AllowStdIo::new(BufReader::new(file));
+
+    let roots =
+        futures::executor::block_on(load_car(store, reader)).context("failed to load CAR file")?;
+
+    Ok(roots)
+}
+
+#[cfg(test)]
+mod tests {
+    use cid::multihash::Code;
+    use cid::Cid;
+    use fvm_ipld_blockstore::{Blockstore, MemoryBlockstore};
+    use fvm_ipld_encoding::CborStore;
+
+    use super::{export_car, import_car};
+
+    #[test]
+    fn export_import_roundtrip() {
+        let store = MemoryBlockstore::new();
+
+        let put = |value: &(u64, Vec<Cid>)| store.put_cbor(value, Code::Blake2b256).unwrap();
+
+        let leaf1 = put(&(1, vec![]));
+        let leaf2 = put(&(2, vec![]));
+        let branch = put(&(3, vec![leaf1, leaf2]));
+        // Shared links should only be exported once.
+        let root = put(&(4, vec![branch, leaf2]));
+        // Unreachable blocks should not be exported.
+        let orphan = put(&(5, vec![root]));
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.car");
+
+        let count = export_car(&store, root, &path).expect("failed to export");
+        assert_eq!(count, 4);
+
+        let imported = MemoryBlockstore::new();
+        let roots = import_car(&imported, &path).expect("failed to import");
+
+        assert_eq!(roots, vec![root]);
+
+        for cid in [root, branch, leaf1, leaf2] {
+            assert_eq!(imported.get(&cid).unwrap(), store.get(&cid).unwrap());
+        }
+        assert!(!imported.has(&orphan).unwrap());
+    }
+
+    #[test]
+    fn export_missing_block() {
+        let store = MemoryBlockstore::new();
+        let leaf = MemoryBlockstore::new()
+            .put_cbor(&(1u64, Vec::<Cid>::new()), Code::Blake2b256)
+            .unwrap();
+        let root = store
+            .put_cbor(&(2u64, vec![leaf]), Code::Blake2b256)
+            .unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.car");
+
+        assert!(export_car(&store, root, &path).is_err());
+    }
+}
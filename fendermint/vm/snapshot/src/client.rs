@@ -0,0 +1,137 @@
+// Copyright 2022-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use cid::Cid;
+use fendermint_vm_interpreter::fvm::state::FvmStateParams;
+use tokio::sync::mpsc;
+
+use crate::manifest::{list_manifests, SnapshotItem};
+
+/// Interface for the application to the background [SnapshotManager](crate::SnapshotManager).
+#[derive(Clone)]
+pub struct SnapshotClient {
+    notifications: mpsc::UnboundedSender<(u64, FvmStateParams)>,
+    snapshots_dir: PathBuf,
+    /// Export a snapshot at every block height divisible by this number.
+    block_interval: u64,
+    /// State roots of the snapshots queued or being exported, by block height.
+    exporting: Arc<Mutex<BTreeMap<u64, Cid>>>,
+}
+
+impl SnapshotClient {
+    pub(crate) fn new(
+        notifications: mpsc::UnboundedSender<(u64, FvmStateParams)>,
+        snapshots_dir: PathBuf,
+        block_interval: u64,
+        exporting: Arc<Mutex<BTreeMap<u64, Cid>>>,
+    ) -> Self {
+        Self {
+            notifications,
+            snapshots_dir,
+            block_interval,
+            exporting,
+        }
+    }
+
+    /// Notify the manager about a new committed block, which it exports if it's at the snapshot interval.
+    pub fn notify(&self, block_height: u64, state_params: FvmStateParams) {
+        if block_height == 0 || block_height % self.block_interval != 0 {
+            return;
+        }
+
+        let state_root = state_params.state_root;
+        let mut exporting = self.exporting.lock().expect("mutex poisoned");
+
+        if self
+            .notifications
+            .send((block_height, state_params))
+            .is_err()
+        {
+            tracing::warn!(block_height, "snapshot manager is no longer running");
+        } else {
+            exporting.insert(block_height, state_root);
+        }
+    }
+
+    /// State roots of the snapshots which haven't finished exporting yet,
+    /// which have to be kept in the store until they are written to disk.
+    pub fn exporting_state_roots(&self) -> Vec<Cid> {
+        let exporting = self.exporting.lock().expect("mutex poisoned");
+        exporting.values().cloned().collect()
+    }
+
+    /// List the snapshots which are currently available on disk, ordered by height.
+    pub fn list_snapshots(&self) -> anyhow::Result<Vec<SnapshotItem>> {
+        list_manifests(&self.snapshots_dir)
+    }
+
+    /// Find a snapshot by its height and format.
+    pub fn access_snapshot(
+        &self,
+        block_height: u64,
+        format: u32,
+    ) -> anyhow::Result<Option<SnapshotItem>> {
+        let item = self.list_snapshots()?.into_iter().find(|item| {
+            item.manifest.block_height == block_height && item.manifest.version == format
+        });
+        Ok(item)
+    }
+
+    /// Directory where snapshots offered by peers are downloaded to.
+    pub fn download_dir(&self) -> PathBuf {
+        self.snapshots_dir.join(".download")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::sync::{Arc, Mutex};
+
+    use cid::multihash::{Code, MultihashDigest};
+    use cid::Cid;
+    use fendermint_vm_interpreter::fvm::state::FvmStateParams;
+    use fvm_ipld_encoding::DAG_CBOR;
+    use fvm_shared::{econ::TokenAmount, version::NetworkVersion};
+    use tokio::sync::mpsc;
+
+    use super::SnapshotClient;
+
+    #[test]
+    fn track_exporting_state_roots() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let exporting = Arc::new(Mutex::new(BTreeMap::new()));
+        let client = SnapshotClient::new(tx, "snapshots".into(), 5, exporting.clone());
+
+        let state_params = FvmStateParams {
+            state_root: Cid::new_v1(DAG_CBOR, Code::Blake2b256.digest(b"state")),
+            timestamp: fendermint_vm_core::Timestamp(1234),
+            network_version: NetworkVersion::V18,
+            base_fee: TokenAmount::from_atto(100),
+            base_fee_params: None,
+            block_reward: TokenAmount::from_atto(10),
+            circ_supply: TokenAmount::from_atto(1000),
+            chain_id: 1,
+        };
+
+        // Not at the interval, so the manager isn't asked to export it.
+        client.notify(4, state_params.clone());
+        assert!(client.exporting_state_roots().is_empty());
+        assert!(rx.try_recv().is_err());
+
+        client.notify(5, state_params.clone());
+        assert_eq!(
+            client.exporting_state_roots(),
+            vec![state_params.state_root]
+        );
+        assert_eq!(rx.try_recv().unwrap().0, 5);
+
+        // What the manager does when the export is finished.
+        exporting.lock().unwrap().remove(&5);
+        assert!(client.exporting_state_roots().is_empty());
+    }
+}
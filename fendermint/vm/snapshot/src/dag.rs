@@ -0,0 +1,100 @@
+// Copyright 2022-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+use std::collections::HashSet;
+
+use anyhow::anyhow;
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_encoding::DAG_CBOR;
+use libipld_core::ipld::Ipld;
+
+/// Multihash code of the identity "hash", which inlines the data into the CID.
+const IDENTITY_HASH: u64 = 0x0;
+
+/// Depth-first traversal of all blocks reachable from some roots, visiting each block once.
+///
+/// More roots can be added after the iteration finished, in which case only the blocks
+/// which haven't been visited before will be returned.
+pub struct DagWalk<'a, BS> {
+    store: &'a BS,
+    stack: Vec<Cid>,
+    seen: HashSet<Cid>,
+}
+
+impl<'a, BS> DagWalk<'a, BS> {
+    pub fn new(store: &'a BS, roots: impl IntoIterator<Item = Cid>) -> Self {
+        Self {
+            store,
+            stack: roots.into_iter().collect(),
+            seen: Default::default(),
+        }
+    }
+
+    /// Add another root to visit.
+    pub fn push(&mut self, root: Cid) {
+        self.stack.push(root)
+    }
+
+    /// Check whether a block has already been visited.
+    pub fn is_visited(&self, cid: &Cid) -> bool {
+        self.seen.contains(cid)
+    }
+}
+
+impl<'a, BS: Blockstore> DagWalk<'a, BS> {
+    fn push_links(&mut self, cid: &Cid, data: &[u8]) -> anyhow::Result<()> {
+        // Only DAG-CBOR links are reachable in the FVM; other codecs are treated as leaves.
+        if cid.codec() != DAG_CBOR {
+            return Ok(());
+        }
+        let ipld = fvm_ipld_encoding::from_slice::<Ipld>(data)
+            .map_err(|e| anyhow!("failed to decode block {cid}: {e}"))?;
+
+        collect_links(&ipld, &mut self.stack);
+
+        Ok(())
+    }
+}
+
+impl<'a, BS: Blockstore> Iterator for DagWalk<'a, BS> {
+    type Item = anyhow::Result<(Cid, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(cid) = self.stack.pop() {
+            if !self.seen.insert(cid) {
+                continue;
+            }
+
+            // Identity CIDs are not stored, but they might still contain links.
+            if cid.hash().code() == IDENTITY_HASH {
+                if let Err(e) = self.push_links(&cid, cid.hash().digest()) {
+                    return Some(Err(e));
+                }
+                continue;
+            }
+
+            let data = match self.store.get(&cid) {
+                Ok(Some(data)) => data,
+                Ok(None) => return Some(Err(anyhow!("block {cid} is missing from the store"))),
+                Err(e) => return Some(Err(e)),
+            };
+
+            if let Err(e) = self.push_links(&cid, &data) {
+                return Some(Err(e));
+            }
+
+            return Some(Ok((cid, data)));
+        }
+        None
+    }
+}
+
+fn collect_links(ipld: &Ipld, links: &mut Vec<Cid>) {
+    match ipld {
+        Ipld::Link(cid) => links.push(*cid),
+        Ipld::List(xs) => xs.iter().for_each(|x| collect_links(x, links)),
+        Ipld::Map(xs) => xs.values().for_each(|x| collect_links(x, links)),
+        _ => {}
+    }
+}
@@ -0,0 +1,23 @@
+// Copyright 2022-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+//! Snapshots of the ledger, to allow new nodes to join the network through
+//! [CometBFT state sync](https://docs.cometbft.com/v0.37/spec/abci/abci++_app_requirements#state-sync)
+//! instead of replaying all blocks since genesis.
+//!
+//! A snapshot consists of the state tree reachable from `FvmStateParams::state_root`,
+//! exported into a CAR file, which is split into chunks of a fixed size, plus a manifest
+//! which contains the rest of the `FvmStateParams` that aren't part of the state tree.
+
+mod car;
+mod client;
+mod dag;
+mod manager;
+mod manifest;
+mod restore;
+
+pub use car::{export_car, import_car};
+pub use client::SnapshotClient;
+pub use dag::DagWalk;
+pub use manager::SnapshotManager;
+pub use manifest::{SnapshotItem, SnapshotManifest, SNAPSHOT_FORMAT};
+pub use restore::{SnapshotRestore, SnapshotRestoreError};
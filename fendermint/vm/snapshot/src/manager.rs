@@ -0,0 +1,377 @@
+// Copyright 2022-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use anyhow::Context;
+use cid::Cid;
+use fendermint_vm_interpreter::fvm::state::FvmStateParams;
+use fvm_ipld_blockstore::Blockstore;
+use tokio::sync::mpsc;
+
+use crate::car::export_car;
+use crate::client::SnapshotClient;
+use crate::manifest::{
+    chunk_path, list_manifests, write_manifest, SnapshotItem, SnapshotManifest, PARTS_DIR_NAME,
+    SNAPSHOT_DIR_PREFIX, SNAPSHOT_FORMAT,
+};
+
+/// Exports snapshots of the state in the background, every so many blocks,
+/// and removes old ones to keep only a limited history.
+pub struct SnapshotManager<BS> {
+    store: BS,
+    snapshots_dir: PathBuf,
+    /// Number of snapshots to keep; 0 means unlimited.
+    hist_size: usize,
+    /// Maximum size of the parts the CAR file is split into.
+    chunk_size: usize,
+    notifications: mpsc::UnboundedReceiver<(u64, FvmStateParams)>,
+    /// State roots the client asked to be exported, removed once the export is done.
+    exporting: Arc<Mutex<BTreeMap<u64, Cid>>>,
+}
+
+impl<BS> SnapshotManager<BS>
+where
+    BS: Blockstore + Clone + Send + Sync + 'static,
+{
+    /// Create a new manager, and a client the application can use to notify
+    /// it about committed blocks and to access the exported snapshots.
+    pub fn new(
+        store: BS,
+        snapshots_dir: PathBuf,
+        block_interval: u64,
+        hist_size: usize,
+        chunk_size: usize,
+    ) -> anyhow::Result<(Self, SnapshotClient)> {
+        if block_interval == 0 {
+            return Err(anyhow::anyhow!(
+                "the snapshot block interval must be positive"
+            ));
+        }
+        if chunk_size == 0 {
+            return Err(anyhow::anyhow!("the snapshot chunk size must be positive"));
+        }
+
+        std::fs::create_dir_all(&snapshots_dir).context("failed to create snapshots directory")?;
+
+        let (tx, rx) = mpsc::unbounded_channel();
+        let exporting = Arc::new(Mutex::new(BTreeMap::new()));
+
+        let manager = Self {
+            store,
+            snapshots_dir: snapshots_dir.clone(),
+            hist_size,
+            chunk_size,
+            notifications: rx,
+            exporting: exporting.clone(),
+        };
+
+        let client = SnapshotClient::new(tx, snapshots_dir, block_interval, exporting);
+
+        Ok((manager, client))
+    }
+
+    /// Export snapshots as blocks are committed, until all clients are dropped.
+    ///
+    /// The client only sends the heights at the snapshot interval.
+    pub async fn run(mut self) {
+        while let Some((block_height, state_params)) = self.notifications.recv().await {
+            let store = self.store.clone();
+            let snapshots_dir = self.snapshots_dir.clone();
+            let chunk_size = self.chunk_size;
+
+            let res = tokio::task::spawn_blocking(move || {
+                export_snapshot(
+                    &store,
+                    &snapshots_dir,
+                    block_height,
+                    state_params,
+                    chunk_size,
+                )
+            })
+            .await;
+
+            // Success or not, the state doesn't have to be kept for this export any more.
+            self.exporting
+                .lock()
+                .expect("mutex poisoned")
+                .remove(&block_height);
+
+            match res {
+                Ok(Ok(item)) => {
+                    tracing::info!(
+                        block_height,
+                        size = item.manifest.size,
+                        chunks = item.manifest.chunks,
+                        "exported snapshot"
+                    );
+                }
+                Ok(Err(e)) => {
+                    tracing::warn!(
+                        block_height,
+                        error = e.to_string(),
+                        "failed to export snapshot"
+                    );
+                    continue;
+                }
+                Err(e) => {
+                    tracing::error!(
+                        block_height,
+                        error = e.to_string(),
+                        "snapshot export panicked"
+                    );
+                    continue;
+                }
+            }
+
+            if let Err(e) = self.prune() {
+                tracing::warn!(error = e.to_string(), "failed to prune snapshots");
+            }
+        }
+    }
+
+    /// Remove the oldest snapshots beyond the history size.
+    fn prune(&self) -> anyhow::Result<()> {
+        if self.hist_size == 0 {
+            return Ok(());
+        }
+        let items = list_manifests(&self.snapshots_dir)?;
+        let excess = items.len().saturating_sub(self.hist_size);
+
+        for item in items.into_iter().take(excess) {
+            tracing::debug!(
+                block_height = item.manifest.block_height,
+                "removing old snapshot"
+            );
+            std::fs::remove_dir_all(&item.snapshot_dir).context("failed to remove snapshot")?;
+        }
+        Ok(())
+    }
+}
+
+/// Export the state into a temporary directory, split it into parts, write the manifest,
+/// then move it to its final place, so that only complete snapshots are ever listed.
+fn export_snapshot<BS: Blockstore>(
+    store: &BS,
+    snapshots_dir: &Path,
+    block_height: u64,
+    state_params: FvmStateParams,
+    chunk_size: usize,
+) -> anyhow::Result<SnapshotItem> {
+    let tmp_dir = snapshots_dir.join(format!(".tmp-{block_height}"));
+    let snapshot_dir = snapshots_dir.join(format!("{SNAPSHOT_DIR_PREFIX}{block_height}"));
+
+    if tmp_dir.exists() {
+        std::fs::remove_dir_all(&tmp_dir)?;
+    }
+    std::fs::create_dir_all(tmp_dir.join(PARTS_DIR_NAME))?;
+
+    let car_path = tmp_dir.join("snapshot.car");
+
+    export_car(store, state_params.state_root, &car_path)?;
+
+    let (size, chunks, checksum) = split_file(&car_path, &tmp_dir, chunk_size)?;
+
+    std::fs::remove_file(&car_path)?;
+
+    let manifest = SnapshotManifest {
+        block_height,
+        size,
+        chunks,
+        checksum,
+        state_params,
+        version: SNAPSHOT_FORMAT,
+    };
+
+    write_manifest(&tmp_dir, &manifest)?;
+
+    if snapshot_dir.exists() {
+        std::fs::remove_dir_all(&snapshot_dir)?;
+    }
+    std::fs::rename(&tmp_dir, &snapshot_dir).context("failed to move snapshot into place")?;
+
+    Ok(SnapshotItem {
+        snapshot_dir,
+        manifest,
+    })
+}
+
+/// Split a file into parts of at most `chunk_size` bytes.
+///
+/// Returns the total size, the number of parts and the checksum of the whole file.
+fn split_file(
+    path: &Path,
+    snapshot_dir: &Path,
+    chunk_size: usize,
+) -> anyhow::Result<(u64, u32, Vec<u8>)> {
+    let mut file = File::open(path).context("failed to open CAR file")?;
+    let mut hasher = blake2b_simd::Params::new().hash_length(32).to_state();
+    let mut buffer = vec![0u8; chunk_size];
+    let mut size = 0u64;
+    let mut chunks = 0u32;
+
+    loop {
+        let n = read_chunk(&mut file, &mut buffer)?;
+        if n == 0 {
+            break;
+        }
+        let chunk = &buffer[..n];
+        hasher.update(chunk);
+
+        let mut part = BufWriter::new(File::create(chunk_path(snapshot_dir, chunks))?);
+        part.write_all(chunk)?;
+        part.flush()?;
+
+        size += n as u64;
+        chunks += 1;
+    }
+
+    Ok((size, chunks, hasher.finalize().as_bytes().to_vec()))
+}
+
+/// Fill the buffer as much as possible; only returns less than the buffer size at the end of the file.
+fn read_chunk(file: &mut File, buffer: &mut [u8]) -> std::io::Result<usize> {
+    let mut total = 0;
+    while total < buffer.len() {
+        let n = file.read(&mut buffer[total..])?;
+        if n == 0 {
+            break;
+        }
+        total += n;
+    }
+    Ok(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use cid::multihash::{Code, MultihashDigest};
+    use cid::Cid;
+    use fendermint_vm_interpreter::fvm::state::FvmStateParams;
+    use fvm_ipld_blockstore::{Blockstore, MemoryBlockstore};
+    use fvm_ipld_car::CarHeader;
+    use fvm_ipld_encoding::{CborStore, DAG_CBOR};
+    use fvm_shared::{econ::TokenAmount, version::NetworkVersion};
+
+    use crate::{SnapshotManifest, SnapshotRestore, SnapshotRestoreError, SNAPSHOT_FORMAT};
+
+    use super::export_snapshot;
+
+    fn test_state() -> (MemoryBlockstore, FvmStateParams) {
+        let store = MemoryBlockstore::new();
+        let mut links = Vec::new();
+        for i in 0..100u64 {
+            let leaf = store
+                .put_cbor(&(i, Vec::<Cid>::new()), Code::Blake2b256)
+                .unwrap();
+            links.push(leaf);
+        }
+        let state_root = store.put_cbor(&(0u64, links), Code::Blake2b256).unwrap();
+
+        let state_params = FvmStateParams {
+            state_root,
+            timestamp: fendermint_vm_core::Timestamp(1234),
+            network_version: NetworkVersion::V18,
+            base_fee: TokenAmount::from_atto(100),
+            base_fee_params: None,
+            block_reward: TokenAmount::from_atto(10),
+            circ_supply: TokenAmount::from_atto(1000),
+            chain_id: 1,
+        };
+
+        (store, state_params)
+    }
+
+    #[test]
+    fn export_and_restore() {
+        let (store, state_params) = test_state();
+        let dir = tempfile::tempdir().unwrap();
+
+        let item = export_snapshot(&store, dir.path(), 10, state_params.clone(), 128)
+            .expect("failed to export snapshot");
+
+        assert!(item.manifest.chunks > 1);
+
+        let download_dir = dir.path().join("download");
+        let mut restore = SnapshotRestore::new(&download_dir, item.manifest.clone()).unwrap();
+
+        for i in 0..item.manifest.chunks {
+            let chunk = item.load_chunk(i).unwrap().expect("chunk exists");
+            let done = restore.append_chunk(i, &chunk).unwrap();
+            assert_eq!(done, i + 1 == item.manifest.chunks);
+        }
+        assert!(item.load_chunk(item.manifest.chunks).unwrap().is_none());
+
+        let restored = MemoryBlockstore::new();
+        let manifest = restore.finish(&restored).expect("failed to restore");
+
+        assert_eq!(manifest.state_params.state_root, state_params.state_root);
+        assert!(restored.has(&state_params.state_root).unwrap());
+    }
+
+    #[test]
+    fn restore_bad_checksum() {
+        let (store, state_params) = test_state();
+        let dir = tempfile::tempdir().unwrap();
+
+        let mut item = export_snapshot(&store, dir.path(), 10, state_params, 1024).unwrap();
+        item.manifest.checksum[0] ^= 1;
+
+        let mut restore =
+            SnapshotRestore::new(&dir.path().join("download"), item.manifest.clone()).unwrap();
+
+        for i in 0..item.manifest.chunks {
+            let chunk = item.load_chunk(i).unwrap().unwrap();
+            restore.append_chunk(i, &chunk).unwrap();
+        }
+
+        let res = restore.finish(&MemoryBlockstore::new());
+        assert!(matches!(res, Err(SnapshotRestoreError::ChecksumMismatch)));
+    }
+
+    #[test]
+    fn restore_incomplete() {
+        let (_, state_params) = test_state();
+        let dir = tempfile::tempdir().unwrap();
+
+        // A CAR file which has the state root, but not the block it links to.
+        let store = MemoryBlockstore::new();
+        let missing = Cid::new_v1(DAG_CBOR, Code::Blake2b256.digest(b"missing"));
+        let state_root = store
+            .put_cbor(&(0u64, vec![missing]), Code::Blake2b256)
+            .unwrap();
+        let data = store.get(&state_root).unwrap().unwrap();
+
+        let mut car = Vec::new();
+        let mut blocks = futures::stream::iter(vec![(state_root, data)]);
+        futures::executor::block_on(
+            CarHeader::new(vec![state_root], 1).write_stream_async(&mut car, &mut blocks),
+        )
+        .unwrap();
+
+        let manifest = SnapshotManifest {
+            block_height: 10,
+            size: car.len() as u64,
+            chunks: 1,
+            checksum: blake2b_simd::Params::new()
+                .hash_length(32)
+                .hash(&car)
+                .as_bytes()
+                .to_vec(),
+            state_params: FvmStateParams {
+                state_root,
+                ..state_params
+            },
+            version: SNAPSHOT_FORMAT,
+        };
+
+        let mut restore = SnapshotRestore::new(&dir.path().join("download"), manifest).unwrap();
+        assert!(restore.append_chunk(0, &car).unwrap());
+
+        let res = restore.finish(&MemoryBlockstore::new());
+        assert!(matches!(res, Err(SnapshotRestoreError::Incomplete(_))));
+    }
+}
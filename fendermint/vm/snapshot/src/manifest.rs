@@ -0,0 +1,122 @@
+// Copyright 2022-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use fendermint_vm_interpreter::fvm::state::FvmStateParams;
+use serde::{Deserialize, Serialize};
+
+/// Version of the snapshot layout, reported to CometBFT as the `format` of the snapshot.
+pub const SNAPSHOT_FORMAT: u32 = 1;
+
+pub(crate) const MANIFEST_FILE_NAME: &str = "manifest.cbor";
+pub(crate) const PARTS_DIR_NAME: &str = "parts";
+pub(crate) const SNAPSHOT_DIR_PREFIX: &str = "snapshot-";
+
+/// Everything a peer needs to know about a snapshot before downloading it.
+///
+/// The manifest is sent to CometBFT as the `metadata` of the snapshot.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SnapshotManifest {
+    /// Height of the block which committed the state in the snapshot.
+    pub block_height: u64,
+    /// Size of the CAR file in bytes.
+    pub size: u64,
+    /// Number of chunks the CAR file was split into.
+    pub chunks: u32,
+    /// Blake2b-256 hash of the whole CAR file.
+    #[serde(with = "fvm_ipld_encoding::strict_bytes")]
+    pub checksum: Vec<u8>,
+    /// The parameters which were committed at `block_height`, including the `state_root`.
+    pub state_params: FvmStateParams,
+    /// Format of the snapshot, see [SNAPSHOT_FORMAT].
+    pub version: u32,
+}
+
+/// A snapshot which has been exported to the local file system.
+#[derive(Debug, Clone)]
+pub struct SnapshotItem {
+    /// Directory containing the manifest and the parts of the CAR file.
+    pub snapshot_dir: PathBuf,
+    pub manifest: SnapshotManifest,
+}
+
+impl SnapshotItem {
+    /// Path to one of the parts of the CAR file.
+    pub fn chunk_path(&self, index: u32) -> PathBuf {
+        chunk_path(&self.snapshot_dir, index)
+    }
+
+    /// Read a chunk from disk; returns `None` if the index is out of range.
+    pub fn load_chunk(&self, index: u32) -> anyhow::Result<Option<Vec<u8>>> {
+        if index >= self.manifest.chunks {
+            return Ok(None);
+        }
+        let path = self.chunk_path(index);
+        let bytes = std::fs::read(&path)
+            .with_context(|| format!("failed to read snapshot chunk {}", path.to_string_lossy()))?;
+        Ok(Some(bytes))
+    }
+}
+
+pub(crate) fn chunk_path(snapshot_dir: &Path, index: u32) -> PathBuf {
+    snapshot_dir
+        .join(PARTS_DIR_NAME)
+        .join(format!("{index}.part"))
+}
+
+pub(crate) fn write_manifest(
+    snapshot_dir: &Path,
+    manifest: &SnapshotManifest,
+) -> anyhow::Result<()> {
+    let bytes = fvm_ipld_encoding::to_vec(manifest).context("failed to encode manifest")?;
+    std::fs::write(snapshot_dir.join(MANIFEST_FILE_NAME), bytes)
+        .context("failed to write manifest")?;
+    Ok(())
+}
+
+pub(crate) fn read_manifest(snapshot_dir: &Path) -> anyhow::Result<SnapshotManifest> {
+    let path = snapshot_dir.join(MANIFEST_FILE_NAME);
+    let bytes = std::fs::read(&path)
+        .with_context(|| format!("failed to read manifest {}", path.to_string_lossy()))?;
+    let manifest = fvm_ipld_encoding::from_slice(&bytes)
+        .with_context(|| format!("failed to decode manifest {}", path.to_string_lossy()))?;
+    Ok(manifest)
+}
+
+/// List the completed snapshots in a directory, ordered by block height.
+///
+/// Directories which are still being written don't have the snapshot prefix yet.
+pub(crate) fn list_manifests(snapshots_dir: &Path) -> anyhow::Result<Vec<SnapshotItem>> {
+    let mut items = Vec::new();
+
+    if !snapshots_dir.exists() {
+        return Ok(items);
+    }
+
+    for entry in std::fs::read_dir(snapshots_dir).context("failed to list snapshots")? {
+        let entry = entry?;
+        let is_snapshot = entry
+            .file_name()
+            .to_str()
+            .map(|name| name.starts_with(SNAPSHOT_DIR_PREFIX))
+            .unwrap_or_default();
+
+        if !is_snapshot || !entry.path().join(MANIFEST_FILE_NAME).exists() {
+            continue;
+        }
+
+        let snapshot_dir = entry.path();
+        let manifest = read_manifest(&snapshot_dir)?;
+
+        items.push(SnapshotItem {
+            snapshot_dir,
+            manifest,
+        })
+    }
+
+    items.sort_by_key(|item| item.manifest.block_height);
+
+    Ok(items)
+}
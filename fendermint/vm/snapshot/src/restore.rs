@@ -0,0 +1,135 @@
+// Copyright 2022-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+
+use crate::car::import_car;
+use crate::dag::DagWalk;
+use crate::manifest::SnapshotManifest;
+
+#[derive(Debug, thiserror::Error)]
+pub enum SnapshotRestoreError {
+    #[error("unexpected chunk index {0}; expected {1}")]
+    UnexpectedChunk(u32, u32),
+    #[error("snapshot checksum mismatch")]
+    ChecksumMismatch,
+    #[error("unexpected roots in snapshot: {0:?}; expected {1}")]
+    StateRootMismatch(Vec<Cid>, Cid),
+    #[error("incomplete snapshot: {0}")]
+    Incomplete(String),
+    #[error("failed to restore snapshot: {0}")]
+    Other(#[from] anyhow::Error),
+}
+
+/// A snapshot being downloaded from peers, one chunk at a time.
+///
+/// The chunks are appended to a CAR file, which is only imported
+/// into the blockstore once all of them arrived and the checksum
+/// of the whole file is verified.
+pub struct SnapshotRestore {
+    manifest: SnapshotManifest,
+    car_path: PathBuf,
+    writer: BufWriter<File>,
+    hasher: blake2b_simd::State,
+    next_index: u32,
+}
+
+impl SnapshotRestore {
+    pub fn new(download_dir: &Path, manifest: SnapshotManifest) -> anyhow::Result<Self> {
+        std::fs::create_dir_all(download_dir).context("failed to create download directory")?;
+
+        let car_path = download_dir.join(format!("snapshot-{}.car", manifest.block_height));
+        let file = File::create(&car_path).context("failed to create snapshot file")?;
+
+        Ok(Self {
+            manifest,
+            car_path,
+            writer: BufWriter::new(file),
+            hasher: blake2b_simd::Params::new().hash_length(32).to_state(),
+            next_index: 0,
+        })
+    }
+
+    pub fn manifest(&self) -> &SnapshotManifest {
+        &self.manifest
+    }
+
+    /// Append the next chunk to the snapshot file.
+    ///
+    /// Returns `true` once all the chunks have been received. Chunks which
+    /// have already been applied are ignored, in case they are sent again.
+    pub fn append_chunk(&mut self, index: u32, chunk: &[u8]) -> Result<bool, SnapshotRestoreError> {
+        if index < self.next_index {
+            return Ok(false);
+        }
+        if index > self.next_index {
+            return Err(SnapshotRestoreError::UnexpectedChunk(
+                index,
+                self.next_index,
+            ));
+        }
+
+        self.writer
+            .write_all(chunk)
+            .context("failed to write snapshot chunk")?;
+
+        self.hasher.update(chunk);
+        self.next_index += 1;
+
+        Ok(self.next_index == self.manifest.chunks)
+    }
+
+    /// Verify the checksum of the downloaded file and import its contents into the store,
+    /// then check that every block reachable from the state root has been imported.
+    ///
+    /// This is a blocking operation.
+    ///
+    /// Returns the manifest, so the application can adopt the state parameters in it.
+    pub fn finish<BS: Blockstore>(
+        mut self,
+        store: &BS,
+    ) -> Result<SnapshotManifest, SnapshotRestoreError> {
+        self.writer
+            .flush()
+            .context("failed to flush snapshot file")?;
+
+        if self.hasher.finalize().as_bytes() != self.manifest.checksum.as_slice() {
+            return Err(SnapshotRestoreError::ChecksumMismatch);
+        }
+
+        let roots = import_car(store, &self.car_path)?;
+        let state_root = self.manifest.state_params.state_root;
+
+        if roots != vec![state_root] {
+            return Err(SnapshotRestoreError::StateRootMismatch(roots, state_root));
+        }
+
+        // The checksum only proves that we got what the peer exported, not that it's the whole state.
+        for res in DagWalk::new(store, [state_root]) {
+            if let Err(e) = res {
+                return Err(SnapshotRestoreError::Incomplete(e.to_string()));
+            }
+        }
+
+        Ok(self.manifest.clone())
+    }
+}
+
+impl Drop for SnapshotRestore {
+    fn drop(&mut self) {
+        // Whether it was imported or abandoned, the downloaded file is no longer needed.
+        if let Err(e) = std::fs::remove_file(&self.car_path) {
+            tracing::warn!(
+                error = e.to_string(),
+                path = self.car_path.to_string_lossy().to_string(),
+                "failed to remove snapshot file"
+            );
+        }
+    }
+}